@@ -21,6 +21,11 @@ pub mod wg {
         /// On success, returns the created WGDevice instance.
         fn create_device(&self, device_name: &str, port: u16) -> Result<WGDevice, WGError>;
 
+        /// Sets the firewall mark of packets sent by the device; 0 disables it.
+        ///
+        /// Returns Ok(()) if the mark was successfully applied.
+        fn set_fwmark(&self, device_name: &str, fwmark: u32) -> Result<(), WGError>;
+
         /// Deletes the WireGuard device with the specified name.
         ///
         /// Returns Ok(()) if the device was successfully deleted.
@@ -41,10 +46,36 @@ pub mod wg {
             persistent_keepalive_interval: u16,
         ) -> Result<WGPeer, WGError>;
 
+        /// Adds several peers to the specified device in a single update of the device.
+        ///
+        /// Each entry holds the allowed IPs and keepalive of a peer; the created WGPeer instances
+        /// are returned in the same order.
+        fn add_peers(
+            &self,
+            device_name: &str,
+            peers: Vec<(Vec<&str>, u16)>,
+        ) -> Result<Vec<WGPeer>, WGError>;
+
+        /// Replaces the allowed IPs and keepalive of the peer with the given public key.
+        ///
+        /// Returns the updated WGPeer instance.
+        fn update_peer(
+            &self,
+            device_name: &str,
+            public_key: &str,
+            allowed_ips: Vec<&str>,
+            persistent_keepalive_interval: u16,
+        ) -> Result<WGPeer, WGError>;
+
         /// Deletes a peer from the device using its public key.
         ///
         /// Returns Ok(()) if the peer was successfully removed.
         fn delete_peer(&self, device_name: &str, public_key: &str) -> Result<(), WGError>;
+
+        /// Checks that WireGuard can be managed, i.e. that the kernel module is available.
+        ///
+        /// Returns Ok(()) if the generic netlink family of WireGuard was resolved.
+        fn check(&self) -> Result<(), WGError>;
     }
 }
 
@@ -57,5 +88,105 @@ pub mod netdev {
         fn set_ip(&self, device_name: &str, ip: &NetDevIp) -> Result<(), NetDevError>;
 
         fn up(&self, device_name: &str) -> Result<(), NetDevError>;
+
+        fn add_route(&self, device_name: &str, route: &NetDevRoute) -> Result<(), NetDevError>;
+
+        fn delete_route(&self, device_name: &str, route: &NetDevRoute) -> Result<(), NetDevError>;
+
+        fn add_rule(&self, rule: &NetDevRule) -> Result<(), NetDevError>;
+
+        fn delete_rule(&self, rule: &NetDevRule) -> Result<(), NetDevError>;
+
+        /// Lets the reverse path filter of IPv4 take the fwmark of a packet into account.
+        fn enable_src_valid_mark(&self) -> Result<(), NetDevError>;
+
+        fn enter_netns(&self, netns: &NetNs) -> Result<NetNsHandle, NetDevError>;
+
+        fn leave_netns(&self, handle: NetNsHandle) -> Result<(), NetDevError>;
+
+        fn set_netns(&self, device_name: &str, netns: &NetNs) -> Result<(), NetDevError>;
+
+        fn check(&self) -> Result<(), NetDevError>;
+    }
+}
+
+pub mod firewall {
+    use crate::models::firewall::*;
+    use crate::models::netdev::IpFamily;
+
+    /// Adapter interface for the packet filtering rules managed on behalf of devices.
+    pub trait FirewallAdapter: Send + Sync {
+        /// Retrieves the masquerade setup of the device.
+        ///
+        /// Returns None if traffic from the device is not masqueraded.
+        fn get_masquerade(&self, device_name: &str) -> Result<Option<Masquerade>, FirewallError>;
+
+        /// Masquerades traffic from the device, replacing any existing setup.
+        ///
+        /// Without an egress interface, traffic leaving through any other interface is masqueraded.
+        fn add_masquerade(
+            &self,
+            device_name: &str,
+            masquerade: &Masquerade,
+        ) -> Result<(), FirewallError>;
+
+        /// Removes the masquerade setup of the device.
+        ///
+        /// Returns Ok(()) if there was nothing to remove.
+        fn delete_masquerade(&self, device_name: &str) -> Result<(), FirewallError>;
+
+        /// Reports whether the host forwards packets of the given family.
+        fn get_forwarding(&self, family: IpFamily) -> Result<bool, FirewallError>;
+
+        /// Enables or disables packet forwarding of the given family host-wide.
+        fn set_forwarding(&self, family: IpFamily, enabled: bool) -> Result<(), FirewallError>;
+
+        /// Lists the ports forwarded from the host to peers of the device.
+        fn list_forwards(&self, device_name: &str) -> Result<Vec<PortForward>, FirewallError>;
+
+        /// Forwards a port on the host's local addresses to a peer of the device.
+        ///
+        /// Fails with ForwardExists if any device already forwards the protocol and public port.
+        fn add_forward(
+            &self,
+            device_name: &str,
+            forward: &PortForward,
+        ) -> Result<(), FirewallError>;
+
+        /// Removes the forward of the given protocol and public port.
+        ///
+        /// Fails with ForwardNotFound if the device does not forward the port.
+        fn delete_forward(
+            &self,
+            device_name: &str,
+            protocol: Protocol,
+            public_port: u16,
+        ) -> Result<(), FirewallError>;
+
+        /// Removes all ports forwarded to peers of the device.
+        fn delete_forwards(&self, device_name: &str) -> Result<(), FirewallError>;
+
+        /// Lists the access rules for traffic forwarded from peers of the device.
+        fn list_acl_rules(&self, device_name: &str) -> Result<Vec<AclRule>, FirewallError>;
+
+        /// Replaces the access rules with the given id atomically; an empty slice removes them.
+        ///
+        /// Allow rules take precedence over deny rules.
+        fn replace_acl_rules(
+            &self,
+            device_name: &str,
+            id: u32,
+            rules: &[AclRule],
+        ) -> Result<(), FirewallError>;
+
+        /// Removes the access rules of the peer with the given public key.
+        fn delete_peer_acl_rules(
+            &self,
+            device_name: &str,
+            public_key: &str,
+        ) -> Result<(), FirewallError>;
+
+        /// Removes all access rules of the device.
+        fn delete_acl_rules(&self, device_name: &str) -> Result<(), FirewallError>;
     }
 }
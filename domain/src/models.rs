@@ -1,10 +1,76 @@
 pub mod wg {
-    #[derive(Debug)]
-    pub struct WGError(pub String);
+    /// Kind of failure reported by libwgshim, one per `LIBWGSHIM_ERR_*` code.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum WGErrorKind {
+        NoMem,
+        DevNotFound,
+        DevAddFailed,
+        DevSetFailed,
+        PeerNotFound,
+        FamilyNotFound,
+    }
+
+    impl std::fmt::Display for WGErrorKind {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let msg = match self {
+                WGErrorKind::NoMem => "no memory",
+                WGErrorKind::DevNotFound => "device not found",
+                WGErrorKind::DevAddFailed => "adding device failed",
+                WGErrorKind::DevSetFailed => "setting device failed",
+                WGErrorKind::PeerNotFound => "peer not found",
+                WGErrorKind::FamilyNotFound => "wireguard netlink family not found",
+            };
+            f.write_str(msg)
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum WGError {
+        /// Failure reported by libwgshim, with the errno behind it and the extended ack message of
+        /// the kernel if there were any.
+        Lib {
+            kind: WGErrorKind,
+            errno: Option<i32>,
+            extack: Option<String>,
+        },
+        /// Argument that cannot be passed to libwgshim, such as a name containing a nul byte.
+        Invalid(String),
+        /// Any other failure.
+        Other(String),
+    }
+
+    impl WGError {
+        /// Human readable description, without the errno.
+        pub fn message(&self) -> String {
+            match self {
+                WGError::Lib { kind, .. } => kind.to_string(),
+                WGError::Invalid(msg) | WGError::Other(msg) => msg.clone(),
+            }
+        }
+    }
+
+    impl From<WGErrorKind> for WGError {
+        fn from(kind: WGErrorKind) -> Self {
+            WGError::Lib {
+                kind,
+                errno: None,
+                extack: None,
+            }
+        }
+    }
 
     impl std::fmt::Display for WGError {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            write!(f, "WGError: {}", self.0)
+            write!(f, "WGError: {}", self.message())?;
+            if let WGError::Lib { errno, extack, .. } = self {
+                if let Some(errno) = errno {
+                    write!(f, " ({})", std::io::Error::from_raw_os_error(*errno))?;
+                }
+                if let Some(extack) = extack {
+                    write!(f, ": {}", extack)?;
+                }
+            }
+            Ok(())
         }
     }
 
@@ -16,6 +82,7 @@ pub mod wg {
         pub public_key: String,
         pub private_key: String,
         pub port: u16,
+        pub fwmark: u32,
         pub peers: u64,
     }
 
@@ -39,12 +106,112 @@ pub mod wg {
 pub mod netdev {
     use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
-    #[derive(Debug)]
-    pub struct NetDevError(pub String);
+    /// Kind of failure reported by libnetdev, one per `LIBNETDEV_ERR_*` code.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum NetDevErrorKind {
+        NoMem,
+        CtlSocketFailed,
+        NetlinkSocketFailed,
+        GetDevFlagsFailed,
+        SetDevFlagsFailed,
+        InvalidIpStr,
+        InvalidIp,
+        InvalidIpPrefix,
+        DevIpSetFailed,
+        DevNetmaskSetFailed,
+        DevNotFound,
+        NetlinkSendFailed,
+        GetifaddrsFailed,
+        NetlinkRecvFailed,
+        RouteAddFailed,
+        RouteDelFailed,
+        RouteExists,
+        RuleAddFailed,
+        RuleDelFailed,
+        InvalidNetns,
+        NetnsOpenFailed,
+        NetnsEnterFailed,
+        LinkNetnsFailed,
+    }
+
+    impl std::fmt::Display for NetDevErrorKind {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let msg = match self {
+                NetDevErrorKind::NoMem => "memory allocation failed",
+                NetDevErrorKind::CtlSocketFailed => "failed to open control socket",
+                NetDevErrorKind::NetlinkSocketFailed => "failed to open netlink socket",
+                NetDevErrorKind::GetDevFlagsFailed => "failed to get interface flags",
+                NetDevErrorKind::SetDevFlagsFailed => "failed to set interface flags",
+                NetDevErrorKind::InvalidIpStr => "invalid ip string format",
+                NetDevErrorKind::InvalidIp => "invalid ip address",
+                NetDevErrorKind::InvalidIpPrefix => "invalid ip prefix length",
+                NetDevErrorKind::DevIpSetFailed => "failed to set device ip",
+                NetDevErrorKind::DevNetmaskSetFailed => "failed to set device netmask",
+                NetDevErrorKind::DevNotFound => "device not found",
+                NetDevErrorKind::NetlinkSendFailed => "failed to send netlink message",
+                NetDevErrorKind::GetifaddrsFailed => "getifaddrs() system call failed",
+                NetDevErrorKind::NetlinkRecvFailed => "failed to receive netlink message",
+                NetDevErrorKind::RouteAddFailed => "failed to add route",
+                NetDevErrorKind::RouteDelFailed => "failed to delete route",
+                NetDevErrorKind::RouteExists => "route exists through another device",
+                NetDevErrorKind::RuleAddFailed => "failed to add rule",
+                NetDevErrorKind::RuleDelFailed => "failed to delete rule",
+                NetDevErrorKind::InvalidNetns => "invalid network namespace",
+                NetDevErrorKind::NetnsOpenFailed => "network namespace not found",
+                NetDevErrorKind::NetnsEnterFailed => "failed to switch network namespace",
+                NetDevErrorKind::LinkNetnsFailed => "failed to move device to network namespace",
+            };
+            f.write_str(msg)
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum NetDevError {
+        /// Failure reported by libnetdev, with the errno behind it and the extended ack message of
+        /// the kernel if there were any.
+        Lib {
+            kind: NetDevErrorKind,
+            errno: Option<i32>,
+            extack: Option<String>,
+        },
+        /// Argument that cannot be passed to libnetdev, such as a name containing a nul byte.
+        Invalid(String),
+        /// Any other failure.
+        Other(String),
+    }
+
+    impl NetDevError {
+        /// Human readable description, without the errno.
+        pub fn message(&self) -> String {
+            match self {
+                NetDevError::Lib { kind, .. } => kind.to_string(),
+                NetDevError::Invalid(msg) | NetDevError::Other(msg) => msg.clone(),
+            }
+        }
+    }
+
+    impl From<NetDevErrorKind> for NetDevError {
+        fn from(kind: NetDevErrorKind) -> Self {
+            NetDevError::Lib {
+                kind,
+                errno: None,
+                extack: None,
+            }
+        }
+    }
 
     impl std::fmt::Display for NetDevError {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            write!(f, "NetDevError: {}", self.0)
+            write!(f, "NetDevError: {}", self.message())?;
+            if let NetDevError::Lib { errno, extack, .. } = self {
+                if let Some(errno) = errno {
+                    write!(f, " ({})", std::io::Error::from_raw_os_error(*errno))?;
+                }
+                if let Some(extack) = extack {
+                    write!(f, ": {}", extack)?;
+                }
+            }
+            Ok(())
         }
     }
 
@@ -68,18 +235,179 @@ pub mod netdev {
                 _ => None,
             };
 
-            Self {
-                ipv4: ipv4,
-                ipv6: ipv6,
-            }
+            Self { ipv4, ipv6 }
         }
 
         pub fn ipv4_str(&self) -> Option<String> {
-            self.ipv4.map(|t| format!("{}/{}", t.0.to_string(), t.1))
+            self.ipv4.map(|t| format!("{}/{}", t.0, t.1))
         }
 
         pub fn ipv6_str(&self) -> Option<String> {
-            self.ipv6.map(|t| format!("{}/{}", t.0.to_string(), t.1))
+            self.ipv6.map(|t| format!("{}/{}", t.0, t.1))
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct NetDevRoute {
+        pub destination: (IpAddr, u8),
+        pub table: Option<u32>,
+        pub metric: Option<u32>,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum IpFamily {
+        V4,
+        V6,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct NetDevRule {
+        pub family: IpFamily,
+        pub table: u32,
+        pub fwmark: Option<u32>,
+        pub invert: bool,
+        pub suppress_prefixlength: Option<u32>,
+        pub priority: Option<u32>,
+    }
+
+    impl NetDevRoute {
+        pub fn destination_str(&self) -> String {
+            format!("{}/{}", self.destination.0, self.destination.1)
+        }
+    }
+
+    /// Network namespace, either a named one under /var/run/netns or the one of a process.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum NetNs {
+        Named(String),
+        Pid(u32),
+    }
+
+    /// Namespace a thread was in before entering another one, needed to switch back.
+    #[derive(Debug)]
+    pub struct NetNsHandle(pub i32);
+}
+
+pub mod firewall {
+    use std::net::IpAddr;
+
+    /// Kind of failure reported by libfirewall, one per `LIBFIREWALL_ERR_*` code.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum FirewallErrorKind {
+        NoMem,
+        NetlinkSocketFailed,
+        NetlinkSendFailed,
+        NetlinkRecvFailed,
+        InvalidDevName,
+        RulesetUpdateFailed,
+        RulesetReadFailed,
+        InvalidAddr,
+        InvalidProtocol,
+        InvalidAclId,
+        ForwardExists,
+        ForwardNotFound,
+    }
+
+    impl std::fmt::Display for FirewallErrorKind {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let msg = match self {
+                FirewallErrorKind::NoMem => "memory allocation failed",
+                FirewallErrorKind::NetlinkSocketFailed => "failed to open netlink socket",
+                FirewallErrorKind::NetlinkSendFailed => "failed to send netlink message",
+                FirewallErrorKind::NetlinkRecvFailed => "failed to receive netlink message",
+                FirewallErrorKind::InvalidDevName => "invalid device name",
+                FirewallErrorKind::RulesetUpdateFailed => "failed to update nftables ruleset",
+                FirewallErrorKind::RulesetReadFailed => "failed to read nftables ruleset",
+                FirewallErrorKind::InvalidAddr => "invalid ip address",
+                FirewallErrorKind::InvalidProtocol => "invalid protocol",
+                FirewallErrorKind::InvalidAclId => "acl rules must share the same id",
+                FirewallErrorKind::ForwardExists => "public port is already forwarded",
+                FirewallErrorKind::ForwardNotFound => "forward not found",
+            };
+            f.write_str(msg)
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum FirewallError {
+        /// Failure reported by libfirewall, with the errno behind it if there were any.
+        Lib {
+            kind: FirewallErrorKind,
+            errno: Option<i32>,
+        },
+        /// Argument that cannot be passed to libfirewall, such as a name containing a nul byte.
+        Invalid(String),
+        /// Any other failure.
+        Other(String),
+    }
+
+    impl FirewallError {
+        /// Human readable description, without the errno.
+        pub fn message(&self) -> String {
+            match self {
+                FirewallError::Lib { kind, .. } => kind.to_string(),
+                FirewallError::Invalid(msg) | FirewallError::Other(msg) => msg.clone(),
+            }
+        }
+    }
+
+    impl From<FirewallErrorKind> for FirewallError {
+        fn from(kind: FirewallErrorKind) -> Self {
+            FirewallError::Lib { kind, errno: None }
         }
     }
+
+    impl std::fmt::Display for FirewallError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "FirewallError: {}", self.message())?;
+            if let FirewallError::Lib {
+                errno: Some(errno), ..
+            } = self
+            {
+                write!(f, " ({})", std::io::Error::from_raw_os_error(*errno))?;
+            }
+            Ok(())
+        }
+    }
+
+    impl std::error::Error for FirewallError {}
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Masquerade {
+        pub egress_interface: Option<String>,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Protocol {
+        Tcp,
+        Udp,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct PortForward {
+        pub peer_public_key: String,
+        pub protocol: Protocol,
+        pub public_port: u16,
+        pub destination_ip: IpAddr,
+        pub destination_port: u16,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum AclAction {
+        Allow,
+        Deny,
+    }
+
+    /// Access rule for traffic forwarded from one of the peer's allowed ips. Rules sharing an
+    /// id were created together, typically one per source of every peer in a group.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct AclRule {
+        pub id: u32,
+        pub action: AclAction,
+        pub peer_public_key: String,
+        pub source: (IpAddr, u8),
+        pub destination: (IpAddr, u8),
+        pub protocol: Option<Protocol>,
+        pub port: Option<u16>,
+    }
 }
@@ -0,0 +1,9 @@
+fn main() {
+    cc::Build::new()
+        .files(["src/libfirewall/libfirewall.c"])
+        .include("src/libfirewall")
+        .compile("firewall");
+
+    println!("cargo:rerun-if-changed=src/libfirewall/libfirewall.c");
+    println!("cargo:rerun-if-changed=src/libfirewall/libfirewall.h");
+}
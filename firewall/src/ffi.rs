@@ -0,0 +1,238 @@
+use domain::models::firewall::{AclAction, AclRule, PortForward, Protocol};
+use std::ffi::{CStr, CString};
+use std::net::IpAddr;
+use std::os::raw::{c_char, c_int};
+use std::str::FromStr;
+
+pub const IF_NAMESIZE: usize = 16;
+pub const KEY_STRLEN: usize = 45;
+pub const INET6_ADDRSTRLEN: usize = 46;
+pub const CIDR_STRLEN: usize = 51;
+
+pub const IPPROTO_TCP: u8 = 6;
+pub const IPPROTO_UDP: u8 = 17;
+
+#[repr(C)]
+#[derive(Debug)]
+pub enum LibFirewallError {
+    NoMem = 1,
+    NetlinkSocketFailed,
+    NetlinkSendFailed,
+    NetlinkRecvFailed,
+    InvalidDevName,
+    RulesetUpdateFailed,
+    RulesetReadFailed,
+    InvalidAddr,
+    InvalidProtocol,
+    InvalidAclId,
+    ForwardExists,
+    ForwardNotFound,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct LibFirewallMasquerade {
+    pub enabled: u8,
+    pub egress_name: [c_char; IF_NAMESIZE],
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct LibFirewallForward {
+    pub peer_key: [c_char; KEY_STRLEN],
+    pub protocol: u8,
+    pub public_port: u16,
+    pub dst_addr: [c_char; INET6_ADDRSTRLEN],
+    pub dst_port: u16,
+    pub next: *mut LibFirewallForward,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct LibFirewallAcl {
+    pub id: u32,
+    pub allow: u8,
+    pub peer_key: [c_char; KEY_STRLEN],
+    pub src: [c_char; CIDR_STRLEN],
+    pub dst: [c_char; CIDR_STRLEN],
+    pub protocol: u8,
+    pub port: u16,
+    pub next: *mut LibFirewallAcl,
+}
+
+unsafe extern "C" {
+    pub unsafe fn libfirewall_add_masquerade(
+        device_name: *const c_char,
+        egress_name: *const c_char,
+    ) -> c_int;
+
+    pub unsafe fn libfirewall_get_masquerade(
+        device_name: *const c_char,
+        masquerade: *mut LibFirewallMasquerade,
+    ) -> c_int;
+
+    pub unsafe fn libfirewall_del_masquerade(device_name: *const c_char) -> c_int;
+
+    pub unsafe fn libfirewall_add_forward(
+        device_name: *const c_char,
+        forward: *const LibFirewallForward,
+    ) -> c_int;
+
+    pub unsafe fn libfirewall_list_forwards(
+        device_name: *const c_char,
+        forwards: *mut *mut LibFirewallForward,
+    ) -> c_int;
+
+    pub unsafe fn libfirewall_del_forward(
+        device_name: *const c_char,
+        protocol: u8,
+        public_port: u16,
+    ) -> c_int;
+
+    pub unsafe fn libfirewall_del_forwards(device_name: *const c_char) -> c_int;
+
+    pub unsafe fn libfirewall_free_forwards(forwards: *mut LibFirewallForward);
+
+    pub unsafe fn libfirewall_set_acls(
+        device_name: *const c_char,
+        id: u32,
+        acls: *const LibFirewallAcl,
+    ) -> c_int;
+
+    pub unsafe fn libfirewall_list_acls(
+        device_name: *const c_char,
+        acls: *mut *mut LibFirewallAcl,
+    ) -> c_int;
+
+    pub unsafe fn libfirewall_del_peer_acls(
+        device_name: *const c_char,
+        peer_key: *const c_char,
+    ) -> c_int;
+
+    pub unsafe fn libfirewall_del_acls(device_name: *const c_char) -> c_int;
+
+    pub unsafe fn libfirewall_free_acls(acls: *mut LibFirewallAcl);
+
+    pub unsafe fn libfirewall_last_errno() -> c_int;
+}
+
+pub fn protocol_number(protocol: Protocol) -> u8 {
+    match protocol {
+        Protocol::Tcp => IPPROTO_TCP,
+        Protocol::Udp => IPPROTO_UDP,
+    }
+}
+
+fn protocol_from_number(protocol: u8) -> Option<Protocol> {
+    match protocol {
+        IPPROTO_TCP => Some(Protocol::Tcp),
+        IPPROTO_UDP => Some(Protocol::Udp),
+        _ => None,
+    }
+}
+
+fn parse_cidr(cidr: &str) -> Option<(IpAddr, u8)> {
+    let (ip, prefix) = cidr.split_once('/')?;
+    Some((IpAddr::from_str(ip).ok()?, prefix.parse().ok()?))
+}
+
+fn copy_str<const N: usize>(s: &str) -> [c_char; N] {
+    let mut out = [0 as c_char; N];
+    if let Ok(cstring) = CString::new(s) {
+        let bytes = cstring.as_bytes_with_nul();
+        let len = bytes.len().min(N - 1);
+        for (dst, src) in out[..len].iter_mut().zip(bytes) {
+            *dst = *src as c_char;
+        }
+    }
+    out
+}
+
+impl LibFirewallMasquerade {
+    pub fn egress_name(&self) -> Option<String> {
+        unsafe { CStr::from_ptr(self.egress_name.as_ptr()) }
+            .to_str()
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_owned())
+    }
+}
+
+impl LibFirewallForward {
+    pub fn from_port_forward(forward: &PortForward) -> Self {
+        LibFirewallForward {
+            peer_key: copy_str(&forward.peer_public_key),
+            protocol: protocol_number(forward.protocol),
+            public_port: forward.public_port,
+            dst_addr: copy_str(&forward.destination_ip.to_string()),
+            dst_port: forward.destination_port,
+            next: std::ptr::null_mut(),
+        }
+    }
+
+    pub fn to_port_forward(&self) -> Option<PortForward> {
+        let protocol = protocol_from_number(self.protocol)?;
+
+        let peer_public_key = unsafe { CStr::from_ptr(self.peer_key.as_ptr()) }
+            .to_str()
+            .ok()?
+            .to_owned();
+
+        let dst_addr = unsafe { CStr::from_ptr(self.dst_addr.as_ptr()) }
+            .to_str()
+            .ok()?;
+        let destination_ip = IpAddr::from_str(dst_addr).ok()?;
+
+        Some(PortForward {
+            peer_public_key,
+            protocol,
+            public_port: self.public_port,
+            destination_ip,
+            destination_port: self.dst_port,
+        })
+    }
+}
+
+impl LibFirewallAcl {
+    pub fn from_acl_rule(rule: &AclRule) -> Self {
+        LibFirewallAcl {
+            id: rule.id,
+            allow: (rule.action == AclAction::Allow) as u8,
+            peer_key: copy_str(&rule.peer_public_key),
+            src: copy_str(&format!("{}/{}", rule.source.0, rule.source.1)),
+            dst: copy_str(&format!("{}/{}", rule.destination.0, rule.destination.1)),
+            protocol: rule.protocol.map(protocol_number).unwrap_or(0),
+            port: rule.port.unwrap_or(0),
+            next: std::ptr::null_mut(),
+        }
+    }
+
+    pub fn to_acl_rule(&self) -> Option<AclRule> {
+        let protocol = match self.protocol {
+            0 => None,
+            p => Some(protocol_from_number(p)?),
+        };
+
+        let peer_public_key = unsafe { CStr::from_ptr(self.peer_key.as_ptr()) }
+            .to_str()
+            .ok()?
+            .to_owned();
+
+        let src = unsafe { CStr::from_ptr(self.src.as_ptr()) }.to_str().ok()?;
+        let dst = unsafe { CStr::from_ptr(self.dst.as_ptr()) }.to_str().ok()?;
+
+        Some(AclRule {
+            id: self.id,
+            action: if self.allow != 0 {
+                AclAction::Allow
+            } else {
+                AclAction::Deny
+            },
+            peer_public_key,
+            source: parse_cidr(src)?,
+            destination: parse_cidr(dst)?,
+            protocol,
+            port: Some(self.port).filter(|p| *p != 0),
+        })
+    }
+}
@@ -0,0 +1,287 @@
+use std::ffi::CString;
+use std::fs;
+use std::ptr;
+
+use domain::adapters::firewall::FirewallAdapter;
+use domain::models::firewall::*;
+use domain::models::netdev::IpFamily;
+
+mod ffi;
+
+use std::convert::TryFrom;
+
+#[cfg(test)]
+mod tests;
+
+const IPV4_FORWARDING: &str = "/proc/sys/net/ipv4/ip_forward";
+const IPV6_FORWARDING: &str = "/proc/sys/net/ipv6/conf/all/forwarding";
+
+impl TryFrom<i32> for ffi::LibFirewallError {
+    type Error = ();
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::NoMem),
+            2 => Ok(Self::NetlinkSocketFailed),
+            3 => Ok(Self::NetlinkSendFailed),
+            4 => Ok(Self::NetlinkRecvFailed),
+            5 => Ok(Self::InvalidDevName),
+            6 => Ok(Self::RulesetUpdateFailed),
+            7 => Ok(Self::RulesetReadFailed),
+            8 => Ok(Self::InvalidAddr),
+            9 => Ok(Self::InvalidProtocol),
+            10 => Ok(Self::InvalidAclId),
+            11 => Ok(Self::ForwardExists),
+            12 => Ok(Self::ForwardNotFound),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<ffi::LibFirewallError> for FirewallErrorKind {
+    fn from(err: ffi::LibFirewallError) -> Self {
+        match err {
+            ffi::LibFirewallError::NoMem => FirewallErrorKind::NoMem,
+            ffi::LibFirewallError::NetlinkSocketFailed => FirewallErrorKind::NetlinkSocketFailed,
+            ffi::LibFirewallError::NetlinkSendFailed => FirewallErrorKind::NetlinkSendFailed,
+            ffi::LibFirewallError::NetlinkRecvFailed => FirewallErrorKind::NetlinkRecvFailed,
+            ffi::LibFirewallError::InvalidDevName => FirewallErrorKind::InvalidDevName,
+            ffi::LibFirewallError::RulesetUpdateFailed => FirewallErrorKind::RulesetUpdateFailed,
+            ffi::LibFirewallError::RulesetReadFailed => FirewallErrorKind::RulesetReadFailed,
+            ffi::LibFirewallError::InvalidAddr => FirewallErrorKind::InvalidAddr,
+            ffi::LibFirewallError::InvalidProtocol => FirewallErrorKind::InvalidProtocol,
+            ffi::LibFirewallError::InvalidAclId => FirewallErrorKind::InvalidAclId,
+            ffi::LibFirewallError::ForwardExists => FirewallErrorKind::ForwardExists,
+            ffi::LibFirewallError::ForwardNotFound => FirewallErrorKind::ForwardNotFound,
+        }
+    }
+}
+
+macro_rules! libfirewall_try {
+    ($code:expr) => {
+        let result: i32 = unsafe { $code };
+        if result != 0 {
+            let errno = unsafe { ffi::libfirewall_last_errno() };
+            let err = ffi::LibFirewallError::try_from(result)
+                .map(|e| FirewallError::Lib {
+                    kind: e.into(),
+                    errno: (errno != 0).then_some(errno),
+                })
+                .unwrap_or_else(|_| FirewallError::Other("firewall error".to_owned()));
+            return Err(err);
+        }
+    };
+}
+
+fn forwarding_path(family: IpFamily) -> &'static str {
+    match family {
+        IpFamily::V4 => IPV4_FORWARDING,
+        IpFamily::V6 => IPV6_FORWARDING,
+    }
+}
+
+pub struct NftAdapter;
+
+impl FirewallAdapter for NftAdapter {
+    fn get_masquerade(&self, device_name: &str) -> Result<Option<Masquerade>, FirewallError> {
+        let dev_name =
+            CString::new(device_name).map_err(|e| FirewallError::Invalid(e.to_string()))?;
+
+        let mut masquerade = ffi::LibFirewallMasquerade {
+            enabled: 0,
+            egress_name: [0; ffi::IF_NAMESIZE],
+        };
+        libfirewall_try!(ffi::libfirewall_get_masquerade(
+            dev_name.as_ptr(),
+            &mut masquerade
+        ));
+
+        if masquerade.enabled == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(Masquerade {
+            egress_interface: masquerade.egress_name(),
+        }))
+    }
+
+    fn add_masquerade(
+        &self,
+        device_name: &str,
+        masquerade: &Masquerade,
+    ) -> Result<(), FirewallError> {
+        let dev_name =
+            CString::new(device_name).map_err(|e| FirewallError::Invalid(e.to_string()))?;
+        let egress_name = CString::new(masquerade.egress_interface.as_deref().unwrap_or(""))
+            .map_err(|e| FirewallError::Invalid(e.to_string()))?;
+
+        libfirewall_try!(ffi::libfirewall_add_masquerade(
+            dev_name.as_ptr(),
+            egress_name.as_ptr()
+        ));
+
+        Ok(())
+    }
+
+    fn delete_masquerade(&self, device_name: &str) -> Result<(), FirewallError> {
+        let dev_name =
+            CString::new(device_name).map_err(|e| FirewallError::Invalid(e.to_string()))?;
+
+        libfirewall_try!(ffi::libfirewall_del_masquerade(dev_name.as_ptr()));
+
+        Ok(())
+    }
+
+    fn get_forwarding(&self, family: IpFamily) -> Result<bool, FirewallError> {
+        let value = fs::read_to_string(forwarding_path(family)).map_err(|e| {
+            FirewallError::Other(format!("failed to read forwarding sysctl: {}", e))
+        })?;
+
+        Ok(value.trim() == "1")
+    }
+
+    fn set_forwarding(&self, family: IpFamily, enabled: bool) -> Result<(), FirewallError> {
+        let value = if enabled { "1" } else { "0" };
+        fs::write(forwarding_path(family), value)
+            .map_err(|e| FirewallError::Other(format!("failed to write forwarding sysctl: {}", e)))
+    }
+
+    fn list_forwards(&self, device_name: &str) -> Result<Vec<PortForward>, FirewallError> {
+        let dev_name =
+            CString::new(device_name).map_err(|e| FirewallError::Invalid(e.to_string()))?;
+
+        let mut head: *mut ffi::LibFirewallForward = ptr::null_mut();
+        libfirewall_try!(ffi::libfirewall_list_forwards(dev_name.as_ptr(), &mut head));
+
+        let mut forwards = vec![];
+        let mut current = head;
+        while !current.is_null() {
+            let forward = unsafe { &*current };
+            if let Some(f) = forward.to_port_forward() {
+                forwards.push(f);
+            }
+            current = forward.next;
+        }
+
+        unsafe {
+            ffi::libfirewall_free_forwards(head);
+        }
+
+        Ok(forwards)
+    }
+
+    fn add_forward(&self, device_name: &str, forward: &PortForward) -> Result<(), FirewallError> {
+        let dev_name =
+            CString::new(device_name).map_err(|e| FirewallError::Invalid(e.to_string()))?;
+
+        let libfirewall_forward = &ffi::LibFirewallForward::from_port_forward(forward);
+        libfirewall_try!(ffi::libfirewall_add_forward(
+            dev_name.as_ptr(),
+            libfirewall_forward
+        ));
+
+        Ok(())
+    }
+
+    fn delete_forward(
+        &self,
+        device_name: &str,
+        protocol: Protocol,
+        public_port: u16,
+    ) -> Result<(), FirewallError> {
+        let dev_name =
+            CString::new(device_name).map_err(|e| FirewallError::Invalid(e.to_string()))?;
+
+        libfirewall_try!(ffi::libfirewall_del_forward(
+            dev_name.as_ptr(),
+            ffi::protocol_number(protocol),
+            public_port
+        ));
+
+        Ok(())
+    }
+
+    fn delete_forwards(&self, device_name: &str) -> Result<(), FirewallError> {
+        let dev_name =
+            CString::new(device_name).map_err(|e| FirewallError::Invalid(e.to_string()))?;
+
+        libfirewall_try!(ffi::libfirewall_del_forwards(dev_name.as_ptr()));
+
+        Ok(())
+    }
+
+    fn list_acl_rules(&self, device_name: &str) -> Result<Vec<AclRule>, FirewallError> {
+        let dev_name =
+            CString::new(device_name).map_err(|e| FirewallError::Invalid(e.to_string()))?;
+
+        let mut head: *mut ffi::LibFirewallAcl = ptr::null_mut();
+        libfirewall_try!(ffi::libfirewall_list_acls(dev_name.as_ptr(), &mut head));
+
+        let mut rules = vec![];
+        let mut current = head;
+        while !current.is_null() {
+            let acl = unsafe { &*current };
+            if let Some(r) = acl.to_acl_rule() {
+                rules.push(r);
+            }
+            current = acl.next;
+        }
+
+        unsafe {
+            ffi::libfirewall_free_acls(head);
+        }
+
+        Ok(rules)
+    }
+
+    fn replace_acl_rules(
+        &self,
+        device_name: &str,
+        id: u32,
+        rules: &[AclRule],
+    ) -> Result<(), FirewallError> {
+        let dev_name =
+            CString::new(device_name).map_err(|e| FirewallError::Invalid(e.to_string()))?;
+
+        let mut acls: Vec<ffi::LibFirewallAcl> = rules
+            .iter()
+            .map(ffi::LibFirewallAcl::from_acl_rule)
+            .collect();
+        for i in (1..acls.len()).rev() {
+            let next: *mut ffi::LibFirewallAcl = &mut acls[i];
+            acls[i - 1].next = next;
+        }
+
+        let head = acls.first().map_or(ptr::null(), |a| a as *const _);
+        libfirewall_try!(ffi::libfirewall_set_acls(dev_name.as_ptr(), id, head));
+
+        Ok(())
+    }
+
+    fn delete_peer_acl_rules(
+        &self,
+        device_name: &str,
+        public_key: &str,
+    ) -> Result<(), FirewallError> {
+        let dev_name =
+            CString::new(device_name).map_err(|e| FirewallError::Invalid(e.to_string()))?;
+        let peer_key =
+            CString::new(public_key).map_err(|e| FirewallError::Invalid(e.to_string()))?;
+
+        libfirewall_try!(ffi::libfirewall_del_peer_acls(
+            dev_name.as_ptr(),
+            peer_key.as_ptr()
+        ));
+
+        Ok(())
+    }
+
+    fn delete_acl_rules(&self, device_name: &str) -> Result<(), FirewallError> {
+        let dev_name =
+            CString::new(device_name).map_err(|e| FirewallError::Invalid(e.to_string()))?;
+
+        libfirewall_try!(ffi::libfirewall_del_acls(dev_name.as_ptr()));
+
+        Ok(())
+    }
+}
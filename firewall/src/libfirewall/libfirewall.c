@@ -0,0 +1,1341 @@
+#include "libfirewall.h"
+
+#include <arpa/inet.h>
+#include <endian.h>
+#include <errno.h>
+#include <linux/netfilter.h>
+#include <linux/netfilter/nf_conntrack_common.h>
+#include <linux/netfilter/nf_tables.h>
+#include <linux/netfilter/nfnetlink.h>
+#include <linux/netlink.h>
+#include <linux/rtnetlink.h>
+#include <netinet/in.h>
+#include <pthread.h>
+#include <stdbool.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <unistd.h>
+
+#define NFT_BUF_SIZE 8192
+#define NFT_BATCH_SIZE 65536
+
+// Chain names are the prefix followed by the device name
+#define NFT_CHAIN_NAME_SIZE (IF_NAMESIZE + 16)
+
+// Type of the rule comment in NFTA_RULE_USERDATA, the same one `nft` uses for comments
+#define NFT_UDATA_RULE_COMMENT 0
+
+#define MASQUERADE_CHAIN_PREFIX "masq_"
+#define MASQUERADE_COMMENT_PREFIX "egress="
+
+#define FORWARD_CHAIN_PREFIX "dnat_"
+#define FORWARD_COMMENT_FORMAT "forward %hhu %hu %45s %hu %44s"
+
+#define ACL_CHAIN_PREFIX "acl_"
+#define ACL_COMMENT_FORMAT "acl %u %hhu %44s %50s %50s %hhu %hu"
+
+// Priority of source and destination nat chains, same as NF_IP_PRI_NAT_SRC and NF_IP_PRI_NAT_DST
+#define NFT_PRIORITY_SRCNAT 100
+#define NFT_PRIORITY_DSTNAT -100
+
+// Priority of filter chains, same as NF_IP_PRI_FILTER
+#define NFT_PRIORITY_FILTER 0
+
+/**
+ * A batch of nf_tables messages sent in a single transaction. The kernel applies either all of
+ * the messages or none of them. Batches are allocated per call since the library is called from
+ * several threads at once.
+ */
+typedef struct nft_batch {
+    char buf[NFT_BATCH_SIZE];
+    size_t len;
+    uint32_t seq;
+    int acks;  // number of messages that are answered with an ack
+} nft_batch;
+
+// Cause of the last failure on this thread, reported through libfirewall_last_errno.
+static __thread int last_errno;
+
+static void clear_error(void) {
+    last_errno = 0;
+}
+
+// Records the errno behind a failure and passes the error code through.
+static int fail(int code, int err) {
+    last_errno = err;
+    return code;
+}
+
+int libfirewall_last_errno(void) {
+    return last_errno;
+}
+
+static struct nlmsghdr *nft_msg_start(nft_batch *batch, uint16_t type, uint16_t flags,
+                                      uint8_t family) {
+    size_t hdr_len = NLMSG_LENGTH(sizeof(struct nfgenmsg));
+    if (batch->len + NLMSG_ALIGN(hdr_len) > sizeof(batch->buf)) {
+        return NULL;
+    }
+
+    struct nlmsghdr *nlh = (struct nlmsghdr *)(batch->buf + batch->len);
+    memset(nlh, 0, NLMSG_ALIGN(hdr_len));
+    nlh->nlmsg_len = hdr_len;
+    nlh->nlmsg_type = type;
+    nlh->nlmsg_flags = NLM_F_REQUEST | flags;
+    nlh->nlmsg_seq = ++batch->seq;
+
+    struct nfgenmsg *nfg = (struct nfgenmsg *)NLMSG_DATA(nlh);
+    nfg->nfgen_family = family;
+    nfg->version = NFNETLINK_V0;
+
+    return nlh;
+}
+
+static void nft_msg_end(nft_batch *batch, struct nlmsghdr *nlh) {
+    batch->len += NLMSG_ALIGN(nlh->nlmsg_len);
+    if (nlh->nlmsg_flags & NLM_F_ACK) {
+        batch->acks++;
+    }
+}
+
+static struct nlattr *nft_attr_put(nft_batch *batch, struct nlmsghdr *nlh, uint16_t type,
+                                   const void *data, size_t len) {
+    size_t attr_len = NLA_HDRLEN + len;
+    size_t offset = (char *)nlh - batch->buf;
+    if (offset + NLMSG_ALIGN(nlh->nlmsg_len) + NLA_ALIGN(attr_len) > sizeof(batch->buf)) {
+        return NULL;
+    }
+
+    struct nlattr *attr = (struct nlattr *)((char *)nlh + NLMSG_ALIGN(nlh->nlmsg_len));
+    memset(attr, 0, NLA_ALIGN(attr_len));
+    attr->nla_type = type;
+    attr->nla_len = attr_len;
+    if (len > 0) {
+        memcpy((char *)attr + NLA_HDRLEN, data, len);
+    }
+    nlh->nlmsg_len = NLMSG_ALIGN(nlh->nlmsg_len) + NLA_ALIGN(attr_len);
+    return attr;
+}
+
+static bool nft_attr_put_strz(nft_batch *batch, struct nlmsghdr *nlh, uint16_t type,
+                              const char *str) {
+    return nft_attr_put(batch, nlh, type, str, strlen(str) + 1) != NULL;
+}
+
+static bool nft_attr_put_be32(nft_batch *batch, struct nlmsghdr *nlh, uint16_t type,
+                              uint32_t value) {
+    uint32_t be = htonl(value);
+    return nft_attr_put(batch, nlh, type, &be, sizeof(be)) != NULL;
+}
+
+static struct nlattr *nft_nest_start(nft_batch *batch, struct nlmsghdr *nlh, uint16_t type) {
+    return nft_attr_put(batch, nlh, NLA_F_NESTED | type, NULL, 0);
+}
+
+static void nft_nest_end(struct nlmsghdr *nlh, struct nlattr *nest) {
+    nest->nla_len = ((char *)nlh + nlh->nlmsg_len) - (char *)nest;
+}
+
+static bool nft_batch_begin(nft_batch *batch) {
+    struct nlmsghdr *nlh = nft_msg_start(batch, NFNL_MSG_BATCH_BEGIN, 0, AF_UNSPEC);
+    if (!nlh) {
+        return false;
+    }
+
+    struct nfgenmsg *nfg = (struct nfgenmsg *)NLMSG_DATA(nlh);
+    nfg->res_id = htons(NFNL_SUBSYS_NFTABLES);
+    nft_msg_end(batch, nlh);
+    return true;
+}
+
+static bool nft_batch_end(nft_batch *batch) {
+    struct nlmsghdr *nlh = nft_msg_start(batch, NFNL_MSG_BATCH_END, 0, AF_UNSPEC);
+    if (!nlh) {
+        return false;
+    }
+
+    struct nfgenmsg *nfg = (struct nfgenmsg *)NLMSG_DATA(nlh);
+    nfg->res_id = htons(NFNL_SUBSYS_NFTABLES);
+    nft_msg_end(batch, nlh);
+    return true;
+}
+
+static uint16_t nft_type(uint8_t msg) {
+    return (NFNL_SUBSYS_NFTABLES << 8) | msg;
+}
+
+static int nft_batch_send(nft_batch *batch, int *nl_errno) {
+    *nl_errno = 0;
+
+    int fd = socket(AF_NETLINK, SOCK_RAW | SOCK_CLOEXEC, NETLINK_NETFILTER);
+    if (fd < 0) {
+        return fail(LIBFIREWALL_ERR_NETLINK_SOCKET_FAILED, errno);
+    }
+
+    struct sockaddr_nl addr = {.nl_family = AF_NETLINK};
+    if (sendto(fd, batch->buf, batch->len, 0, (struct sockaddr *)&addr, sizeof(addr)) < 0) {
+        int err = errno;
+        close(fd);
+        return fail(LIBFIREWALL_ERR_NETLINK_SEND_FAILED, err);
+    }
+
+    char buf[NFT_BUF_SIZE];
+    int acks = 0;
+    while (acks < batch->acks && *nl_errno == 0) {
+        int len = (int)recv(fd, buf, sizeof(buf), 0);
+        if (len < 0) {
+            int err = errno;
+            close(fd);
+            return fail(LIBFIREWALL_ERR_NETLINK_RECV_FAILED, err);
+        }
+
+        for (struct nlmsghdr *nlh = (struct nlmsghdr *)buf; NLMSG_OK(nlh, len);
+             nlh = NLMSG_NEXT(nlh, len)) {
+            if (nlh->nlmsg_type != NLMSG_ERROR) {
+                continue;
+            }
+
+            struct nlmsgerr *err = (struct nlmsgerr *)NLMSG_DATA(nlh);
+            acks++;
+            if (err->error != 0) {
+                *nl_errno = -err->error;
+                break;
+            }
+        }
+    }
+
+    close(fd);
+    return 0;
+}
+
+static bool put_table(nft_batch *batch) {
+    struct nlmsghdr *nlh =
+        nft_msg_start(batch, nft_type(NFT_MSG_NEWTABLE), NLM_F_CREATE | NLM_F_ACK, NFPROTO_INET);
+    if (!nlh || !nft_attr_put_strz(batch, nlh, NFTA_TABLE_NAME, LIBFIREWALL_TABLE)) {
+        return false;
+    }
+
+    nft_msg_end(batch, nlh);
+    return true;
+}
+
+static bool put_base_chain(nft_batch *batch, const char *chain, const char *type, uint32_t hook,
+                           int32_t priority) {
+    struct nlmsghdr *nlh =
+        nft_msg_start(batch, nft_type(NFT_MSG_NEWCHAIN), NLM_F_CREATE | NLM_F_ACK, NFPROTO_INET);
+    if (!nlh || !nft_attr_put_strz(batch, nlh, NFTA_CHAIN_TABLE, LIBFIREWALL_TABLE) ||
+        !nft_attr_put_strz(batch, nlh, NFTA_CHAIN_NAME, chain)) {
+        return false;
+    }
+
+    struct nlattr *hook_nest = nft_nest_start(batch, nlh, NFTA_CHAIN_HOOK);
+    if (!hook_nest || !nft_attr_put_be32(batch, nlh, NFTA_HOOK_HOOKNUM, hook) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_HOOK_PRIORITY, (uint32_t)priority)) {
+        return false;
+    }
+    nft_nest_end(nlh, hook_nest);
+
+    if (!nft_attr_put_strz(batch, nlh, NFTA_CHAIN_TYPE, type)) {
+        return false;
+    }
+
+    nft_msg_end(batch, nlh);
+    return true;
+}
+
+static bool put_flush_chain(nft_batch *batch, const char *chain) {
+    struct nlmsghdr *nlh =
+        nft_msg_start(batch, nft_type(NFT_MSG_DELRULE), NLM_F_ACK, NFPROTO_INET);
+    if (!nlh || !nft_attr_put_strz(batch, nlh, NFTA_RULE_TABLE, LIBFIREWALL_TABLE) ||
+        !nft_attr_put_strz(batch, nlh, NFTA_RULE_CHAIN, chain)) {
+        return false;
+    }
+
+    nft_msg_end(batch, nlh);
+    return true;
+}
+
+static bool put_del_chain(nft_batch *batch, const char *chain) {
+    struct nlmsghdr *nlh =
+        nft_msg_start(batch, nft_type(NFT_MSG_DELCHAIN), NLM_F_ACK, NFPROTO_INET);
+    if (!nlh || !nft_attr_put_strz(batch, nlh, NFTA_CHAIN_TABLE, LIBFIREWALL_TABLE) ||
+        !nft_attr_put_strz(batch, nlh, NFTA_CHAIN_NAME, chain)) {
+        return false;
+    }
+
+    nft_msg_end(batch, nlh);
+    return true;
+}
+
+static bool put_expr_start(nft_batch *batch, struct nlmsghdr *nlh, const char *name,
+                           struct nlattr **elem, struct nlattr **data) {
+    *elem = nft_nest_start(batch, nlh, NFTA_LIST_ELEM);
+    if (!*elem || !nft_attr_put_strz(batch, nlh, NFTA_EXPR_NAME, name)) {
+        return false;
+    }
+
+    *data = nft_nest_start(batch, nlh, NFTA_EXPR_DATA);
+    return *data != NULL;
+}
+
+static void put_expr_end(struct nlmsghdr *nlh, struct nlattr *elem, struct nlattr *data) {
+    nft_nest_end(nlh, data);
+    nft_nest_end(nlh, elem);
+}
+
+// Appends `meta <key>` loading into register 1.
+static bool put_meta(nft_batch *batch, struct nlmsghdr *nlh, uint32_t meta_key) {
+    struct nlattr *elem, *data;
+    if (!put_expr_start(batch, nlh, "meta", &elem, &data) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_META_KEY, meta_key) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_META_DREG, NFT_REG_1)) {
+        return false;
+    }
+
+    put_expr_end(nlh, elem, data);
+    return true;
+}
+
+// Appends a comparison of register 1 with the given value.
+static bool put_cmp(nft_batch *batch, struct nlmsghdr *nlh, uint32_t op, const void *value,
+                    size_t len) {
+    struct nlattr *elem, *data;
+    if (!put_expr_start(batch, nlh, "cmp", &elem, &data) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_CMP_SREG, NFT_REG_1) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_CMP_OP, op)) {
+        return false;
+    }
+
+    struct nlattr *cmp_data = nft_nest_start(batch, nlh, NFTA_CMP_DATA);
+    if (!cmp_data || !nft_attr_put(batch, nlh, NFTA_DATA_VALUE, value, len)) {
+        return false;
+    }
+    nft_nest_end(nlh, cmp_data);
+
+    put_expr_end(nlh, elem, data);
+    return true;
+}
+
+// Appends `meta <key> <op> <ifname>` to an expression list.
+static bool put_ifname_match(nft_batch *batch, struct nlmsghdr *nlh, uint32_t meta_key,
+                             uint32_t op, const char *ifname) {
+    char value[IF_NAMESIZE] = {0};
+    strncpy(value, ifname, IF_NAMESIZE - 1);
+
+    return put_meta(batch, nlh, meta_key) && put_cmp(batch, nlh, op, value, sizeof(value));
+}
+
+// Appends `th dport == <port>` to an expression list.
+static bool put_dport_match(nft_batch *batch, struct nlmsghdr *nlh, uint16_t port) {
+    struct nlattr *elem, *data;
+    if (!put_expr_start(batch, nlh, "payload", &elem, &data) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_PAYLOAD_DREG, NFT_REG_1) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_PAYLOAD_BASE, NFT_PAYLOAD_TRANSPORT_HEADER) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_PAYLOAD_OFFSET, 2) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_PAYLOAD_LEN, sizeof(uint16_t))) {
+        return false;
+    }
+    put_expr_end(nlh, elem, data);
+
+    uint16_t be_port = htons(port);
+    return put_cmp(batch, nlh, NFT_CMP_EQ, &be_port, sizeof(be_port));
+}
+
+// Appends `fib daddr type local` to an expression list.
+static bool put_local_daddr_match(nft_batch *batch, struct nlmsghdr *nlh) {
+    struct nlattr *elem, *data;
+    if (!put_expr_start(batch, nlh, "fib", &elem, &data) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_FIB_DREG, NFT_REG_1) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_FIB_RESULT, NFT_FIB_RESULT_ADDRTYPE) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_FIB_FLAGS, NFTA_FIB_F_DADDR)) {
+        return false;
+    }
+    put_expr_end(nlh, elem, data);
+
+    uint32_t type = RTN_LOCAL;
+    return put_cmp(batch, nlh, NFT_CMP_EQ, &type, sizeof(type));
+}
+
+static bool put_immediate(nft_batch *batch, struct nlmsghdr *nlh, uint32_t reg,
+                          const void *value, size_t len) {
+    struct nlattr *elem, *data;
+    if (!put_expr_start(batch, nlh, "immediate", &elem, &data) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_IMMEDIATE_DREG, reg)) {
+        return false;
+    }
+
+    struct nlattr *imm_data = nft_nest_start(batch, nlh, NFTA_IMMEDIATE_DATA);
+    if (!imm_data || !nft_attr_put(batch, nlh, NFTA_DATA_VALUE, value, len)) {
+        return false;
+    }
+    nft_nest_end(nlh, imm_data);
+
+    put_expr_end(nlh, elem, data);
+    return true;
+}
+
+static bool put_comment(nft_batch *batch, struct nlmsghdr *nlh, const char *comment) {
+    uint8_t udata[2 + UINT8_MAX];
+    size_t len = strlen(comment) + 1;
+    if (len > UINT8_MAX) {
+        return false;
+    }
+
+    udata[0] = NFT_UDATA_RULE_COMMENT;
+    udata[1] = (uint8_t)len;
+    memcpy(udata + 2, comment, len);
+
+    return nft_attr_put(batch, nlh, NFTA_RULE_USERDATA, udata, len + 2) != NULL;
+}
+
+static struct nlmsghdr *put_rule_start(nft_batch *batch, const char *chain) {
+    struct nlmsghdr *nlh = nft_msg_start(batch, nft_type(NFT_MSG_NEWRULE),
+                                         NLM_F_CREATE | NLM_F_APPEND | NLM_F_ACK, NFPROTO_INET);
+    if (!nlh || !nft_attr_put_strz(batch, nlh, NFTA_RULE_TABLE, LIBFIREWALL_TABLE) ||
+        !nft_attr_put_strz(batch, nlh, NFTA_RULE_CHAIN, chain)) {
+        return NULL;
+    }
+
+    return nlh;
+}
+
+static bool put_masquerade_rule(nft_batch *batch, const char *chain, const char *device_name,
+                                const char *egress_name) {
+    struct nlmsghdr *nlh = put_rule_start(batch, chain);
+    if (!nlh) {
+        return false;
+    }
+
+    struct nlattr *exprs = nft_nest_start(batch, nlh, NFTA_RULE_EXPRESSIONS);
+    if (!exprs || !put_ifname_match(batch, nlh, NFT_META_IIFNAME, NFT_CMP_EQ, device_name)) {
+        return false;
+    }
+
+    bool any_egress = egress_name[0] == '\0';
+    if (!put_ifname_match(batch, nlh, NFT_META_OIFNAME, any_egress ? NFT_CMP_NEQ : NFT_CMP_EQ,
+                          any_egress ? device_name : egress_name)) {
+        return false;
+    }
+
+    struct nlattr *elem = nft_nest_start(batch, nlh, NFTA_LIST_ELEM);
+    if (!elem || !nft_attr_put_strz(batch, nlh, NFTA_EXPR_NAME, "masq")) {
+        return false;
+    }
+    nft_nest_end(nlh, elem);
+    nft_nest_end(nlh, exprs);
+
+    char comment[sizeof(MASQUERADE_COMMENT_PREFIX) + IF_NAMESIZE];
+    snprintf(comment, sizeof(comment), "%s%s", MASQUERADE_COMMENT_PREFIX, egress_name);
+    if (!put_comment(batch, nlh, comment)) {
+        return false;
+    }
+
+    nft_msg_end(batch, nlh);
+    return true;
+}
+
+/**
+ * Appends `meta nfproto <family> meta l4proto <protocol> th dport <public port>
+ * fib daddr type local dnat to <addr>:<port>`. The forward itself is kept in the rule comment,
+ * so that it can be listed without decoding the expressions.
+ */
+static bool put_forward_rule(nft_batch *batch, const char *chain, uint8_t family,
+                             const void *addr, size_t addr_len,
+                             const libfirewall_forward *forward) {
+    struct nlmsghdr *nlh = put_rule_start(batch, chain);
+    if (!nlh) {
+        return false;
+    }
+
+    uint16_t dst_port = htons(forward->dst_port);
+    struct nlattr *exprs = nft_nest_start(batch, nlh, NFTA_RULE_EXPRESSIONS);
+    if (!exprs || !put_meta(batch, nlh, NFT_META_NFPROTO) ||
+        !put_cmp(batch, nlh, NFT_CMP_EQ, &family, sizeof(family)) ||
+        !put_meta(batch, nlh, NFT_META_L4PROTO) ||
+        !put_cmp(batch, nlh, NFT_CMP_EQ, &forward->protocol, sizeof(forward->protocol)) ||
+        !put_dport_match(batch, nlh, forward->public_port) ||
+        !put_local_daddr_match(batch, nlh) ||
+        !put_immediate(batch, nlh, NFT_REG_1, addr, addr_len) ||
+        !put_immediate(batch, nlh, NFT_REG_2, &dst_port, sizeof(dst_port))) {
+        return false;
+    }
+
+    struct nlattr *elem, *data;
+    if (!put_expr_start(batch, nlh, "nat", &elem, &data) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_NAT_TYPE, NFT_NAT_DNAT) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_NAT_FAMILY, family) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_NAT_REG_ADDR_MIN, NFT_REG_1) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_NAT_REG_PROTO_MIN, NFT_REG_2)) {
+        return false;
+    }
+    put_expr_end(nlh, elem, data);
+    nft_nest_end(nlh, exprs);
+
+    char comment[UINT8_MAX];
+    snprintf(comment, sizeof(comment), "forward %u %u %s %u %s", forward->protocol,
+             forward->public_port, forward->dst_addr, forward->dst_port, forward->peer_key);
+    if (!put_comment(batch, nlh, comment)) {
+        return false;
+    }
+
+    nft_msg_end(batch, nlh);
+    return true;
+}
+
+static bool put_del_rule(nft_batch *batch, const char *chain, uint64_t handle) {
+    struct nlmsghdr *nlh =
+        nft_msg_start(batch, nft_type(NFT_MSG_DELRULE), NLM_F_ACK, NFPROTO_INET);
+    uint64_t be_handle = htobe64(handle);
+    if (!nlh || !nft_attr_put_strz(batch, nlh, NFTA_RULE_TABLE, LIBFIREWALL_TABLE) ||
+        !nft_attr_put_strz(batch, nlh, NFTA_RULE_CHAIN, chain) ||
+        !nft_attr_put(batch, nlh, NFTA_RULE_HANDLE, &be_handle, sizeof(be_handle))) {
+        return false;
+    }
+
+    nft_msg_end(batch, nlh);
+    return true;
+}
+
+static bool valid_ifname(const char *name) {
+    size_t len = strlen(name);
+    return len > 0 && len < IF_NAMESIZE;
+}
+
+// Sends a batch and maps the outcome, treating a missing table, chain or rule as success when
+// `ignore_missing` is set.
+static int send_update(nft_batch *batch, bool ignore_missing) {
+    int nl_errno = 0;
+    int res = nft_batch_send(batch, &nl_errno);
+    if (res != 0) {
+        return res;
+    }
+
+    if (nl_errno == 0 || (ignore_missing && nl_errno == ENOENT)) {
+        return 0;
+    }
+
+    return fail(LIBFIREWALL_ERR_RULESET_UPDATE_FAILED, nl_errno);
+}
+
+// Flushes and deletes the chain; a missing chain means there is nothing to remove.
+static int del_chain(const char *chain) {
+    nft_batch *batch = calloc(1, sizeof(nft_batch));
+    if (!batch) {
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    if (!nft_batch_begin(batch) || !put_flush_chain(batch, chain) ||
+        !put_del_chain(batch, chain) || !nft_batch_end(batch)) {
+        free(batch);
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    int res = send_update(batch, true);
+    free(batch);
+    return res;
+}
+
+int libfirewall_add_masquerade(const char *device_name, const char *egress_name) {
+    clear_error();
+    if (!valid_ifname(device_name) || (egress_name[0] && !valid_ifname(egress_name))) {
+        return LIBFIREWALL_ERR_INVALID_DEV_NAME;
+    }
+
+    char chain[NFT_CHAIN_NAME_SIZE];
+    snprintf(chain, sizeof(chain), "%s%s", MASQUERADE_CHAIN_PREFIX, device_name);
+
+    nft_batch *batch = calloc(1, sizeof(nft_batch));
+    if (!batch) {
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    // the chain is flushed before the rule is added, so the setup is replaced as a whole
+    if (!nft_batch_begin(batch) || !put_table(batch) ||
+        !put_base_chain(batch, chain, "nat", NF_INET_POST_ROUTING, NFT_PRIORITY_SRCNAT) ||
+        !put_flush_chain(batch, chain) ||
+        !put_masquerade_rule(batch, chain, device_name, egress_name) || !nft_batch_end(batch)) {
+        free(batch);
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    int res = send_update(batch, false);
+    free(batch);
+    return res;
+}
+
+int libfirewall_del_masquerade(const char *device_name) {
+    clear_error();
+    if (!valid_ifname(device_name)) {
+        return LIBFIREWALL_ERR_INVALID_DEV_NAME;
+    }
+
+    char chain[NFT_CHAIN_NAME_SIZE];
+    snprintf(chain, sizeof(chain), "%s%s", MASQUERADE_CHAIN_PREFIX, device_name);
+
+    return del_chain(chain);
+}
+
+// Copies the rule comment out of NFTA_RULE_USERDATA, returns false if there is none.
+static bool read_comment(const struct nlattr *udata, char *comment, size_t size) {
+    const uint8_t *data = (const uint8_t *)udata + NLA_HDRLEN;
+    size_t len = udata->nla_len - NLA_HDRLEN;
+
+    for (size_t off = 0; off + 2 <= len; off += 2 + data[off + 1]) {
+        uint8_t type = data[off];
+        uint8_t value_len = data[off + 1];
+        if (off + 2 + value_len > len) {
+            break;
+        }
+
+        if (type == NFT_UDATA_RULE_COMMENT && value_len > 0) {
+            size_t copy = value_len < size ? value_len : size;
+            memcpy(comment, data + off + 2, copy);
+            comment[copy - 1] = '\0';
+            return true;
+        }
+    }
+
+    return false;
+}
+
+/**
+ * Called for each rule of a dumped chain with the rule handle and comment (empty if the rule
+ * has none). Returns 0 to continue or an error code to stop the dump.
+ */
+typedef int (*rule_cb)(uint64_t handle, const char *comment, void *ctx);
+
+// Dumps the rules of the chain, or of every chain of the table if chain is NULL.
+static int dump_rules(const char *chain, rule_cb cb, void *ctx) {
+    nft_batch *req = calloc(1, sizeof(nft_batch));
+    if (!req) {
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    struct nlmsghdr *nlh =
+        nft_msg_start(req, nft_type(NFT_MSG_GETRULE), NLM_F_DUMP, NFPROTO_INET);
+    if (!nlh || !nft_attr_put_strz(req, nlh, NFTA_RULE_TABLE, LIBFIREWALL_TABLE) ||
+        (chain && !nft_attr_put_strz(req, nlh, NFTA_RULE_CHAIN, chain))) {
+        free(req);
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+    nft_msg_end(req, nlh);
+
+    int fd = socket(AF_NETLINK, SOCK_RAW | SOCK_CLOEXEC, NETLINK_NETFILTER);
+    if (fd < 0) {
+        int err = errno;
+        free(req);
+        return fail(LIBFIREWALL_ERR_NETLINK_SOCKET_FAILED, err);
+    }
+
+    struct sockaddr_nl addr = {.nl_family = AF_NETLINK};
+    ssize_t sent = sendto(fd, req->buf, req->len, 0, (struct sockaddr *)&addr, sizeof(addr));
+    int err = errno;
+    free(req);
+    if (sent < 0) {
+        close(fd);
+        return fail(LIBFIREWALL_ERR_NETLINK_SEND_FAILED, err);
+    }
+
+    char buf[NFT_BUF_SIZE * 4];
+    int res = 0;
+    bool done = false;
+    while (!done) {
+        int len = (int)recv(fd, buf, sizeof(buf), 0);
+        if (len < 0) {
+            res = fail(LIBFIREWALL_ERR_NETLINK_RECV_FAILED, errno);
+            break;
+        }
+
+        for (struct nlmsghdr *msg = (struct nlmsghdr *)buf; NLMSG_OK(msg, len);
+             msg = NLMSG_NEXT(msg, len)) {
+            if (msg->nlmsg_type == NLMSG_DONE) {
+                done = true;
+                break;
+            }
+
+            if (msg->nlmsg_type == NLMSG_ERROR) {
+                // a missing table or chain has no rules
+                struct nlmsgerr *err = (struct nlmsgerr *)NLMSG_DATA(msg);
+                if (err->error != 0 && err->error != -ENOENT) {
+                    res = fail(LIBFIREWALL_ERR_RULESET_READ_FAILED, -err->error);
+                }
+                done = true;
+                break;
+            }
+
+            if (msg->nlmsg_type != nft_type(NFT_MSG_NEWRULE) || res != 0) {
+                continue;
+            }
+
+            uint64_t handle = 0;
+            char comment[UINT8_MAX + 1] = {0};
+
+            size_t hdr = NLMSG_ALIGN(sizeof(struct nfgenmsg));
+            struct nlattr *attr = (struct nlattr *)((char *)NLMSG_DATA(msg) + hdr);
+            int attrs_len = (int)(msg->nlmsg_len - NLMSG_LENGTH(hdr));
+            while (attrs_len >= NLA_HDRLEN && attr->nla_len >= NLA_HDRLEN &&
+                   attr->nla_len <= attrs_len) {
+                uint16_t type = attr->nla_type & NLA_TYPE_MASK;
+                if (type == NFTA_RULE_USERDATA) {
+                    read_comment(attr, comment, sizeof(comment));
+                } else if (type == NFTA_RULE_HANDLE &&
+                           attr->nla_len >= NLA_HDRLEN + sizeof(uint64_t)) {
+                    uint64_t be_handle;
+                    memcpy(&be_handle, (char *)attr + NLA_HDRLEN, sizeof(be_handle));
+                    handle = be64toh(be_handle);
+                }
+
+                attrs_len -= NLA_ALIGN(attr->nla_len);
+                attr = (struct nlattr *)((char *)attr + NLA_ALIGN(attr->nla_len));
+            }
+
+            // the rest of the dump is still drained so the socket can be closed cleanly
+            res = cb(handle, comment, ctx);
+        }
+    }
+
+    close(fd);
+    return res;
+}
+
+/**
+ * Handles of the rules whose comment satisfies `matches`, filled by collect_handle.
+ */
+typedef struct rule_handles {
+    bool (*matches)(const char *comment, const void *arg);
+    const void *arg;
+    uint64_t *handles;
+    size_t count;
+    size_t cap;
+} rule_handles;
+
+static int collect_handle(uint64_t handle, const char *comment, void *ctx) {
+    rule_handles *found = ctx;
+    if (!found->matches(comment, found->arg)) {
+        return 0;
+    }
+
+    if (found->count == found->cap) {
+        size_t cap = found->cap ? found->cap * 2 : 16;
+        uint64_t *handles = realloc(found->handles, cap * sizeof(uint64_t));
+        if (!handles) {
+            return LIBFIREWALL_ERR_NOMEM;
+        }
+        found->handles = handles;
+        found->cap = cap;
+    }
+
+    found->handles[found->count++] = handle;
+    return 0;
+}
+
+static bool put_del_rules(nft_batch *batch, const char *chain, const rule_handles *found) {
+    for (size_t i = 0; i < found->count; i++) {
+        if (!put_del_rule(batch, chain, found->handles[i])) {
+            return false;
+        }
+    }
+
+    return true;
+}
+
+// Deletes the rules of the chain whose comment satisfies `matches`. Returns `missing` when no
+// rule matches, 0 if that is not an error.
+static int del_matching_rules(const char *chain,
+                              bool (*matches)(const char *comment, const void *arg),
+                              const void *arg, int missing) {
+    rule_handles found = {.matches = matches, .arg = arg};
+    int res = dump_rules(chain, collect_handle, &found);
+    if (res != 0 || found.count == 0) {
+        free(found.handles);
+        return res != 0 ? res : missing;
+    }
+
+    nft_batch *batch = calloc(1, sizeof(nft_batch));
+    if (!batch) {
+        free(found.handles);
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    bool ok = nft_batch_begin(batch) && put_del_rules(batch, chain, &found) &&
+              nft_batch_end(batch);
+    free(found.handles);
+    if (!ok) {
+        free(batch);
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    res = send_update(batch, true);
+    free(batch);
+    return res;
+}
+
+static int read_masquerade(uint64_t handle, const char *comment, void *ctx) {
+    (void)handle;
+    libfirewall_masquerade *masquerade = ctx;
+
+    masquerade->enabled = 1;
+    size_t prefix_len = strlen(MASQUERADE_COMMENT_PREFIX);
+    if (strncmp(comment, MASQUERADE_COMMENT_PREFIX, prefix_len) == 0) {
+        strncpy(masquerade->egress_name, comment + prefix_len, IF_NAMESIZE - 1);
+    }
+
+    return 0;
+}
+
+int libfirewall_get_masquerade(const char *device_name, libfirewall_masquerade *masquerade) {
+    clear_error();
+    if (!valid_ifname(device_name)) {
+        return LIBFIREWALL_ERR_INVALID_DEV_NAME;
+    }
+
+    memset(masquerade, 0, sizeof(*masquerade));
+
+    char chain[NFT_CHAIN_NAME_SIZE];
+    snprintf(chain, sizeof(chain), "%s%s", MASQUERADE_CHAIN_PREFIX, device_name);
+
+    return dump_rules(chain, read_masquerade, masquerade);
+}
+
+// Held from the check for a taken port until the forward is added, so that concurrent additions
+// of the same port cannot both pass the check.
+static pthread_mutex_t forward_lock = PTHREAD_MUTEX_INITIALIZER;
+
+typedef struct forward_key {
+    uint8_t protocol;
+    uint16_t public_port;
+} forward_key;
+
+static bool matches_forward(const char *comment, const void *arg) {
+    const forward_key *key = arg;
+
+    libfirewall_forward forward = {0};
+    if (sscanf(comment, FORWARD_COMMENT_FORMAT, &forward.protocol, &forward.public_port,
+               forward.dst_addr, &forward.dst_port, forward.peer_key) != 5) {
+        return false;
+    }
+
+    return forward.protocol == key->protocol && forward.public_port == key->public_port;
+}
+
+static int add_forward_rule(const char *chain, uint8_t family, const unsigned char *addr,
+                            size_t addr_len, const libfirewall_forward *forward) {
+    nft_batch *batch = calloc(1, sizeof(nft_batch));
+    if (!batch) {
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    if (!nft_batch_begin(batch) || !put_table(batch) ||
+        !put_base_chain(batch, chain, "nat", NF_INET_PRE_ROUTING, NFT_PRIORITY_DSTNAT) ||
+        !put_forward_rule(batch, chain, family, addr, addr_len, forward) ||
+        !nft_batch_end(batch)) {
+        free(batch);
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    int res = send_update(batch, false);
+    free(batch);
+    return res;
+}
+
+int libfirewall_add_forward(const char *device_name, const libfirewall_forward *forward) {
+    clear_error();
+    if (!valid_ifname(device_name)) {
+        return LIBFIREWALL_ERR_INVALID_DEV_NAME;
+    }
+
+    if (forward->protocol != IPPROTO_TCP && forward->protocol != IPPROTO_UDP) {
+        return LIBFIREWALL_ERR_INVALID_PROTOCOL;
+    }
+
+    uint8_t family;
+    size_t addr_len;
+    unsigned char addr[sizeof(struct in6_addr)];
+    if (inet_pton(AF_INET, forward->dst_addr, addr) == 1) {
+        family = NFPROTO_IPV4;
+        addr_len = sizeof(struct in_addr);
+    } else if (inet_pton(AF_INET6, forward->dst_addr, addr) == 1) {
+        family = NFPROTO_IPV6;
+        addr_len = sizeof(struct in6_addr);
+    } else {
+        return LIBFIREWALL_ERR_INVALID_ADDR;
+    }
+
+    char chain[NFT_CHAIN_NAME_SIZE];
+    snprintf(chain, sizeof(chain), "%s%s", FORWARD_CHAIN_PREFIX, device_name);
+
+    // the chains of all devices hook prerouting at the same priority, a second rule for the
+    // same port would never match whichever device it is on
+    forward_key key = {.protocol = forward->protocol, .public_port = forward->public_port};
+    rule_handles found = {.matches = matches_forward, .arg = &key};
+
+    pthread_mutex_lock(&forward_lock);
+    int res = dump_rules(NULL, collect_handle, &found);
+    free(found.handles);
+    if (res == 0 && found.count > 0) {
+        res = LIBFIREWALL_ERR_FORWARD_EXISTS;
+    }
+    if (res == 0) {
+        res = add_forward_rule(chain, family, addr, addr_len, forward);
+    }
+    pthread_mutex_unlock(&forward_lock);
+
+    return res;
+}
+
+typedef struct forward_list {
+    libfirewall_forward *head;
+    libfirewall_forward *tail;
+} forward_list;
+
+static int read_forward(uint64_t handle, const char *comment, void *ctx) {
+    (void)handle;
+    forward_list *list = ctx;
+
+    libfirewall_forward forward = {0};
+    if (sscanf(comment, FORWARD_COMMENT_FORMAT, &forward.protocol, &forward.public_port,
+               forward.dst_addr, &forward.dst_port, forward.peer_key) != 5) {
+        // not a rule created by libfirewall_add_forward
+        return 0;
+    }
+
+    libfirewall_forward *item = malloc(sizeof(libfirewall_forward));
+    if (!item) {
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+    *item = forward;
+
+    if (list->tail) {
+        list->tail->next = item;
+    } else {
+        list->head = item;
+    }
+    list->tail = item;
+
+    return 0;
+}
+
+int libfirewall_list_forwards(const char *device_name, libfirewall_forward **forwards) {
+    clear_error();
+    *forwards = NULL;
+
+    if (!valid_ifname(device_name)) {
+        return LIBFIREWALL_ERR_INVALID_DEV_NAME;
+    }
+
+    char chain[NFT_CHAIN_NAME_SIZE];
+    snprintf(chain, sizeof(chain), "%s%s", FORWARD_CHAIN_PREFIX, device_name);
+
+    forward_list list = {0};
+    int res = dump_rules(chain, read_forward, &list);
+    if (res != 0) {
+        libfirewall_free_forwards(list.head);
+        return res;
+    }
+
+    *forwards = list.head;
+    return 0;
+}
+
+int libfirewall_del_forward(const char *device_name, uint8_t protocol, uint16_t public_port) {
+    clear_error();
+    if (!valid_ifname(device_name)) {
+        return LIBFIREWALL_ERR_INVALID_DEV_NAME;
+    }
+
+    char chain[NFT_CHAIN_NAME_SIZE];
+    snprintf(chain, sizeof(chain), "%s%s", FORWARD_CHAIN_PREFIX, device_name);
+
+    forward_key key = {.protocol = protocol, .public_port = public_port};
+    return del_matching_rules(chain, matches_forward, &key, LIBFIREWALL_ERR_FORWARD_NOT_FOUND);
+}
+
+int libfirewall_del_forwards(const char *device_name) {
+    clear_error();
+    if (!valid_ifname(device_name)) {
+        return LIBFIREWALL_ERR_INVALID_DEV_NAME;
+    }
+
+    char chain[NFT_CHAIN_NAME_SIZE];
+    snprintf(chain, sizeof(chain), "%s%s", FORWARD_CHAIN_PREFIX, device_name);
+
+    return del_chain(chain);
+}
+
+void libfirewall_free_forwards(libfirewall_forward *forwards) {
+    while (forwards) {
+        libfirewall_forward *next = forwards->next;
+        free(forwards);
+        forwards = next;
+    }
+}
+
+// Parses "addr/prefix" into the address bytes, returns false if it is not a valid cidr.
+static bool parse_cidr(const char *cidr, uint8_t *family, unsigned char *addr, uint8_t *prefix) {
+    char buf[LIBFIREWALL_CIDR_STRLEN];
+    strncpy(buf, cidr, sizeof(buf) - 1);
+    buf[sizeof(buf) - 1] = '\0';
+
+    char *slash = strchr(buf, '/');
+    if (!slash) {
+        return false;
+    }
+    *slash = '\0';
+
+    char *end;
+    long len = strtol(slash + 1, &end, 10);
+    if (*end != '\0' || end == slash + 1) {
+        return false;
+    }
+
+    if (inet_pton(AF_INET, buf, addr) == 1) {
+        *family = NFPROTO_IPV4;
+    } else if (inet_pton(AF_INET6, buf, addr) == 1) {
+        *family = NFPROTO_IPV6;
+    } else {
+        return false;
+    }
+
+    long max = *family == NFPROTO_IPV4 ? 32 : 128;
+    if (len < 0 || len > max) {
+        return false;
+    }
+
+    *prefix = (uint8_t)len;
+    return true;
+}
+
+/**
+ * Appends a match of the source or destination address against the cidr. The offset is the
+ * position of the address in the network header.
+ */
+static bool put_addr_match(nft_batch *batch, struct nlmsghdr *nlh, uint8_t family,
+                           uint32_t offset, const unsigned char *addr, uint8_t prefix) {
+    uint32_t len = family == NFPROTO_IPV4 ? sizeof(struct in_addr) : sizeof(struct in6_addr);
+
+    struct nlattr *elem, *data;
+    if (!put_expr_start(batch, nlh, "payload", &elem, &data) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_PAYLOAD_DREG, NFT_REG_1) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_PAYLOAD_BASE, NFT_PAYLOAD_NETWORK_HEADER) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_PAYLOAD_OFFSET, offset) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_PAYLOAD_LEN, len)) {
+        return false;
+    }
+    put_expr_end(nlh, elem, data);
+
+    unsigned char mask[sizeof(struct in6_addr)] = {0};
+    unsigned char network[sizeof(struct in6_addr)] = {0};
+    for (uint32_t i = 0; i < len; i++) {
+        int bits = prefix - (int)(i * 8);
+        mask[i] = bits >= 8 ? 0xff : bits <= 0 ? 0 : (unsigned char)(0xff << (8 - bits));
+        network[i] = addr[i] & mask[i];
+    }
+
+    if (prefix < len * 8) {
+        unsigned char xor[sizeof(struct in6_addr)] = {0};
+        if (!put_expr_start(batch, nlh, "bitwise", &elem, &data) ||
+            !nft_attr_put_be32(batch, nlh, NFTA_BITWISE_SREG, NFT_REG_1) ||
+            !nft_attr_put_be32(batch, nlh, NFTA_BITWISE_DREG, NFT_REG_1) ||
+            !nft_attr_put_be32(batch, nlh, NFTA_BITWISE_LEN, len)) {
+            return false;
+        }
+
+        struct nlattr *mask_data = nft_nest_start(batch, nlh, NFTA_BITWISE_MASK);
+        if (!mask_data || !nft_attr_put(batch, nlh, NFTA_DATA_VALUE, mask, len)) {
+            return false;
+        }
+        nft_nest_end(nlh, mask_data);
+
+        struct nlattr *xor_data = nft_nest_start(batch, nlh, NFTA_BITWISE_XOR);
+        if (!xor_data || !nft_attr_put(batch, nlh, NFTA_DATA_VALUE, xor, len)) {
+            return false;
+        }
+        nft_nest_end(nlh, xor_data);
+        put_expr_end(nlh, elem, data);
+    }
+
+    return put_cmp(batch, nlh, NFT_CMP_EQ, network, len);
+}
+
+// Appends `ct state != established,related`, so that replies are never dropped.
+static bool put_ct_new_match(nft_batch *batch, struct nlmsghdr *nlh) {
+    struct nlattr *elem, *data;
+    if (!put_expr_start(batch, nlh, "ct", &elem, &data) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_CT_KEY, NFT_CT_STATE) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_CT_DREG, NFT_REG_1)) {
+        return false;
+    }
+    put_expr_end(nlh, elem, data);
+
+    uint32_t mask = NF_CT_STATE_BIT(IP_CT_ESTABLISHED) | NF_CT_STATE_BIT(IP_CT_RELATED);
+    uint32_t xor = 0;
+    if (!put_expr_start(batch, nlh, "bitwise", &elem, &data) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_BITWISE_SREG, NFT_REG_1) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_BITWISE_DREG, NFT_REG_1) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_BITWISE_LEN, sizeof(uint32_t))) {
+        return false;
+    }
+
+    struct nlattr *mask_data = nft_nest_start(batch, nlh, NFTA_BITWISE_MASK);
+    if (!mask_data || !nft_attr_put(batch, nlh, NFTA_DATA_VALUE, &mask, sizeof(mask))) {
+        return false;
+    }
+    nft_nest_end(nlh, mask_data);
+
+    struct nlattr *xor_data = nft_nest_start(batch, nlh, NFTA_BITWISE_XOR);
+    if (!xor_data || !nft_attr_put(batch, nlh, NFTA_DATA_VALUE, &xor, sizeof(xor))) {
+        return false;
+    }
+    nft_nest_end(nlh, xor_data);
+    put_expr_end(nlh, elem, data);
+
+    uint32_t zero = 0;
+    return put_cmp(batch, nlh, NFT_CMP_EQ, &zero, sizeof(zero));
+}
+
+static bool put_verdict(nft_batch *batch, struct nlmsghdr *nlh, uint32_t verdict) {
+    struct nlattr *elem, *data;
+    if (!put_expr_start(batch, nlh, "immediate", &elem, &data) ||
+        !nft_attr_put_be32(batch, nlh, NFTA_IMMEDIATE_DREG, NFT_REG_VERDICT)) {
+        return false;
+    }
+
+    struct nlattr *imm_data = nft_nest_start(batch, nlh, NFTA_IMMEDIATE_DATA);
+    if (!imm_data) {
+        return false;
+    }
+    struct nlattr *verdict_data = nft_nest_start(batch, nlh, NFTA_DATA_VERDICT);
+    if (!verdict_data || !nft_attr_put_be32(batch, nlh, NFTA_VERDICT_CODE, verdict)) {
+        return false;
+    }
+    nft_nest_end(nlh, verdict_data);
+    nft_nest_end(nlh, imm_data);
+
+    put_expr_end(nlh, elem, data);
+    return true;
+}
+
+/**
+ * Appends `iifname <device> <saddr> <daddr> [l4proto <protocol> [th dport <port>]]
+ * accept|drop`. Allow rules are inserted at the head of the chain and deny rules at its tail,
+ * so an allow rule always takes precedence over a deny rule.
+ */
+static int put_acl_rule(nft_batch *batch, const char *chain, const char *device_name,
+                        const libfirewall_acl *acl) {
+    uint8_t src_family, dst_family, src_prefix, dst_prefix;
+    unsigned char src[sizeof(struct in6_addr)], dst[sizeof(struct in6_addr)];
+    if (!parse_cidr(acl->src, &src_family, src, &src_prefix) ||
+        !parse_cidr(acl->dst, &dst_family, dst, &dst_prefix) || src_family != dst_family) {
+        return LIBFIREWALL_ERR_INVALID_ADDR;
+    }
+
+    if ((acl->protocol != 0 && acl->protocol != IPPROTO_TCP && acl->protocol != IPPROTO_UDP) ||
+        (acl->port != 0 && acl->protocol == 0)) {
+        return LIBFIREWALL_ERR_INVALID_PROTOCOL;
+    }
+
+    uint16_t flags = NLM_F_CREATE | NLM_F_ACK | (acl->allow ? 0 : NLM_F_APPEND);
+    struct nlmsghdr *nlh = nft_msg_start(batch, nft_type(NFT_MSG_NEWRULE), flags, NFPROTO_INET);
+    if (!nlh || !nft_attr_put_strz(batch, nlh, NFTA_RULE_TABLE, LIBFIREWALL_TABLE) ||
+        !nft_attr_put_strz(batch, nlh, NFTA_RULE_CHAIN, chain)) {
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    // offsets of the source and destination addresses in the ipv4 and ipv6 headers
+    uint32_t src_offset = src_family == NFPROTO_IPV4 ? 12 : 8;
+    uint32_t dst_offset = src_family == NFPROTO_IPV4 ? 16 : 24;
+
+    struct nlattr *exprs = nft_nest_start(batch, nlh, NFTA_RULE_EXPRESSIONS);
+    if (!exprs || !put_ifname_match(batch, nlh, NFT_META_IIFNAME, NFT_CMP_EQ, device_name) ||
+        !put_meta(batch, nlh, NFT_META_NFPROTO) ||
+        !put_cmp(batch, nlh, NFT_CMP_EQ, &src_family, sizeof(src_family)) ||
+        !put_addr_match(batch, nlh, src_family, src_offset, src, src_prefix) ||
+        !put_addr_match(batch, nlh, dst_family, dst_offset, dst, dst_prefix)) {
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    if (acl->protocol != 0 && (!put_meta(batch, nlh, NFT_META_L4PROTO) ||
+                               !put_cmp(batch, nlh, NFT_CMP_EQ, &acl->protocol,
+                                        sizeof(acl->protocol)))) {
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    if (acl->port != 0 && !put_dport_match(batch, nlh, acl->port)) {
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    if (!acl->allow && !put_ct_new_match(batch, nlh)) {
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    if (!put_verdict(batch, nlh, acl->allow ? NF_ACCEPT : NF_DROP)) {
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+    nft_nest_end(nlh, exprs);
+
+    char comment[UINT8_MAX];
+    snprintf(comment, sizeof(comment), "acl %u %u %s %s %s %u %u", acl->id, acl->allow,
+             acl->peer_key, acl->src, acl->dst, acl->protocol, acl->port);
+    if (!put_comment(batch, nlh, comment)) {
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    nft_msg_end(batch, nlh);
+    return 0;
+}
+
+static bool read_acl_comment(const char *comment, libfirewall_acl *acl) {
+    memset(acl, 0, sizeof(*acl));
+    return sscanf(comment, ACL_COMMENT_FORMAT, &acl->id, &acl->allow, acl->peer_key, acl->src,
+                  acl->dst, &acl->protocol, &acl->port) == 7;
+}
+
+static bool matches_acl_id(const char *comment, const void *arg) {
+    libfirewall_acl acl;
+    return read_acl_comment(comment, &acl) && acl.id == *(const uint32_t *)arg;
+}
+
+static bool matches_acl_peer(const char *comment, const void *arg) {
+    libfirewall_acl acl;
+    return read_acl_comment(comment, &acl) && strcmp(acl.peer_key, (const char *)arg) == 0;
+}
+
+int libfirewall_set_acls(const char *device_name, uint32_t id, const libfirewall_acl *acls) {
+    clear_error();
+    if (!valid_ifname(device_name)) {
+        return LIBFIREWALL_ERR_INVALID_DEV_NAME;
+    }
+
+    char chain[NFT_CHAIN_NAME_SIZE];
+    snprintf(chain, sizeof(chain), "%s%s", ACL_CHAIN_PREFIX, device_name);
+
+    rule_handles found = {.matches = matches_acl_id, .arg = &id};
+    int res = dump_rules(chain, collect_handle, &found);
+    if (res != 0) {
+        free(found.handles);
+        return res;
+    }
+
+    nft_batch *batch = calloc(1, sizeof(nft_batch));
+    if (!batch) {
+        free(found.handles);
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    bool ok = nft_batch_begin(batch) && put_table(batch) &&
+              put_base_chain(batch, chain, "filter", NF_INET_FORWARD, NFT_PRIORITY_FILTER) &&
+              put_del_rules(batch, chain, &found);
+    free(found.handles);
+    if (!ok) {
+        free(batch);
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    for (const libfirewall_acl *acl = acls; acl; acl = acl->next) {
+        if (acl->id != id) {
+            free(batch);
+            return LIBFIREWALL_ERR_INVALID_ACL_ID;
+        }
+
+        res = put_acl_rule(batch, chain, device_name, acl);
+        if (res != 0) {
+            free(batch);
+            return res;
+        }
+    }
+
+    if (!nft_batch_end(batch)) {
+        free(batch);
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+
+    res = send_update(batch, false);
+    free(batch);
+    return res;
+}
+
+typedef struct acl_list {
+    libfirewall_acl *head;
+    libfirewall_acl *tail;
+} acl_list;
+
+static int read_acl(uint64_t handle, const char *comment, void *ctx) {
+    (void)handle;
+    acl_list *list = ctx;
+
+    libfirewall_acl acl;
+    if (!read_acl_comment(comment, &acl)) {
+        return 0;
+    }
+
+    libfirewall_acl *item = malloc(sizeof(libfirewall_acl));
+    if (!item) {
+        return LIBFIREWALL_ERR_NOMEM;
+    }
+    *item = acl;
+
+    if (list->tail) {
+        list->tail->next = item;
+    } else {
+        list->head = item;
+    }
+    list->tail = item;
+
+    return 0;
+}
+
+int libfirewall_list_acls(const char *device_name, libfirewall_acl **acls) {
+    clear_error();
+    *acls = NULL;
+
+    if (!valid_ifname(device_name)) {
+        return LIBFIREWALL_ERR_INVALID_DEV_NAME;
+    }
+
+    char chain[NFT_CHAIN_NAME_SIZE];
+    snprintf(chain, sizeof(chain), "%s%s", ACL_CHAIN_PREFIX, device_name);
+
+    acl_list list = {0};
+    int res = dump_rules(chain, read_acl, &list);
+    if (res != 0) {
+        libfirewall_free_acls(list.head);
+        return res;
+    }
+
+    *acls = list.head;
+    return 0;
+}
+
+int libfirewall_del_peer_acls(const char *device_name, const char *peer_key) {
+    clear_error();
+    if (!valid_ifname(device_name)) {
+        return LIBFIREWALL_ERR_INVALID_DEV_NAME;
+    }
+
+    char chain[NFT_CHAIN_NAME_SIZE];
+    snprintf(chain, sizeof(chain), "%s%s", ACL_CHAIN_PREFIX, device_name);
+
+    return del_matching_rules(chain, matches_acl_peer, peer_key, 0);
+}
+
+int libfirewall_del_acls(const char *device_name) {
+    clear_error();
+    if (!valid_ifname(device_name)) {
+        return LIBFIREWALL_ERR_INVALID_DEV_NAME;
+    }
+
+    char chain[NFT_CHAIN_NAME_SIZE];
+    snprintf(chain, sizeof(chain), "%s%s", ACL_CHAIN_PREFIX, device_name);
+
+    return del_chain(chain);
+}
+
+void libfirewall_free_acls(libfirewall_acl *acls) {
+    while (acls) {
+        libfirewall_acl *next = acls->next;
+        free(acls);
+        acls = next;
+    }
+}
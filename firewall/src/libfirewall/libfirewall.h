@@ -0,0 +1,212 @@
+/**
+ * @file libfirewall.h
+ * @brief Public API for managing the nftables rules of wghttp.
+ *
+ * This library talks to nf_tables over netlink and keeps every rule it creates in a dedicated
+ * "wghttp" table of the inet family. Each feature gets its own chains so that rules can be
+ * replaced or removed without touching rules managed by other tools.
+ */
+
+#ifndef LIBFIREWALL_H
+#define LIBFIREWALL_H
+
+#include <net/if.h>
+#include <netinet/in.h>
+#include <stdint.h>
+
+// Name of the nftables table that holds all chains created by libfirewall
+#define LIBFIREWALL_TABLE "wghttp"
+
+// Length of a base64 encoded WireGuard key, including the null terminator
+#define LIBFIREWALL_KEY_STRLEN 45
+
+// Length of an address with prefix (e.g., "fd86:ea04:1111::/64"), including the null terminator
+#define LIBFIREWALL_CIDR_STRLEN 51
+
+/**
+ * @brief Error codes returned by libfirewall functions.
+ */
+typedef enum {
+    LIBFIREWALL_ERR_NOMEM = 1,
+    LIBFIREWALL_ERR_NETLINK_SOCKET_FAILED,
+    LIBFIREWALL_ERR_NETLINK_SEND_FAILED,
+    LIBFIREWALL_ERR_NETLINK_RECV_FAILED,
+    LIBFIREWALL_ERR_INVALID_DEV_NAME,
+    LIBFIREWALL_ERR_RULESET_UPDATE_FAILED,
+    LIBFIREWALL_ERR_RULESET_READ_FAILED,
+    LIBFIREWALL_ERR_INVALID_ADDR,
+    LIBFIREWALL_ERR_INVALID_PROTOCOL,
+    LIBFIREWALL_ERR_INVALID_ACL_ID,
+    LIBFIREWALL_ERR_FORWARD_EXISTS,
+    LIBFIREWALL_ERR_FORWARD_NOT_FOUND,
+} libfirewall_error;
+
+/**
+ * @brief Represents the masquerade setup of a WireGuard device.
+ */
+typedef struct libfirewall_masquerade {
+    uint8_t enabled;                // 1 if traffic from the device is masqueraded
+    char egress_name[IF_NAMESIZE];  // Egress interface, empty for any but the device itself
+} libfirewall_masquerade;
+
+/**
+ * @brief Represents a port forwarded from the host to a peer (linked list node).
+ */
+typedef struct libfirewall_forward {
+    char peer_key[LIBFIREWALL_KEY_STRLEN];  // Public key of the peer the port is forwarded to
+    uint8_t protocol;                       // IPPROTO_TCP or IPPROTO_UDP
+    uint16_t public_port;                   // Port on the host's local addresses
+    char dst_addr[INET6_ADDRSTRLEN];        // Tunnel address of the peer
+    uint16_t dst_port;                      // Port on the peer
+    struct libfirewall_forward *next;       // Next forward in the list
+} libfirewall_forward;
+
+/**
+ * @brief Represents an access rule for traffic forwarded from one source of a peer
+ * (linked list node).
+ */
+typedef struct libfirewall_acl {
+    uint32_t id;                            // Rules created together share the same id
+    uint8_t allow;                          // 1 to accept, 0 to drop matching traffic
+    char peer_key[LIBFIREWALL_KEY_STRLEN];  // Public key of the peer the rule applies to
+    char src[LIBFIREWALL_CIDR_STRLEN];      // Source cidr, one of the peer's allowed ips
+    char dst[LIBFIREWALL_CIDR_STRLEN];      // Destination cidr of the same family
+    uint8_t protocol;                       // IPPROTO_TCP, IPPROTO_UDP or 0 for any
+    uint16_t port;                          // Destination port, 0 for any
+    struct libfirewall_acl *next;           // Next rule in the list
+} libfirewall_acl;
+
+/**
+ * @brief Masquerades traffic that enters through the given device.
+ *
+ * Traffic is masqueraded when it leaves through the egress interface, or through any interface
+ * other than the device itself when no egress interface is given. An existing setup for the
+ * device is replaced.
+ *
+ * @param device_name Name of the WireGuard device (e.g., "wg0")
+ * @param egress_name Name of the egress interface (e.g., "eth0"), or an empty string
+ * @return 0 on success, non-zero on failure
+ */
+int libfirewall_add_masquerade(const char *device_name, const char *egress_name);
+
+/**
+ * @brief Reads the masquerade setup of the given device.
+ *
+ * @param device_name Name of the WireGuard device
+ * @param masquerade Output structure, enabled is 0 if the device is not masqueraded
+ * @return 0 on success, non-zero on failure
+ */
+int libfirewall_get_masquerade(const char *device_name, libfirewall_masquerade *masquerade);
+
+/**
+ * @brief Removes the masquerade setup of the given device.
+ *
+ * Removing a setup that does not exist is not an error.
+ *
+ * @param device_name Name of the WireGuard device
+ * @return 0 on success, non-zero on failure
+ */
+int libfirewall_del_masquerade(const char *device_name);
+
+/**
+ * @brief Forwards a port on the host's local addresses to a peer of the given device.
+ *
+ * Fails with LIBFIREWALL_ERR_FORWARD_EXISTS if the protocol and public port are already forwarded
+ * by any device.
+ *
+ * @param device_name Name of the WireGuard device
+ * @param forward Forward to add; next is ignored
+ * @return 0 on success, non-zero on failure
+ */
+int libfirewall_add_forward(const char *device_name, const libfirewall_forward *forward);
+
+/**
+ * @brief Lists the ports forwarded to peers of the given device.
+ *
+ * @param device_name Name of the WireGuard device
+ * @param forwards Output pointer to a linked list, NULL if there are none
+ * @return 0 on success, non-zero on failure
+ */
+int libfirewall_list_forwards(const char *device_name, libfirewall_forward **forwards);
+
+/**
+ * @brief Removes the forward of the given protocol and public port.
+ *
+ * Fails with LIBFIREWALL_ERR_FORWARD_NOT_FOUND if the port is not forwarded.
+ *
+ * @param device_name Name of the WireGuard device
+ * @param protocol IPPROTO_TCP or IPPROTO_UDP
+ * @param public_port Port on the host's local addresses
+ * @return 0 on success, non-zero on failure
+ */
+int libfirewall_del_forward(const char *device_name, uint8_t protocol, uint16_t public_port);
+
+/**
+ * @brief Removes all forwards of the given device.
+ *
+ * @param device_name Name of the WireGuard device
+ * @return 0 on success, non-zero on failure
+ */
+int libfirewall_del_forwards(const char *device_name);
+
+/**
+ * @brief Frees a list returned by libfirewall_list_forwards.
+ *
+ * @param forwards Head of the list, may be NULL
+ */
+void libfirewall_free_forwards(libfirewall_forward *forwards);
+
+/**
+ * @brief Replaces the access rules with the given id in a single transaction.
+ *
+ * Traffic entering through the device is matched in the forward hook. Allow rules take
+ * precedence over deny rules, and deny rules never drop replies of established connections.
+ *
+ * @param device_name Name of the WireGuard device
+ * @param id Id of the rules to replace
+ * @param acls Linked list of rules that all carry the id, NULL to only remove the rules
+ * @return 0 on success, non-zero on failure
+ */
+int libfirewall_set_acls(const char *device_name, uint32_t id, const libfirewall_acl *acls);
+
+/**
+ * @brief Lists the access rules of the given device.
+ *
+ * @param device_name Name of the WireGuard device
+ * @param acls Output pointer to a linked list, NULL if there are none
+ * @return 0 on success, non-zero on failure
+ */
+int libfirewall_list_acls(const char *device_name, libfirewall_acl **acls);
+
+/**
+ * @brief Removes the access rules of the given peer.
+ *
+ * @param device_name Name of the WireGuard device
+ * @param peer_key Public key of the peer
+ * @return 0 on success, non-zero on failure
+ */
+int libfirewall_del_peer_acls(const char *device_name, const char *peer_key);
+
+/**
+ * @brief Removes all access rules of the given device.
+ *
+ * @param device_name Name of the WireGuard device
+ * @return 0 on success, non-zero on failure
+ */
+int libfirewall_del_acls(const char *device_name);
+
+/**
+ * @brief Frees a list returned by libfirewall_list_acls.
+ *
+ * @param acls Head of the list, may be NULL
+ */
+void libfirewall_free_acls(libfirewall_acl *acls);
+
+/**
+ * @brief Returns the errno behind the last failed libfirewall call of the calling thread.
+ *
+ * @return errno of the failed system call or netlink request, 0 if the failure had no such cause.
+ */
+int libfirewall_last_errno(void);
+
+#endif  // LIBFIREWALL_H
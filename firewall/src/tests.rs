@@ -0,0 +1,247 @@
+use crate::NftAdapter;
+use domain::adapters::firewall::FirewallAdapter;
+use domain::models::firewall::*;
+use domain::models::netdev::IpFamily;
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+#[test]
+fn test_firewall_invalid_device_name_returns_error() {
+    let adapter = NftAdapter;
+
+    let result = adapter.add_masquerade(
+        "",
+        &Masquerade {
+            egress_interface: None,
+        },
+    );
+    assert!(result.is_err());
+
+    let result = adapter.get_masquerade("a-very-long-device-name");
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_firewall_add_get_and_delete_masquerade() {
+    let adapter = NftAdapter;
+    let device_name = "fwtest0";
+
+    let result = adapter.get_masquerade(device_name);
+    assert!(result.is_ok());
+    assert!(result.unwrap().is_none());
+
+    let masquerade = Masquerade {
+        egress_interface: Some("eth0".to_string()),
+    };
+    let result = adapter.add_masquerade(device_name, &masquerade);
+    assert!(result.is_ok());
+
+    let result = adapter.get_masquerade(device_name);
+    assert_eq!(result.unwrap(), Some(masquerade));
+
+    // an existing setup is replaced
+    let masquerade = Masquerade {
+        egress_interface: None,
+    };
+    let result = adapter.add_masquerade(device_name, &masquerade);
+    assert!(result.is_ok());
+
+    let result = adapter.get_masquerade(device_name);
+    assert_eq!(result.unwrap(), Some(masquerade));
+
+    let result = adapter.delete_masquerade(device_name);
+    assert!(result.is_ok());
+
+    let result = adapter.get_masquerade(device_name);
+    assert!(result.unwrap().is_none());
+
+    let result = adapter.delete_masquerade(device_name);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_firewall_get_forwarding() {
+    let adapter = NftAdapter;
+
+    let result = adapter.get_forwarding(IpFamily::V4);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_firewall_concurrent_masquerades_do_not_interfere() {
+    let handles: Vec<_> = (0..8)
+        .map(|i| {
+            std::thread::spawn(move || {
+                let adapter = NftAdapter;
+                let device_name = format!("fwtest{}", i + 10);
+                let masquerade = Masquerade {
+                    egress_interface: Some(format!("eth{}", i)),
+                };
+
+                for _ in 0..20 {
+                    assert!(adapter.add_masquerade(&device_name, &masquerade).is_ok());
+                    let result = adapter.get_masquerade(&device_name);
+                    assert_eq!(result.unwrap(), Some(masquerade.clone()));
+                }
+
+                assert!(adapter.delete_masquerade(&device_name).is_ok());
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn test_firewall_add_list_and_delete_forwards() {
+    let adapter = NftAdapter;
+    let device_name = "fwtest1";
+
+    let result = adapter.list_forwards(device_name);
+    assert!(result.unwrap().is_empty());
+
+    let tcp_forward = PortForward {
+        peer_public_key: "wfbGOdrEgIGn15y6FMgfJjpaZv02ZQb5xQ5yvnkPhyg=".to_string(),
+        protocol: Protocol::Tcp,
+        public_port: 8443,
+        destination_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+        destination_port: 443,
+    };
+    let udp_forward = PortForward {
+        peer_public_key: "wfbGOdrEgIGn15y6FMgfJjpaZv02ZQb5xQ5yvnkPhyg=".to_string(),
+        protocol: Protocol::Udp,
+        public_port: 5353,
+        destination_ip: IpAddr::V6(Ipv6Addr::from_str("fd86:ea04:1111::2").unwrap()),
+        destination_port: 53,
+    };
+
+    assert!(adapter.add_forward(device_name, &tcp_forward).is_ok());
+    assert!(adapter.add_forward(device_name, &udp_forward).is_ok());
+
+    let result = adapter.list_forwards(device_name);
+    assert_eq!(result.unwrap(), vec![tcp_forward, udp_forward.clone()]);
+
+    let result = adapter.delete_forward(device_name, Protocol::Tcp, 8443);
+    assert!(result.is_ok());
+
+    let result = adapter.list_forwards(device_name);
+    assert_eq!(result.unwrap(), vec![udp_forward]);
+
+    let result = adapter.delete_forwards(device_name);
+    assert!(result.is_ok());
+
+    let result = adapter.list_forwards(device_name);
+    assert!(result.unwrap().is_empty());
+}
+
+#[test]
+fn test_firewall_forward_conflicts_are_reported() {
+    let adapter = NftAdapter;
+    let device_name = "fwtest3";
+
+    let forward = PortForward {
+        peer_public_key: "wfbGOdrEgIGn15y6FMgfJjpaZv02ZQb5xQ5yvnkPhyg=".to_string(),
+        protocol: Protocol::Tcp,
+        public_port: 9443,
+        destination_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+        destination_port: 443,
+    };
+
+    let result = adapter.delete_forward(device_name, Protocol::Tcp, 9443);
+    assert_eq!(
+        result.unwrap_err(),
+        FirewallError::from(FirewallErrorKind::ForwardNotFound)
+    );
+
+    assert!(adapter.add_forward(device_name, &forward).is_ok());
+
+    let result = adapter.add_forward(device_name, &forward);
+    assert_eq!(
+        result.unwrap_err(),
+        FirewallError::from(FirewallErrorKind::ForwardExists)
+    );
+
+    // the port is taken on every device
+    let result = adapter.add_forward("fwtest4", &forward);
+    assert_eq!(
+        result.unwrap_err(),
+        FirewallError::from(FirewallErrorKind::ForwardExists)
+    );
+
+    assert!(adapter.delete_forwards(device_name).is_ok());
+    assert!(adapter.add_forward("fwtest4", &forward).is_ok());
+    assert!(adapter.delete_forwards("fwtest4").is_ok());
+}
+
+#[test]
+fn test_firewall_replace_list_and_delete_acl_rules() {
+    let adapter = NftAdapter;
+    let device_name = "fwtest2";
+    let peer = "wfbGOdrEgIGn15y6FMgfJjpaZv02ZQb5xQ5yvnkPhyg=";
+    let other_peer = "UMp441pv9vfOq2eMRK0CURJeSZlsyIDXurczqVKPums=";
+
+    let result = adapter.list_acl_rules(device_name);
+    assert!(result.unwrap().is_empty());
+
+    let deny = |peer: &str, source: Ipv4Addr| AclRule {
+        id: 1,
+        action: AclAction::Deny,
+        peer_public_key: peer.to_string(),
+        source: (IpAddr::V4(source), 32),
+        destination: (IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+        protocol: None,
+        port: None,
+    };
+    let deny_rules = vec![
+        deny(peer, Ipv4Addr::new(10, 0, 0, 2)),
+        deny(other_peer, Ipv4Addr::new(10, 0, 0, 3)),
+    ];
+    let allow_rule = AclRule {
+        id: 2,
+        action: AclAction::Allow,
+        peer_public_key: peer.to_string(),
+        source: (
+            IpAddr::V6(Ipv6Addr::from_str("fd86:ea04:1111::2").unwrap()),
+            128,
+        ),
+        destination: (IpAddr::V6(Ipv6Addr::from_str("fd00:1::").unwrap()), 48),
+        protocol: Some(Protocol::Tcp),
+        port: Some(443),
+    };
+
+    assert!(
+        adapter
+            .replace_acl_rules(device_name, 1, &deny_rules)
+            .is_ok()
+    );
+    let result = adapter.replace_acl_rules(device_name, 2, std::slice::from_ref(&allow_rule));
+    assert!(result.is_ok());
+
+    // allow rules are evaluated first
+    let result = adapter.list_acl_rules(device_name);
+    let mut expected = vec![allow_rule.clone()];
+    expected.extend(deny_rules.clone());
+    assert_eq!(result.unwrap(), expected);
+
+    // a rule with another id is rejected
+    let result = adapter.replace_acl_rules(device_name, 3, &deny_rules);
+    assert!(result.is_err());
+
+    let result = adapter.delete_peer_acl_rules(device_name, peer);
+    assert!(result.is_ok());
+
+    let result = adapter.list_acl_rules(device_name);
+    assert_eq!(result.unwrap(), vec![deny_rules[1].clone()]);
+
+    let result = adapter.replace_acl_rules(device_name, 1, &[]);
+    assert!(result.is_ok());
+
+    let result = adapter.list_acl_rules(device_name);
+    assert!(result.unwrap().is_empty());
+
+    let result = adapter.delete_acl_rules(device_name);
+    assert!(result.is_ok());
+}
@@ -1,10 +1,11 @@
-use domain::models::netdev::NetDevIp;
+use domain::models::netdev::{IpFamily, NetDevIp, NetDevRoute, NetDevRule, NetNs};
 use std::ffi::{CStr, CString};
 use std::net::{Ipv4Addr, Ipv6Addr};
 use std::os::raw::{c_char, c_int};
 use std::str::FromStr;
 
 pub const IP_NETMASK_STRLEN: usize = 51;
+pub const NAME_MAX: usize = 255;
 
 #[repr(C)]
 #[derive(Debug)]
@@ -22,6 +23,16 @@ pub enum LibNetDevError {
     DevNotFound,
     NetlinkSendFailed,
     GetifaddrsFailed,
+    NetlinkRecvFailed,
+    RouteAddFailed,
+    RouteDelFailed,
+    RouteExists,
+    RuleAddFailed,
+    RuleDelFailed,
+    InvalidNetns,
+    NetnsOpenFailed,
+    NetnsEnterFailed,
+    LinkNetnsFailed,
 }
 
 #[repr(C)]
@@ -31,6 +42,33 @@ pub struct LibNetDevIp {
     pub ipv6: [c_char; IP_NETMASK_STRLEN],
 }
 
+#[repr(C)]
+#[derive(Debug)]
+pub struct LibNetDevRoute {
+    pub dst: [c_char; IP_NETMASK_STRLEN],
+    pub table: u32,
+    pub metric: u32,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct LibNetDevRule {
+    pub ipv6: u8,
+    pub invert: u8,
+
+    pub table: u32,
+    pub fwmark: u32,
+    pub suppress_prefixlength: i32,
+    pub priority: u32,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct LibNetDevNetns {
+    pub name: [c_char; NAME_MAX + 1],
+    pub pid: u32,
+}
+
 unsafe extern "C" {
     pub unsafe fn libnetdev_get_ip(device_name: *const c_char, ip: *mut *mut LibNetDevIp) -> c_int;
 
@@ -38,6 +76,38 @@ unsafe extern "C" {
 
     pub unsafe fn libnetdev_up(device_name: *const c_char) -> c_int;
 
+    pub unsafe fn libnetdev_add_route(
+        device_name: *const c_char,
+        route: *const LibNetDevRoute,
+    ) -> c_int;
+
+    pub unsafe fn libnetdev_del_route(
+        device_name: *const c_char,
+        route: *const LibNetDevRoute,
+    ) -> c_int;
+
+    pub unsafe fn libnetdev_add_rule(rule: *const LibNetDevRule) -> c_int;
+
+    pub unsafe fn libnetdev_del_rule(rule: *const LibNetDevRule) -> c_int;
+
+    pub unsafe fn libnetdev_enter_netns(
+        netns: *const LibNetDevNetns,
+        saved_fd: *mut c_int,
+    ) -> c_int;
+
+    pub unsafe fn libnetdev_leave_netns(saved_fd: c_int) -> c_int;
+
+    pub unsafe fn libnetdev_set_netns(
+        device_name: *const c_char,
+        netns: *const LibNetDevNetns,
+    ) -> c_int;
+
+    pub unsafe fn libnetdev_check() -> c_int;
+
+    pub unsafe fn libnetdev_last_errno() -> c_int;
+
+    pub unsafe fn libnetdev_last_extack() -> *const c_char;
+
     pub unsafe fn libnetdev_free_ip(ip: *mut LibNetDevIp);
 }
 
@@ -95,3 +165,61 @@ impl LibNetDevIp {
         LibNetDevIp { ipv4, ipv6 }
     }
 }
+
+impl LibNetDevRoute {
+    pub fn from_netdev_route(route: &NetDevRoute) -> Self {
+        let mut dst = [0 as c_char; IP_NETMASK_STRLEN];
+
+        if let Ok(cstring) = CString::new(route.destination_str()) {
+            let bytes = cstring.as_bytes_with_nul();
+            let len = bytes.len().min(IP_NETMASK_STRLEN);
+            dst[..len].copy_from_slice(
+                &bytes[..len]
+                    .iter()
+                    .map(|b| *b as c_char)
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        LibNetDevRoute {
+            dst,
+            table: route.table.unwrap_or(0),
+            metric: route.metric.unwrap_or(0),
+        }
+    }
+}
+
+impl LibNetDevRule {
+    pub fn from_netdev_rule(rule: &NetDevRule) -> Self {
+        LibNetDevRule {
+            ipv6: (rule.family == IpFamily::V6) as u8,
+            invert: rule.invert as u8,
+            table: rule.table,
+            fwmark: rule.fwmark.unwrap_or(0),
+            suppress_prefixlength: rule.suppress_prefixlength.map(|p| p as i32).unwrap_or(-1),
+            priority: rule.priority.unwrap_or(0),
+        }
+    }
+}
+
+impl LibNetDevNetns {
+    pub fn from_netns(netns: &NetNs) -> Self {
+        let mut name = [0 as c_char; NAME_MAX + 1];
+        let mut pid = 0;
+
+        match netns {
+            NetNs::Named(n) => {
+                if let Ok(cstring) = CString::new(n.as_str()) {
+                    let bytes = cstring.as_bytes_with_nul();
+                    let len = bytes.len().min(NAME_MAX);
+                    for (dst, src) in name[..len].iter_mut().zip(bytes) {
+                        *dst = *src as c_char;
+                    }
+                }
+            }
+            NetNs::Pid(p) => pid = *p,
+        }
+
+        LibNetDevNetns { name, pid }
+    }
+}
@@ -1,4 +1,5 @@
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
+use std::fs;
 use std::ptr;
 
 use domain::adapters::netdev::NetworkDeviceAdapter;
@@ -11,6 +12,8 @@ use std::convert::TryFrom;
 #[cfg(test)]
 mod tests;
 
+const SRC_VALID_MARK: &str = "/proc/sys/net/ipv4/conf/all/src_valid_mark";
+
 impl TryFrom<i32> for ffi::LibNetDevError {
     type Error = ();
 
@@ -29,40 +32,76 @@ impl TryFrom<i32> for ffi::LibNetDevError {
             11 => Ok(Self::DevNotFound),
             12 => Ok(Self::NetlinkSendFailed),
             13 => Ok(Self::GetifaddrsFailed),
+            14 => Ok(Self::NetlinkRecvFailed),
+            15 => Ok(Self::RouteAddFailed),
+            16 => Ok(Self::RouteDelFailed),
+            17 => Ok(Self::RouteExists),
+            18 => Ok(Self::RuleAddFailed),
+            19 => Ok(Self::RuleDelFailed),
+            20 => Ok(Self::InvalidNetns),
+            21 => Ok(Self::NetnsOpenFailed),
+            22 => Ok(Self::NetnsEnterFailed),
+            23 => Ok(Self::LinkNetnsFailed),
             _ => Err(()),
         }
     }
 }
 
-impl From<ffi::LibNetDevError> for NetDevError {
+impl From<ffi::LibNetDevError> for NetDevErrorKind {
     fn from(err: ffi::LibNetDevError) -> Self {
-        let msg = match err {
-            ffi::LibNetDevError::NoMem => "memory allocation failed",
-            ffi::LibNetDevError::CtlSocketFailed => "failed to open control socket",
-            ffi::LibNetDevError::NetlinkSocketFailed => "failed to open netlink socket",
-            ffi::LibNetDevError::GetDevFlagsFailed => "failed to get interface flags",
-            ffi::LibNetDevError::SetDevFlagsFailed => "failed to set interface flags",
-            ffi::LibNetDevError::InvalidIpStr => "invalid ip string format",
-            ffi::LibNetDevError::InvalidIp => "invalid ip address",
-            ffi::LibNetDevError::InvalidIpPrefix => "invalid ip prefix length",
-            ffi::LibNetDevError::DevIpSetFailed => "failed to set device ip",
-            ffi::LibNetDevError::DevNetmaskSetFailed => "failed to set device netmask",
-            ffi::LibNetDevError::DevNotFound => "device not found",
-            ffi::LibNetDevError::NetlinkSendFailed => "failed to send netlink message",
-            ffi::LibNetDevError::GetifaddrsFailed => "getifaddrs() system call failed",
-        };
-
-        NetDevError(msg.to_string())
+        match err {
+            ffi::LibNetDevError::NoMem => NetDevErrorKind::NoMem,
+            ffi::LibNetDevError::CtlSocketFailed => NetDevErrorKind::CtlSocketFailed,
+            ffi::LibNetDevError::NetlinkSocketFailed => NetDevErrorKind::NetlinkSocketFailed,
+            ffi::LibNetDevError::GetDevFlagsFailed => NetDevErrorKind::GetDevFlagsFailed,
+            ffi::LibNetDevError::SetDevFlagsFailed => NetDevErrorKind::SetDevFlagsFailed,
+            ffi::LibNetDevError::InvalidIpStr => NetDevErrorKind::InvalidIpStr,
+            ffi::LibNetDevError::InvalidIp => NetDevErrorKind::InvalidIp,
+            ffi::LibNetDevError::InvalidIpPrefix => NetDevErrorKind::InvalidIpPrefix,
+            ffi::LibNetDevError::DevIpSetFailed => NetDevErrorKind::DevIpSetFailed,
+            ffi::LibNetDevError::DevNetmaskSetFailed => NetDevErrorKind::DevNetmaskSetFailed,
+            ffi::LibNetDevError::DevNotFound => NetDevErrorKind::DevNotFound,
+            ffi::LibNetDevError::NetlinkSendFailed => NetDevErrorKind::NetlinkSendFailed,
+            ffi::LibNetDevError::GetifaddrsFailed => NetDevErrorKind::GetifaddrsFailed,
+            ffi::LibNetDevError::NetlinkRecvFailed => NetDevErrorKind::NetlinkRecvFailed,
+            ffi::LibNetDevError::RouteAddFailed => NetDevErrorKind::RouteAddFailed,
+            ffi::LibNetDevError::RouteDelFailed => NetDevErrorKind::RouteDelFailed,
+            ffi::LibNetDevError::RouteExists => NetDevErrorKind::RouteExists,
+            ffi::LibNetDevError::RuleAddFailed => NetDevErrorKind::RuleAddFailed,
+            ffi::LibNetDevError::RuleDelFailed => NetDevErrorKind::RuleDelFailed,
+            ffi::LibNetDevError::InvalidNetns => NetDevErrorKind::InvalidNetns,
+            ffi::LibNetDevError::NetnsOpenFailed => NetDevErrorKind::NetnsOpenFailed,
+            ffi::LibNetDevError::NetnsEnterFailed => NetDevErrorKind::NetnsEnterFailed,
+            ffi::LibNetDevError::LinkNetnsFailed => NetDevErrorKind::LinkNetnsFailed,
+        }
     }
 }
 
+// Reads the errno and the extended ack message libnetdev kept for the last failure of the thread.
+unsafe fn last_error() -> (Option<i32>, Option<String>) {
+    let errno = unsafe { ffi::libnetdev_last_errno() };
+    let extack = unsafe { CStr::from_ptr(ffi::libnetdev_last_extack()) }
+        .to_string_lossy()
+        .into_owned();
+
+    (
+        (errno != 0).then_some(errno),
+        (!extack.is_empty()).then_some(extack),
+    )
+}
+
 macro_rules! libnetdev_try {
     ($code:expr) => {
         let result: i32 = unsafe { $code };
         if result != 0 {
-            let err: NetDevError = ffi::LibNetDevError::try_from(result)
-                .map(|e| e.into())
-                .unwrap_or_else(|_| NetDevError("network device error".to_owned()));
+            let (errno, extack) = unsafe { last_error() };
+            let err = ffi::LibNetDevError::try_from(result)
+                .map(|e| NetDevError::Lib {
+                    kind: e.into(),
+                    errno,
+                    extack,
+                })
+                .unwrap_or_else(|_| NetDevError::Other("network device error".to_owned()));
             return Err(err);
         }
     };
@@ -72,13 +111,14 @@ pub struct NetDevAdapter;
 
 impl NetworkDeviceAdapter for NetDevAdapter {
     fn get_ip(&self, device_name: &str) -> Result<NetDevIp, NetDevError> {
-        let dev_name = CString::new(device_name).map_err(|e| NetDevError(e.to_string()))?;
+        let dev_name =
+            CString::new(device_name).map_err(|e| NetDevError::Invalid(e.to_string()))?;
 
         let mut ip_ptr: *mut ffi::LibNetDevIp = ptr::null_mut();
         libnetdev_try!(ffi::libnetdev_get_ip(dev_name.as_ptr(), &mut ip_ptr));
 
         if ip_ptr.is_null() {
-            return Err(NetDevError("network device error".to_owned()));
+            return Err(NetDevError::Other("network device error".to_owned()));
         }
 
         let libnetdev_ip = unsafe { &(*ip_ptr) };
@@ -92,7 +132,8 @@ impl NetworkDeviceAdapter for NetDevAdapter {
     }
 
     fn set_ip(&self, device_name: &str, ip: &NetDevIp) -> Result<(), NetDevError> {
-        let dev_name = CString::new(device_name).map_err(|e| NetDevError(e.to_string()))?;
+        let dev_name =
+            CString::new(device_name).map_err(|e| NetDevError::Invalid(e.to_string()))?;
 
         let libnetdev_ip = &ffi::LibNetDevIp::from_netdev_ip(ip);
         libnetdev_try!(ffi::libnetdev_set_ip(dev_name.as_ptr(), libnetdev_ip));
@@ -101,10 +142,82 @@ impl NetworkDeviceAdapter for NetDevAdapter {
     }
 
     fn up(&self, device_name: &str) -> Result<(), NetDevError> {
-        let dev_name = CString::new(device_name).map_err(|e| NetDevError(e.to_string()))?;
+        let dev_name =
+            CString::new(device_name).map_err(|e| NetDevError::Invalid(e.to_string()))?;
 
         libnetdev_try!(ffi::libnetdev_up(dev_name.as_ptr()));
 
         Ok(())
     }
+
+    fn add_route(&self, device_name: &str, route: &NetDevRoute) -> Result<(), NetDevError> {
+        let dev_name =
+            CString::new(device_name).map_err(|e| NetDevError::Invalid(e.to_string()))?;
+
+        let libnetdev_route = &ffi::LibNetDevRoute::from_netdev_route(route);
+        libnetdev_try!(ffi::libnetdev_add_route(dev_name.as_ptr(), libnetdev_route));
+
+        Ok(())
+    }
+
+    fn delete_route(&self, device_name: &str, route: &NetDevRoute) -> Result<(), NetDevError> {
+        let dev_name =
+            CString::new(device_name).map_err(|e| NetDevError::Invalid(e.to_string()))?;
+
+        let libnetdev_route = &ffi::LibNetDevRoute::from_netdev_route(route);
+        libnetdev_try!(ffi::libnetdev_del_route(dev_name.as_ptr(), libnetdev_route));
+
+        Ok(())
+    }
+
+    fn add_rule(&self, rule: &NetDevRule) -> Result<(), NetDevError> {
+        let libnetdev_rule = &ffi::LibNetDevRule::from_netdev_rule(rule);
+        libnetdev_try!(ffi::libnetdev_add_rule(libnetdev_rule));
+
+        Ok(())
+    }
+
+    fn delete_rule(&self, rule: &NetDevRule) -> Result<(), NetDevError> {
+        let libnetdev_rule = &ffi::LibNetDevRule::from_netdev_rule(rule);
+        libnetdev_try!(ffi::libnetdev_del_rule(libnetdev_rule));
+
+        Ok(())
+    }
+
+    fn enable_src_valid_mark(&self) -> Result<(), NetDevError> {
+        fs::write(SRC_VALID_MARK, "1").map_err(|e| {
+            NetDevError::Other(format!("failed to write src_valid_mark sysctl: {}", e))
+        })
+    }
+
+    fn enter_netns(&self, netns: &NetNs) -> Result<NetNsHandle, NetDevError> {
+        let libnetdev_netns = &ffi::LibNetDevNetns::from_netns(netns);
+
+        let mut saved_fd = -1;
+        libnetdev_try!(ffi::libnetdev_enter_netns(libnetdev_netns, &mut saved_fd));
+
+        Ok(NetNsHandle(saved_fd))
+    }
+
+    fn leave_netns(&self, handle: NetNsHandle) -> Result<(), NetDevError> {
+        libnetdev_try!(ffi::libnetdev_leave_netns(handle.0));
+
+        Ok(())
+    }
+
+    fn set_netns(&self, device_name: &str, netns: &NetNs) -> Result<(), NetDevError> {
+        let dev_name =
+            CString::new(device_name).map_err(|e| NetDevError::Invalid(e.to_string()))?;
+
+        let libnetdev_netns = &ffi::LibNetDevNetns::from_netns(netns);
+        libnetdev_try!(ffi::libnetdev_set_netns(dev_name.as_ptr(), libnetdev_netns));
+
+        Ok(())
+    }
+
+    fn check(&self) -> Result<(), NetDevError> {
+        libnetdev_try!(ffi::libnetdev_check());
+
+        Ok(())
+    }
 }
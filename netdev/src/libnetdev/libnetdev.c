@@ -1,10 +1,16 @@
+#define _GNU_SOURCE
+
 #include "libnetdev.h"
 
 #include <arpa/inet.h>
+#include <errno.h>
+#include <fcntl.h>
 #include <ifaddrs.h>
+#include <linux/fib_rules.h>
 #include <linux/netlink.h>
 #include <linux/rtnetlink.h>
 #include <net/if.h>
+#include <sched.h>
 #include <stdbool.h>
 #include <stdio.h>
 #include <stdlib.h>
@@ -13,6 +19,32 @@
 #include <sys/socket.h>
 #include <unistd.h>
 
+// cause of the last failure on this thread, reported through libnetdev_last_errno and
+// libnetdev_last_extack.
+static __thread int last_errno;
+static __thread char last_extack[LIBNETDEV_EXTACK_MAXLEN];
+
+static void clear_error(void) {
+    last_errno = 0;
+    last_extack[0] = '\0';
+}
+
+// Records the errno behind a failure and passes the error code through.
+static int fail(int code, int err) {
+    last_errno = err;
+    return code;
+}
+
+int libnetdev_last_errno(void) {
+    return last_errno;
+}
+
+const char *libnetdev_last_extack(void) {
+    return last_extack;
+}
+
+int netlink_request(struct nlmsghdr *nlh, int *nl_errno);
+
 int count_prefix_bits_v4(uint32_t netmask) {
     int bits = 0;
     netmask = ntohl(netmask);
@@ -39,16 +71,17 @@ int count_prefix_bits_v6(struct in6_addr *mask) {
 }
 
 int libnetdev_get_ip(const char *device_name, libnetdev_ip **ip) {
+    clear_error();
     struct ifaddrs *ifaddr = NULL, *ifa = NULL;
 
     if (getifaddrs(&ifaddr) == -1) {
-        return LIBNETDEV_ERR_GETIFADDRS_FAILED;
+        return fail(LIBNETDEV_ERR_GETIFADDRS_FAILED, errno);
     }
 
     *ip = calloc(1, sizeof(libnetdev_ip));
-    if (!ip) {
+    if (!*ip) {
         freeifaddrs(ifaddr);
-        return LIBNETDEV_ERR_NOMEM;
+        return fail(LIBNETDEV_ERR_NOMEM, ENOMEM);
     }
 
     bool found = false;
@@ -134,12 +167,7 @@ int set_ipv6(const char *device_name, const char *ipv6_str, const char *prefix_s
 
     int if_index = if_nametoindex(device_name);
     if (if_index == 0) {
-        return LIBNETDEV_ERR_DEV_NOT_FOUND;
-    }
-
-    int fd = socket(AF_NETLINK, SOCK_RAW, NETLINK_ROUTE);
-    if (fd < 0) {
-        return LIBNETDEV_ERR_NETLINK_SOCKET_FAILED;
+        return fail(LIBNETDEV_ERR_DEV_NOT_FOUND, errno);
     }
 
     char buf[512];
@@ -172,20 +200,13 @@ int set_ipv6(const char *device_name, const char *ipv6_str, const char *prefix_s
     memcpy(RTA_DATA(rta_local), &ipv6, sizeof(struct in6_addr));
     nlh->nlmsg_len = NLMSG_ALIGN(nlh->nlmsg_len) + RTA_LENGTH(sizeof(struct in6_addr));
 
-    struct sockaddr_nl addr = {.nl_family = AF_NETLINK};
-
-    struct iovec iov = {.iov_base = nlh, .iov_len = nlh->nlmsg_len};
-
-    struct msghdr msg = {
-        .msg_name = &addr, .msg_namelen = sizeof(addr), .msg_iov = &iov, .msg_iovlen = 1};
-
-    if (sendmsg(fd, &msg, 0) < 0) {
-        close(fd);
-        return LIBNETDEV_ERR_NETLINK_SEND_FAILED;
+    int nl_errno = 0;
+    int res = netlink_request(nlh, &nl_errno);
+    if (res != 0) {
+        return res;
     }
 
-    close(fd);
-    return 0;
+    return nl_errno == 0 ? 0 : fail(LIBNETDEV_ERR_DEV_IP_SET_FAILED, nl_errno);
 }
 
 int set_ipv4(const char *device_name, const char *ipv4_str, const char *prefix_str) {
@@ -209,7 +230,7 @@ int set_ipv4(const char *device_name, const char *ipv4_str, const char *prefix_s
 
     int fd = socket(AF_INET, SOCK_DGRAM, 0);
     if (fd < 0) {
-        return LIBNETDEV_ERR_CTL_SOCKET_FAILED;
+        return fail(LIBNETDEV_ERR_CTL_SOCKET_FAILED, errno);
     }
 
     struct ifreq ifr = {0};
@@ -217,12 +238,14 @@ int set_ipv4(const char *device_name, const char *ipv4_str, const char *prefix_s
     memcpy(&ifr.ifr_addr, &addr, sizeof(struct sockaddr_in));
 
     if (ioctl(fd, SIOCSIFADDR, &ifr) < 0) {
+        fail(LIBNETDEV_ERR_DEV_IP_SET_FAILED, errno);
         close(fd);
         return LIBNETDEV_ERR_DEV_IP_SET_FAILED;
     }
 
     memcpy(&ifr.ifr_netmask, &netmask, sizeof(struct sockaddr_in));
     if (ioctl(fd, SIOCSIFNETMASK, &ifr) < 0) {
+        fail(LIBNETDEV_ERR_DEV_NETMASK_SET_FAILED, errno);
         close(fd);
         return LIBNETDEV_ERR_DEV_NETMASK_SET_FAILED;
     }
@@ -232,6 +255,7 @@ int set_ipv4(const char *device_name, const char *ipv4_str, const char *prefix_s
 }
 
 int libnetdev_set_ip(const char *device_name, libnetdev_ip *ip) {
+    clear_error();
     char ip_buf[IP_NETMASK_STRLEN];
     char prefix_buf[IP_PREFIX_MAXLEN];
 
@@ -264,9 +288,10 @@ int libnetdev_set_ip(const char *device_name, libnetdev_ip *ip) {
 }
 
 int libnetdev_up(const char *device_name) {
+    clear_error();
     int fd = socket(AF_INET, SOCK_DGRAM, 0);
     if (fd < 0) {
-        return LIBNETDEV_ERR_CTL_SOCKET_FAILED;
+        return fail(LIBNETDEV_ERR_CTL_SOCKET_FAILED, errno);
     }
 
     struct ifreq ifr = {0};
@@ -274,6 +299,7 @@ int libnetdev_up(const char *device_name) {
     strncpy(ifr.ifr_name, device_name, IF_NAMESIZE - 1);
 
     if (ioctl(fd, SIOCGIFFLAGS, &ifr) < 0) {
+        fail(LIBNETDEV_ERR_GET_DEV_FLAGS_FAILED, errno);
         close(fd);
         return LIBNETDEV_ERR_GET_DEV_FLAGS_FAILED;
     }
@@ -281,6 +307,7 @@ int libnetdev_up(const char *device_name) {
     ifr.ifr_flags |= IFF_UP;
 
     if (ioctl(fd, SIOCSIFFLAGS, &ifr) < 0) {
+        fail(LIBNETDEV_ERR_SET_DEV_FLAGS_FAILED, errno);
         close(fd);
         return LIBNETDEV_ERR_SET_DEV_FLAGS_FAILED;
     }
@@ -289,6 +316,559 @@ int libnetdev_up(const char *device_name) {
     return 0;
 }
 
+int add_rtattr(struct nlmsghdr *nlh, size_t maxlen, int type, const void *data, size_t len) {
+    size_t rta_len = RTA_LENGTH(len);
+    if (NLMSG_ALIGN(nlh->nlmsg_len) + RTA_ALIGN(rta_len) > maxlen) {
+        return -1;
+    }
+
+    struct rtattr *rta = (struct rtattr *)(((char *)nlh) + NLMSG_ALIGN(nlh->nlmsg_len));
+    rta->rta_type = type;
+    rta->rta_len = rta_len;
+    memcpy(RTA_DATA(rta), data, len);
+    nlh->nlmsg_len = NLMSG_ALIGN(nlh->nlmsg_len) + RTA_ALIGN(rta_len);
+    return 0;
+}
+
+// Copies the NLMSGERR_ATTR_MSG attribute of an error message into last_extack.
+static void read_extack(const struct nlmsghdr *h) {
+    const struct nlmsgerr *err = (const struct nlmsgerr *)NLMSG_DATA(h);
+    if (!(h->nlmsg_flags & NLM_F_ACK_TLVS)) {
+        return;
+    }
+
+    // the request is echoed back between the error and its attributes unless capped
+    size_t offset = sizeof(*err);
+    if (!(h->nlmsg_flags & NLM_F_CAPPED)) {
+        offset += err->msg.nlmsg_len - NLMSG_HDRLEN;
+    }
+
+    if (NLMSG_HDRLEN + offset > h->nlmsg_len) {
+        return;
+    }
+
+    const char *attrs = (const char *)err + offset;
+    size_t len = h->nlmsg_len - NLMSG_HDRLEN - offset;
+    while (len >= NLA_HDRLEN) {
+        const struct nlattr *attr = (const struct nlattr *)attrs;
+        if (attr->nla_len < NLA_HDRLEN || attr->nla_len > len) {
+            return;
+        }
+
+        if ((attr->nla_type & NLA_TYPE_MASK) == NLMSGERR_ATTR_MSG) {
+            size_t msg_len = attr->nla_len - NLA_HDRLEN;
+            if (msg_len >= sizeof(last_extack)) {
+                msg_len = sizeof(last_extack) - 1;
+            }
+            memcpy(last_extack, attrs + NLA_HDRLEN, msg_len);
+            last_extack[msg_len] = '\0';
+            return;
+        }
+
+        size_t step = NLA_ALIGN(attr->nla_len);
+        if (step >= len) {
+            return;
+        }
+        attrs += step;
+        len -= step;
+    }
+}
+
+int netlink_request(struct nlmsghdr *nlh, int *nl_errno) {
+    *nl_errno = 0;
+
+    int fd = socket(AF_NETLINK, SOCK_RAW | SOCK_CLOEXEC, NETLINK_ROUTE);
+    if (fd < 0) {
+        return fail(LIBNETDEV_ERR_NETLINK_SOCKET_FAILED, errno);
+    }
+
+    // kernels without extended acks reject the option, errors are then reported without them
+    int one = 1;
+    setsockopt(fd, SOL_NETLINK, NETLINK_EXT_ACK, &one, sizeof(one));
+
+    nlh->nlmsg_flags |= NLM_F_ACK;
+
+    struct sockaddr_nl addr = {.nl_family = AF_NETLINK};
+    struct iovec iov = {.iov_base = nlh, .iov_len = nlh->nlmsg_len};
+    struct msghdr msg = {
+        .msg_name = &addr, .msg_namelen = sizeof(addr), .msg_iov = &iov, .msg_iovlen = 1};
+
+    if (sendmsg(fd, &msg, 0) < 0) {
+        fail(LIBNETDEV_ERR_NETLINK_SEND_FAILED, errno);
+        close(fd);
+        return LIBNETDEV_ERR_NETLINK_SEND_FAILED;
+    }
+
+    char buf[4096];
+    ssize_t len = recv(fd, buf, sizeof(buf), 0);
+    if (len < 0) {
+        fail(LIBNETDEV_ERR_NETLINK_RECV_FAILED, errno);
+        close(fd);
+        return LIBNETDEV_ERR_NETLINK_RECV_FAILED;
+    }
+    close(fd);
+
+    for (struct nlmsghdr *h = (struct nlmsghdr *)buf; NLMSG_OK(h, len); h = NLMSG_NEXT(h, len)) {
+        if (h->nlmsg_type == NLMSG_ERROR) {
+            struct nlmsgerr *err = (struct nlmsgerr *)NLMSG_DATA(h);
+            *nl_errno = -err->error;
+            if (*nl_errno != 0) {
+                read_extack(h);
+            }
+            break;
+        }
+    }
+
+    return 0;
+}
+
+int build_route_msg(const char *device_name, const libnetdev_route *route, char *buf,
+                    size_t buf_size) {
+    char ip_buf[IP_NETMASK_STRLEN];
+    char prefix_buf[IP_PREFIX_MAXLEN];
+
+    int res = split_ip_and_prefix(route->dst, ip_buf, IP_NETMASK_STRLEN, prefix_buf,
+                                  IP_PREFIX_MAXLEN);
+    if (res != 0) {
+        return res;
+    }
+
+    bool is_ipv6 = strchr(ip_buf, ':') != NULL;
+    int family = is_ipv6 ? AF_INET6 : AF_INET;
+
+    struct in6_addr dst = {0};
+    if (inet_pton(family, ip_buf, &dst) != 1) {
+        return LIBNETDEV_ERR_INVALID_IP;
+    }
+
+    int prefix = atoi(prefix_buf);
+    if (prefix < 0 || prefix > (is_ipv6 ? 128 : 32)) {
+        return LIBNETDEV_ERR_INVALID_IP_PREFIX;
+    }
+
+    uint32_t if_index = if_nametoindex(device_name);
+    if (if_index == 0) {
+        return fail(LIBNETDEV_ERR_DEV_NOT_FOUND, errno);
+    }
+
+    memset(buf, 0, buf_size);
+
+    struct nlmsghdr *nlh = (struct nlmsghdr *)buf;
+    struct rtmsg *rtm = (struct rtmsg *)NLMSG_DATA(nlh);
+
+    nlh->nlmsg_len = NLMSG_LENGTH(sizeof(*rtm));
+    nlh->nlmsg_flags = NLM_F_REQUEST;
+    nlh->nlmsg_seq = 1;
+    nlh->nlmsg_pid = getpid();
+
+    uint32_t table = route->table ? route->table : RT_TABLE_MAIN;
+
+    rtm->rtm_family = family;
+    rtm->rtm_dst_len = prefix;
+    rtm->rtm_table = table < 256 ? table : RT_TABLE_UNSPEC;
+    rtm->rtm_type = RTN_UNICAST;
+
+    size_t addr_len = is_ipv6 ? sizeof(struct in6_addr) : sizeof(struct in_addr);
+    if (add_rtattr(nlh, buf_size, RTA_DST, &dst, addr_len) != 0 ||
+        add_rtattr(nlh, buf_size, RTA_OIF, &if_index, sizeof(if_index)) != 0 ||
+        add_rtattr(nlh, buf_size, RTA_TABLE, &table, sizeof(table)) != 0) {
+        return LIBNETDEV_ERR_NOMEM;
+    }
+
+    if (route->metric &&
+        add_rtattr(nlh, buf_size, RTA_PRIORITY, &route->metric, sizeof(route->metric)) != 0) {
+        return LIBNETDEV_ERR_NOMEM;
+    }
+
+    return 0;
+}
+
+// Returns the attribute of the given type of a route message, or NULL if it has none.
+static struct rtattr *route_attr(struct nlmsghdr *nlh, int type) {
+    int len = RTM_PAYLOAD(nlh);
+    for (struct rtattr *rta = RTM_RTA(NLMSG_DATA(nlh)); RTA_OK(rta, len);
+         rta = RTA_NEXT(rta, len)) {
+        if (rta->rta_type == type) {
+            return rta;
+        }
+    }
+    return NULL;
+}
+
+// Tells whether a dumped route has the destination, table and, if the request gives one, the
+// metric of the requested route. These are what the kernel compares to refuse a duplicate.
+static bool same_route(struct nlmsghdr *request, struct nlmsghdr *dumped) {
+    struct rtmsg *req = (struct rtmsg *)NLMSG_DATA(request);
+    struct rtmsg *rtm = (struct rtmsg *)NLMSG_DATA(dumped);
+    if (rtm->rtm_family != req->rtm_family || rtm->rtm_dst_len != req->rtm_dst_len) {
+        return false;
+    }
+
+    int types[] = {RTA_DST, RTA_TABLE, RTA_PRIORITY};
+    for (size_t i = 0; i < sizeof(types) / sizeof(types[0]); i++) {
+        struct rtattr *want = route_attr(request, types[i]);
+        if (!want) {
+            continue;
+        }
+
+        struct rtattr *have = route_attr(dumped, types[i]);
+        if (!have || RTA_PAYLOAD(have) != RTA_PAYLOAD(want) ||
+            memcmp(RTA_DATA(have), RTA_DATA(want), RTA_PAYLOAD(want)) != 0) {
+            return false;
+        }
+    }
+    return true;
+}
+
+// Looks through the routes of the requested route's family for one like it through the device
+// the request names.
+static int find_device_route(struct nlmsghdr *request, bool *found) {
+    *found = false;
+    struct rtattr *oif = route_attr(request, RTA_OIF);
+    if (!oif) {
+        return 0;
+    }
+
+    int fd = socket(AF_NETLINK, SOCK_RAW | SOCK_CLOEXEC, NETLINK_ROUTE);
+    if (fd < 0) {
+        return fail(LIBNETDEV_ERR_NETLINK_SOCKET_FAILED, errno);
+    }
+
+    struct {
+        struct nlmsghdr nlh;
+        struct rtmsg rtm;
+    } dump = {0};
+    dump.nlh.nlmsg_len = NLMSG_LENGTH(sizeof(dump.rtm));
+    dump.nlh.nlmsg_type = RTM_GETROUTE;
+    dump.nlh.nlmsg_flags = NLM_F_REQUEST | NLM_F_DUMP;
+    dump.nlh.nlmsg_seq = 1;
+    dump.nlh.nlmsg_pid = getpid();
+    dump.rtm.rtm_family = ((struct rtmsg *)NLMSG_DATA(request))->rtm_family;
+
+    struct sockaddr_nl addr = {.nl_family = AF_NETLINK};
+    if (sendto(fd, &dump, dump.nlh.nlmsg_len, 0, (struct sockaddr *)&addr, sizeof(addr)) < 0) {
+        fail(LIBNETDEV_ERR_NETLINK_SEND_FAILED, errno);
+        close(fd);
+        return LIBNETDEV_ERR_NETLINK_SEND_FAILED;
+    }
+
+    char buf[16384];
+    for (;;) {
+        ssize_t len = recv(fd, buf, sizeof(buf), 0);
+        if (len < 0) {
+            fail(LIBNETDEV_ERR_NETLINK_RECV_FAILED, errno);
+            close(fd);
+            return LIBNETDEV_ERR_NETLINK_RECV_FAILED;
+        }
+
+        for (struct nlmsghdr *h = (struct nlmsghdr *)buf; NLMSG_OK(h, len);
+             h = NLMSG_NEXT(h, len)) {
+            if (h->nlmsg_type == NLMSG_DONE) {
+                close(fd);
+                return 0;
+            }
+            if (h->nlmsg_type == NLMSG_ERROR) {
+                int err = -((struct nlmsgerr *)NLMSG_DATA(h))->error;
+                close(fd);
+                return err == 0 ? 0 : fail(LIBNETDEV_ERR_NETLINK_RECV_FAILED, err);
+            }
+            if (h->nlmsg_type != RTM_NEWROUTE || !same_route(request, h)) {
+                continue;
+            }
+
+            struct rtattr *dev = route_attr(h, RTA_OIF);
+            if (dev && *(uint32_t *)RTA_DATA(dev) == *(uint32_t *)RTA_DATA(oif)) {
+                *found = true;
+            }
+        }
+    }
+}
+
+int libnetdev_add_route(const char *device_name, const libnetdev_route *route) {
+    clear_error();
+    char buf[512];
+    int res = build_route_msg(device_name, route, buf, sizeof(buf));
+    if (res != 0) {
+        return res;
+    }
+
+    struct nlmsghdr *nlh = (struct nlmsghdr *)buf;
+    struct rtmsg *rtm = (struct rtmsg *)NLMSG_DATA(nlh);
+
+    nlh->nlmsg_type = RTM_NEWROUTE;
+    nlh->nlmsg_flags |= NLM_F_CREATE | NLM_F_EXCL;
+    rtm->rtm_protocol = LIBNETDEV_RTPROT;
+    rtm->rtm_scope = RT_SCOPE_LINK;
+
+    int nl_errno = 0;
+    res = netlink_request(nlh, &nl_errno);
+    if (res != 0) {
+        return res;
+    }
+
+    if (nl_errno != EEXIST) {
+        return nl_errno == 0 ? 0 : fail(LIBNETDEV_ERR_ROUTE_ADD_FAILED, nl_errno);
+    }
+
+    // like `ip route add`, an existing route is never taken over. One through the same device
+    // is what the caller asked for.
+    bool found = false;
+    res = find_device_route(nlh, &found);
+    if (res != 0) {
+        return res;
+    }
+    if (found) {
+        last_extack[0] = '\0';
+        return 0;
+    }
+
+    return fail(LIBNETDEV_ERR_ROUTE_EXISTS, EEXIST);
+}
+
+int libnetdev_del_route(const char *device_name, const libnetdev_route *route) {
+    clear_error();
+    char buf[512];
+    int res = build_route_msg(device_name, route, buf, sizeof(buf));
+    if (res != 0) {
+        return res;
+    }
+
+    struct nlmsghdr *nlh = (struct nlmsghdr *)buf;
+    struct rtmsg *rtm = (struct rtmsg *)NLMSG_DATA(nlh);
+
+    nlh->nlmsg_type = RTM_DELROUTE;
+    rtm->rtm_protocol = LIBNETDEV_RTPROT;
+    rtm->rtm_scope = RT_SCOPE_NOWHERE;
+
+    int nl_errno = 0;
+    res = netlink_request(nlh, &nl_errno);
+    if (res != 0) {
+        return res;
+    }
+
+    // a route that is already gone, or was not added by this library, is left as it is
+    if (nl_errno == 0 || nl_errno == ESRCH) {
+        last_extack[0] = '\0';
+        return 0;
+    }
+
+    return fail(LIBNETDEV_ERR_ROUTE_DEL_FAILED, nl_errno);
+}
+
+int build_rule_msg(const libnetdev_rule *rule, char *buf, size_t buf_size) {
+    memset(buf, 0, buf_size);
+
+    struct nlmsghdr *nlh = (struct nlmsghdr *)buf;
+    struct fib_rule_hdr *frh = (struct fib_rule_hdr *)NLMSG_DATA(nlh);
+
+    nlh->nlmsg_len = NLMSG_LENGTH(sizeof(*frh));
+    nlh->nlmsg_flags = NLM_F_REQUEST;
+    nlh->nlmsg_seq = 1;
+    nlh->nlmsg_pid = getpid();
+
+    uint32_t table = rule->table ? rule->table : RT_TABLE_MAIN;
+
+    frh->family = rule->ipv6 ? AF_INET6 : AF_INET;
+    frh->table = table < 256 ? table : RT_TABLE_UNSPEC;
+    frh->action = FR_ACT_TO_TBL;
+    if (rule->invert) {
+        frh->flags |= FIB_RULE_INVERT;
+    }
+
+    if (add_rtattr(nlh, buf_size, FRA_TABLE, &table, sizeof(table)) != 0) {
+        return LIBNETDEV_ERR_NOMEM;
+    }
+
+    if (rule->fwmark &&
+        add_rtattr(nlh, buf_size, FRA_FWMARK, &rule->fwmark, sizeof(rule->fwmark)) != 0) {
+        return LIBNETDEV_ERR_NOMEM;
+    }
+
+    if (rule->suppress_prefixlength >= 0 &&
+        add_rtattr(nlh, buf_size, FRA_SUPPRESS_PREFIXLEN, &rule->suppress_prefixlength,
+                   sizeof(rule->suppress_prefixlength)) != 0) {
+        return LIBNETDEV_ERR_NOMEM;
+    }
+
+    if (rule->priority &&
+        add_rtattr(nlh, buf_size, FRA_PRIORITY, &rule->priority, sizeof(rule->priority)) != 0) {
+        return LIBNETDEV_ERR_NOMEM;
+    }
+
+    return 0;
+}
+
+int libnetdev_add_rule(const libnetdev_rule *rule) {
+    clear_error();
+    char buf[256];
+    int res = build_rule_msg(rule, buf, sizeof(buf));
+    if (res != 0) {
+        return res;
+    }
+
+    struct nlmsghdr *nlh = (struct nlmsghdr *)buf;
+    nlh->nlmsg_type = RTM_NEWRULE;
+    nlh->nlmsg_flags |= NLM_F_CREATE | NLM_F_EXCL;
+
+    int nl_errno = 0;
+    res = netlink_request(nlh, &nl_errno);
+    if (res != 0) {
+        return res;
+    }
+
+    // an identical rule is shared, e.g. the suppress_prefixlength rule of full tunnels
+    if (nl_errno == 0 || nl_errno == EEXIST) {
+        last_extack[0] = '\0';
+        return 0;
+    }
+
+    return fail(LIBNETDEV_ERR_RULE_ADD_FAILED, nl_errno);
+}
+
+int libnetdev_del_rule(const libnetdev_rule *rule) {
+    clear_error();
+    char buf[256];
+    int res = build_rule_msg(rule, buf, sizeof(buf));
+    if (res != 0) {
+        return res;
+    }
+
+    struct nlmsghdr *nlh = (struct nlmsghdr *)buf;
+    nlh->nlmsg_type = RTM_DELRULE;
+
+    int nl_errno = 0;
+    res = netlink_request(nlh, &nl_errno);
+    if (res != 0) {
+        return res;
+    }
+
+    if (nl_errno == 0 || nl_errno == ENOENT) {
+        last_extack[0] = '\0';
+        return 0;
+    }
+
+    return fail(LIBNETDEV_ERR_RULE_DEL_FAILED, nl_errno);
+}
+
+int open_netns(const libnetdev_netns *netns) {
+    char path[PATH_MAX];
+
+    if (netns->name[0]) {
+        size_t len = strnlen(netns->name, sizeof(netns->name));
+        if (len == sizeof(netns->name) || strchr(netns->name, '/') ||
+            strcmp(netns->name, ".") == 0 || strcmp(netns->name, "..") == 0) {
+            return -LIBNETDEV_ERR_INVALID_NETNS;
+        }
+        snprintf(path, sizeof(path), "/var/run/netns/%s", netns->name);
+    } else if (netns->pid) {
+        snprintf(path, sizeof(path), "/proc/%u/ns/net", netns->pid);
+    } else {
+        return -LIBNETDEV_ERR_INVALID_NETNS;
+    }
+
+    int fd = open(path, O_RDONLY | O_CLOEXEC);
+    return fd < 0 ? -fail(LIBNETDEV_ERR_NETNS_OPEN_FAILED, errno) : fd;
+}
+
+int libnetdev_enter_netns(const libnetdev_netns *netns, int *saved_fd) {
+    clear_error();
+    *saved_fd = -1;
+
+    int fd = open_netns(netns);
+    if (fd < 0) {
+        return -fd;
+    }
+
+    int saved = open("/proc/thread-self/ns/net", O_RDONLY | O_CLOEXEC);
+    if (saved < 0) {
+        fail(LIBNETDEV_ERR_NETNS_OPEN_FAILED, errno);
+        close(fd);
+        return LIBNETDEV_ERR_NETNS_OPEN_FAILED;
+    }
+
+    if (setns(fd, CLONE_NEWNET) < 0) {
+        fail(LIBNETDEV_ERR_NETNS_ENTER_FAILED, errno);
+        close(saved);
+        close(fd);
+        return LIBNETDEV_ERR_NETNS_ENTER_FAILED;
+    }
+
+    close(fd);
+    *saved_fd = saved;
+    return 0;
+}
+
+int libnetdev_leave_netns(int saved_fd) {
+    clear_error();
+    int res = setns(saved_fd, CLONE_NEWNET);
+    if (res < 0) {
+        fail(LIBNETDEV_ERR_NETNS_ENTER_FAILED, errno);
+    }
+    close(saved_fd);
+    return res < 0 ? LIBNETDEV_ERR_NETNS_ENTER_FAILED : 0;
+}
+
+int libnetdev_set_netns(const char *device_name, const libnetdev_netns *netns) {
+    clear_error();
+    int if_index = if_nametoindex(device_name);
+    if (if_index == 0) {
+        return fail(LIBNETDEV_ERR_DEV_NOT_FOUND, errno);
+    }
+
+    int ns_fd = open_netns(netns);
+    if (ns_fd < 0) {
+        return -ns_fd;
+    }
+
+    char buf[256];
+    memset(buf, 0, sizeof(buf));
+
+    struct nlmsghdr *nlh = (struct nlmsghdr *)buf;
+    struct ifinfomsg *ifi = (struct ifinfomsg *)NLMSG_DATA(nlh);
+
+    nlh->nlmsg_len = NLMSG_LENGTH(sizeof(*ifi));
+    nlh->nlmsg_type = RTM_NEWLINK;
+    nlh->nlmsg_flags = NLM_F_REQUEST;
+    nlh->nlmsg_seq = 1;
+    nlh->nlmsg_pid = getpid();
+
+    ifi->ifi_family = AF_UNSPEC;
+    ifi->ifi_index = if_index;
+
+    uint32_t fd = ns_fd;
+    if (add_rtattr(nlh, sizeof(buf), IFLA_NET_NS_FD, &fd, sizeof(fd)) != 0) {
+        close(ns_fd);
+        return LIBNETDEV_ERR_NOMEM;
+    }
+
+    int nl_errno = 0;
+    int res = netlink_request(nlh, &nl_errno);
+    close(ns_fd);
+    if (res != 0) {
+        return res;
+    }
+
+    return nl_errno == 0 ? 0 : fail(LIBNETDEV_ERR_LINK_NETNS_FAILED, nl_errno);
+}
+
+int libnetdev_check(void) {
+    clear_error();
+    int fd = socket(AF_NETLINK, SOCK_RAW | SOCK_CLOEXEC, NETLINK_ROUTE);
+    if (fd < 0) {
+        return fail(LIBNETDEV_ERR_NETLINK_SOCKET_FAILED, errno);
+    }
+
+    struct sockaddr_nl addr = {.nl_family = AF_NETLINK};
+    if (bind(fd, (struct sockaddr *)&addr, sizeof(addr)) < 0) {
+        fail(LIBNETDEV_ERR_NETLINK_SOCKET_FAILED, errno);
+        close(fd);
+        return LIBNETDEV_ERR_NETLINK_SOCKET_FAILED;
+    }
+    close(fd);
+
+    return 0;
+}
+
 void libnetdev_free_ip(libnetdev_ip *ip) {
     if (!ip) {
         return;
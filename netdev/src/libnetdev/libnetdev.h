@@ -11,6 +11,8 @@
 #define LIBNETDEV_H
 
 #include <arpa/inet.h>
+#include <limits.h>
+#include <stdint.h>
 
 // Maximum length for ip prefix addition in CIDR notation. Since Ipv6 can have a prefix length of up
 // to 3 digits, we define a maximum length of 4 to accommodate the prefix and the null terminator.
@@ -19,6 +21,12 @@
 // Maximum length for an IPv4 or IPv6 address string in CIDR notation
 #define IP_NETMASK_STRLEN (INET6_ADDRSTRLEN + IP_PREFIX_MAXLEN + 1)
 
+// Maximum length for an extended ack message of the kernel, including the null terminator.
+#define LIBNETDEV_EXTACK_MAXLEN 256
+
+// Routing protocol of the routes added by this library, so that only they are ever deleted.
+#define LIBNETDEV_RTPROT 87
+
 /**
  * @brief Error codes returned by libnetdev functions.
  */
@@ -36,6 +44,16 @@ typedef enum {
     LIBNETDEV_ERR_DEV_NOT_FOUND,
     LIBNETDEV_ERR_NETLINK_SEND_FAILED,
     LIBNETDEV_ERR_GETIFADDRS_FAILED,
+    LIBNETDEV_ERR_NETLINK_RECV_FAILED,
+    LIBNETDEV_ERR_ROUTE_ADD_FAILED,
+    LIBNETDEV_ERR_ROUTE_DEL_FAILED,
+    LIBNETDEV_ERR_ROUTE_EXISTS,
+    LIBNETDEV_ERR_RULE_ADD_FAILED,
+    LIBNETDEV_ERR_RULE_DEL_FAILED,
+    LIBNETDEV_ERR_INVALID_NETNS,
+    LIBNETDEV_ERR_NETNS_OPEN_FAILED,
+    LIBNETDEV_ERR_NETNS_ENTER_FAILED,
+    LIBNETDEV_ERR_LINK_NETNS_FAILED,
 } libnetdev_error;
 
 /**
@@ -48,6 +66,45 @@ typedef struct libnetdev_ip {
     char ipv6_addr[IP_NETMASK_STRLEN];
 } libnetdev_ip;
 
+/**
+ * @brief Represents a route through a network device.
+ *
+ * The destination is given in CIDR notation. A table of 0 selects the main routing table and a
+ * metric of 0 leaves the kernel default in place.
+ */
+typedef struct libnetdev_route {
+    char dst[IP_NETMASK_STRLEN];
+    uint32_t table;
+    uint32_t metric;
+} libnetdev_route;
+
+/**
+ * @brief Represents a policy routing rule that looks up a routing table.
+ *
+ * A table of 0 selects the main routing table. A fwmark or priority of 0 and a negative
+ * suppress_prefixlength leave the respective selector out of the rule.
+ */
+typedef struct libnetdev_rule {
+    uint8_t ipv6;    // 1 for an IPv6 rule, 0 for IPv4
+    uint8_t invert;  // 1 to match packets that do not satisfy the selectors
+
+    uint32_t table;
+    uint32_t fwmark;
+    int32_t suppress_prefixlength;
+    uint32_t priority;
+} libnetdev_rule;
+
+/**
+ * @brief Identifies a network namespace.
+ *
+ * A non-empty name refers to a named namespace under /var/run/netns, as created by `ip netns add`.
+ * Otherwise the namespace of the process with the given pid is used.
+ */
+typedef struct libnetdev_netns {
+    char name[NAME_MAX + 1];
+    uint32_t pid;
+} libnetdev_netns;
+
 /**
  * @brief Retrieves the IP configuration for a given network device.
  *
@@ -78,6 +135,110 @@ int libnetdev_set_ip(const char *device_name, libnetdev_ip *ip);
  */
 int libnetdev_up(const char *device_name);
 
+/**
+ * @brief Adds a route to the given destination through a network device.
+ *
+ * The route is added with the LIBNETDEV_RTPROT protocol. An existing route with the same
+ * destination, table and metric is left in place: adding succeeds if it goes through the same
+ * device and fails with LIBNETDEV_ERR_ROUTE_EXISTS if it goes through another one.
+ *
+ * @param device_name Name of the network device (e.g., "eth0", "wg0")
+ * @param route Pointer to the libnetdev_route structure describing the route.
+ * @return 0 on success, non-zero on failure.
+ */
+int libnetdev_add_route(const char *device_name, const libnetdev_route *route);
+
+/**
+ * @brief Deletes a route to the given destination through a network device.
+ *
+ * Only a route added by libnetdev_add_route is deleted. Deleting a route that does not exist,
+ * or that was added by someone else, is not an error and leaves the routing table as it is.
+ *
+ * @param device_name Name of the network device (e.g., "eth0", "wg0")
+ * @param route Pointer to the libnetdev_route structure describing the route.
+ * @return 0 on success, non-zero on failure.
+ */
+int libnetdev_del_route(const char *device_name, const libnetdev_route *route);
+
+/**
+ * @brief Adds a policy routing rule.
+ *
+ * Adding a rule that already exists is not an error. The kernel only recognizes duplicates when
+ * the priority is given explicitly.
+ *
+ * @param rule Pointer to the libnetdev_rule structure describing the rule.
+ * @return 0 on success, non-zero on failure.
+ */
+int libnetdev_add_rule(const libnetdev_rule *rule);
+
+/**
+ * @brief Deletes a policy routing rule.
+ *
+ * Deleting a rule that does not exist is not an error.
+ *
+ * @param rule Pointer to the libnetdev_rule structure describing the rule.
+ * @return 0 on success, non-zero on failure.
+ */
+int libnetdev_del_rule(const libnetdev_rule *rule);
+
+/**
+ * @brief Moves the calling thread into a network namespace.
+ *
+ * Every function of this library, and any other netlink or socket call, made by the thread
+ * afterwards acts inside the namespace until libnetdev_leave_netns is called.
+ *
+ * @param netns Pointer to the libnetdev_netns structure identifying the namespace.
+ * @param saved_fd Output pointer to a descriptor of the thread's previous namespace.
+ * @return 0 on success, non-zero on failure.
+ */
+int libnetdev_enter_netns(const libnetdev_netns *netns, int *saved_fd);
+
+/**
+ * @brief Moves the calling thread back into the namespace saved by libnetdev_enter_netns.
+ *
+ * The descriptor is closed in any case.
+ *
+ * @param saved_fd Descriptor returned by libnetdev_enter_netns.
+ * @return 0 on success, non-zero on failure.
+ */
+int libnetdev_leave_netns(int saved_fd);
+
+/**
+ * @brief Moves a network device into a network namespace.
+ *
+ * Sockets the device has opened, such as the UDP socket of a WireGuard device, stay in the
+ * namespace they were created in. The device loses its addresses and is brought down.
+ *
+ * @param device_name Name of the network device (e.g., "eth0", "wg0")
+ * @param netns Pointer to the libnetdev_netns structure identifying the target namespace.
+ * @return 0 on success, non-zero on failure.
+ */
+int libnetdev_set_netns(const char *device_name, const libnetdev_netns *netns);
+
+/**
+ * @brief Checks that a routing netlink socket can be opened and bound.
+ *
+ * @return 0 on success, non-zero on failure.
+ */
+int libnetdev_check(void);
+
+/**
+ * @brief Returns the errno behind the last failed libnetdev call of the calling thread.
+ *
+ * @return errno of the failed system call or netlink request, 0 if the failure had no such cause.
+ */
+int libnetdev_last_errno(void);
+
+/**
+ * @brief Returns the extended ack message the kernel sent for the last failed libnetdev call of
+ * the calling thread.
+ *
+ * The string is owned by the library and valid until the next libnetdev call on the thread.
+ *
+ * @return Message of the kernel, empty if it sent none.
+ */
+const char *libnetdev_last_extack(void);
+
 /**
  * @brief Frees the memory allocated for a libnetdev_ip structure.
  *
@@ -46,13 +46,35 @@ fn set_dummy_device_ip(name: &str, ip: &str) {
         .expect("failed to set IP address");
 }
 
+fn get_rules() -> String {
+    let output = Command::new("ip")
+        .arg("rule")
+        .arg("show")
+        .output()
+        .expect("failed to get rules");
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn get_dummy_device_routes(name: &str) -> String {
+    let output = Command::new("ip")
+        .arg("route")
+        .arg("show")
+        .arg("dev")
+        .arg(name)
+        .arg("table")
+        .arg("all")
+        .output()
+        .expect("failed to get device routes");
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
 #[test]
 fn test_netdev_get_ip_non_existing_dev_returns_error() {
     let adapter = NetDevAdapter;
     let result = adapter.get_ip("non_existing_device");
     assert!(result.is_err());
     if let Err(err) = result {
-        assert_eq!(err.0, "device not found");
+        assert_eq!(err.message(), "device not found");
     }
 }
 
@@ -82,7 +104,7 @@ fn test_netdev_set_ip_invalid_ipv4_prefix() {
     );
     assert!(result.is_err());
     if let Err(err) = result {
-        assert_eq!(err.0, "invalid ip prefix length");
+        assert_eq!(err.message(), "invalid ip prefix length");
     }
     delete_dummy_device("test1");
 }
@@ -103,7 +125,7 @@ fn test_netdev_set_ip_invalid_ipv6_prefix() {
     );
     assert!(result.is_err());
     if let Err(err) = result {
-        assert_eq!(err.0, "invalid ip prefix length");
+        assert_eq!(err.message(), "invalid ip prefix length");
     }
     delete_dummy_device("test2");
 }
@@ -123,7 +145,7 @@ fn test_netdev_set_ip_failed() {
     assert!(result.is_err());
     if let Err(err) = result {
         // ioctl does not fail for same ip until netmask is set
-        assert_eq!(err.0, "failed to set device netmask");
+        assert_eq!(err.message(), "failed to set device netmask");
     }
 
     delete_dummy_device("test3");
@@ -165,3 +187,157 @@ fn test_netdev_up() {
     assert!(status.contains("UP"));
     delete_dummy_device("test5");
 }
+
+#[test]
+fn test_netdev_add_route_non_existing_dev_returns_error() {
+    let adapter = NetDevAdapter;
+    let route = NetDevRoute {
+        destination: (IpAddr::V4(Ipv4Addr::new(192, 168, 50, 0)), 24),
+        table: None,
+        metric: None,
+    };
+    let result = adapter.add_route("testz", &route);
+    assert!(result.is_err());
+    if let Err(err) = result {
+        assert_eq!(err.message(), "device not found");
+    }
+}
+
+#[test]
+fn test_netdev_add_and_delete_route() {
+    create_dummy_device("test6");
+    set_dummy_device_ip("test6", "10.1.0.1/24");
+    let adapter = NetDevAdapter;
+    assert!(adapter.up("test6").is_ok());
+
+    let route = NetDevRoute {
+        destination: (IpAddr::V4(Ipv4Addr::new(192, 168, 50, 0)), 24),
+        table: Some(1234),
+        metric: Some(100),
+    };
+    let result = adapter.add_route("test6", &route);
+    assert!(result.is_ok());
+    let routes = get_dummy_device_routes("test6");
+    assert!(routes.contains("192.168.50.0/24 table 1234"));
+    assert!(routes.contains("metric 100"));
+
+    let result = adapter.delete_route("test6", &route);
+    assert!(result.is_ok());
+    let routes = get_dummy_device_routes("test6");
+    assert!(!routes.contains("192.168.50.0/24"));
+
+    // deleting a missing route is not an error
+    let result = adapter.delete_route("test6", &route);
+    assert!(result.is_ok());
+
+    delete_dummy_device("test6");
+}
+
+#[test]
+fn test_netdev_add_route_keeps_routes_of_other_devices() {
+    create_dummy_device("test11");
+    create_dummy_device("test12");
+    let adapter = NetDevAdapter;
+    assert!(adapter.up("test11").is_ok());
+    assert!(adapter.up("test12").is_ok());
+    Command::new("ip")
+        .args([
+            "route",
+            "add",
+            "192.168.60.0/24",
+            "dev",
+            "test11",
+            "table",
+            "1235",
+        ])
+        .output()
+        .expect("failed to add route");
+
+    let route = NetDevRoute {
+        destination: (IpAddr::V4(Ipv4Addr::new(192, 168, 60, 0)), 24),
+        table: Some(1235),
+        metric: None,
+    };
+    let result = adapter.add_route("test12", &route);
+    assert!(result.is_err());
+    if let Err(err) = result {
+        assert_eq!(err.message(), "route exists through another device");
+    }
+
+    // the route through the same device is taken as it is, and is not ours to delete
+    assert!(adapter.add_route("test11", &route).is_ok());
+    assert!(adapter.delete_route("test11", &route).is_ok());
+    let routes = get_dummy_device_routes("test11");
+    assert!(routes.contains("192.168.60.0/24 table 1235"));
+
+    delete_dummy_device("test11");
+    delete_dummy_device("test12");
+}
+
+#[test]
+fn test_netdev_add_and_delete_rule() {
+    let adapter = NetDevAdapter;
+    let rule = NetDevRule {
+        family: IpFamily::V4,
+        table: 4321,
+        fwmark: Some(4321),
+        invert: true,
+        suppress_prefixlength: None,
+        priority: Some(32000),
+    };
+
+    let result = adapter.add_rule(&rule);
+    assert!(result.is_ok());
+    // adding the same rule again does not duplicate it
+    let result = adapter.add_rule(&rule);
+    assert!(result.is_ok());
+    let rules = get_rules();
+    assert_eq!(rules.matches("lookup 4321").count(), 1);
+    assert!(rules.contains("32000:\tnot from all fwmark 0x10e1 lookup 4321"));
+
+    let result = adapter.delete_rule(&rule);
+    assert!(result.is_ok());
+    assert!(!get_rules().contains("lookup 4321"));
+
+    // deleting a missing rule is not an error
+    let result = adapter.delete_rule(&rule);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_netdev_enter_netns_invalid_returns_error() {
+    let adapter = NetDevAdapter;
+
+    let result = adapter.enter_netns(&NetNs::Named("../net".to_string()));
+    assert_eq!(result.unwrap_err().message(), "invalid network namespace");
+
+    let result = adapter.enter_netns(&NetNs::Named("non_existing_netns".to_string()));
+    assert_eq!(result.unwrap_err().message(), "network namespace not found");
+}
+
+#[test]
+fn test_netdev_enter_and_leave_netns() {
+    let adapter = NetDevAdapter;
+
+    let handle = adapter.enter_netns(&NetNs::Pid(std::process::id()));
+    assert!(handle.is_ok());
+
+    let result = adapter.leave_netns(handle.unwrap());
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_netdev_set_netns_non_existing_dev_returns_error() {
+    let adapter = NetDevAdapter;
+
+    let result = adapter.set_netns("non_existing_device", &NetNs::Pid(std::process::id()));
+    assert_eq!(result.unwrap_err().message(), "device not found");
+}
+
+#[test]
+fn test_netdev_check() {
+    let adapter = NetDevAdapter;
+
+    let result = adapter.check();
+    assert!(result.is_ok());
+}
@@ -0,0 +1,276 @@
+use crate::config::Config;
+use crate::metrics::MetricsLabels;
+use crate::middleware::audit::AuditLog;
+use crate::middleware::auth::ApiKeys;
+use crate::middleware::peercred::SocketAccess;
+use crate::webhooks::Webhooks;
+use crate::{logging, preflight, tls, unix};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::path::{Path, PathBuf};
+
+/// wghttp - HTTP service to manage wireguard devices
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+pub struct Args {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    /// path to a toml config file; flags and WGHTTP_* environment variables override its keys
+    #[clap(short, long, env = "WGHTTP_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// path to unix socket. it is default unless you provide tcp socket
+    #[clap(
+        short,
+        long,
+        default_value = "/var/run/wghttp.sock",
+        env = "WGHTTP_UNIX"
+    )]
+    pub unix: String,
+
+    /// tcp socket to listen on (optional)
+    #[clap(short, long, env = "WGHTTP_TCP")]
+    pub tcp: Option<String>,
+
+    /// routing table for peer allowed ips routes (default: main)
+    #[clap(long, env = "WGHTTP_ROUTE_TABLE")]
+    pub route_table: Option<u32>,
+
+    /// metric for peer allowed ips routes (optional)
+    #[clap(long, env = "WGHTTP_ROUTE_METRIC")]
+    pub route_metric: Option<u32>,
+
+    /// enable masquerading, port forwarding and acls for peers through nftables
+    #[clap(long, alias = "nat", env = "WGHTTP_FIREWALL")]
+    pub firewall: bool,
+
+    /// path to a toml file with hashed api keys required on every request, reloaded on SIGHUP
+    #[clap(long, env = "WGHTTP_API_KEYS")]
+    pub api_keys: Option<PathBuf>,
+
+    /// path to a pem certificate chain to serve the tcp socket over tls, reloaded when renewed
+    #[clap(long, env = "WGHTTP_TLS_CERT")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// path to the pem private key of the tls certificate
+    #[clap(long, env = "WGHTTP_TLS_KEY")]
+    pub tls_key: Option<PathBuf>,
+
+    /// path to pem ca certificates; clients must present a certificate signed by one of them
+    #[clap(long, env = "WGHTTP_CLIENT_CA")]
+    pub client_ca: Option<PathBuf>,
+
+    /// mode of the unix socket file in octal, e.g. 660
+    #[clap(long, value_parser = unix::parse_mode, env = "WGHTTP_SOCKET_MODE")]
+    pub socket_mode: Option<u32>,
+
+    /// owner of the unix socket file, a user name or uid
+    #[clap(long, value_parser = unix::lookup_user, env = "WGHTTP_SOCKET_OWNER")]
+    pub socket_owner: Option<u32>,
+
+    /// group of the unix socket file, a group name or gid
+    #[clap(long, value_parser = unix::lookup_group, env = "WGHTTP_SOCKET_GROUP")]
+    pub socket_group: Option<u32>,
+
+    /// access of local users to the unix socket, e.g. group:wheel=full or uid:1000=read-only.
+    /// can be repeated; once given, users without a matching rule are denied
+    #[clap(long, value_delimiter = ',', env = "WGHTTP_SOCKET_ACCESS")]
+    pub socket_access: Vec<String>,
+
+    /// file to append an audit record of every state-changing request to, or "syslog"
+    #[clap(long, env = "WGHTTP_AUDIT_LOG")]
+    pub audit_log: Option<String>,
+
+    /// labels of the series at /metrics: device, public_key, route, status.
+    /// series differing only in a left out label are merged
+    #[clap(
+        long,
+        value_delimiter = ',',
+        default_value = "device,public_key,route,status",
+        env = "WGHTTP_METRICS_LABELS"
+    )]
+    pub metrics_labels: Vec<String>,
+
+    /// seconds between samples of the devices for handshake, roaming and out of band changes
+    /// published at /events; 0 disables sampling
+    #[clap(long, default_value_t = 1, env = "WGHTTP_EVENTS_INTERVAL")]
+    pub events_interval: u64,
+
+    /// toml file of the webhook subscribers and the directory of their delivery queue
+    #[clap(long, env = "WGHTTP_WEBHOOKS")]
+    pub webhooks: Option<String>,
+
+    /// format of the logs written to stderr: text or json
+    #[clap(long, default_value = "text", value_parser = logging::parse_format, env = "WGHTTP_LOG_FORMAT")]
+    pub log_format: logging::LogFormat,
+
+    /// level of the logs, e.g. info, or per-target directives such as info,wghttp=debug
+    #[clap(long, default_value = "info", env = "WGHTTP_LOG_LEVEL")]
+    pub log_level: String,
+
+    /// seconds the responses to requests with an Idempotency-Key are replayed for; 0 disables
+    /// idempotency keys
+    #[clap(long, default_value_t = 86400, env = "WGHTTP_IDEMPOTENCY_TTL")]
+    pub idempotency_ttl: u64,
+
+    /// require an If-Match header with the current ETag on changes to devices and peers
+    #[clap(long, env = "WGHTTP_REQUIRE_IF_MATCH")]
+    pub require_if_match: bool,
+
+    /// url of an OTLP/HTTP collector to export the spans of requests and library calls to,
+    /// e.g. http://127.0.0.1:4318
+    #[clap(long, env = "WGHTTP_OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<String>,
+
+    /// service name of the exported spans
+    #[clap(long, default_value = "wghttp", env = "WGHTTP_OTLP_SERVICE_NAME")]
+    pub otlp_service_name: String,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// print the kernel and module versions and the startup checks, then exit
+    Doctor,
+    /// manage the config file
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// validate the config file along with the flags and environment, without starting the server
+    Check {
+        /// path to the config file, --config if omitted
+        path: Option<PathBuf>,
+    },
+}
+
+impl Args {
+    pub fn is_unix(&self) -> bool {
+        self.tcp.is_none()
+    }
+
+    pub fn listen(&self) -> preflight::Listen<'_> {
+        match &self.tcp {
+            Some(tcp) => preflight::Listen::Tcp(tcp),
+            None => preflight::Listen::Unix(Path::new(&self.unix)),
+        }
+    }
+
+    pub fn unix_path(&self) -> String {
+        self.unix.clone()
+    }
+
+    /// Takes the settings given neither as a flag nor in the environment from the config file.
+    pub fn apply(&mut self, config: Config, matches: &ArgMatches) {
+        let unset = |id: &str| {
+            !matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        };
+        macro_rules! fill {
+            ($field:ident, $value:expr) => {
+                if let Some(value) = $value
+                    && unset(stringify!($field))
+                {
+                    self.$field = value;
+                }
+            };
+        }
+
+        fill!(unix, config.listen.unix);
+        fill!(tcp, config.listen.tcp.map(Some));
+        fill!(route_table, config.routes.table.map(Some));
+        fill!(route_metric, config.routes.metric.map(Some));
+        fill!(firewall, config.firewall);
+        fill!(api_keys, config.api_keys.map(Some));
+        fill!(tls_cert, config.tls.cert.map(Some));
+        fill!(tls_key, config.tls.key.map(Some));
+        fill!(client_ca, config.tls.client_ca.map(Some));
+        fill!(socket_mode, config.socket.mode.map(Some));
+        fill!(socket_owner, config.socket.owner.map(Some));
+        fill!(socket_group, config.socket.group.map(Some));
+        fill!(socket_access, config.socket.access);
+        fill!(audit_log, config.audit_log.map(Some));
+        fill!(metrics_labels, config.metrics.labels);
+        fill!(events_interval, config.events.interval);
+        fill!(webhooks, config.webhooks.map(Some));
+        fill!(log_format, config.logging.format);
+        fill!(log_level, config.logging.level);
+        fill!(idempotency_ttl, config.idempotency.ttl);
+        fill!(require_if_match, config.require_if_match);
+        fill!(otlp_endpoint, config.otlp.endpoint.map(Some));
+        fill!(otlp_service_name, config.otlp.service_name);
+    }
+
+    /// Checks the settings that depend on each other, wherever each of them came from.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.tls_cert.is_some() && (self.tcp.is_none() || self.tls_key.is_none()) {
+            return Err(
+                "tls.cert (--tls-cert) requires listen.tcp (--tcp) and tls.key (--tls-key)"
+                    .to_owned(),
+            );
+        }
+        if self.tls_cert.is_none() && (self.tls_key.is_some() || self.client_ca.is_some()) {
+            return Err(
+                "tls.key (--tls-key) and tls.client_ca (--client-ca) require tls.cert (--tls-cert)"
+                    .to_owned(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Parses the flags and the environment, filling in the rest from the config file.
+    pub fn load() -> Result<Self, String> {
+        Self::from_matches(&Self::command().get_matches())
+    }
+
+    /// Builds the settings from the parsed flags and environment and the config file they
+    /// point to, or the one given to `config check`.
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, String> {
+        let mut args = Self::from_arg_matches(matches).map_err(|e| e.to_string())?;
+
+        let path = match &args.command {
+            Some(Command::Config {
+                command: ConfigCommand::Check { path: Some(path) },
+            }) => Some(path.clone()),
+            _ => args.config.clone(),
+        };
+        if let Some(path) = path {
+            let config = Config::load(&path)?;
+            args.apply(config, matches);
+        }
+
+        args.validate()?;
+        Ok(args)
+    }
+
+    /// Loads the files the settings refer to as far as it goes without side effects.
+    pub fn check(&self) -> Result<(), String> {
+        if let Some(path) = &self.api_keys {
+            ApiKeys::load(path)?;
+        }
+        if let (Some(cert), Some(key)) = (&self.tls_cert, &self.tls_key) {
+            tls::server_config(cert, key, self.client_ca.as_deref())?;
+        }
+        if let Some(tcp) = &self.tcp {
+            preflight::parse_tcp_address(tcp)?;
+        }
+        if let Some(target) = &self.audit_log {
+            AuditLog::check_target(target)?;
+        }
+        if let Some(path) = &self.webhooks {
+            Webhooks::check_file(Path::new(path))?;
+        }
+        SocketAccess::parse(&self.socket_access)?;
+        MetricsLabels::parse(&self.metrics_labels)?;
+        logging::parse_filter(&self.log_level)?;
+
+        Ok(())
+    }
+}
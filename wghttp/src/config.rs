@@ -0,0 +1,209 @@
+use crate::logging::{self, LogFormat};
+use crate::metrics::MetricsLabels;
+use crate::middleware::peercred::SocketAccess;
+use crate::preflight::parse_tcp_address;
+use crate::unix;
+use serde::Deserialize;
+use serde::de::{self, Deserializer};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Settings of the daemon read from the file given with `--config`. Every key stands in for the
+/// flag of the same name; flags and `WGHTTP_*` environment variables take precedence over it.
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// enables masquerading, port forwarding and acls through nftables.
+    pub firewall: Option<bool>,
+
+    /// toml file of the hashed api keys.
+    pub api_keys: Option<PathBuf>,
+
+    /// file the audit records are appended to, or "syslog".
+    pub audit_log: Option<String>,
+
+    /// toml file of the webhook subscribers.
+    pub webhooks: Option<String>,
+
+    /// rejects changes to devices and peers without If-Match.
+    pub require_if_match: Option<bool>,
+
+    #[serde(default)]
+    pub listen: ListenConfig,
+
+    #[serde(default)]
+    pub socket: SocketConfig,
+
+    #[serde(default)]
+    pub tls: TlsConfig,
+
+    #[serde(default)]
+    pub routes: RoutesConfig,
+
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+
+    #[serde(default)]
+    pub events: EventsConfig,
+
+    #[serde(default)]
+    pub logging: LoggingConfig,
+
+    #[serde(default)]
+    pub idempotency: IdempotencyConfig,
+
+    #[serde(default)]
+    pub otlp: OtlpConfig,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ListenConfig {
+    pub unix: Option<String>,
+
+    /// ip:port to listen on instead of the unix socket.
+    #[serde(default, deserialize_with = "tcp_address")]
+    pub tcp: Option<String>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SocketConfig {
+    /// octal mode of the socket file, e.g. "660".
+    #[serde(default, deserialize_with = "socket_mode")]
+    pub mode: Option<u32>,
+
+    /// user name or uid owning the socket file.
+    #[serde(default, deserialize_with = "socket_owner")]
+    pub owner: Option<u32>,
+
+    /// group name or gid of the socket file.
+    #[serde(default, deserialize_with = "socket_group")]
+    pub group: Option<u32>,
+
+    /// access rules of local users, e.g. "group:wheel=full".
+    #[serde(default, deserialize_with = "socket_access")]
+    pub access: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub client_ca: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RoutesConfig {
+    pub table: Option<u32>,
+    pub metric: Option<u32>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    #[serde(default, deserialize_with = "metrics_labels")]
+    pub labels: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct EventsConfig {
+    /// seconds between samples of the devices, 0 disables sampling.
+    pub interval: Option<u64>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LoggingConfig {
+    #[serde(default, deserialize_with = "log_format")]
+    pub format: Option<LogFormat>,
+
+    #[serde(default, deserialize_with = "log_level")]
+    pub level: Option<String>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct IdempotencyConfig {
+    /// seconds responses are replayed for, 0 disables idempotency keys.
+    pub ttl: Option<u64>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct OtlpConfig {
+    pub endpoint: Option<String>,
+    pub service_name: Option<String>,
+}
+
+impl Config {
+    /// Parses the file; a value that fails validation is reported along with its key.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        toml::from_str(content).map_err(|e| format!("invalid config file: {}", e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+
+        Self::parse(&content)
+    }
+}
+
+// Runs the value through the parser its flag uses, so that both reject the same input.
+fn parsed<'de, D, T, U>(
+    deserializer: D,
+    parse: impl FnOnce(&T) -> Result<U, String>,
+) -> Result<Option<U>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer)?
+        .map(|value| parse(&value))
+        .transpose()
+        .map_err(de::Error::custom)
+}
+
+fn tcp_address<'de, D: Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    parsed(d, |tcp: &String| {
+        parse_tcp_address(tcp).map(|_| tcp.clone())
+    })
+}
+
+fn socket_mode<'de, D: Deserializer<'de>>(d: D) -> Result<Option<u32>, D::Error> {
+    parsed(d, |mode: &String| unix::parse_mode(mode))
+}
+
+fn socket_owner<'de, D: Deserializer<'de>>(d: D) -> Result<Option<u32>, D::Error> {
+    parsed(d, |owner: &String| unix::lookup_user(owner))
+}
+
+fn socket_group<'de, D: Deserializer<'de>>(d: D) -> Result<Option<u32>, D::Error> {
+    parsed(d, |group: &String| unix::lookup_group(group))
+}
+
+fn socket_access<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<String>>, D::Error> {
+    parsed(d, |specs: &Vec<String>| {
+        SocketAccess::parse(specs).map(|_| specs.clone())
+    })
+}
+
+fn metrics_labels<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<String>>, D::Error> {
+    parsed(d, |names: &Vec<String>| {
+        MetricsLabels::parse(names).map(|_| names.clone())
+    })
+}
+
+fn log_format<'de, D: Deserializer<'de>>(d: D) -> Result<Option<LogFormat>, D::Error> {
+    parsed(d, |format: &String| logging::parse_format(format))
+}
+
+fn log_level<'de, D: Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    parsed(d, |level: &String| {
+        logging::parse_filter(level).map(|_| level.clone())
+    })
+}
@@ -0,0 +1,130 @@
+use crate::models::errors::Error;
+use actix_web::dev::Payload;
+use actix_web::http::{StatusCode, header};
+use actix_web::{FromRequest, HttpRequest, HttpResponse, web};
+use domain::models::netdev::NetDevIp;
+use domain::models::wg::{WGDevice, WGPeer};
+use sha2::{Digest, Sha256};
+use std::future::{Ready, ready};
+
+/// Whether mutations of devices and peers must carry an `If-Match` header.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Preconditions {
+    pub required: bool,
+}
+
+fn etag(fields: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for field in fields {
+        hasher.update(field.as_bytes());
+        hasher.update(b"\0");
+    }
+    format!("\"{}\"", hex::encode(&hasher.finalize()[..16]))
+}
+
+/// ETag of a device, from its port, public key, fwmark and addresses. ETags are sent to callers
+/// that may not see secrets, so the private key is left out; the public key changes with it.
+pub fn device_etag(device: &WGDevice, ip: &NetDevIp) -> String {
+    etag(&[
+        &device.name,
+        &device.port.to_string(),
+        &device.public_key,
+        &device.fwmark.to_string(),
+        &ip.ipv4_str().unwrap_or_default(),
+        &ip.ipv6_str().unwrap_or_default(),
+    ])
+}
+
+/// ETag of a peer, from its public key, whether it has a preshared key, allowed ips and
+/// keepalive. The endpoint and the counters are left out, since they change without anyone
+/// editing the peer, and so is the preshared key itself.
+pub fn peer_etag(peer: &WGPeer) -> String {
+    let mut allowed_ips = peer.allowed_ips.clone();
+    allowed_ips.sort();
+    etag(&[
+        &peer.public_key,
+        if has_preshared_key(&peer.preshared_key) {
+            "psk"
+        } else {
+            ""
+        },
+        &allowed_ips.join(","),
+        &peer.persistent_keepalive_interval.to_string(),
+    ])
+}
+
+// WireGuard reports a peer without a preshared key as one of all zero bytes.
+fn has_preshared_key(key: &str) -> bool {
+    key.trim_end_matches('=').chars().any(|c| c != 'A')
+}
+
+pub enum PreconditionError {
+    Required,
+    Failed,
+}
+
+impl PreconditionError {
+    pub fn response(&self) -> HttpResponse {
+        match self {
+            PreconditionError::Required => HttpResponse::build(StatusCode::PRECONDITION_REQUIRED)
+                .json(Error::new(
+                    "precondition_required",
+                    "If-Match header is required",
+                )),
+            PreconditionError::Failed => HttpResponse::PreconditionFailed().json(Error::new(
+                "precondition_failed",
+                "the resource was changed since it was read",
+            )),
+        }
+    }
+}
+
+/// `If-Match` header of a mutation, and whether it is required.
+pub struct IfMatch {
+    tags: Option<String>,
+    required: bool,
+}
+
+impl IfMatch {
+    /// Whether the current state has to be read to check the request.
+    pub fn is_checked(&self) -> bool {
+        self.tags.is_some() || self.required
+    }
+
+    /// Checks the request against the ETag of the current state.
+    pub fn check(&self, current: &str) -> Result<(), PreconditionError> {
+        let Some(tags) = &self.tags else {
+            return match self.required {
+                true => Err(PreconditionError::Required),
+                false => Ok(()),
+            };
+        };
+
+        // weak tags never match, If-Match compares strongly.
+        if tags
+            .split(',')
+            .map(str::trim)
+            .any(|t| t == "*" || t == current)
+        {
+            return Ok(());
+        }
+        Err(PreconditionError::Failed)
+    }
+}
+
+impl FromRequest for IfMatch {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let tags = req
+            .headers()
+            .get(header::IF_MATCH)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned);
+        let required = req
+            .app_data::<web::Data<Preconditions>>()
+            .is_some_and(|p| p.required);
+        ready(Ok(IfMatch { tags, required }))
+    }
+}
@@ -0,0 +1,251 @@
+use crate::models::events::{Change, Event};
+use crate::services::TunnelManager;
+use domain::models::wg::{WGError, WGPeer};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+// events a slow subscriber may fall behind by before it starts missing them.
+const CAPACITY: usize = 256;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct PeerState {
+    allowed_ips: Vec<String>,
+    persistent_keepalive_interval: u16,
+    endpoint: String,
+    last_handshake_time: i64,
+}
+
+// devices by namespace and name, with their peers by public key.
+type Devices = HashMap<(Option<String>, String), HashMap<String, PeerState>>;
+
+/// Peers of the devices in the host namespace, `None` when they could not be listed.
+pub type Sample = Vec<(String, Option<Vec<WGPeer>>)>;
+
+#[derive(Default)]
+struct State {
+    devices: Devices,
+    sampled: bool,
+}
+
+/// Fans out device and peer changes to subscribers. Both the api and the sampler publish here;
+/// changes published by the api are recorded, so the sampler does not send them again.
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+    state: Mutex<State>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        EventBus {
+            sender,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    pub fn publish(&self, event: Event) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        apply(&mut state.devices, &event);
+        let _ = self.sender.send(event);
+    }
+
+    /// Publishes the changes between the known state and the devices of the host namespace.
+    /// The first sample only records the state. Devices without peers, as listing them failed,
+    /// keep their known state.
+    pub fn sample(&self, devices: Sample) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut events = vec![];
+        for (netns, name) in state.devices.keys() {
+            if netns.is_none() && !devices.iter().any(|(d, _)| d == name) {
+                events.push(Event::new(None, name, Change::DeviceDeleted));
+            }
+        }
+
+        for (device, peers) in &devices {
+            let Some(peers) = peers else {
+                continue;
+            };
+
+            let known = state.devices.get(&(None, device.clone()));
+            if known.is_none() {
+                events.push(Event::new(None, device, Change::DeviceCreated));
+            }
+            events.extend(diff_peers(device, known, peers));
+        }
+
+        let send = state.sampled;
+        state.sampled = true;
+        for event in events {
+            apply(&mut state.devices, &event);
+            if send {
+                let _ = self.sender.send(event);
+            }
+        }
+    }
+}
+
+fn diff_peers(
+    device: &str,
+    known: Option<&HashMap<String, PeerState>>,
+    peers: &[WGPeer],
+) -> Vec<Event> {
+    let mut events = vec![];
+    let empty = HashMap::new();
+    let known = known.unwrap_or(&empty);
+
+    for public_key in known.keys() {
+        if !peers.iter().any(|p| &p.public_key == public_key) {
+            let change = Change::PeerRemoved {
+                public_key: public_key.clone(),
+            };
+            events.push(Event::new(None, device, change));
+        }
+    }
+
+    for peer in peers {
+        let previous = known.get(&peer.public_key);
+        match previous {
+            None => events.push(Event::new(None, device, Change::peer_added(peer))),
+            Some(p)
+                if p.allowed_ips != peer.allowed_ips
+                    || p.persistent_keepalive_interval != peer.persistent_keepalive_interval =>
+            {
+                events.push(Event::new(None, device, Change::peer_updated(peer)));
+            }
+            Some(_) => {}
+        }
+
+        let previous = previous.cloned().unwrap_or_default();
+        if !peer.endpoint.is_empty() && previous.endpoint != peer.endpoint {
+            let change = Change::EndpointChanged {
+                public_key: peer.public_key.clone(),
+                endpoint: peer.endpoint.clone(),
+            };
+            events.push(Event::new(None, device, change));
+        }
+        if peer.last_handshake_time != 0 && previous.last_handshake_time != peer.last_handshake_time
+        {
+            let change = Change::Handshake {
+                public_key: peer.public_key.clone(),
+                last_handshake_time: peer.last_handshake_time,
+            };
+            events.push(Event::new(None, device, change));
+
+            if previous.last_handshake_time == 0 {
+                let change = Change::PeerConnected {
+                    public_key: peer.public_key.clone(),
+                    endpoint: peer.endpoint.clone(),
+                };
+                events.push(Event::new(None, device, change));
+            }
+        }
+    }
+
+    events
+}
+
+// Records the change in the state.
+fn apply(devices: &mut Devices, event: &Event) {
+    let key = (event.netns.clone(), event.device.clone());
+
+    match &event.change {
+        Change::DeviceCreated => {
+            devices.entry(key).or_default();
+        }
+        Change::DeviceDeleted => {
+            devices.remove(&key);
+        }
+        Change::PeerAdded {
+            public_key,
+            allowed_ips,
+            persistent_keepalive_interval,
+        }
+        | Change::PeerUpdated {
+            public_key,
+            allowed_ips,
+            persistent_keepalive_interval,
+        } => {
+            let peer = devices
+                .entry(key)
+                .or_default()
+                .entry(public_key.clone())
+                .or_default();
+            peer.allowed_ips = allowed_ips.clone();
+            peer.persistent_keepalive_interval = *persistent_keepalive_interval;
+        }
+        Change::PeerRemoved { public_key } => {
+            if let Some(peers) = devices.get_mut(&key) {
+                peers.remove(public_key);
+            }
+        }
+        Change::Handshake {
+            public_key,
+            last_handshake_time,
+        } => {
+            if let Some(peer) = devices.get_mut(&key).and_then(|p| p.get_mut(public_key)) {
+                peer.last_handshake_time = *last_handshake_time;
+            }
+        }
+        Change::PeerConnected { .. } => {}
+        Change::EndpointChanged {
+            public_key,
+            endpoint,
+        } => {
+            if let Some(peer) = devices.get_mut(&key).and_then(|p| p.get_mut(public_key)) {
+                peer.endpoint = endpoint.clone();
+            }
+        }
+    }
+}
+
+fn list_peers(manager: &TunnelManager) -> Result<Sample, WGError> {
+    let mut out = vec![];
+    for device in manager.wireguard.list_devices()? {
+        // only a device missing from the list is deleted, one deleted since it was listed shows
+        // up as deleted on the next sample.
+        let peers = match manager.wireguard.list_peers(&device.name) {
+            Ok(peers) => Some(peers),
+            Err(e) => {
+                tracing::warn!(
+                    device = device.name,
+                    "listing peers for events failed: {}",
+                    e
+                );
+                None
+            }
+        };
+        out.push((device.name, peers));
+    }
+
+    Ok(out)
+}
+
+/// Samples the devices of the host namespace every interval and publishes what changed, such as
+/// handshakes and roaming endpoints that no api request causes.
+pub async fn run_sampler(manager: TunnelManager, interval: Duration) {
+    let Some(bus) = manager.events.clone() else {
+        return;
+    };
+
+    let mut ticker = actix_web::rt::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        match list_peers(&manager) {
+            Ok(devices) => bus.sample(devices),
+            Err(e) => tracing::warn!("sampling devices for events failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use crate::middleware::audit::AuditLog;
+use crate::models::health::{Check, CheckStatus, Health};
+use crate::services::TunnelManager;
+use crate::webhooks::Webhooks;
+use std::fs;
+use std::time::Instant;
+
+/// Capability needed to manage devices, routes and nftables rules.
+pub const CAP_NET_ADMIN: u32 = 12;
+
+/// Effective capability set from the contents of `/proc/<pid>/status`.
+pub fn effective_capabilities(status: &str) -> Option<u64> {
+    let line = status.lines().find(|l| l.starts_with("CapEff:"))?;
+    u64::from_str_radix(line["CapEff:".len()..].trim(), 16).ok()
+}
+
+/// Checks that CAP_NET_ADMIN is in the effective capability set of the process.
+pub fn check_capabilities() -> Result<(), String> {
+    let status =
+        fs::read_to_string("/proc/self/status").map_err(|e| format!("/proc/self/status: {}", e))?;
+    let caps = effective_capabilities(&status)
+        .ok_or_else(|| "no effective capabilities in /proc/self/status".to_owned())?;
+
+    if caps & (1 << CAP_NET_ADMIN) == 0 {
+        return Err("CAP_NET_ADMIN is not effective".to_owned());
+    }
+
+    Ok(())
+}
+
+/// Runs the check and times it.
+pub fn check(name: &str, run: impl FnOnce() -> Result<(), String>) -> Check {
+    let start = Instant::now();
+    let result = run();
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    let (status, error) = match result {
+        Ok(()) => (CheckStatus::Ok, None),
+        Err(e) => (CheckStatus::Fail, Some(e)),
+    };
+    Check {
+        name: name.to_owned(),
+        status,
+        latency_ms,
+        error,
+    }
+}
+
+/// Runs the readiness checks: the wireguard generic netlink family resolves, a routing netlink
+/// socket opens, CAP_NET_ADMIN is effective, and the audit log and webhook queue, when
+/// configured, are writable.
+pub fn readiness(
+    manager: &TunnelManager,
+    audit_log: Option<&AuditLog>,
+    webhooks: Option<&Webhooks>,
+) -> Health {
+    let mut checks = vec![
+        check("wireguard", || {
+            manager.wireguard.check().map_err(|e| e.to_string())
+        }),
+        check("rtnetlink", || {
+            manager.netdev.check().map_err(|e| e.to_string())
+        }),
+        check("capabilities", check_capabilities),
+    ];
+    if let Some(audit_log) = audit_log {
+        checks.push(check("audit_log", || audit_log.check()));
+    }
+    if let Some(webhooks) = webhooks {
+        checks.push(check("webhook_queue", || webhooks.check()));
+    }
+
+    let status = if checks.iter().all(|c| c.status == CheckStatus::Ok) {
+        CheckStatus::Ok
+    } else {
+        CheckStatus::Fail
+    };
+    Health { status, checks }
+}
@@ -1,8 +1,10 @@
-use std::net::IpAddr;
+use domain::models::netdev::{NetDevIp, NetNs};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
 
 pub const DEVICE_NAME_MAX_LEN: usize = 15;
 pub const PUBKEY_MAX_LEN: usize = 44;
+pub const NETNS_NAME_MAX_LEN: usize = 255;
 
 pub fn parse_ip(input: &str) -> Result<(IpAddr, u8), String> {
     let (ip_str, prefix_str_opt) = input
@@ -38,3 +40,72 @@ pub fn validate_ip_list(ip_list: &[String]) -> Result<(), String> {
 
     Ok(())
 }
+
+pub fn network_addr(ip: IpAddr, prefix: u8) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask))
+        }
+        IpAddr::V6(v6) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask))
+        }
+    }
+}
+
+pub fn subnet_contains(subnet: (IpAddr, u8), ip: (IpAddr, u8)) -> bool {
+    if subnet.0.is_ipv4() != ip.0.is_ipv4() || ip.1 < subnet.1 {
+        return false;
+    }
+
+    network_addr(subnet.0, subnet.1) == network_addr(ip.0, subnet.1)
+}
+
+// Allowed ips that need a route through the device. Subnets already covered by the
+// device's own address are reachable through the connected route and default routes
+// are left to the full tunnel setup.
+pub fn routed_subnets(allowed_ips: &[String], device_ip: &NetDevIp) -> Vec<(IpAddr, u8)> {
+    let own: Vec<(IpAddr, u8)> = [
+        device_ip.ipv4.map(|(a, p)| (IpAddr::V4(a), p)),
+        device_ip.ipv6.map(|(a, p)| (IpAddr::V6(a), p)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    let mut subnets: Vec<(IpAddr, u8)> = vec![];
+    for (ip, prefix) in allowed_ips.iter().filter_map(|s| parse_ip(s).ok()) {
+        let subnet = (network_addr(ip, prefix), prefix);
+        if prefix == 0 || own.iter().any(|o| subnet_contains(*o, subnet)) {
+            continue;
+        }
+
+        if !subnets.contains(&subnet) {
+            subnets.push(subnet);
+        }
+    }
+
+    subnets
+}
+
+pub fn parse_netns(name: Option<&str>, pid: Option<u32>) -> Result<Option<NetNs>, String> {
+    match (name, pid) {
+        (None, None) => Ok(None),
+        (Some(_), Some(_)) => Err("netns and netns_pid are mutually exclusive".to_owned()),
+        (Some(name), None) => {
+            if name.is_empty()
+                || name.len() > NETNS_NAME_MAX_LEN
+                || name.contains('/')
+                || name == "."
+                || name == ".."
+            {
+                return Err(format!("invalid network namespace name: {}", name));
+            }
+
+            Ok(Some(NetNs::Named(name.to_owned())))
+        }
+        (None, Some(0)) => Err("netns_pid must be greater than 0".to_owned()),
+        (None, Some(pid)) => Ok(Some(NetNs::Pid(pid))),
+    }
+}
@@ -1,4 +1,17 @@
+pub mod cli;
+pub mod config;
+pub mod etag;
+pub mod events;
+pub mod health;
 pub mod helpers;
+pub mod logging;
+pub mod metrics;
+pub mod middleware;
 pub mod models;
+pub mod preflight;
 pub mod routes;
 pub mod services;
+pub mod telemetry;
+pub mod tls;
+pub mod unix;
+pub mod webhooks;
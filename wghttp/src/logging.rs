@@ -0,0 +1,52 @@
+use crate::telemetry;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, fmt};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+pub fn parse_format(format: &str) -> Result<LogFormat, String> {
+    match format {
+        "text" => Ok(LogFormat::Text),
+        "json" => Ok(LogFormat::Json),
+        _ => Err(format!(
+            "invalid log format: {}, expected text or json",
+            format
+        )),
+    }
+}
+
+/// Installs the subscriber writing logs to stderr, and exporting spans through the provider if
+/// given. The filter is a level such as `info`, or per-target directives such as
+/// `info,wghttp=debug`; it applies to the logs only.
+/// Parses a log level, or per-target directives such as `info,wghttp=debug`.
+pub fn parse_filter(filter: &str) -> Result<EnvFilter, String> {
+    EnvFilter::try_new(filter).map_err(|e| format!("invalid log level {}: {}", filter, e))
+}
+
+pub fn init(
+    format: LogFormat,
+    filter: &str,
+    provider: Option<&SdkTracerProvider>,
+) -> Result<(), String> {
+    let env_filter = parse_filter(filter)?;
+    let logs = match format {
+        LogFormat::Text => fmt::layer().with_writer(std::io::stderr).boxed(),
+        LogFormat::Json => fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_writer(std::io::stderr)
+            .boxed(),
+    };
+
+    tracing_subscriber::registry()
+        .with(logs.with_filter(env_filter))
+        .with(provider.map(telemetry::layer))
+        .try_init()
+        .map_err(|e| e.to_string())
+}
@@ -1,103 +1,203 @@
+use actix_web::middleware::from_fn;
+use actix_web::rt::signal::unix::{SignalKind, signal};
 use actix_web::{App, HttpServer, web};
-use clap::Parser;
-use utoipa::OpenApi;
-use utoipa_swagger_ui::SwaggerUi;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
+use wghttp::cli::{Args, Command, ConfigCommand};
+use wghttp::events::EventBus;
+use wghttp::metrics::{Metrics, MetricsLabels};
+use wghttp::middleware::audit::AuditLog;
+use wghttp::middleware::auth::Auth;
+use wghttp::middleware::idempotency::IdempotencyStore;
+use wghttp::middleware::peercred::SocketAccess;
+use wghttp::models::health::CheckStatus;
+use wghttp::webhooks::Webhooks;
 use wghttp::*;
 
+use firewall::NftAdapter;
 use netdev::NetDevAdapter;
 use wgshim::WGShimAdapter;
 
-/// wghttp - HTTP service to manage wireguard devices
-#[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
-struct Args {
-    /// path to unix socket. it is default unless you provide tcp socket
-    #[clap(short, long, default_value = "/var/run/wghttp.sock")]
-    unix: String,
-
-    /// tcp socket to listen on (optional)
-    #[clap(short, long)]
-    tcp: Option<String>,
-}
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    // logging is not set up before the config is read, so its errors go to stderr as they are.
+    let args = Args::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    if let Some(Command::Config {
+        command: ConfigCommand::Check { .. },
+    }) = args.command
+    {
+        if let Err(e) = args.check() {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        println!("config is valid");
+        return Ok(());
+    }
+
+    let tracer_provider = match &args.otlp_endpoint {
+        Some(endpoint) => Some(
+            telemetry::tracer_provider(endpoint, &args.otlp_service_name)
+                .map_err(std::io::Error::other)?,
+        ),
+        None => None,
+    };
+    logging::init(args.log_format, &args.log_level, tracer_provider.as_ref())
+        .map_err(std::io::Error::other)?;
+
+    let checks = preflight::preflight(&WGShimAdapter, &args.listen());
+    if let Some(Command::Doctor) = args.command {
+        let versions = [
+            ("wghttp", Some(env!("CARGO_PKG_VERSION").to_owned())),
+            ("kernel", preflight::kernel_version()),
+            ("wireguard", Some(preflight::wireguard_module_version())),
+        ];
+        print!("{}", preflight::render(&versions, &checks));
+        let healthy = checks.iter().all(|c| c.status == CheckStatus::Ok);
+        std::process::exit(if healthy { 0 } else { 1 });
+    }
+    let failed: Vec<_> = checks
+        .iter()
+        .filter(|c| c.status == CheckStatus::Fail)
+        .collect();
+    if !failed.is_empty() {
+        for c in failed {
+            tracing::error!("{}: {}", c.name, c.error.as_deref().unwrap_or_default());
+        }
+        return Err(std::io::Error::other(
+            "preflight checks failed, run `wghttp doctor` for details",
+        ));
+    }
 
-impl Args {
-    fn is_unix(&self) -> bool {
-        self.tcp.is_none()
+    let route_options = services::RouteOptions {
+        table: args.route_table,
+        metric: args.route_metric,
+    };
+    let mut tunnel_manager = services::TunnelManager::new(WGShimAdapter, NetDevAdapter)
+        .with_route_options(route_options);
+    if args.firewall {
+        tunnel_manager = tunnel_manager.with_firewall(NftAdapter);
     }
 
-    fn host_and_port(&self) -> Option<(String, u16)> {
-        if let Some(tcp) = &self.tcp {
-            let parts: Vec<&str> = tcp.split(':').collect();
-            if parts.len() == 2 {
-                if let Ok(port) = parts[1].parse::<u16>() {
-                    return Some((parts[0].to_string(), port));
+    let labels = MetricsLabels::parse(&args.metrics_labels).map_err(std::io::Error::other)?;
+    let metrics = Arc::new(Metrics::new(labels));
+    let event_bus = Arc::new(EventBus::new());
+    let tunnel_manager = tunnel_manager
+        .with_metrics(metrics.clone())
+        .with_events(event_bus.clone());
+    let metrics = web::Data::from(metrics);
+
+    let auth = match &args.api_keys {
+        Some(path) => Some(Auth::from_file(path).map_err(std::io::Error::other)?),
+        None => None,
+    };
+
+    if let Some(auth) = auth.clone() {
+        let mut hangup = signal(SignalKind::hangup())?;
+        actix_web::rt::spawn(async move {
+            while hangup.recv().await.is_some() {
+                if let Err(e) = auth.reload() {
+                    tracing::error!("api keys are not reloaded: {}", e);
                 }
             }
-        }
-        None
+        });
     }
 
-    fn unix_path(&self) -> String {
-        self.unix.clone()
+    if args.events_interval > 0 {
+        let interval = Duration::from_secs(args.events_interval);
+        actix_web::rt::spawn(events::run_sampler(tunnel_manager.clone(), interval));
     }
-}
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    let args = Args::parse();
-
-    #[derive(OpenApi)]
-    #[openapi(
-        info(
-            title = "wghttp",
-            description = "http service to manage wireguard devices",
-            version = "1.0.0",
-            license(name = "MIT")
-        ),
-        tags(
-            (name = "health", description = "health check endpoint."),
-            (name = "devices", description = "device management endpoints."),
-            (name = "peers", description = "peer management endpoints.")
-        ),
-        paths(
-            routes::health::health,
-            routes::devices::list_devices,
-            routes::devices::create_device,
-            routes::devices::get_device,
-            routes::devices::delete_device,
-            routes::peers::list_peers,
-            routes::peers::create_peer,
-            routes::peers::delete_peer,
-        )
-    )]
-    struct ApiDoc;
-
-    let tunnel_manager = services::TunnelManager::new(WGShimAdapter, NetDevAdapter);
+    let webhooks = match &args.webhooks {
+        Some(path) => Some(Arc::new(
+            Webhooks::load(Path::new(path)).map_err(std::io::Error::other)?,
+        )),
+        None => None,
+    };
+    if let Some(webhooks) = &webhooks {
+        wghttp::webhooks::run(webhooks.clone(), &event_bus);
+    }
+    let webhooks = webhooks.map(web::Data::from);
+
+    let socket_access = SocketAccess::parse(&args.socket_access).map_err(std::io::Error::other)?;
+
+    let audit_log = match args.audit_log.as_deref() {
+        None => None,
+        Some("syslog") => Some(AuditLog::syslog().map_err(std::io::Error::other)?),
+        Some(path) => Some(AuditLog::file(Path::new(path)).map_err(std::io::Error::other)?),
+    };
+    let audit_log = audit_log.map(web::Data::new);
+
+    let idempotency = match args.idempotency_ttl {
+        0 => None,
+        ttl => Some(web::Data::new(
+            IdempotencyStore::new(Duration::from_secs(ttl)).map_err(std::io::Error::other)?,
+        )),
+    };
+
+    let preconditions = etag::Preconditions {
+        required: args.require_if_match,
+    };
 
     let server = HttpServer::new(move || {
-        App::new()
+        let mut app = App::new()
+            .wrap(from_fn(middleware::audit::record_changes))
+            .wrap(from_fn(middleware::auth::require_api_key))
+            .wrap(from_fn(middleware::peercred::require_socket_access))
+            .wrap(from_fn(middleware::metrics::observe_requests))
+            .wrap(from_fn(middleware::request_id::trace_requests))
             .app_data(web::Data::new(tunnel_manager.clone()))
-            .service(routes::health::health)
-            .service(routes::devices::list_devices)
-            .service(routes::devices::create_device)
-            .service(routes::devices::get_device)
-            .service(routes::devices::delete_device)
-            .service(routes::peers::list_peers)
-            .service(routes::peers::create_peer)
-            .service(routes::peers::delete_peer)
-            .service(
-                SwaggerUi::new("/swagger-ui/{_:.*}")
-                    .url("/api-docs/openapi.json", ApiDoc::openapi()),
-            )
+            .app_data(metrics.clone())
+            .app_data(web::Data::new(socket_access.clone()))
+            .app_data(web::Data::new(preconditions));
+        if let Some(auth) = &auth {
+            app = app.app_data(web::Data::new(auth.clone()));
+        }
+        if let Some(audit_log) = &audit_log {
+            app = app.app_data(audit_log.clone());
+        }
+        if let Some(webhooks) = &webhooks {
+            app = app.app_data(webhooks.clone());
+        }
+        if let Some(idempotency) = &idempotency {
+            app = app.app_data(idempotency.clone());
+        }
+
+        app.configure(routes::configure)
     });
 
     let bound = if args.is_unix() {
-        server.bind_uds(&args.unix_path())
+        let options = unix::SocketOptions {
+            mode: args.socket_mode,
+            owner: args.socket_owner,
+            group: args.socket_group,
+        };
+        unix::bind(Path::new(&args.unix_path()), &options)
+            .and_then(|listener| server.on_connect(unix::on_connect).listen_uds(listener))
     } else {
-        let (host, port) = args.host_and_port().expect("invalid tcp socket");
-        server.bind((host.as_str(), port))
+        let tcp = args.tcp.as_deref().unwrap_or_default();
+        let addr = preflight::parse_tcp_address(tcp).map_err(std::io::Error::other)?;
+        match (&args.tls_cert, &args.tls_key) {
+            (Some(cert), Some(key)) => {
+                let config = tls::server_config(cert, key, args.client_ca.as_deref())
+                    .map_err(std::io::Error::other)?;
+                server
+                    .on_connect(tls::on_connect)
+                    .bind_rustls_0_23(addr, config)
+            }
+            _ => server.bind(addr),
+        }
     };
 
-    bound?.run().await
+    let result = bound?.run().await;
+    if let Some(provider) = tracer_provider
+        && let Err(e) = provider.shutdown()
+    {
+        tracing::error!("spans are not exported: {}", e);
+    }
+    result
 }
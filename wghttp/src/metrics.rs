@@ -0,0 +1,351 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Prometheus' default buckets, in seconds.
+const HTTP_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+// Calls into the C libraries are mostly a single netlink round trip.
+const FFI_BUCKETS: [f64; 11] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25,
+];
+
+/// Labels that may be left out of the exported series. Series differing only in a left out
+/// label are merged, which keeps the number of series down on hosts with many peers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MetricsLabels {
+    pub device: bool,
+    pub public_key: bool,
+    pub route: bool,
+    pub status: bool,
+}
+
+impl Default for MetricsLabels {
+    fn default() -> Self {
+        MetricsLabels {
+            device: true,
+            public_key: true,
+            route: true,
+            status: true,
+        }
+    }
+}
+
+impl MetricsLabels {
+    pub const NAMES: [&str; 4] = ["device", "public_key", "route", "status"];
+
+    /// Parses the names of the labels to export; labels that are not named are left out.
+    pub fn parse(names: &[String]) -> Result<Self, String> {
+        let mut labels = MetricsLabels {
+            device: false,
+            public_key: false,
+            route: false,
+            status: false,
+        };
+
+        for name in names.iter().map(|n| n.trim()).filter(|n| !n.is_empty()) {
+            match name {
+                "device" => labels.device = true,
+                "public_key" => labels.public_key = true,
+                "route" => labels.route = true,
+                "status" => labels.status = true,
+                _ => {
+                    return Err(format!(
+                        "unknown metrics label {}, expected one of {}",
+                        name,
+                        Self::NAMES.join(", ")
+                    ));
+                }
+            }
+        }
+
+        Ok(labels)
+    }
+}
+
+type LabelSet = Vec<(&'static str, String)>;
+
+struct Histogram {
+    buckets: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(buckets: &'static [f64]) -> Self {
+        Histogram {
+            buckets,
+            counts: vec![0; buckets.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, count) in self.buckets.iter().zip(self.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    http_requests: BTreeMap<LabelSet, Histogram>,
+    http_errors: BTreeMap<LabelSet, u64>,
+    ffi_calls: BTreeMap<LabelSet, Histogram>,
+    ffi_errors: BTreeMap<LabelSet, u64>,
+}
+
+/// Latencies and errors of the http requests and of the calls into the C libraries, kept in
+/// memory until the process exits.
+pub struct Metrics {
+    pub labels: MetricsLabels,
+    registry: Mutex<Registry>,
+}
+
+impl Metrics {
+    pub fn new(labels: MetricsLabels) -> Self {
+        Metrics {
+            labels,
+            registry: Mutex::new(Registry::default()),
+        }
+    }
+
+    /// Records a served request. The route is the pattern the request matched, not its path.
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let mut labels: LabelSet = vec![("method", method.to_owned())];
+        if self.labels.route {
+            labels.push(("route", route.to_owned()));
+        }
+        if self.labels.status {
+            labels.push(("status", status.to_string()));
+        }
+
+        let mut registry = self.registry.lock().unwrap_or_else(|e| e.into_inner());
+        if status >= 400 {
+            *registry.http_errors.entry(labels.clone()).or_default() += 1;
+        }
+        registry
+            .http_requests
+            .entry(labels)
+            .or_insert_with(|| Histogram::new(&HTTP_BUCKETS))
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Records a call of an adapter into one of the C libraries.
+    pub fn observe_call(&self, adapter: &str, operation: &str, failed: bool, elapsed: Duration) {
+        let labels: LabelSet = vec![
+            ("adapter", adapter.to_owned()),
+            ("operation", operation.to_owned()),
+        ];
+
+        let mut registry = self.registry.lock().unwrap_or_else(|e| e.into_inner());
+        if failed {
+            *registry.ffi_errors.entry(labels.clone()).or_default() += 1;
+        }
+        registry
+            .ffi_calls
+            .entry(labels)
+            .or_insert_with(|| Histogram::new(&FFI_BUCKETS))
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Writes the recorded metrics in the prometheus text format.
+    pub fn render(&self, out: &mut String) {
+        let registry = self.registry.lock().unwrap_or_else(|e| e.into_inner());
+
+        write_histograms(
+            out,
+            "wghttp_http_request_duration_seconds",
+            "Time taken to serve http requests.",
+            &registry.http_requests,
+        );
+        write_counters(
+            out,
+            "wghttp_http_request_errors_total",
+            "Http requests answered with a 4xx or 5xx status.",
+            &registry.http_errors,
+        );
+        write_histograms(
+            out,
+            "wghttp_ffi_call_duration_seconds",
+            "Time taken by calls into the wireguard, netdev and firewall libraries.",
+            &registry.ffi_calls,
+        );
+        write_counters(
+            out,
+            "wghttp_ffi_call_errors_total",
+            "Calls into the wireguard, netdev and firewall libraries that failed.",
+            &registry.ffi_errors,
+        );
+    }
+}
+
+/// Device and peer series, collected from wireguard on every scrape.
+#[derive(Default)]
+pub struct PeerMetrics {
+    device_peers: BTreeMap<LabelSet, u64>,
+    rx: BTreeMap<LabelSet, u64>,
+    tx: BTreeMap<LabelSet, u64>,
+    last_handshake: BTreeMap<LabelSet, i64>,
+}
+
+impl PeerMetrics {
+    pub fn add_device(&mut self, labels: &MetricsLabels, device: &str, peers: u64) {
+        let key = device_labels(labels, device);
+        *self.device_peers.entry(key).or_default() += peers;
+    }
+
+    /// Adds a peer; merged peers sum up their traffic and keep the latest handshake.
+    pub fn add_peer(
+        &mut self,
+        labels: &MetricsLabels,
+        device: &str,
+        public_key: &str,
+        rx: u64,
+        tx: u64,
+        last_handshake: i64,
+    ) {
+        let mut key = device_labels(labels, device);
+        if labels.public_key {
+            key.push(("public_key", public_key.to_owned()));
+        }
+
+        *self.rx.entry(key.clone()).or_default() += rx;
+        *self.tx.entry(key.clone()).or_default() += tx;
+        let handshake = self.last_handshake.entry(key).or_default();
+        *handshake = (*handshake).max(last_handshake);
+    }
+
+    /// Writes the series in the prometheus text format; `now` is in seconds since the epoch.
+    pub fn render(&self, out: &mut String, now: i64) {
+        write_gauges(
+            out,
+            "wghttp_device_peers",
+            "Peers configured on the device.",
+            self.device_peers.iter().map(|(k, v)| (k, *v as f64)),
+        );
+        write_counters(
+            out,
+            "wghttp_peer_receive_bytes_total",
+            "Bytes received from the peer.",
+            &self.rx,
+        );
+        write_counters(
+            out,
+            "wghttp_peer_transmit_bytes_total",
+            "Bytes sent to the peer.",
+            &self.tx,
+        );
+        write_gauges(
+            out,
+            "wghttp_peer_last_handshake_timestamp_seconds",
+            "Time of the latest handshake with the peer, 0 if there was none.",
+            self.last_handshake.iter().map(|(k, v)| (k, *v as f64)),
+        );
+        // peers without a handshake have no age, an absent series is easier to alert on.
+        write_gauges(
+            out,
+            "wghttp_peer_seconds_since_last_handshake",
+            "Seconds since the latest handshake with the peer.",
+            self.last_handshake
+                .iter()
+                .filter(|(_, v)| **v > 0)
+                .map(|(k, v)| (k, (now - *v).max(0) as f64)),
+        );
+    }
+}
+
+fn device_labels(labels: &MetricsLabels, device: &str) -> LabelSet {
+    match labels.device {
+        true => vec![("device", device.to_owned())],
+        false => vec![],
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('"', "\\\"")
+}
+
+fn format_labels(labels: &[(&str, String)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+fn write_gauges<'a>(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    series: impl Iterator<Item = (&'a LabelSet, f64)>,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    for (labels, value) in series {
+        let _ = writeln!(out, "{}{} {}", name, format_labels(labels), value);
+    }
+}
+
+fn write_counters(out: &mut String, name: &str, help: &str, series: &BTreeMap<LabelSet, u64>) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    for (labels, value) in series {
+        let _ = writeln!(out, "{}{} {}", name, format_labels(labels), value);
+    }
+}
+
+fn write_histograms(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    series: &BTreeMap<LabelSet, Histogram>,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} histogram", name);
+    for (labels, histogram) in series {
+        for (bound, count) in histogram.buckets.iter().zip(histogram.counts.iter()) {
+            let mut bucket = labels.clone();
+            bucket.push(("le", bound.to_string()));
+            let _ = writeln!(out, "{}_bucket{} {}", name, format_labels(&bucket), count);
+        }
+        let mut bucket = labels.clone();
+        bucket.push(("le", "+Inf".to_owned()));
+        let _ = writeln!(
+            out,
+            "{}_bucket{} {}",
+            name,
+            format_labels(&bucket),
+            histogram.count
+        );
+        let _ = writeln!(
+            out,
+            "{}_sum{} {}",
+            name,
+            format_labels(labels),
+            histogram.sum
+        );
+        let _ = writeln!(
+            out,
+            "{}_count{} {}",
+            name,
+            format_labels(labels),
+            histogram.count
+        );
+    }
+}
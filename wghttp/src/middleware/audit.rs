@@ -0,0 +1,270 @@
+use crate::middleware::auth::ApiKeyName;
+use crate::models::audit::{AuditOutcome, AuditQuery, AuditRecord};
+use crate::routes;
+use crate::tls::ClientCertificate;
+use crate::unix::PeerCredentials;
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, web};
+use serde_json::Value;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// authpriv facility, informational severity.
+const SYSLOG_PRIORITY: u8 = 10 * 8 + 6;
+const SYSLOG_SOCKET: &str = "/dev/log";
+
+enum Sink {
+    File(PathBuf, File),
+    Syslog(UnixDatagram),
+}
+
+/// Append-only log of state-changing requests, written as json lines to a file or to syslog.
+pub struct AuditLog {
+    sink: Mutex<Sink>,
+}
+
+impl AuditLog {
+    pub fn file(path: &Path) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .mode(0o600)
+            .open(path)
+            .map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+
+        Ok(AuditLog {
+            sink: Mutex::new(Sink::File(path.to_path_buf(), file)),
+        })
+    }
+
+    pub fn syslog() -> Result<Self, String> {
+        let socket = UnixDatagram::unbound()
+            .and_then(|s| s.connect(SYSLOG_SOCKET).map(|_| s))
+            .map_err(|e| format!("failed to connect to {}: {}", SYSLOG_SOCKET, e))?;
+
+        Ok(AuditLog {
+            sink: Mutex::new(Sink::Syslog(socket)),
+        })
+    }
+
+    /// Checks that records can be written to the target, a file or "syslog", without creating
+    /// the file.
+    pub fn check_target(target: &str) -> Result<(), String> {
+        if target == "syslog" {
+            return fs::metadata(SYSLOG_SOCKET)
+                .map(|_| ())
+                .map_err(|e| format!("{}: {}", SYSLOG_SOCKET, e));
+        }
+
+        let path = Path::new(target);
+        if path.exists() {
+            return OpenOptions::new()
+                .append(true)
+                .open(path)
+                .map(|_| ())
+                .map_err(|e| format!("{}: {}", path.display(), e));
+        }
+
+        match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() && !dir.is_dir() => {
+                Err(format!("{}: directory does not exist", dir.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn write(&self, record: &AuditRecord) -> Result<(), String> {
+        let line = serde_json::to_string(record).map_err(|e| e.to_string())?;
+        let mut sink = self.sink.lock().unwrap_or_else(|e| e.into_inner());
+
+        match &mut *sink {
+            Sink::File(_, file) => file
+                .write_all(format!("{}\n", line).as_bytes())
+                .map_err(|e| format!("failed to write audit record: {}", e)),
+            Sink::Syslog(socket) => {
+                let message = format!(
+                    "<{}>wghttp[{}]: {}",
+                    SYSLOG_PRIORITY,
+                    std::process::id(),
+                    line
+                );
+                socket
+                    .send(message.as_bytes())
+                    .map(|_| ())
+                    .map_err(|e| format!("failed to send audit record: {}", e))
+            }
+        }
+    }
+
+    /// Checks that records can still be written: the file is in place and writable, or the syslog
+    /// socket exists.
+    pub fn check(&self) -> Result<(), String> {
+        let path = match &*self.sink.lock().unwrap_or_else(|e| e.into_inner()) {
+            Sink::File(path, _) => path.clone(),
+            Sink::Syslog(_) => PathBuf::from(SYSLOG_SOCKET),
+        };
+
+        let metadata = fs::metadata(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if metadata.is_file() {
+            OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the records matching the query back from the file.
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditRecord>, String> {
+        let path = match &*self.sink.lock().unwrap_or_else(|e| e.into_inner()) {
+            Sink::File(path, _) => path.clone(),
+            Sink::Syslog(_) => return Err("audit log is written to syslog".to_owned()),
+        };
+
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+
+        let records = content
+            .lines()
+            .filter_map(|line| serde_json::from_str::<AuditRecord>(line).ok())
+            .filter(|r| query.since.is_none_or(|since| r.time >= since))
+            .filter(|r| query.until.is_none_or(|until| r.time <= until))
+            .filter(|r| query.device.is_none() || r.device == query.device)
+            .collect();
+
+        Ok(records)
+    }
+}
+
+/// Who made the request: the api key, the client certificate subject or the uid of the local user.
+pub fn caller(req: &ServiceRequest) -> String {
+    if let Some(name) = req.extensions().get::<ApiKeyName>() {
+        return format!("api-key:{}", name.0);
+    }
+
+    if let Some(cert) = req.conn_data::<ClientCertificate>() {
+        return format!("cert:{}", cert.subject);
+    }
+
+    match req.conn_data::<PeerCredentials>() {
+        Some(cred) => format!("uid:{}", cred.uid),
+        None => "anonymous".to_owned(),
+    }
+}
+
+fn source(req: &ServiceRequest) -> String {
+    if let Some(cred) = req.conn_data::<PeerCredentials>() {
+        return match cred.pid {
+            Some(pid) => format!("unix:{}", pid),
+            None => "unix".to_owned(),
+        };
+    }
+
+    req.peer_addr()
+        .map(|addr| addr.to_string())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+/// Records the requests that change state into the `AuditLog` registered as app data.
+pub async fn record_changes(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let Some(log) = req.app_data::<web::Data<AuditLog>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let caller = caller(&req);
+    let source = source(&req);
+    let fallback_operation = format!("{} {}", req.method(), req.path());
+
+    // a new device is only named in the request body.
+    let mut device = None;
+    if req.method() == Method::POST && routes::unversioned(req.path()) == "/devices" {
+        let bytes = req.extract::<web::Bytes>().await?;
+        device = serde_json::from_slice::<Value>(&bytes)
+            .ok()
+            .and_then(|v| v.get("device_name")?.as_str().map(str::to_owned));
+        req.set_payload(Payload::from(bytes));
+    }
+
+    let res = next.call(req).await?;
+    let operation = res
+        .request()
+        .match_name()
+        .map(str::to_owned)
+        .unwrap_or(fallback_operation);
+    let device = res
+        .request()
+        .match_info()
+        .get("dev")
+        .map(str::to_owned)
+        .or(device);
+    let mut peer = res
+        .request()
+        .match_info()
+        .get("public_key")
+        .map(str::to_owned);
+
+    let status = res.status();
+    let (http_req, http_res) = res.into_parts();
+    let (http_res, res_body) = http_res.into_parts();
+    let bytes = body::to_bytes(res_body)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e.into().to_string()))?;
+
+    let value = serde_json::from_slice::<Value>(&bytes).ok();
+    let field = |name: &str| {
+        value
+            .as_ref()
+            .and_then(|v| v.get(name)?.as_str().map(str::to_owned))
+    };
+
+    let outcome = if status.is_success() {
+        AuditOutcome::Success
+    } else {
+        AuditOutcome::Failure
+    };
+    let error = match outcome {
+        AuditOutcome::Failure => field("message"),
+        AuditOutcome::Success => None,
+    };
+    if operation == "create_peer" && outcome == AuditOutcome::Success {
+        peer = field("public_key");
+    }
+
+    let record = AuditRecord {
+        time,
+        caller,
+        source,
+        operation,
+        device,
+        peer,
+        status: status.as_u16(),
+        outcome,
+        error,
+    };
+    if let Err(e) = log.write(&record) {
+        tracing::error!("{}", e);
+    }
+
+    let http_res = http_res.set_body(bytes);
+    Ok(ServiceResponse::new(http_req, http_res).map_into_boxed_body())
+}
@@ -0,0 +1,233 @@
+use crate::middleware::rbac::{Permissions, Role};
+use crate::models::errors::Error;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, HttpResponse, web};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+fn default_public_health() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ApiKeysFile {
+    #[serde(default = "default_public_health")]
+    public_health: bool,
+
+    #[serde(default)]
+    public_docs: bool,
+
+    #[serde(default)]
+    keys: Vec<ApiKeyEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ApiKeyEntry {
+    name: String,
+    sha256: String,
+
+    #[serde(default)]
+    role: Option<Role>,
+
+    #[serde(default)]
+    devices: Option<Vec<String>>,
+
+    #[serde(default)]
+    secrets: Option<bool>,
+}
+
+/// Name of the api key a request was authenticated with, stored in the request extensions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApiKeyName(pub String);
+
+#[derive(Debug)]
+pub struct ApiKey {
+    pub name: String,
+    pub permissions: Permissions,
+    digest: [u8; 32],
+}
+
+/// Api keys by name, stored as sha256 digests of the keys.
+#[derive(Debug)]
+pub struct ApiKeys {
+    keys: Vec<ApiKey>,
+    public_health: bool,
+    public_docs: bool,
+}
+
+impl ApiKeys {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let file: ApiKeysFile =
+            toml::from_str(content).map_err(|e| format!("invalid api keys file: {}", e))?;
+
+        let mut keys: Vec<ApiKey> = vec![];
+        for entry in file.keys {
+            if entry.name.is_empty() {
+                return Err("api key name must not be empty".to_owned());
+            }
+
+            if keys.iter().any(|k| k.name == entry.name) {
+                return Err(format!("duplicate api key name: {}", entry.name));
+            }
+
+            let mut digest = [0u8; 32];
+            hex::decode_to_slice(&entry.sha256, &mut digest)
+                .map_err(|_| format!("invalid sha256 digest for api key {}", entry.name))?;
+
+            // keys without a role keep the full access they had before roles existed.
+            let role = entry.role.unwrap_or(Role::Admin);
+            let devices = entry.devices.unwrap_or_else(|| vec!["*".to_owned()]);
+            if devices.iter().any(|d| d.is_empty()) {
+                return Err(format!("empty device pattern for api key {}", entry.name));
+            }
+
+            keys.push(ApiKey {
+                permissions: Permissions {
+                    role,
+                    devices,
+                    secrets: entry.secrets.unwrap_or(role == Role::Admin),
+                },
+                name: entry.name,
+                digest,
+            });
+        }
+
+        Ok(ApiKeys {
+            keys,
+            public_health: file.public_health,
+            public_docs: file.public_docs,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+
+        Self::parse(&content)
+    }
+
+    /// Returns the configured key matching the given one.
+    pub fn authenticate(&self, key: &str) -> Option<&ApiKey> {
+        let digest: [u8; 32] = Sha256::digest(key.as_bytes()).into();
+
+        // every digest is compared in full, so the timing does not reveal a partial match.
+        let mut found = None;
+        for api_key in &self.keys {
+            let diff = api_key
+                .digest
+                .iter()
+                .zip(digest.iter())
+                .fold(0u8, |acc, (a, b)| acc | (a ^ b));
+            if diff == 0 {
+                found = Some(api_key);
+            }
+        }
+
+        found
+    }
+
+    pub fn is_public(&self, path: &str) -> bool {
+        (self.public_health && (path == "/" || path == "/healthz" || path == "/readyz"))
+            || (self.public_docs
+                && (path.starts_with("/swagger-ui/") || path.starts_with("/api-docs/")))
+    }
+}
+
+/// Shared api keys, reloadable from the file they were loaded from.
+#[derive(Clone)]
+pub struct Auth {
+    path: Option<PathBuf>,
+    keys: Arc<RwLock<ApiKeys>>,
+}
+
+impl Auth {
+    pub fn new(keys: ApiKeys) -> Self {
+        Auth {
+            path: None,
+            keys: Arc::new(RwLock::new(keys)),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let keys = ApiKeys::load(path)?;
+
+        Ok(Auth {
+            path: Some(path.to_path_buf()),
+            keys: Arc::new(RwLock::new(keys)),
+        })
+    }
+
+    /// Replaces the keys with the current content of the file; on error the old keys are kept.
+    pub fn reload(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let keys = ApiKeys::load(path)?;
+        let mut current = self.keys.write().map_err(|_| "api keys lock is poisoned")?;
+        *current = keys;
+
+        Ok(())
+    }
+
+    fn authorize(&self, req: &ServiceRequest) -> Result<Option<(String, Permissions)>, ()> {
+        let Ok(keys) = self.keys.read() else {
+            return Err(());
+        };
+
+        if keys.is_public(req.path()) {
+            return Ok(None);
+        }
+
+        let headers = req.headers();
+        let bearer = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        let key = bearer.or_else(|| headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok()));
+
+        match key.and_then(|k| keys.authenticate(k.trim())) {
+            Some(k) => Ok(Some((k.name.clone(), k.permissions.clone()))),
+            None => Err(()),
+        }
+    }
+}
+
+/// Rejects requests without a valid api key when an `Auth` is registered as app data. Without
+/// one, requests get all permissions unless the socket access already limited them.
+pub async fn require_api_key<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let Some(auth) = req.app_data::<web::Data<Auth>>().cloned() else {
+        if req.extensions().get::<Permissions>().is_none() {
+            req.extensions_mut().insert(Permissions::all());
+        }
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+
+    match auth.authorize(&req) {
+        Ok(Some((name, permissions))) => {
+            req.extensions_mut().insert(ApiKeyName(name));
+            req.extensions_mut().insert(permissions);
+            Ok(next.call(req).await?.map_into_left_body())
+        }
+        Ok(None) => Ok(next.call(req).await?.map_into_left_body()),
+        Err(()) => {
+            let resp = HttpResponse::Unauthorized()
+                .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+                .json(Error::new("unauthorized", "missing or invalid api key"));
+            Ok(req.into_response(resp).map_into_right_body())
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use crate::routes::v1;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+
+/// When the routes at the root were deprecated in favour of `/v1`, as an RFC 9745 date.
+pub const DEPRECATION: &str = "@1792368000";
+
+/// Date after which the routes at the root may be removed, as an RFC 8594 http date.
+pub const SUNSET: &str = "Tue, 19 Oct 2027 00:00:00 GMT";
+
+/// Flags responses of the deprecated routes at the root, linking to the same route under `/v1`.
+pub async fn mark_deprecated<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, actix_web::Error> {
+    let successor = format!("<{}{}>; rel=\"successor-version\"", v1::PREFIX, req.path());
+    let mut res = next.call(req).await?;
+
+    // paths matching no route are not aliases of anything.
+    if res.request().match_pattern().is_none() {
+        return Ok(res);
+    }
+
+    let headers = res.headers_mut();
+    headers.insert(
+        HeaderName::from_static("deprecation"),
+        HeaderValue::from_static(DEPRECATION),
+    );
+    headers.insert(
+        HeaderName::from_static("sunset"),
+        HeaderValue::from_static(SUNSET),
+    );
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert(HeaderName::from_static("link"), link);
+    }
+
+    Ok(res)
+}
@@ -0,0 +1,287 @@
+use crate::middleware::audit::caller;
+use crate::models::errors::Error;
+use crate::routes;
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{HttpResponse, web};
+use ring::aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+pub const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+// longest idempotency key taken from a client.
+const MAX_KEY_LEN: usize = 255;
+
+// keys are scoped to the caller, so that callers cannot replay each other's responses.
+type Id = (String, String);
+
+struct Sealed {
+    status: StatusCode,
+    content_type: Option<HeaderValue>,
+    nonce: [u8; NONCE_LEN],
+    body: Vec<u8>,
+}
+
+enum Entry {
+    InFlight {
+        fingerprint: String,
+    },
+    Done {
+        fingerprint: String,
+        expires: Instant,
+        response: Sealed,
+    },
+}
+
+enum Reservation {
+    Reserved,
+    Replay(HttpResponse),
+    InProgress,
+    Mismatch,
+}
+
+/// Responses to requests sent with an `Idempotency-Key`, kept to be replayed until they expire.
+/// Bodies hold private keys, so they are encrypted with a key that never leaves the process.
+pub struct IdempotencyStore {
+    ttl: Duration,
+    key: LessSafeKey,
+    rng: SystemRandom,
+    entries: Mutex<HashMap<Id, Entry>>,
+}
+
+impl IdempotencyStore {
+    pub fn new(ttl: Duration) -> Result<Self, String> {
+        let rng = SystemRandom::new();
+        let mut key = [0u8; 32];
+        rng.fill(&mut key)
+            .map_err(|_| "failed to generate idempotency store key")?;
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &key)
+            .map_err(|_| "invalid idempotency store key")?;
+
+        Ok(IdempotencyStore {
+            ttl,
+            key: LessSafeKey::new(key),
+            rng,
+            entries: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn seal(
+        &self,
+        fingerprint: &str,
+        status: StatusCode,
+        content_type: Option<HeaderValue>,
+        body: &[u8],
+    ) -> Result<Sealed, String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| "failed to generate nonce")?;
+        let mut body = body.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(fingerprint.as_bytes()),
+                &mut body,
+            )
+            .map_err(|_| "failed to encrypt response")?;
+
+        Ok(Sealed {
+            status,
+            content_type,
+            nonce,
+            body,
+        })
+    }
+
+    fn open(&self, fingerprint: &str, sealed: &Sealed) -> Result<HttpResponse, String> {
+        let mut body = sealed.body.clone();
+        let plain = self
+            .key
+            .open_in_place(
+                Nonce::assume_unique_for_key(sealed.nonce),
+                Aad::from(fingerprint.as_bytes()),
+                &mut body,
+            )
+            .map_err(|_| "failed to decrypt stored response")?;
+
+        let mut res = HttpResponse::build(sealed.status);
+        if let Some(content_type) = &sealed.content_type {
+            res.insert_header((header::CONTENT_TYPE, content_type.clone()));
+        }
+        res.insert_header((REPLAYED_HEADER, "true"));
+        Ok(res.body(plain.to_vec()))
+    }
+
+    fn reserve(&self, id: &Id, fingerprint: &str) -> Result<Reservation, String> {
+        let mut entries = self
+            .entries
+            .lock()
+            .map_err(|_| "idempotency store lock is poisoned")?;
+        let now = Instant::now();
+        entries.retain(|_, e| !matches!(e, Entry::Done { expires, .. } if *expires <= now));
+
+        let reservation = match entries.get(id) {
+            None => Reservation::Reserved,
+            Some(Entry::InFlight { fingerprint: f } | Entry::Done { fingerprint: f, .. })
+                if f != fingerprint =>
+            {
+                Reservation::Mismatch
+            }
+            Some(Entry::InFlight { .. }) => Reservation::InProgress,
+            Some(Entry::Done { response, .. }) => {
+                Reservation::Replay(self.open(fingerprint, response)?)
+            }
+        };
+        if let Reservation::Reserved = reservation {
+            entries.insert(
+                id.clone(),
+                Entry::InFlight {
+                    fingerprint: fingerprint.to_owned(),
+                },
+            );
+        }
+
+        Ok(reservation)
+    }
+
+    fn complete(&self, id: Id, fingerprint: String, response: Sealed) {
+        if let Ok(mut entries) = self.entries.lock() {
+            let expires = Instant::now() + self.ttl;
+            entries.insert(
+                id,
+                Entry::Done {
+                    fingerprint,
+                    expires,
+                    response,
+                },
+            );
+        }
+    }
+
+    fn release(&self, id: &Id) {
+        if let Ok(mut entries) = self.entries.lock()
+            && let Some(Entry::InFlight { .. }) = entries.get(id)
+        {
+            entries.remove(id);
+        }
+    }
+}
+
+// Frees the key of a request that did not complete, e.g. when the client went away, so that it
+// can be retried.
+struct InFlight {
+    store: web::Data<IdempotencyStore>,
+    id: Id,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.store.release(&self.id);
+    }
+}
+
+fn is_valid(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_LEN && key.bytes().all(|b| b.is_ascii_graphic())
+}
+
+// The route is taken without its version, so that an alias and its versioned route agree.
+fn fingerprint(req: &ServiceRequest, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(req.method().as_str().as_bytes());
+    hasher.update(b"\0");
+    hasher.update(routes::unversioned(req.path()).as_bytes());
+    hasher.update(b"\0");
+    hasher.update(req.query_string().as_bytes());
+    hasher.update(b"\0");
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+/// Replays the stored response to a request retried with the same `Idempotency-Key` and body,
+/// rather than running it again. A key reused with another request is rejected with `422`, and
+/// one whose request is still running with `409`. Server errors are not stored, so that they can
+/// be retried.
+pub async fn replay_responses(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let Some(store) = req.app_data::<web::Data<IdempotencyStore>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let Some(key) = req.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let Some(key) = key.to_str().ok().filter(|k| is_valid(k)).map(str::to_owned) else {
+        let res = HttpResponse::BadRequest().json(Error::invalid(
+            "idempotency key must be 1 to 255 printable ascii characters",
+        ));
+        return Ok(req.into_response(res).map_into_boxed_body());
+    };
+
+    let bytes = req.extract::<web::Bytes>().await?;
+    let fingerprint = fingerprint(&req, &bytes);
+    req.set_payload(Payload::from(bytes));
+    let id = (caller(&req), key);
+
+    let res = match store.reserve(&id, &fingerprint) {
+        Err(e) => HttpResponse::InternalServerError().json(Error::new("system_error", e)),
+        Ok(Reservation::Replay(res)) => res,
+        Ok(Reservation::InProgress) => HttpResponse::Conflict().json(Error::new(
+            "idempotency_key_in_use",
+            "a request with this idempotency key is in progress",
+        )),
+        Ok(Reservation::Mismatch) => HttpResponse::UnprocessableEntity().json(Error::new(
+            "idempotency_key_reused",
+            "idempotency key was used with a different request",
+        )),
+        Ok(Reservation::Reserved) => {
+            let in_flight = InFlight {
+                store: store.clone(),
+                id,
+            };
+            return run(req, next, in_flight, fingerprint).await;
+        }
+    };
+
+    Ok(req.into_response(res).map_into_boxed_body())
+}
+
+async fn run(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+    in_flight: InFlight,
+    fingerprint: String,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let res = next.call(req).await?;
+    let status = res.status();
+    let content_type = res.headers().get(header::CONTENT_TYPE).cloned();
+    let (http_req, http_res) = res.into_parts();
+    let (http_res, res_body) = http_res.into_parts();
+    let bytes = body::to_bytes(res_body)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e.into().to_string()))?;
+
+    if !status.is_server_error() {
+        match in_flight
+            .store
+            .seal(&fingerprint, status, content_type, &bytes)
+        {
+            Ok(sealed) => in_flight
+                .store
+                .complete(in_flight.id.clone(), fingerprint, sealed),
+            Err(e) => tracing::error!("response is not stored for replays: {}", e),
+        }
+    }
+
+    let http_res = http_res.set_body(bytes);
+    Ok(ServiceResponse::new(http_req, http_res).map_into_boxed_body())
+}
@@ -0,0 +1,39 @@
+use crate::metrics::Metrics;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::web;
+use std::time::Instant;
+
+/// Records the latency and status of every request into the `Metrics` registered as app data.
+pub async fn observe_requests<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, actix_web::Error> {
+    let Some(metrics) = req.app_data::<web::Data<Metrics>>().cloned() else {
+        return next.call(req).await;
+    };
+
+    let start = Instant::now();
+    let method = match *req.method() {
+        Method::GET
+        | Method::HEAD
+        | Method::POST
+        | Method::PUT
+        | Method::PATCH
+        | Method::DELETE
+        | Method::OPTIONS => req.method().to_string(),
+        _ => "other".to_owned(),
+    };
+    let res = next.call(req).await?;
+
+    // unmatched paths share a label, so that scanners cannot add series.
+    let route = res
+        .request()
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_owned());
+    metrics.observe_request(&method, &route, res.status().as_u16(), start.elapsed());
+
+    Ok(res)
+}
@@ -0,0 +1,8 @@
+pub mod audit;
+pub mod auth;
+pub mod deprecation;
+pub mod idempotency;
+pub mod metrics;
+pub mod peercred;
+pub mod rbac;
+pub mod request_id;
@@ -0,0 +1,117 @@
+use crate::middleware::rbac::{Permissions, Role};
+use crate::models::errors::Error;
+use crate::unix::{PeerCredentials, UnixConnection, lookup_group, lookup_user};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, HttpResponse, web};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Full,
+    ReadOnly,
+    Denied,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Principal {
+    Uid(u32),
+    Gid(u32),
+}
+
+/// Access of local users to the unix socket, by uid or gid of the connecting process.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SocketAccess {
+    rules: Vec<(Principal, Access)>,
+}
+
+impl SocketAccess {
+    /// Parses rules such as `user:alice=full`, `uid:1000=read-only` or `group:wheel=denied`.
+    pub fn parse(specs: &[String]) -> Result<Self, String> {
+        let mut rules = vec![];
+        for spec in specs {
+            let invalid = || format!("invalid socket access rule: {}", spec);
+            let (principal, access) = spec.split_once('=').ok_or_else(invalid)?;
+            let (kind, name) = principal.split_once(':').ok_or_else(invalid)?;
+
+            let principal = match kind {
+                "user" | "uid" => Principal::Uid(lookup_user(name)?),
+                "group" | "gid" => Principal::Gid(lookup_group(name)?),
+                _ => return Err(invalid()),
+            };
+            let access = match access {
+                "full" => Access::Full,
+                "read-only" => Access::ReadOnly,
+                "denied" => Access::Denied,
+                _ => return Err(invalid()),
+            };
+            rules.push((principal, access));
+        }
+
+        Ok(SocketAccess { rules })
+    }
+
+    /// Returns the access of the first rule for the uid, otherwise of the first rule for one
+    /// of the groups. Without any rules everyone has full access; with rules, others are denied.
+    pub fn access(&self, cred: &PeerCredentials) -> Access {
+        if self.rules.is_empty() {
+            return Access::Full;
+        }
+
+        let by_uid = self
+            .rules
+            .iter()
+            .find(|(p, _)| *p == Principal::Uid(cred.uid));
+        let by_gid = || {
+            self.rules.iter().find(|(p, _)| match p {
+                Principal::Gid(gid) => *gid == cred.gid || cred.groups.contains(gid),
+                Principal::Uid(_) => false,
+            })
+        };
+
+        by_uid
+            .or_else(by_gid)
+            .map(|(_, access)| *access)
+            .unwrap_or(Access::Denied)
+    }
+}
+
+/// Applies the `SocketAccess` registered as app data to requests over the unix socket.
+/// Read-only callers get the permissions of a viewer. Callers whose credentials could not be
+/// read are denied as soon as there are rules.
+pub async fn require_socket_access<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let socket_access = req.app_data::<web::Data<SocketAccess>>();
+    let access = match (socket_access, req.conn_data::<UnixConnection>()) {
+        (Some(access), Some(_)) => match req.conn_data::<PeerCredentials>() {
+            Some(cred) => (
+                access.access(cred),
+                format!("uid {} is not allowed to use the socket", cred.uid),
+            ),
+            None if access.rules.is_empty() => (Access::Full, String::new()),
+            None => (
+                Access::Denied,
+                "peer credentials of the connection are unavailable".to_owned(),
+            ),
+        },
+        _ => (Access::Full, String::new()),
+    };
+
+    match access {
+        (Access::Denied, message) => {
+            let resp = HttpResponse::Forbidden().json(Error::forbidden(message));
+            Ok(req.into_response(resp).map_into_right_body())
+        }
+        (Access::ReadOnly, _) => {
+            req.extensions_mut().insert(Permissions {
+                role: Role::Viewer,
+                devices: vec!["*".to_owned()],
+                secrets: false,
+            });
+            Ok(next.call(req).await?.map_into_left_body())
+        }
+        (Access::Full, _) => Ok(next.call(req).await?.map_into_left_body()),
+    }
+}
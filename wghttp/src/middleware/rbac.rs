@@ -0,0 +1,107 @@
+use crate::models::errors::Error;
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use std::future::{Ready, ready};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    Viewer,
+    PeerOperator,
+    Admin,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Read,
+    ManagePeers,
+    ManageDevices,
+}
+
+impl Action {
+    fn required_role(&self) -> Role {
+        match self {
+            Action::Read => Role::Viewer,
+            Action::ManagePeers => Role::PeerOperator,
+            Action::ManageDevices => Role::Admin,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Action::Read => "read",
+            Action::ManagePeers => "manage peers of",
+            Action::ManageDevices => "manage",
+        }
+    }
+}
+
+/// What the api key of a request may do. Handlers extract it from the request; the auth middleware
+/// sets it, and a request that did not pass through the middleware is rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Permissions {
+    pub role: Role,
+
+    /// device name patterns, where `*` matches any number of characters.
+    pub devices: Vec<String>,
+
+    /// whether private keys and preshared keys may be returned.
+    pub secrets: bool,
+}
+
+/// Whether the name matches the pattern, where `*` matches any number of characters.
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let Some(name) = name.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=name.len())
+                .filter(|i| name.is_char_boundary(*i))
+                .any(|i| matches_pattern(rest, &name[i..]))
+        }
+    }
+}
+
+impl Permissions {
+    pub fn all() -> Self {
+        Permissions {
+            role: Role::Admin,
+            devices: vec!["*".to_owned()],
+            secrets: true,
+        }
+    }
+
+    pub fn can_access(&self, device: &str) -> bool {
+        self.devices.iter().any(|p| matches_pattern(p, device))
+    }
+
+    pub fn authorize(&self, device: &str, action: Action) -> Result<(), String> {
+        if self.role >= action.required_role() && self.can_access(device) {
+            return Ok(());
+        }
+
+        Err(format!(
+            "not allowed to {} device {}",
+            action.describe(),
+            device
+        ))
+    }
+}
+
+impl FromRequest for Permissions {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let permissions = req.extensions().get::<Permissions>().cloned();
+        ready(permissions.ok_or_else(|| {
+            let resp =
+                HttpResponse::Forbidden().json(Error::forbidden("request has no permissions"));
+            InternalError::from_response("request has no permissions", resp).into()
+        }))
+    }
+}
@@ -0,0 +1,93 @@
+use crate::telemetry;
+use actix_web::HttpMessage;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use opentelemetry::trace::TraceContextExt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::Instrument;
+use tracing::field::Empty;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-ID";
+
+// longest request id taken from a client.
+const MAX_LEN: usize = 128;
+
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// Id of the request, taken from the `X-Request-ID` header or generated.
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+pub fn generate() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    format!(
+        "{:016x}{:08x}",
+        nanos,
+        SEQUENCE.fetch_add(1, Ordering::Relaxed) as u32
+    )
+}
+
+/// Gives every request an id, echoed in the response, and runs it inside a span carrying the id,
+/// so that everything logged while serving it can be told apart. The span continues the trace of
+/// the caller given in the `traceparent` header.
+pub async fn trace_requests<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, actix_web::Error> {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(|id| id.to_owned())
+        .unwrap_or_else(generate);
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    // the query string is left out, only the path names devices and peers.
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %req.method(),
+        path = %req.path(),
+        otel.kind = "server",
+        otel.status_code = Empty,
+    );
+    // fails only when spans are not exported.
+    let _ = span.set_parent(telemetry::remote_context(req.headers()));
+    let start = Instant::now();
+    let mut res = next.call(req).instrument(span.clone()).await?;
+
+    // named after the route, so that spans of a route are grouped whatever the device.
+    let route = res.request().match_pattern();
+    let route = route.as_deref().unwrap_or("unmatched");
+    span.context()
+        .span()
+        .update_name(format!("{} {}", res.request().method(), route));
+    if res.status().is_server_error() {
+        span.record("otel.status_code", "error");
+    }
+
+    tracing::info!(
+        parent: &span,
+        status = res.status().as_u16(),
+        duration_ms = start.elapsed().as_secs_f64() * 1000.0,
+        "request completed"
+    );
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut()
+            .insert(HeaderName::from_static("x-request-id"), value);
+    }
+
+    Ok(res)
+}
@@ -0,0 +1,49 @@
+use crate::models::forwards::ForwardProtocol;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AclPolicy {
+    Allow,
+    Deny,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AclRequest {
+    /// allow rules take precedence over deny rules.
+    pub action: AclPolicy,
+
+    /// public keys of the peers the rule applies to.
+    #[schema(example = json!(["wfbGOdrEgIGn15y6FMgfJjpaZv02ZQb5xQ5yvnkPhyg="]))]
+    pub peers: Vec<String>,
+
+    #[schema(example = "192.168.1.0/24")]
+    pub destination: String,
+
+    /// any protocol if omitted.
+    pub protocol: Option<ForwardProtocol>,
+
+    /// destination port, any port if omitted; requires a protocol.
+    #[schema(example = 443)]
+    pub port: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AclResponse {
+    #[schema(example = 1)]
+    pub id: u32,
+
+    pub action: AclPolicy,
+
+    #[schema(example = json!(["wfbGOdrEgIGn15y6FMgfJjpaZv02ZQb5xQ5yvnkPhyg="]))]
+    pub peers: Vec<String>,
+
+    #[schema(example = "192.168.1.0/24")]
+    pub destination: String,
+
+    pub protocol: Option<ForwardProtocol>,
+
+    #[schema(example = 443)]
+    pub port: Option<u16>,
+}
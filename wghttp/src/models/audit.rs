@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+/// A state-changing request. Request and response bodies are not recorded, so neither are the
+/// keys they carry.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct AuditRecord {
+    /// unix time in seconds.
+    #[schema(example = 1745760960)]
+    pub time: u64,
+
+    /// api key, client certificate or local user the request was made with.
+    #[schema(example = "api-key:helpdesk")]
+    pub caller: String,
+
+    /// tcp address of the client, or the pid of the process on the unix socket.
+    #[schema(example = "192.0.2.10:53124")]
+    pub source: String,
+
+    #[schema(example = "delete_peer")]
+    pub operation: String,
+
+    #[schema(example = "wg0")]
+    pub device: Option<String>,
+
+    #[schema(example = "wfbGOdrEgIGn15y6FMgfJjpaZv02ZQb5xQ5yvnkPhyg=")]
+    pub peer: Option<String>,
+
+    #[schema(example = 204)]
+    pub status: u16,
+
+    pub outcome: AuditOutcome,
+
+    #[schema(example = "peer not found")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, IntoParams, Default)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    /// records at or after this unix time.
+    pub since: Option<u64>,
+
+    /// records at or before this unix time.
+    pub until: Option<u64>,
+
+    pub device: Option<String>,
+}
@@ -0,0 +1,107 @@
+use crate::models::devices::{CreateDeviceResponse, DeviceIpAddr};
+use crate::models::errors::Error;
+use crate::models::peers::{CreatePeerResponse, UpdatePeerResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOperation {
+    /// creates a device without full tunnel or nat, which are only set up through POST /devices.
+    CreateDevice {
+        #[schema(example = "wg0")]
+        device_name: String,
+
+        #[schema(example = 51820)]
+        port: u16,
+
+        ip_addresses: DeviceIpAddr,
+    },
+    DeleteDevice {
+        #[schema(example = "wg0")]
+        device_name: String,
+    },
+    AddPeer {
+        #[schema(example = "wg0")]
+        device_name: String,
+
+        #[schema(example = json!(["10.0.0.2/32"]))]
+        allowed_ips: Vec<String>,
+
+        #[serde(default)]
+        #[schema(example = 30)]
+        persistent_keepalive_interval: u16,
+    },
+    UpdatePeer {
+        #[schema(example = "wg0")]
+        device_name: String,
+
+        #[schema(example = "wfbGOdrEgIGn15y6FMgfJjpaZv02ZQb5xQ5yvnkPhyg=")]
+        public_key: String,
+
+        #[schema(example = json!(["10.0.0.2/32", "192.168.10.0/24"]))]
+        allowed_ips: Vec<String>,
+
+        #[serde(default)]
+        #[schema(example = 25)]
+        persistent_keepalive_interval: u16,
+    },
+    DeletePeer {
+        #[schema(example = "wg0")]
+        device_name: String,
+
+        #[schema(example = "wfbGOdrEgIGn15y6FMgfJjpaZv02ZQb5xQ5yvnkPhyg=")]
+        public_key: String,
+    },
+    /// sets the addresses of the device; routes of its peers follow the new addresses.
+    SetAddresses {
+        #[schema(example = "wg0")]
+        device_name: String,
+
+        ip_addresses: DeviceIpAddr,
+    },
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BatchRequest {
+    /// applied in order; a `delete_device` or `delete_peer` can only be the last operation.
+    pub operations: Vec<BatchOperation>,
+}
+
+/// result of an operation, at the same position as the operation in the request.
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchResult {
+    CreateDevice {
+        #[serde(flatten)]
+        device: CreateDeviceResponse,
+    },
+    DeleteDevice,
+    AddPeer {
+        #[serde(flatten)]
+        peer: CreatePeerResponse,
+    },
+    UpdatePeer {
+        #[serde(flatten)]
+        peer: UpdatePeerResponse,
+    },
+    DeletePeer,
+    SetAddresses {
+        ip_addresses: DeviceIpAddr,
+    },
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BatchResponse {
+    pub results: Vec<BatchResult>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BatchError {
+    /// position of the operation that failed; the operations before it were rolled back.
+    #[schema(example = 2)]
+    pub index: usize,
+
+    #[serde(flatten)]
+    pub error: Error,
+}
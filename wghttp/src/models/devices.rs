@@ -1,5 +1,33 @@
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
+
+/// network namespace the device lives in, the daemon's own if neither is given.
+#[derive(Serialize, Deserialize, ToSchema, IntoParams, Default)]
+#[into_params(parameter_in = Query)]
+pub struct NetNsParams {
+    /// named network namespace under /var/run/netns.
+    #[schema(example = "tenant1")]
+    pub netns: Option<String>,
+
+    /// pid of a process whose network namespace is used.
+    pub netns_pid: Option<u32>,
+}
+
+/// namespace to move the device to.
+#[derive(Serialize, Deserialize, ToSchema, Default)]
+pub struct MoveDeviceRequest {
+    /// named network namespace under /var/run/netns.
+    #[schema(example = "tenant1")]
+    pub netns: Option<String>,
+
+    /// pid of a process whose network namespace is used.
+    pub netns_pid: Option<u32>,
+
+    /// removes the masquerade setup, port forwards and access rules of the device instead of
+    /// refusing to move it. They match the device in its current namespace and are not recreated.
+    #[serde(default)]
+    pub drop_firewall: bool,
+}
 
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct ListDeviceResponse {
@@ -22,6 +50,25 @@ pub struct DeviceIpAddr {
     pub ipv6: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct NatConfig {
+    /// interface the traffic is masqueraded on, any interface but the device if omitted.
+    #[schema(example = "eth0")]
+    pub egress_interface: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct NatState {
+    #[schema(example = "eth0")]
+    pub egress_interface: Option<String>,
+
+    #[schema(example = true)]
+    pub ipv4_forwarding: bool,
+
+    #[schema(example = false)]
+    pub ipv6_forwarding: bool,
+}
+
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct CreateDeviceRequest {
     #[schema(example = "wg0")]
@@ -30,6 +77,14 @@ pub struct CreateDeviceRequest {
     #[schema(example = 51820)]
     pub port: u16,
 
+    /// route all traffic through the device; the port is used as fwmark and routing table.
+    #[serde(default)]
+    #[schema(example = false)]
+    pub full_tunnel: bool,
+
+    /// masquerade traffic from peers; requires the daemon to run with `--firewall`.
+    pub nat: Option<NatConfig>,
+
     pub ip_addresses: DeviceIpAddr,
 }
 
@@ -43,8 +98,15 @@ pub struct CreateDeviceResponse {
 
     pub ip_addresses: DeviceIpAddr,
 
+    #[schema(example = false)]
+    pub full_tunnel: bool,
+
+    pub nat: Option<NatConfig>,
+
+    /// left out unless the api key may read secrets.
     #[schema(example = "UMp441pv9vfOq2eMRK0CURJeSZlsyIDXurczqVKPums=")]
-    pub private_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<String>,
 
     #[schema(example = "wfbGOdrEgIGn15y6FMgfJjpaZv02ZQb5xQ5yvnkPhyg=")]
     pub public_key: String,
@@ -60,6 +122,11 @@ pub struct DetailDeviceResponse {
 
     pub ip_addresses: DeviceIpAddr,
 
+    #[schema(example = false)]
+    pub full_tunnel: bool,
+
+    pub nat: Option<NatState>,
+
     #[schema(example = "wfbGOdrEgIGn15y6FMgfJjpaZv02ZQb5xQ5yvnkPhyg=")]
     pub public_key: String,
 
@@ -1,7 +1,226 @@
+use actix_web::HttpResponse;
+use actix_web::http::StatusCode;
+use domain::models::firewall::{FirewallError, FirewallErrorKind};
+use domain::models::netdev::{NetDevError, NetDevErrorKind};
+use domain::models::wg::{WGError, WGErrorKind};
 use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
 use utoipa::ToSchema;
 
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct Error {
+    /// machine-readable error code, stable across releases.
+    #[schema(example = "device_not_found")]
+    pub code: String,
     pub message: String,
+    /// errno of the system call or netlink request that failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub errno: Option<i32>,
+    /// message the kernel attached to the failed netlink request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extack: Option<String>,
+}
+
+impl Error {
+    pub fn new(code: &str, message: impl Into<String>) -> Self {
+        Self {
+            code: code.to_owned(),
+            message: message.into(),
+            errno: None,
+            extack: None,
+        }
+    }
+
+    /// Error of a request that failed validation.
+    pub fn invalid(message: impl Into<String>) -> Self {
+        Self::new("invalid_request", message)
+    }
+
+    /// Error of a request the caller is not allowed to make.
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new("permission_denied", message)
+    }
+}
+
+/// Status and body of the response for a failure of the wireguard, netdev or firewall layers.
+pub struct SystemError {
+    pub status: StatusCode,
+    pub error: Error,
+}
+
+impl SystemError {
+    fn new(status: StatusCode, code: &str, message: String) -> Self {
+        Self {
+            status,
+            error: Error::new(code, message),
+        }
+    }
+
+    // Adds the cause reported by the C libraries, the errno is spelled out in the message too.
+    fn with_cause(mut self, errno: Option<i32>, extack: Option<String>) -> Self {
+        if let Some(errno) = errno {
+            let cause = std::io::Error::from_raw_os_error(errno);
+            self.error.message = format!("{}: {}", self.error.message, cause);
+        }
+        self.error.errno = errno;
+        self.error.extack = extack;
+        self
+    }
+
+    pub fn response(self) -> HttpResponse {
+        HttpResponse::build(self.status).json(self.error)
+    }
+}
+
+impl From<SystemError> for (StatusCode, Error) {
+    fn from(e: SystemError) -> Self {
+        (e.status, e.error)
+    }
+}
+
+/// Builds the error response for a failure of the wireguard, netdev or firewall layers.
+pub fn system_error(e: impl Into<SystemError>) -> HttpResponse {
+    e.into().response()
+}
+
+// Failures of the system layers are logged, unless they are the caller's to fix.
+fn log(status: StatusCode, e: &dyn std::fmt::Display) {
+    if status.is_server_error() {
+        tracing::error!("{}", e);
+    }
+}
+
+fn errno_kind(errno: Option<i32>) -> Option<ErrorKind> {
+    errno.map(|errno| std::io::Error::from_raw_os_error(errno).kind())
+}
+
+impl From<WGError> for SystemError {
+    fn from(e: WGError) -> Self {
+        let (status, code) = match &e {
+            WGError::Lib { kind, errno, .. } => match kind {
+                WGErrorKind::NoMem => (StatusCode::SERVICE_UNAVAILABLE, "out_of_memory"),
+                WGErrorKind::DevNotFound => (StatusCode::NOT_FOUND, "device_not_found"),
+                WGErrorKind::PeerNotFound => (StatusCode::NOT_FOUND, "peer_not_found"),
+                WGErrorKind::FamilyNotFound => {
+                    (StatusCode::SERVICE_UNAVAILABLE, "wireguard_unavailable")
+                }
+                WGErrorKind::DevAddFailed => match errno_kind(*errno) {
+                    Some(ErrorKind::AlreadyExists) => (StatusCode::CONFLICT, "device_exists"),
+                    _ => (StatusCode::INTERNAL_SERVER_ERROR, "device_add_failed"),
+                },
+                WGErrorKind::DevSetFailed => {
+                    (StatusCode::INTERNAL_SERVER_ERROR, "device_set_failed")
+                }
+            },
+            WGError::Invalid(_) => (StatusCode::UNPROCESSABLE_ENTITY, "invalid_argument"),
+            WGError::Other(_) => (StatusCode::INTERNAL_SERVER_ERROR, "system_error"),
+        };
+        log(status, &e);
+
+        let error = SystemError::new(status, code, e.message());
+        match e {
+            WGError::Lib { errno, extack, .. } => error.with_cause(errno, extack),
+            _ => error,
+        }
+    }
+}
+
+impl From<NetDevError> for SystemError {
+    fn from(e: NetDevError) -> Self {
+        let (status, code) = match &e {
+            NetDevError::Lib { kind, errno, .. } => match kind {
+                NetDevErrorKind::NoMem => (StatusCode::SERVICE_UNAVAILABLE, "out_of_memory"),
+                NetDevErrorKind::CtlSocketFailed
+                | NetDevErrorKind::NetlinkSocketFailed
+                | NetDevErrorKind::NetlinkSendFailed
+                | NetDevErrorKind::NetlinkRecvFailed
+                | NetDevErrorKind::GetifaddrsFailed => {
+                    (StatusCode::SERVICE_UNAVAILABLE, "netlink_unavailable")
+                }
+                NetDevErrorKind::InvalidIpStr
+                | NetDevErrorKind::InvalidIp
+                | NetDevErrorKind::InvalidIpPrefix => {
+                    (StatusCode::UNPROCESSABLE_ENTITY, "invalid_address")
+                }
+                NetDevErrorKind::InvalidNetns => {
+                    (StatusCode::UNPROCESSABLE_ENTITY, "invalid_namespace")
+                }
+                NetDevErrorKind::DevNotFound => (StatusCode::NOT_FOUND, "device_not_found"),
+                NetDevErrorKind::NetnsOpenFailed => (StatusCode::NOT_FOUND, "namespace_not_found"),
+                NetDevErrorKind::DevIpSetFailed | NetDevErrorKind::DevNetmaskSetFailed => {
+                    match errno_kind(*errno) {
+                        Some(ErrorKind::AlreadyExists) => (StatusCode::CONFLICT, "address_exists"),
+                        Some(ErrorKind::AddrNotAvailable) => {
+                            (StatusCode::UNPROCESSABLE_ENTITY, "invalid_address")
+                        }
+                        _ => (StatusCode::INTERNAL_SERVER_ERROR, "address_set_failed"),
+                    }
+                }
+                NetDevErrorKind::GetDevFlagsFailed | NetDevErrorKind::SetDevFlagsFailed => {
+                    (StatusCode::INTERNAL_SERVER_ERROR, "device_flags_failed")
+                }
+                NetDevErrorKind::RouteAddFailed | NetDevErrorKind::RouteDelFailed => {
+                    (StatusCode::INTERNAL_SERVER_ERROR, "route_failed")
+                }
+                NetDevErrorKind::RouteExists => (StatusCode::CONFLICT, "route_exists"),
+                NetDevErrorKind::RuleAddFailed | NetDevErrorKind::RuleDelFailed => {
+                    (StatusCode::INTERNAL_SERVER_ERROR, "rule_failed")
+                }
+                NetDevErrorKind::NetnsEnterFailed | NetDevErrorKind::LinkNetnsFailed => {
+                    (StatusCode::INTERNAL_SERVER_ERROR, "namespace_failed")
+                }
+            },
+            NetDevError::Invalid(_) => (StatusCode::UNPROCESSABLE_ENTITY, "invalid_argument"),
+            NetDevError::Other(_) => (StatusCode::INTERNAL_SERVER_ERROR, "system_error"),
+        };
+        log(status, &e);
+
+        let error = SystemError::new(status, code, e.message());
+        match e {
+            NetDevError::Lib { errno, extack, .. } => error.with_cause(errno, extack),
+            _ => error,
+        }
+    }
+}
+
+impl From<FirewallError> for SystemError {
+    fn from(e: FirewallError) -> Self {
+        let (status, code) = match &e {
+            FirewallError::Lib { kind, errno } => match kind {
+                FirewallErrorKind::NoMem => (StatusCode::SERVICE_UNAVAILABLE, "out_of_memory"),
+                FirewallErrorKind::NetlinkSocketFailed
+                | FirewallErrorKind::NetlinkSendFailed
+                | FirewallErrorKind::NetlinkRecvFailed => {
+                    (StatusCode::SERVICE_UNAVAILABLE, "netlink_unavailable")
+                }
+                FirewallErrorKind::InvalidAddr => {
+                    (StatusCode::UNPROCESSABLE_ENTITY, "invalid_address")
+                }
+                FirewallErrorKind::InvalidDevName
+                | FirewallErrorKind::InvalidProtocol
+                | FirewallErrorKind::InvalidAclId => {
+                    (StatusCode::UNPROCESSABLE_ENTITY, "invalid_argument")
+                }
+                FirewallErrorKind::ForwardExists => (StatusCode::CONFLICT, "forward_exists"),
+                FirewallErrorKind::ForwardNotFound => (StatusCode::NOT_FOUND, "forward_not_found"),
+                FirewallErrorKind::RulesetUpdateFailed => match errno_kind(*errno) {
+                    Some(ErrorKind::AlreadyExists) => (StatusCode::CONFLICT, "rule_exists"),
+                    Some(ErrorKind::NotFound) => (StatusCode::NOT_FOUND, "rule_not_found"),
+                    _ => (StatusCode::INTERNAL_SERVER_ERROR, "firewall_error"),
+                },
+                FirewallErrorKind::RulesetReadFailed => {
+                    (StatusCode::INTERNAL_SERVER_ERROR, "firewall_error")
+                }
+            },
+            FirewallError::Invalid(_) => (StatusCode::UNPROCESSABLE_ENTITY, "invalid_argument"),
+            FirewallError::Other(_) => (StatusCode::INTERNAL_SERVER_ERROR, "firewall_error"),
+        };
+        log(status, &e);
+
+        let error = SystemError::new(status, code, e.message());
+        match e {
+            FirewallError::Lib { errno, .. } => error.with_cause(errno, None),
+            _ => error,
+        }
+    }
 }
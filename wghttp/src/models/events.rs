@@ -0,0 +1,126 @@
+use domain::models::netdev::NetNs;
+use domain::models::wg::WGPeer;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// What happened to a device or one of its peers.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Change {
+    DeviceCreated,
+    DeviceDeleted,
+    PeerAdded {
+        public_key: String,
+        allowed_ips: Vec<String>,
+        persistent_keepalive_interval: u16,
+    },
+    PeerUpdated {
+        public_key: String,
+        allowed_ips: Vec<String>,
+        persistent_keepalive_interval: u16,
+    },
+    PeerRemoved {
+        public_key: String,
+    },
+    /// the peer completed a handshake; the time is in seconds since the epoch.
+    Handshake {
+        public_key: String,
+        last_handshake_time: i64,
+    },
+    /// first handshake of the peer since it was added, or since wghttp started.
+    PeerConnected {
+        public_key: String,
+        endpoint: String,
+    },
+    /// the peer was seen at a new address.
+    EndpointChanged {
+        public_key: String,
+        endpoint: String,
+    },
+}
+
+impl Change {
+    pub fn peer_added(peer: &WGPeer) -> Self {
+        Change::PeerAdded {
+            public_key: peer.public_key.clone(),
+            allowed_ips: peer.allowed_ips.clone(),
+            persistent_keepalive_interval: peer.persistent_keepalive_interval,
+        }
+    }
+
+    pub fn peer_updated(peer: &WGPeer) -> Self {
+        Change::PeerUpdated {
+            public_key: peer.public_key.clone(),
+            allowed_ips: peer.allowed_ips.clone(),
+            persistent_keepalive_interval: peer.persistent_keepalive_interval,
+        }
+    }
+
+    pub const NAMES: [&str; 8] = [
+        "device_created",
+        "device_deleted",
+        "peer_added",
+        "peer_updated",
+        "peer_removed",
+        "handshake",
+        "peer_connected",
+        "endpoint_changed",
+    ];
+
+    /// Name of the server-sent event carrying the change.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Change::DeviceCreated => "device_created",
+            Change::DeviceDeleted => "device_deleted",
+            Change::PeerAdded { .. } => "peer_added",
+            Change::PeerUpdated { .. } => "peer_updated",
+            Change::PeerRemoved { .. } => "peer_removed",
+            Change::Handshake { .. } => "handshake",
+            Change::PeerConnected { .. } => "peer_connected",
+            Change::EndpointChanged { .. } => "endpoint_changed",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct Event {
+    #[schema(example = "wg0")]
+    pub device: String,
+
+    /// namespace of the device, a name or `pid:<pid>`; absent for the host namespace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub netns: Option<String>,
+
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+impl Event {
+    pub fn new(netns: Option<&NetNs>, device: &str, change: Change) -> Self {
+        Event {
+            device: device.to_owned(),
+            netns: netns.map(|n| match n {
+                NetNs::Named(name) => name.clone(),
+                NetNs::Pid(pid) => format!("pid:{}", pid),
+            }),
+            change,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, IntoParams, Default)]
+#[into_params(parameter_in = Query)]
+pub struct EventsQuery {
+    /// comma separated device names to receive events of; all devices by default.
+    #[param(example = "wg0,wg1")]
+    pub device: Option<String>,
+}
+
+impl EventsQuery {
+    pub fn matches(&self, event: &Event) -> bool {
+        match &self.device {
+            None => true,
+            Some(devices) => devices.split(',').any(|d| d.trim() == event.device),
+        }
+    }
+}
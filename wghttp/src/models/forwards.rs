@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ForwardProtocol {
+    Tcp,
+    Udp,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CreateForwardRequest {
+    pub protocol: ForwardProtocol,
+
+    /// port on the host's local addresses.
+    #[schema(example = 8443)]
+    pub public_port: u16,
+
+    /// tunnel ip of the peer; must be within its allowed ips.
+    #[schema(example = "10.0.0.2")]
+    pub destination_ip: String,
+
+    #[schema(example = 443)]
+    pub destination_port: u16,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ForwardResponse {
+    pub protocol: ForwardProtocol,
+
+    #[schema(example = 8443)]
+    pub public_port: u16,
+
+    #[schema(example = "10.0.0.2")]
+    pub destination_ip: String,
+
+    #[schema(example = 443)]
+    pub destination_port: u16,
+}
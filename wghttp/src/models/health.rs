@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Fail,
+}
+
+/// Outcome of one readiness check.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct Check {
+    #[schema(example = "wireguard")]
+    pub name: String,
+
+    pub status: CheckStatus,
+
+    /// time the check took, in milliseconds.
+    #[schema(example = 0.42)]
+    pub latency_ms: f64,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "CAP_NET_ADMIN is not effective")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct Health {
+    /// `ok` when every check passed.
+    pub status: CheckStatus,
+
+    pub checks: Vec<Check>,
+}
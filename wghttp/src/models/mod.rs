@@ -1,3 +1,10 @@
+pub mod acls;
+pub mod audit;
+pub mod batch;
 pub mod devices;
 pub mod errors;
+pub mod events;
+pub mod forwards;
+pub mod health;
 pub mod peers;
+pub mod webhooks;
@@ -23,6 +23,10 @@ pub struct ListPeerResponse {
 
     #[schema(example = 7231842)]
     pub tx: u64,
+
+    /// version of the peer, to send back in If-Match.
+    #[schema(example = "\"9f86d081884c7d659a2feaa0c55ad015\"")]
+    pub etag: String,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -39,11 +43,15 @@ pub struct CreatePeerResponse {
     #[schema(example = "wfbGOdrEgIGn15y6FMgfJjpaZv02ZQb5xQ5yvnkPhyg=")]
     pub public_key: String,
 
+    /// left out unless the api key may read secrets.
     #[schema(example = "UMp441pv9vfOq2eMRK0CURJeSZlsyIDXurczqVKPums=")]
-    pub private_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<String>,
 
+    /// left out unless the api key may read secrets.
     #[schema(example = "GBVavxe7VEId8K9/trxquNihyEES3p9ydJ2pWQVI5j0=")]
-    pub preshared_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preshared_key: Option<String>,
 
     #[schema(example = json!(["10.0.0.2/32", "fd86:ea04:1111::2/128"]))]
     pub allowed_ips: Vec<String>,
@@ -51,3 +59,24 @@ pub struct CreatePeerResponse {
     #[schema(example = 30)]
     pub persistent_keepalive_interval: u16,
 }
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UpdatePeerRequest {
+    #[schema(example = json!(["10.0.0.2/32", "192.168.10.0/24"]))]
+    pub allowed_ips: Vec<String>,
+
+    #[schema(example = 25)]
+    pub persistent_keepalive_interval: u16,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UpdatePeerResponse {
+    #[schema(example = "wfbGOdrEgIGn15y6FMgfJjpaZv02ZQb5xQ5yvnkPhyg=")]
+    pub public_key: String,
+
+    #[schema(example = json!(["10.0.0.2/32", "192.168.10.0/24"]))]
+    pub allowed_ips: Vec<String>,
+
+    #[schema(example = 25)]
+    pub persistent_keepalive_interval: u16,
+}
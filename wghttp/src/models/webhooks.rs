@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryOutcome {
+    /// the subscriber answered with a 2xx status.
+    Delivered,
+    /// the attempt failed and the delivery is tried again later.
+    Retrying,
+    /// the attempt failed and the delivery is given up.
+    Failed,
+}
+
+/// An attempt to deliver an event to a webhook subscriber.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct DeliveryRecord {
+    /// unix time in seconds.
+    #[schema(example = 1745760960)]
+    pub time: u64,
+
+    /// id of the delivery, sent in the `X-Wghttp-Delivery` header; retries keep it.
+    #[schema(example = "18398a7e5c1f0a42-3")]
+    pub delivery: String,
+
+    #[schema(example = "chatops")]
+    pub subscriber: String,
+
+    #[schema(example = "peer_added")]
+    pub event: String,
+
+    #[schema(example = "wg0")]
+    pub device: Option<String>,
+
+    /// 1 for the first attempt.
+    #[schema(example = 1)]
+    pub attempt: u32,
+
+    /// status the subscriber answered with, if it answered.
+    #[schema(example = 204)]
+    pub status: Option<u16>,
+
+    pub outcome: DeliveryOutcome,
+
+    #[schema(example = "connection refused")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, IntoParams, Default)]
+#[into_params(parameter_in = Query)]
+pub struct DeliveryQuery {
+    /// records at or after this unix time.
+    pub since: Option<u64>,
+
+    /// records at or before this unix time.
+    pub until: Option<u64>,
+
+    pub subscriber: Option<String>,
+
+    pub device: Option<String>,
+}
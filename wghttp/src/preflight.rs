@@ -0,0 +1,115 @@
+use crate::health::{check, check_capabilities};
+use crate::models::health::{Check, CheckStatus};
+use domain::adapters::wg::WireguardAdapter;
+use std::fmt::Write;
+use std::fs;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+
+/// Where wghttp listens.
+pub enum Listen<'a> {
+    Unix(&'a Path),
+    Tcp(&'a str),
+}
+
+/// Checks that the unix socket can be created: its directory exists and is writable, and nothing
+/// but a stale socket is in the way.
+pub fn check_socket_path(path: &Path) -> Result<(), String> {
+    if let Ok(metadata) = fs::symlink_metadata(path)
+        && !metadata.file_type().is_socket()
+    {
+        return Err(format!("{} exists and is not a socket", path.display()));
+    }
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let probe = dir.join(format!(".wghttp-preflight-{}", std::process::id()));
+    fs::write(&probe, b"")
+        .and_then(|_| fs::remove_file(&probe))
+        .map_err(|e| format!("cannot create files in {}: {}", dir.display(), e))
+}
+
+/// Resolves the tcp address to listen on, e.g. `127.0.0.1:8080` or `[::1]:8080`.
+pub fn parse_tcp_address(tcp: &str) -> Result<SocketAddr, String> {
+    tcp.to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| format!("invalid tcp address {}, expected ip:port", tcp))
+}
+
+// Appends what to do about a failed check to its error.
+fn with_hint(mut check: Check, hint: &str) -> Check {
+    if let Some(error) = &mut check.error {
+        error.push_str("; ");
+        error.push_str(hint);
+    }
+    check
+}
+
+/// Runs the checks that must pass for wghttp to be of any use.
+pub fn preflight(wireguard: &dyn WireguardAdapter, listen: &Listen) -> Vec<Check> {
+    let mut checks = vec![
+        with_hint(
+            check("capabilities", check_capabilities),
+            "run wghttp as root, or grant it with `setcap cap_net_admin+ep`",
+        ),
+        with_hint(
+            check("wireguard", || wireguard.check().map_err(|e| e.to_string())),
+            "load the kernel module with `modprobe wireguard`",
+        ),
+    ];
+
+    checks.push(match listen {
+        Listen::Unix(path) => with_hint(
+            check("unix_socket", || check_socket_path(path)),
+            "pick another path with --unix",
+        ),
+        Listen::Tcp(tcp) => check("tcp_address", || parse_tcp_address(tcp).map(|_| ())),
+    });
+
+    checks
+}
+
+pub fn kernel_version() -> Option<String> {
+    fs::read_to_string("/proc/sys/kernel/osrelease")
+        .ok()
+        .map(|v| v.trim().to_owned())
+}
+
+pub fn wireguard_module_version() -> String {
+    if !Path::new("/sys/module/wireguard").exists() {
+        return "not loaded".to_owned();
+    }
+
+    // a module built into the kernel has no version file.
+    fs::read_to_string("/sys/module/wireguard/version")
+        .map(|v| v.trim().to_owned())
+        .unwrap_or_else(|_| "built in".to_owned())
+}
+
+/// Report printed by `wghttp doctor`.
+pub fn render(versions: &[(&str, Option<String>)], checks: &[Check]) -> String {
+    let mut out = String::new();
+    for (name, version) in versions {
+        let version = version.as_deref().unwrap_or("unknown");
+        let _ = writeln!(out, "{:<16}{}", name, version);
+    }
+    out.push('\n');
+
+    for c in checks {
+        let status = match c.status {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Fail => "fail",
+        };
+        let _ = write!(out, "{:<6}{:<16}{:.2}ms", status, c.name, c.latency_ms);
+        if let Some(error) = &c.error {
+            let _ = write!(out, "  {}", error);
+        }
+        out.push('\n');
+    }
+
+    out
+}
@@ -0,0 +1,388 @@
+use crate::helpers::*;
+use crate::middleware::rbac::{Action, Permissions};
+use crate::models::acls::*;
+use crate::models::errors::{Error, SystemError, system_error};
+use crate::routes::forwards::{from_protocol, to_protocol};
+use crate::services::{TunnelManager, acl_rules_for_peer};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, Responder, delete, get, post, put, web};
+use domain::models::firewall::{AclAction, AclRule};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+fn validate_device(dev: &str) -> Result<(), Error> {
+    if dev.len() > DEVICE_NAME_MAX_LEN {
+        return Err(Error::invalid("device name must be at most 15 characters"));
+    }
+
+    Ok(())
+}
+
+// Groups the rules by id, in order of their ids.
+fn to_responses(rules: Vec<AclRule>) -> Vec<AclResponse> {
+    let mut out: Vec<AclResponse> = vec![];
+    for rule in rules {
+        if let Some(acl) = out.iter_mut().find(|a| a.id == rule.id) {
+            if !acl.peers.contains(&rule.peer_public_key) {
+                acl.peers.push(rule.peer_public_key);
+            }
+            continue;
+        }
+
+        out.push(AclResponse {
+            id: rule.id,
+            action: match rule.action {
+                AclAction::Allow => AclPolicy::Allow,
+                AclAction::Deny => AclPolicy::Deny,
+            },
+            peers: vec![rule.peer_public_key],
+            destination: format!("{}/{}", rule.destination.0, rule.destination.1),
+            protocol: rule.protocol.map(from_protocol),
+            port: rule.port,
+        });
+    }
+
+    out.sort_by_key(|a| a.id);
+    out
+}
+
+/// Validates the request and expands it into one rule per allowed ip of every peer.
+fn build_rules(
+    tm: &TunnelManager,
+    dev: &str,
+    id: u32,
+    acl: &AclRequest,
+) -> Result<Vec<AclRule>, (StatusCode, Error)> {
+    if acl.peers.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Error::invalid("at least one peer is required"),
+        ));
+    }
+
+    if acl.peers.iter().any(|p| p.len() != PUBKEY_MAX_LEN) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Error::invalid("public key must be 44 characters"),
+        ));
+    }
+
+    if acl.port == Some(0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Error::invalid("port must be between 1 and 65535"),
+        ));
+    }
+
+    if acl.port.is_some() && acl.protocol.is_none() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Error::invalid("port requires a protocol"),
+        ));
+    }
+
+    let destination = match parse_ip(&acl.destination) {
+        Err(message) => return Err((StatusCode::BAD_REQUEST, Error::invalid(message))),
+        Ok((ip, prefix)) => (network_addr(ip, prefix), prefix),
+    };
+
+    let peers = match tm.wireguard.list_peers(dev) {
+        Err(e) => return Err(SystemError::from(e).into()),
+        Ok(peers) => peers,
+    };
+
+    let template = AclRule {
+        id,
+        action: match acl.action {
+            AclPolicy::Allow => AclAction::Allow,
+            AclPolicy::Deny => AclAction::Deny,
+        },
+        peer_public_key: String::new(),
+        source: match destination.0.is_ipv4() {
+            true => (Ipv4Addr::UNSPECIFIED.into(), 0),
+            false => (Ipv6Addr::UNSPECIFIED.into(), 0),
+        },
+        destination,
+        protocol: acl.protocol.map(to_protocol),
+        port: acl.port,
+    };
+
+    let mut rules = vec![];
+    for public_key in &acl.peers {
+        let Some(peer) = peers.iter().find(|p| &p.public_key == public_key) else {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Error::new("peer_not_found", "peer not found"),
+            ));
+        };
+
+        let peer_rules = acl_rules_for_peer(&template, peer);
+        if peer_rules.is_empty() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Error::invalid("peer has no allowed ips of the destination's family"),
+            ));
+        }
+
+        for rule in peer_rules {
+            if !rules.contains(&rule) {
+                rules.push(rule);
+            }
+        }
+    }
+
+    Ok(rules)
+}
+
+#[utoipa::path(
+    get,
+    path = "/devices/{dev}/acls",
+    tag = "acls",
+    params(
+        ("dev", description = "device name")
+    ),
+    responses(
+        (status = 200, description = "access rules of the device", body = [AclResponse]),
+        (status = 400, description = "validation error", body = Error),
+        (status = 403, description = "permission denied", body = Error),
+        (status = 500, description = "system error", body = Error),
+    )
+)]
+#[get("/devices/{dev}/acls")]
+async fn list_acls(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    path: web::Path<String>,
+) -> impl Responder {
+    let dev = path.into_inner();
+    if let Err(e) = validate_device(&dev) {
+        return HttpResponse::BadRequest().json(e);
+    }
+
+    if let Err(message) = perms.authorize(&dev, Action::Read) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
+    }
+
+    let Some(firewall) = &tm.firewall else {
+        return HttpResponse::BadRequest().json(Error::invalid("firewall is not enabled"));
+    };
+
+    match firewall.list_acl_rules(&dev) {
+        Err(e) => system_error(e),
+        Ok(rules) => HttpResponse::Ok().json(to_responses(rules)),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/devices/{dev}/acls",
+    tag = "acls",
+    params(
+        ("dev", description = "device name")
+    ),
+    request_body = AclRequest,
+    responses(
+        (status = 201, description = "access rule created successfully", body = AclResponse),
+        (status = 400, description = "validation error", body = Error),
+        (status = 403, description = "permission denied", body = Error),
+        (status = 404, description = "device or peer not found", body = Error),
+        (status = 500, description = "system error", body = Error),
+    )
+)]
+#[post("/devices/{dev}/acls")]
+async fn create_acl(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    path: web::Path<String>,
+    acl: web::Json<AclRequest>,
+) -> impl Responder {
+    let dev = path.into_inner();
+    if let Err(e) = validate_device(&dev) {
+        return HttpResponse::BadRequest().json(e);
+    }
+
+    if let Err(message) = perms.authorize(&dev, Action::ManageDevices) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
+    }
+
+    let Some(firewall) = &tm.firewall else {
+        return HttpResponse::BadRequest().json(Error::invalid("firewall is not enabled"));
+    };
+
+    let id = match firewall.list_acl_rules(&dev) {
+        Err(e) => return system_error(e),
+        Ok(rules) => rules.iter().map(|r| r.id).max().unwrap_or(0) + 1,
+    };
+
+    let rules = match build_rules(tm.get_ref(), &dev, id, &acl) {
+        Err((status, e)) => return HttpResponse::build(status).json(e),
+        Ok(rules) => rules,
+    };
+
+    if let Err(e) = firewall.replace_acl_rules(&dev, id, &rules) {
+        return system_error(e);
+    }
+
+    match to_responses(rules).pop() {
+        Some(out) => HttpResponse::Created().json(out),
+        None => {
+            HttpResponse::InternalServerError().json(Error::new("system_error", "system error"))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/devices/{dev}/acls/{id}",
+    tag = "acls",
+    params(
+        ("dev", description = "device name"),
+        ("id", description = "access rule id")
+    ),
+    responses(
+        (status = 200, description = "access rule found", body = AclResponse),
+        (status = 400, description = "validation error", body = Error),
+        (status = 403, description = "permission denied", body = Error),
+        (status = 404, description = "access rule not found", body = Error),
+        (status = 500, description = "system error", body = Error),
+    )
+)]
+#[get("/devices/{dev}/acls/{id}")]
+async fn get_acl(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    path: web::Path<(String, u32)>,
+) -> impl Responder {
+    let (dev, id) = path.into_inner();
+    if let Err(e) = validate_device(&dev) {
+        return HttpResponse::BadRequest().json(e);
+    }
+
+    if let Err(message) = perms.authorize(&dev, Action::Read) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
+    }
+
+    let Some(firewall) = &tm.firewall else {
+        return HttpResponse::BadRequest().json(Error::invalid("firewall is not enabled"));
+    };
+
+    let rules = match firewall.list_acl_rules(&dev) {
+        Err(e) => return system_error(e),
+        Ok(rules) => rules.into_iter().filter(|r| r.id == id).collect(),
+    };
+
+    match to_responses(rules).pop() {
+        Some(out) => HttpResponse::Ok().json(out),
+        None => HttpResponse::NotFound().json(Error::new("acl_not_found", "acl not found")),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/devices/{dev}/acls/{id}",
+    tag = "acls",
+    params(
+        ("dev", description = "device name"),
+        ("id", description = "access rule id")
+    ),
+    request_body = AclRequest,
+    responses(
+        (status = 200, description = "access rule updated successfully", body = AclResponse),
+        (status = 400, description = "validation error", body = Error),
+        (status = 403, description = "permission denied", body = Error),
+        (status = 404, description = "access rule or peer not found", body = Error),
+        (status = 500, description = "system error", body = Error),
+    )
+)]
+#[put("/devices/{dev}/acls/{id}")]
+async fn update_acl(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    path: web::Path<(String, u32)>,
+    acl: web::Json<AclRequest>,
+) -> impl Responder {
+    let (dev, id) = path.into_inner();
+    if let Err(e) = validate_device(&dev) {
+        return HttpResponse::BadRequest().json(e);
+    }
+
+    if let Err(message) = perms.authorize(&dev, Action::ManageDevices) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
+    }
+
+    let Some(firewall) = &tm.firewall else {
+        return HttpResponse::BadRequest().json(Error::invalid("firewall is not enabled"));
+    };
+
+    match firewall.list_acl_rules(&dev) {
+        Err(e) => return system_error(e),
+        Ok(rules) if !rules.iter().any(|r| r.id == id) => {
+            return HttpResponse::NotFound().json(Error::new("acl_not_found", "acl not found"));
+        }
+        Ok(_) => {}
+    }
+
+    let rules = match build_rules(tm.get_ref(), &dev, id, &acl) {
+        Err((status, e)) => return HttpResponse::build(status).json(e),
+        Ok(rules) => rules,
+    };
+
+    if let Err(e) = firewall.replace_acl_rules(&dev, id, &rules) {
+        return system_error(e);
+    }
+
+    match to_responses(rules).pop() {
+        Some(out) => HttpResponse::Ok().json(out),
+        None => {
+            HttpResponse::InternalServerError().json(Error::new("system_error", "system error"))
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/devices/{dev}/acls/{id}",
+    tag = "acls",
+    params(
+        ("dev", description = "device name"),
+        ("id", description = "access rule id")
+    ),
+    responses(
+        (status = 204, description = "successfully deleted"),
+        (status = 400, description = "validation error", body = Error),
+        (status = 403, description = "permission denied", body = Error),
+        (status = 404, description = "access rule not found", body = Error),
+        (status = 500, description = "system error", body = Error),
+    )
+)]
+#[delete("/devices/{dev}/acls/{id}")]
+async fn delete_acl(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    path: web::Path<(String, u32)>,
+) -> impl Responder {
+    let (dev, id) = path.into_inner();
+    if let Err(e) = validate_device(&dev) {
+        return HttpResponse::BadRequest().json(e);
+    }
+
+    if let Err(message) = perms.authorize(&dev, Action::ManageDevices) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
+    }
+
+    let Some(firewall) = &tm.firewall else {
+        return HttpResponse::BadRequest().json(Error::invalid("firewall is not enabled"));
+    };
+
+    match firewall.list_acl_rules(&dev) {
+        Err(e) => return system_error(e),
+        Ok(rules) if !rules.iter().any(|r| r.id == id) => {
+            return HttpResponse::NotFound().json(Error::new("acl_not_found", "acl not found"));
+        }
+        Ok(_) => {}
+    }
+
+    match firewall.replace_acl_rules(&dev, id, &[]) {
+        Err(e) => system_error(e),
+        Ok(()) => HttpResponse::NoContent().finish(),
+    }
+}
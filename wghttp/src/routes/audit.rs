@@ -0,0 +1,41 @@
+use crate::middleware::audit::AuditLog;
+use crate::middleware::rbac::{Action, Permissions};
+use crate::models::audit::*;
+use crate::models::errors::Error;
+use actix_web::{HttpResponse, Responder, get, web};
+
+#[utoipa::path(
+    get,
+    path = "/audit",
+    tag = "audit",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "state-changing requests, oldest first", body = [AuditRecord]),
+        (status = 400, description = "audit log is not enabled or not queryable", body = Error),
+    )
+)]
+#[get("/audit")]
+async fn list_audit_records(
+    log: Option<web::Data<AuditLog>>,
+    perms: Permissions,
+    query: web::Query<AuditQuery>,
+) -> impl Responder {
+    let Some(log) = log else {
+        return HttpResponse::BadRequest().json(Error::invalid("audit log is not enabled"));
+    };
+
+    let records = match log.query(&query) {
+        Err(message) => return HttpResponse::BadRequest().json(Error::invalid(message)),
+        Ok(records) => records,
+    };
+
+    // records are visible to whoever may manage their device.
+    let out: Vec<AuditRecord> = records
+        .into_iter()
+        .filter(|r| {
+            let device = r.device.as_deref().unwrap_or("");
+            perms.authorize(device, Action::ManageDevices).is_ok()
+        })
+        .collect();
+    HttpResponse::Ok().json(out)
+}
@@ -0,0 +1,290 @@
+use crate::helpers::*;
+use crate::middleware::idempotency::replay_responses;
+use crate::middleware::rbac::{Action, Permissions};
+use crate::models::batch::*;
+use crate::models::devices::{CreateDeviceResponse, DeviceIpAddr, NetNsParams};
+use crate::models::errors::{Error, system_error};
+use crate::models::events::Change;
+use crate::models::peers::{CreatePeerResponse, UpdatePeerResponse};
+use crate::services::TunnelManager;
+use crate::services::batch::{BatchOutcome, BatchStep};
+use actix_web::middleware::from_fn;
+use actix_web::{HttpResponse, Responder, post, web};
+use domain::models::netdev::NetDevIp;
+
+pub const BATCH_MAX_LEN: usize = 100;
+
+#[utoipa::path(
+    post,
+    path = "/batch",
+    tag = "batch",
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "replays the response to an earlier request with the same key and body"),
+        NetNsParams
+    ),
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "all operations applied", body = BatchResponse),
+        (status = 400, description = "validation error", body = BatchError),
+        (status = 403, description = "permission denied", body = BatchError),
+        (status = 404, description = "device or peer not found", body = BatchError),
+        (status = 409, description = "device exists, or a request with the idempotency key is in progress", body = BatchError),
+        (status = 422, description = "rejected by the system, or the idempotency key was used with a different request", body = BatchError),
+        (status = 500, description = "system error", body = BatchError),
+        (status = 503, description = "system resources unavailable", body = BatchError),
+    )
+)]
+#[post("/batch", wrap = "from_fn(replay_responses)")]
+async fn run_batch(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    params: web::Query<NetNsParams>,
+    batch: web::Json<BatchRequest>,
+) -> impl Responder {
+    let operations = batch.into_inner().operations;
+    if operations.is_empty() || operations.len() > BATCH_MAX_LEN {
+        return HttpResponse::BadRequest().json(Error::invalid(format!(
+            "a batch must have 1 to {} operations",
+            BATCH_MAX_LEN
+        )));
+    }
+
+    let mut steps: Vec<BatchStep> = vec![];
+    for (index, operation) in operations.into_iter().enumerate() {
+        let (step, action) = match parse_operation(operation) {
+            Err(message) => {
+                return HttpResponse::BadRequest().json(BatchError {
+                    index,
+                    error: Error::invalid(message),
+                });
+            }
+            Ok(parsed) => parsed,
+        };
+
+        if let Err(message) = perms.authorize(step.device_name(), action) {
+            return HttpResponse::Forbidden().json(BatchError {
+                index,
+                error: Error::forbidden(message),
+            });
+        }
+
+        steps.push(step);
+    }
+
+    let netns = match parse_netns(params.netns.as_deref(), params.netns_pid) {
+        Err(message) => return HttpResponse::BadRequest().json(Error::invalid(message)),
+        Ok(netns) => netns,
+    };
+
+    // a batch waits for the ones in progress on its devices, so it runs on a blocking thread,
+    // kept in the span of the request, which enters the namespace itself.
+    let manager = tm.get_ref().clone();
+    let target = netns.clone();
+    let span = tracing::Span::current();
+    let result = web::block(move || {
+        span.in_scope(|| {
+            let applied = match manager.enter_netns(target.as_ref()) {
+                Err(e) => Err(e),
+                Ok(_netns) => Ok(manager.run_batch(&steps)),
+            };
+            (steps, applied)
+        })
+    })
+    .await;
+
+    let (steps, outcomes) = match result {
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(Error::new("system_error", e.to_string()));
+        }
+        Ok((_, Err(e))) => return system_error(e),
+        Ok((_, Ok(Err(failure)))) => {
+            return HttpResponse::build(failure.error.status).json(BatchError {
+                index: failure.index,
+                error: failure.error.error,
+            });
+        }
+        Ok((steps, Ok(Ok(outcomes)))) => (steps, outcomes),
+    };
+    let manager = tm.get_ref();
+
+    // changes are only published once the batch as a whole went through.
+    let mut results: Vec<BatchResult> = vec![];
+    for (step, outcome) in steps.iter().zip(outcomes) {
+        let device_name = step.device_name();
+        let result = match outcome {
+            BatchOutcome::DeviceCreated(d, ip) => {
+                manager.publish(netns.as_ref(), device_name, Change::DeviceCreated);
+                BatchResult::CreateDevice {
+                    device: CreateDeviceResponse {
+                        device_name: d.name,
+                        port: d.port,
+                        ip_addresses: ip_addresses(&ip),
+                        full_tunnel: false,
+                        nat: None,
+                        private_key: perms.secrets.then_some(d.private_key),
+                        public_key: d.public_key,
+                    },
+                }
+            }
+            BatchOutcome::DeviceDeleted => {
+                manager.publish(netns.as_ref(), device_name, Change::DeviceDeleted);
+                BatchResult::DeleteDevice
+            }
+            BatchOutcome::PeerAdded(p) => {
+                manager.publish(netns.as_ref(), device_name, Change::peer_added(&p));
+                BatchResult::AddPeer {
+                    peer: CreatePeerResponse {
+                        public_key: p.public_key,
+                        private_key: perms.secrets.then_some(p.private_key),
+                        preshared_key: perms.secrets.then_some(p.preshared_key),
+                        allowed_ips: p.allowed_ips,
+                        persistent_keepalive_interval: p.persistent_keepalive_interval,
+                    },
+                }
+            }
+            BatchOutcome::PeerUpdated(p) => {
+                manager.publish(netns.as_ref(), device_name, Change::peer_updated(&p));
+                BatchResult::UpdatePeer {
+                    peer: UpdatePeerResponse {
+                        public_key: p.public_key,
+                        allowed_ips: p.allowed_ips,
+                        persistent_keepalive_interval: p.persistent_keepalive_interval,
+                    },
+                }
+            }
+            BatchOutcome::PeerDeleted => {
+                if let BatchStep::DeletePeer { public_key, .. } = step {
+                    let change = Change::PeerRemoved {
+                        public_key: public_key.clone(),
+                    };
+                    manager.publish(netns.as_ref(), device_name, change);
+                }
+                BatchResult::DeletePeer
+            }
+            BatchOutcome::AddressesSet(ip) => BatchResult::SetAddresses {
+                ip_addresses: ip_addresses(&ip),
+            },
+        };
+        results.push(result);
+    }
+
+    HttpResponse::Ok().json(BatchResponse { results })
+}
+
+// Validates the operation the way its own endpoint does, returning the action it needs.
+fn parse_operation(operation: BatchOperation) -> Result<(BatchStep, Action), String> {
+    let device_name = match &operation {
+        BatchOperation::CreateDevice { device_name, .. }
+        | BatchOperation::DeleteDevice { device_name }
+        | BatchOperation::AddPeer { device_name, .. }
+        | BatchOperation::UpdatePeer { device_name, .. }
+        | BatchOperation::DeletePeer { device_name, .. }
+        | BatchOperation::SetAddresses { device_name, .. } => device_name,
+    };
+    if device_name.is_empty() || device_name.len() > DEVICE_NAME_MAX_LEN {
+        return Err("device name must be 1 to 15 characters".to_owned());
+    }
+
+    let parsed = match operation {
+        BatchOperation::CreateDevice {
+            device_name,
+            port,
+            ip_addresses,
+        } => (
+            BatchStep::CreateDevice {
+                device_name,
+                port,
+                ip: parse_ip_addresses(&ip_addresses)?,
+            },
+            Action::ManageDevices,
+        ),
+        BatchOperation::DeleteDevice { device_name } => (
+            BatchStep::DeleteDevice { device_name },
+            Action::ManageDevices,
+        ),
+        BatchOperation::AddPeer {
+            device_name,
+            allowed_ips,
+            persistent_keepalive_interval,
+        } => {
+            validate_ip_list(&allowed_ips)?;
+            (
+                BatchStep::AddPeer {
+                    device_name,
+                    allowed_ips,
+                    persistent_keepalive_interval,
+                },
+                Action::ManagePeers,
+            )
+        }
+        BatchOperation::UpdatePeer {
+            device_name,
+            public_key,
+            allowed_ips,
+            persistent_keepalive_interval,
+        } => {
+            validate_public_key(&public_key)?;
+            validate_ip_list(&allowed_ips)?;
+            (
+                BatchStep::UpdatePeer {
+                    device_name,
+                    public_key,
+                    allowed_ips,
+                    persistent_keepalive_interval,
+                },
+                Action::ManagePeers,
+            )
+        }
+        BatchOperation::DeletePeer {
+            device_name,
+            public_key,
+        } => {
+            validate_public_key(&public_key)?;
+            (
+                BatchStep::DeletePeer {
+                    device_name,
+                    public_key,
+                },
+                Action::ManagePeers,
+            )
+        }
+        BatchOperation::SetAddresses {
+            device_name,
+            ip_addresses,
+        } => (
+            BatchStep::SetAddresses {
+                device_name,
+                ip: parse_ip_addresses(&ip_addresses)?,
+            },
+            Action::ManageDevices,
+        ),
+    };
+
+    Ok(parsed)
+}
+
+fn validate_public_key(public_key: &str) -> Result<(), String> {
+    if public_key.len() != PUBKEY_MAX_LEN {
+        return Err("public key must be 44 characters".to_owned());
+    }
+
+    Ok(())
+}
+
+fn parse_ip_addresses(ip_addresses: &DeviceIpAddr) -> Result<NetDevIp, String> {
+    if ip_addresses.ipv4.is_none() && ip_addresses.ipv6.is_none() {
+        return Err("you must provide at least one of ipv4 or ipv6".to_owned());
+    }
+
+    let ipv4 = ip_addresses.ipv4.as_deref().map(parse_ip).transpose()?;
+    let ipv6 = ip_addresses.ipv6.as_deref().map(parse_ip).transpose()?;
+    Ok(NetDevIp::new(ipv4, ipv6))
+}
+
+fn ip_addresses(ip: &NetDevIp) -> DeviceIpAddr {
+    DeviceIpAddr {
+        ipv4: ip.ipv4_str(),
+        ipv6: ip.ipv6_str(),
+    }
+}
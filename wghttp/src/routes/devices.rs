@@ -1,27 +1,50 @@
+use crate::etag::{IfMatch, device_etag};
 use crate::helpers::*;
+use crate::middleware::idempotency::replay_responses;
+use crate::middleware::rbac::{Action, Permissions};
 use crate::models::devices::*;
-use crate::models::errors::Error;
+use crate::models::errors::{Error, SystemError, system_error};
+use crate::models::events::Change;
 use crate::services::TunnelManager;
-use actix_web::{HttpResponse, Responder, delete, get, post, web};
-use domain::models::netdev::NetDevIp;
+use actix_web::http::header;
+use actix_web::middleware::from_fn;
+use actix_web::{HttpResponse, Responder, delete, get, post, put, web};
+use domain::models::firewall::Masquerade;
+use domain::models::netdev::{IpFamily, NetDevIp};
 
 #[utoipa::path(
     get,
     path = "/devices",
     tag = "devices",
+    params(NetNsParams),
     responses(
         (status = 200, description = "list of wireguard devices", body = [ListDeviceResponse])
     )
 )]
 #[get("/devices")]
-async fn list_devices(tm: web::Data<TunnelManager>) -> impl Responder {
+async fn list_devices(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    params: web::Query<NetNsParams>,
+) -> impl Responder {
+    let netns = match parse_netns(params.netns.as_deref(), params.netns_pid) {
+        Err(message) => return HttpResponse::BadRequest().json(Error::invalid(message)),
+        Ok(netns) => netns,
+    };
+
     let manager = tm.get_ref();
+    let _netns = match manager.enter_netns(netns.as_ref()) {
+        Err(e) => return system_error(e),
+        Ok(guard) => guard,
+    };
+
     let devices = manager.wireguard.list_devices();
     match devices {
-        Err(e) => HttpResponse::InternalServerError().json(Error { message: e.0 }),
+        Err(e) => system_error(e),
         Ok(wgdevs) => {
             let out: Vec<ListDeviceResponse> = wgdevs
                 .into_iter()
+                .filter(|d| perms.can_access(&d.name))
                 .map(|d| ListDeviceResponse {
                     device_name: d.name,
                     port: d.port,
@@ -37,29 +60,66 @@ async fn list_devices(tm: web::Data<TunnelManager>) -> impl Responder {
     post,
     path = "/devices",
     tag = "devices",
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "replays the response to an earlier request with the same key and body"),
+        NetNsParams
+    ),
     request_body = CreateDeviceRequest,
     responses(
         (status = 201, description = "device created successfully", body = CreateDeviceResponse),
         (status = 400, description = "validation error", body = Error),
-        (status = 409, description = "conflict error", body = Error),
+        (status = 403, description = "permission denied", body = Error),
+        (status = 409, description = "conflict error, or a request with the idempotency key is in progress", body = Error),
+        (status = 422, description = "rejected by the system, or the idempotency key was used with a different request", body = Error),
         (status = 500, description = "system error", body = Error),
+        (status = 503, description = "system resources unavailable", body = Error),
     )
 )]
-#[post("/devices")]
+#[post("/devices", wrap = "from_fn(replay_responses)")]
 async fn create_device(
     tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    params: web::Query<NetNsParams>,
     device: web::Json<CreateDeviceRequest>,
 ) -> impl Responder {
     if device.device_name.len() > DEVICE_NAME_MAX_LEN {
-        return HttpResponse::BadRequest().json(Error {
-            message: "device name must be at most 15 characters".to_owned(),
-        });
+        return HttpResponse::BadRequest()
+            .json(Error::invalid("device name must be at most 15 characters"));
+    }
+
+    if let Err(message) = perms.authorize(&device.device_name, Action::ManageDevices) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
+    }
+
+    let full_tunnel_table = device
+        .full_tunnel
+        .then(|| tm.full_tunnel_table(device.port))
+        .transpose();
+    let table = match full_tunnel_table {
+        Err(message) => return HttpResponse::BadRequest().json(Error::invalid(message)),
+        Ok(table) => table,
+    };
+
+    if let Some(nat) = &device.nat {
+        if tm.firewall.is_none() {
+            return HttpResponse::BadRequest().json(Error::invalid("firewall is not enabled"));
+        }
+
+        if nat
+            .egress_interface
+            .as_ref()
+            .is_some_and(|e| e.is_empty() || e.len() > DEVICE_NAME_MAX_LEN)
+        {
+            return HttpResponse::BadRequest().json(Error::invalid(
+                "egress interface must be 1 to 15 characters",
+            ));
+        }
     }
 
     if device.ip_addresses.ipv4.is_none() && device.ip_addresses.ipv6.is_none() {
-        return HttpResponse::BadRequest().json(Error {
-            message: "you must provide at least one of ipv4 or ipv6".to_owned(),
-        });
+        return HttpResponse::BadRequest().json(Error::invalid(
+            "you must provide at least one of ipv4 or ipv6",
+        ));
     }
 
     let parsed_ipv4 = device
@@ -68,7 +128,7 @@ async fn create_device(
         .as_ref()
         .map(|s| parse_ip(s))
         .transpose()
-        .map_err(|e| Error { message: e });
+        .map_err(Error::invalid);
 
     let ipv4 = match parsed_ipv4 {
         Ok(opt_ip) => opt_ip,
@@ -81,20 +141,29 @@ async fn create_device(
         .as_ref()
         .map(|s| parse_ip(s))
         .transpose()
-        .map_err(|e| Error { message: e });
+        .map_err(Error::invalid);
 
     let ipv6 = match parsed_ipv6 {
         Ok(opt_ip) => opt_ip,
         Err(e) => return HttpResponse::BadRequest().json(e),
     };
 
+    let netns = match parse_netns(params.netns.as_deref(), params.netns_pid) {
+        Err(message) => return HttpResponse::BadRequest().json(Error::invalid(message)),
+        Ok(netns) => netns,
+    };
+
     let manager = tm.get_ref();
+    let _netns = match manager.enter_netns(netns.as_ref()) {
+        Err(e) => return system_error(e),
+        Ok(guard) => guard,
+    };
 
     let wg_result = manager
         .wireguard
         .create_device(&device.device_name, device.port);
     if let Err(e) = wg_result {
-        return HttpResponse::Conflict().json(Error { message: e.0 });
+        return system_error(e);
     }
 
     let ip = NetDevIp::new(ipv4, ipv6);
@@ -103,15 +172,45 @@ async fn create_device(
         .set_ip(&device.device_name, &ip)
         .and_then(|_| manager.netdev.up(&device.device_name));
     if let Err(e) = netdev_result {
-        return HttpResponse::BadRequest().json(Error { message: e.0 });
+        return system_error(e);
     }
 
     let Ok(d) = wg_result else {
-        return HttpResponse::InternalServerError().json(Error {
-            message: "system error".to_string(),
-        });
+        return HttpResponse::InternalServerError()
+            .json(Error::new("system_error", "system error"));
     };
 
+    if let Some(table) = table {
+        let full_tunnel_result = manager
+            .wireguard
+            .set_fwmark(&d.name, table)
+            .map_err(SystemError::from)
+            .and_then(|_| {
+                manager
+                    .setup_full_tunnel(&d.name, table, &ip)
+                    .map_err(SystemError::from)
+            });
+        if let Err(e) = full_tunnel_result {
+            let _ = manager.teardown_full_tunnel(&d.name, table);
+            let _ = manager.wireguard.delete_device(&d.name);
+            return e.response();
+        }
+    }
+
+    if let Some(nat) = &device.nat {
+        let masquerade = Masquerade {
+            egress_interface: nat.egress_interface.clone(),
+        };
+        if let Err(e) = manager.setup_nat(&d.name, &masquerade, &ip) {
+            let _ = manager.teardown_firewall(&d.name);
+            if let Some(table) = table {
+                let _ = manager.teardown_full_tunnel(&d.name, table);
+            }
+            let _ = manager.wireguard.delete_device(&d.name);
+            return system_error(e);
+        }
+    }
+
     let dev = CreateDeviceResponse {
         device_name: d.name,
         port: d.port,
@@ -119,9 +218,14 @@ async fn create_device(
             ipv4: ip.ipv4_str(),
             ipv6: ip.ipv6_str(),
         },
-        private_key: d.private_key,
+        full_tunnel: device.full_tunnel,
+        nat: device.nat.as_ref().map(|n| NatConfig {
+            egress_interface: n.egress_interface.clone(),
+        }),
+        private_key: perms.secrets.then_some(d.private_key),
         public_key: d.public_key,
     };
+    manager.publish(netns.as_ref(), &dev.device_name, Change::DeviceCreated);
     HttpResponse::Created().json(dev)
 }
 
@@ -130,41 +234,81 @@ async fn create_device(
     path = "/devices/{dev}",
     tag = "devices",
     params(
-        ("dev", description = "device name")
+        ("dev", description = "device name"),
+        NetNsParams
     ),
     responses(
-        (status = 200, description = "device found", body = DetailDeviceResponse),
+        (status = 200, description = "device found", body = DetailDeviceResponse,
+            headers(("ETag" = String, description = "version of the device, for If-Match"))),
         (status = 400, description = "validation error", body = Error),
+        (status = 403, description = "permission denied", body = Error),
         (status = 404, description = "device not found"),
         (status = 500, description = "system error", body = Error),
+        (status = 503, description = "system resources unavailable", body = Error),
     )
 )]
 #[get("/devices/{dev}")]
-async fn get_device(tm: web::Data<TunnelManager>, path: web::Path<String>) -> impl Responder {
+async fn get_device(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    params: web::Query<NetNsParams>,
+    path: web::Path<String>,
+) -> impl Responder {
     let dev_name = path.into_inner();
     if dev_name.len() > DEVICE_NAME_MAX_LEN {
-        return HttpResponse::BadRequest().json(Error {
-            message: "device name must be at most 15 characters".to_owned(),
-        });
+        return HttpResponse::BadRequest()
+            .json(Error::invalid("device name must be at most 15 characters"));
+    }
+
+    if let Err(message) = perms.authorize(&dev_name, Action::Read) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
     }
 
+    let netns = match parse_netns(params.netns.as_deref(), params.netns_pid) {
+        Err(message) => return HttpResponse::BadRequest().json(Error::invalid(message)),
+        Ok(netns) => netns,
+    };
+
     let manager = tm.get_ref();
+    let _netns = match manager.enter_netns(netns.as_ref()) {
+        Err(e) => return system_error(e),
+        Ok(guard) => guard,
+    };
+
     let wg_result = manager.wireguard.get_device(&dev_name);
     if let Err(e) = wg_result {
-        return HttpResponse::NotFound().json(Error { message: e.0 });
+        return system_error(e);
     }
 
     let netdev_result = manager.netdev.get_ip(&dev_name);
     if let Err(e) = netdev_result {
-        return HttpResponse::InternalServerError().json(Error { message: e.0 });
+        return system_error(e);
     }
 
     let (Ok(d), Ok(ip)) = (wg_result, netdev_result) else {
-        return HttpResponse::InternalServerError().json(Error {
-            message: "system error".to_string(),
-        });
+        return HttpResponse::InternalServerError()
+            .json(Error::new("system_error", "system error"));
     };
 
+    let nat_result = match &manager.firewall {
+        None => Ok(None),
+        Some(firewall) => firewall.get_masquerade(&d.name).and_then(|m| {
+            m.map(|m| {
+                Ok(NatState {
+                    egress_interface: m.egress_interface,
+                    ipv4_forwarding: firewall.get_forwarding(IpFamily::V4)?,
+                    ipv6_forwarding: firewall.get_forwarding(IpFamily::V6)?,
+                })
+            })
+            .transpose()
+        }),
+    };
+    let nat = match nat_result {
+        Ok(nat) => nat,
+        Err(e) => return system_error(e),
+    };
+
+    let etag = device_etag(&d, &ip);
     let out = DetailDeviceResponse {
         device_name: d.name,
         port: d.port,
@@ -172,10 +316,14 @@ async fn get_device(tm: web::Data<TunnelManager>, path: web::Path<String>) -> im
             ipv4: ip.ipv4_str(),
             ipv6: ip.ipv6_str(),
         },
+        full_tunnel: d.fwmark != 0,
+        nat,
         public_key: d.public_key,
         peers: d.peers,
     };
-    HttpResponse::Ok().json(out)
+    HttpResponse::Ok()
+        .insert_header((header::ETAG, etag))
+        .json(out)
 }
 
 #[utoipa::path(
@@ -183,26 +331,224 @@ async fn get_device(tm: web::Data<TunnelManager>, path: web::Path<String>) -> im
     path = "/devices/{dev}",
     tag = "devices",
     params(
-        ("dev", description = "device name")
+        ("dev", description = "device name"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the device as last read; required in strict mode"),
+        NetNsParams
     ),
     responses(
         (status = 204, description = "successfully deleted"),
         (status = 400, description = "validation error", body = Error),
-        (status = 404, description = "device not found")
+        (status = 403, description = "permission denied", body = Error),
+        (status = 404, description = "device not found"),
+        (status = 412, description = "the device was changed since it was read", body = Error),
+        (status = 428, description = "If-Match is required", body = Error),
+        (status = 500, description = "system error", body = Error),
+        (status = 503, description = "system resources unavailable", body = Error),
     )
 )]
 #[delete("/devices/{dev}")]
-async fn delete_device(tm: web::Data<TunnelManager>, path: web::Path<String>) -> impl Responder {
+async fn delete_device(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    if_match: IfMatch,
+    params: web::Query<NetNsParams>,
+    path: web::Path<String>,
+) -> impl Responder {
     let dev_name = path.into_inner();
     if dev_name.len() > DEVICE_NAME_MAX_LEN {
-        return HttpResponse::BadRequest().json(Error {
-            message: "device name must be at most 15 characters".to_owned(),
-        });
+        return HttpResponse::BadRequest()
+            .json(Error::invalid("device name must be at most 15 characters"));
     }
 
+    if let Err(message) = perms.authorize(&dev_name, Action::ManageDevices) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
+    }
+
+    let netns = match parse_netns(params.netns.as_deref(), params.netns_pid) {
+        Err(message) => return HttpResponse::BadRequest().json(Error::invalid(message)),
+        Ok(netns) => netns,
+    };
+
     let manager = tm.get_ref();
-    match manager.wireguard.delete_device(&dev_name) {
-        Err(e) => HttpResponse::NotFound().json(Error { message: e.0 }),
-        Ok(()) => HttpResponse::NoContent().finish(),
+    let _netns = match manager.enter_netns(netns.as_ref()) {
+        Err(e) => return system_error(e),
+        Ok(guard) => guard,
+    };
+
+    if if_match.is_checked() {
+        let etag = match current_etag(manager, &dev_name) {
+            Err(e) => return e.response(),
+            Ok(etag) => etag,
+        };
+        if let Err(e) = if_match.check(&etag) {
+            return e.response();
+        }
     }
+
+    let fwmark = manager
+        .wireguard
+        .get_device(&dev_name)
+        .map(|d| d.fwmark)
+        .unwrap_or(0);
+
+    if let Err(e) = manager.wireguard.delete_device(&dev_name) {
+        return system_error(e);
+    }
+    manager.publish(netns.as_ref(), &dev_name, Change::DeviceDeleted);
+
+    // routes in the full tunnel table are gone with the device, the rules are not.
+    if fwmark != 0
+        && let Err(e) = manager.teardown_full_tunnel(&dev_name, fwmark)
+    {
+        return system_error(e);
+    }
+
+    if let Err(e) = manager.teardown_firewall(&dev_name) {
+        return system_error(e);
+    }
+
+    HttpResponse::NoContent().finish()
+}
+
+#[utoipa::path(
+    put,
+    path = "/devices/{dev}/netns",
+    tag = "devices",
+    params(
+        ("dev", description = "device name"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the device as last read; required in strict mode"),
+        NetNsParams
+    ),
+    request_body = MoveDeviceRequest,
+    responses(
+        (status = 204, description = "successfully moved"),
+        (status = 400, description = "validation error", body = Error),
+        (status = 403, description = "permission denied", body = Error),
+        (status = 404, description = "device or namespace not found", body = Error),
+        (status = 409, description = "the device has firewall rules and drop_firewall is not set", body = Error),
+        (status = 412, description = "the device was changed since it was read", body = Error),
+        (status = 428, description = "If-Match is required", body = Error),
+        (status = 422, description = "rejected by the system", body = Error),
+        (status = 500, description = "system error", body = Error),
+        (status = 503, description = "system resources unavailable", body = Error),
+    )
+)]
+#[put("/devices/{dev}/netns")]
+async fn move_device(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    if_match: IfMatch,
+    params: web::Query<NetNsParams>,
+    path: web::Path<String>,
+    target: web::Json<MoveDeviceRequest>,
+) -> impl Responder {
+    let dev_name = path.into_inner();
+    if dev_name.len() > DEVICE_NAME_MAX_LEN {
+        return HttpResponse::BadRequest()
+            .json(Error::invalid("device name must be at most 15 characters"));
+    }
+
+    if let Err(message) = perms.authorize(&dev_name, Action::ManageDevices) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
+    }
+
+    let netns = match parse_netns(params.netns.as_deref(), params.netns_pid) {
+        Err(message) => return HttpResponse::BadRequest().json(Error::invalid(message)),
+        Ok(netns) => netns,
+    };
+
+    let drop_firewall = target.drop_firewall;
+    let target = match parse_netns(target.netns.as_deref(), target.netns_pid) {
+        Err(message) => return HttpResponse::BadRequest().json(Error::invalid(message)),
+        Ok(None) => {
+            return HttpResponse::BadRequest()
+                .json(Error::invalid("one of netns or netns_pid is required"));
+        }
+        Ok(Some(target)) => target,
+    };
+
+    let manager = tm.get_ref();
+    let source_netns = match manager.enter_netns(netns.as_ref()) {
+        Err(e) => return system_error(e),
+        Ok(guard) => guard,
+    };
+
+    let d = match manager.wireguard.get_device(&dev_name) {
+        Err(e) => return system_error(e),
+        Ok(d) => d,
+    };
+
+    // the policy routing rules of a full tunnel would be left behind in this namespace.
+    if d.fwmark != 0 {
+        return HttpResponse::BadRequest()
+            .json(Error::invalid("full tunnel devices cannot be moved"));
+    }
+
+    let state = manager
+        .netdev
+        .get_ip(&dev_name)
+        .map_err(SystemError::from)
+        .and_then(|ip| {
+            let peers = manager.wireguard.list_peers(&dev_name)?;
+            Ok((ip, peers))
+        });
+    let (ip, peers) = match state {
+        Err(e) => return e.response(),
+        Ok(state) => state,
+    };
+
+    if let Err(e) = if_match.check(&device_etag(&d, &ip)) {
+        return e.response();
+    }
+
+    // nftables rules match the device by name in this namespace, they cannot follow it.
+    let has_firewall_rules = match manager.has_firewall_rules(&dev_name) {
+        Err(e) => return system_error(e),
+        Ok(found) => found,
+    };
+    if has_firewall_rules && !drop_firewall {
+        return HttpResponse::Conflict().json(Error::new(
+            "firewall_rules_exist",
+            "device has firewall rules, set drop_firewall to move it without them",
+        ));
+    }
+
+    // the udp socket of the device stays in this namespace, only the interface moves.
+    if let Err(e) = manager.netdev.set_netns(&dev_name, &target) {
+        return system_error(e);
+    }
+    manager.publish(netns.as_ref(), &dev_name, Change::DeviceDeleted);
+    manager.publish(Some(&target), &dev_name, Change::DeviceCreated);
+
+    if has_firewall_rules && let Err(e) = manager.teardown_firewall(&dev_name) {
+        return system_error(e);
+    }
+    drop(source_netns);
+
+    let _target_netns = match manager.enter_netns(Some(&target)) {
+        Err(e) => return system_error(e),
+        Ok(guard) => guard,
+    };
+
+    // addresses and routes do not survive the move, the interface comes up again without them.
+    let result = manager
+        .netdev
+        .set_ip(&dev_name, &ip)
+        .and_then(|_| manager.netdev.up(&dev_name))
+        .and_then(|_| {
+            let allowed_ips: Vec<String> = peers.into_iter().flat_map(|p| p.allowed_ips).collect();
+            manager.peer_routes(&dev_name, &allowed_ips)
+        })
+        .and_then(|routes| manager.add_routes(&dev_name, &routes));
+    if let Err(e) = result {
+        return system_error(e);
+    }
+
+    HttpResponse::NoContent().finish()
+}
+
+fn current_etag(manager: &TunnelManager, dev_name: &str) -> Result<String, SystemError> {
+    let device = manager.wireguard.get_device(dev_name)?;
+    let ip = manager.netdev.get_ip(dev_name)?;
+    Ok(device_etag(&device, &ip))
 }
@@ -0,0 +1,150 @@
+use crate::middleware::rbac::Permissions;
+use crate::models::errors::Error;
+use crate::models::events::*;
+use crate::services::TunnelManager;
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse, Responder, get, web};
+use futures_util::future::{Either, select};
+use futures_util::{StreamExt, stream};
+use std::pin::pin;
+use std::time::Duration;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::broadcast::error::RecvError;
+
+// proxies close connections that stay silent for too long.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+enum Next {
+    Event(Event),
+    Lagged(u64),
+    Idle,
+    Closed,
+}
+
+// Waits for the next event the subscriber may see.
+async fn next_event(
+    rx: &mut Receiver<Event>,
+    perms: &Permissions,
+    query: &EventsQuery,
+    timeout: Duration,
+) -> Next {
+    loop {
+        match actix_web::rt::time::timeout(timeout, rx.recv()).await {
+            Err(_) => return Next::Idle,
+            Ok(Err(RecvError::Closed)) => return Next::Closed,
+            Ok(Err(RecvError::Lagged(missed))) => return Next::Lagged(missed),
+            Ok(Ok(event)) if perms.can_access(&event.device) && query.matches(&event) => {
+                return Next::Event(event);
+            }
+            Ok(Ok(_)) => continue,
+        }
+    }
+}
+
+fn lagged(missed: u64) -> String {
+    serde_json::json!({"type": "lagged", "missed": missed}).to_string()
+}
+
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "events",
+    params(EventsQuery),
+    responses(
+        (status = 200, description = "server-sent events of device and peer changes", body = Event, content_type = "text/event-stream"),
+        (status = 400, description = "events are not enabled", body = Error),
+    )
+)]
+#[get("/events")]
+async fn events(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    query: web::Query<EventsQuery>,
+) -> impl Responder {
+    let Some(bus) = &tm.events else {
+        return HttpResponse::BadRequest().json(Error::invalid("events are not enabled"));
+    };
+
+    let rx = bus.subscribe();
+    let state = (rx, perms, query.into_inner());
+    let body = stream::unfold(state, |(mut rx, perms, query)| async move {
+        let chunk = match next_event(&mut rx, &perms, &query, KEEP_ALIVE).await {
+            Next::Closed => return None,
+            Next::Idle => ": keep-alive\n\n".to_owned(),
+            Next::Lagged(missed) => format!("event: lagged\ndata: {}\n\n", lagged(missed)),
+            Next::Event(event) => {
+                let data = serde_json::to_string(&event).unwrap_or_default();
+                format!("event: {}\ndata: {}\n\n", event.change.name(), data)
+            }
+        };
+        Some((
+            Ok::<_, actix_web::Error>(Bytes::from(chunk)),
+            (rx, perms, query),
+        ))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(body)
+}
+
+#[utoipa::path(
+    get,
+    path = "/events/ws",
+    tag = "events",
+    params(EventsQuery),
+    responses(
+        (status = 101, description = "websocket sending the events as json text messages", body = Event),
+        (status = 400, description = "events are not enabled, or not a websocket request", body = Error),
+    )
+)]
+#[get("/events/ws")]
+async fn events_ws(
+    req: HttpRequest,
+    payload: web::Payload,
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    query: web::Query<EventsQuery>,
+) -> impl Responder {
+    let Some(bus) = &tm.events else {
+        return HttpResponse::BadRequest().json(Error::invalid("events are not enabled"));
+    };
+
+    let (response, mut session, mut messages) = match actix_ws::handle(&req, payload) {
+        Err(e) => return HttpResponse::BadRequest().json(Error::invalid(e.to_string())),
+        Ok(ws) => ws,
+    };
+
+    let mut rx = bus.subscribe();
+    let query = query.into_inner();
+    actix_web::rt::spawn(async move {
+        loop {
+            let event = pin!(next_event(&mut rx, &perms, &query, KEEP_ALIVE));
+            let message = messages.next();
+            let sent = match select(event, message).await {
+                Either::Left((Next::Closed, _)) => break,
+                Either::Left((Next::Idle, _)) => session.ping(b"").await,
+                Either::Left((Next::Lagged(missed), _)) => session.text(lagged(missed)).await,
+                Either::Left((Next::Event(event), _)) => {
+                    let data = serde_json::to_string(&event).unwrap_or_default();
+                    session.text(data).await
+                }
+                Either::Right((Some(Ok(actix_ws::Message::Ping(bytes))), _)) => {
+                    session.pong(&bytes).await
+                }
+                // the client only answers pings and eventually closes.
+                Either::Right((Some(Ok(actix_ws::Message::Close(_))) | Some(Err(_)) | None, _)) => {
+                    break;
+                }
+                Either::Right((Some(Ok(_)), _)) => Ok(()),
+            };
+            if sent.is_err() {
+                return;
+            }
+        }
+        let _ = session.close(None).await;
+    });
+
+    response
+}
@@ -0,0 +1,250 @@
+use crate::helpers::*;
+use crate::middleware::rbac::{Action, Permissions};
+use crate::models::errors::{Error, system_error};
+use crate::models::forwards::*;
+use crate::services::TunnelManager;
+use actix_web::{HttpResponse, Responder, delete, get, post, web};
+use domain::models::firewall::{PortForward, Protocol};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+pub fn to_protocol(protocol: ForwardProtocol) -> Protocol {
+    match protocol {
+        ForwardProtocol::Tcp => Protocol::Tcp,
+        ForwardProtocol::Udp => Protocol::Udp,
+    }
+}
+
+pub fn from_protocol(protocol: Protocol) -> ForwardProtocol {
+    match protocol {
+        Protocol::Tcp => ForwardProtocol::Tcp,
+        Protocol::Udp => ForwardProtocol::Udp,
+    }
+}
+
+fn to_response(forward: PortForward) -> ForwardResponse {
+    ForwardResponse {
+        protocol: from_protocol(forward.protocol),
+        public_port: forward.public_port,
+        destination_ip: forward.destination_ip.to_string(),
+        destination_port: forward.destination_port,
+    }
+}
+
+fn validate_path(dev: &str, public_key: &str) -> Result<(), Error> {
+    if dev.len() > DEVICE_NAME_MAX_LEN {
+        return Err(Error::invalid("device name must be at most 15 characters"));
+    }
+
+    if public_key.len() != PUBKEY_MAX_LEN {
+        return Err(Error::invalid("public key must be 44 characters"));
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/devices/{dev}/peers/{public_key}/forwards",
+    tag = "forwards",
+    params(
+        ("dev", description = "device name"),
+        ("public_key", description = "peer' public key")
+    ),
+    responses(
+        (status = 200, description = "ports forwarded to the peer", body = [ForwardResponse]),
+        (status = 400, description = "validation error", body = Error),
+        (status = 403, description = "permission denied", body = Error),
+        (status = 500, description = "system error", body = Error),
+    )
+)]
+#[get("/devices/{dev}/peers/{public_key}/forwards")]
+async fn list_forwards(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (dev, public_key) = path.into_inner();
+    if let Err(e) = validate_path(&dev, &public_key) {
+        return HttpResponse::BadRequest().json(e);
+    }
+
+    if let Err(message) = perms.authorize(&dev, Action::Read) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
+    }
+
+    let Some(firewall) = &tm.firewall else {
+        return HttpResponse::BadRequest().json(Error::invalid("firewall is not enabled"));
+    };
+
+    match firewall.list_forwards(&dev) {
+        Err(e) => system_error(e),
+        Ok(forwards) => {
+            let out: Vec<ForwardResponse> = forwards
+                .into_iter()
+                .filter(|f| f.peer_public_key == public_key)
+                .map(to_response)
+                .collect();
+            HttpResponse::Ok().json(out)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/devices/{dev}/peers/{public_key}/forwards",
+    tag = "forwards",
+    params(
+        ("dev", description = "device name"),
+        ("public_key", description = "peer' public key")
+    ),
+    request_body = CreateForwardRequest,
+    responses(
+        (status = 201, description = "port forwarded successfully", body = ForwardResponse),
+        (status = 400, description = "validation error", body = Error),
+        (status = 403, description = "permission denied", body = Error),
+        (status = 404, description = "device or peer not found"),
+        (status = 409, description = "public port already forwarded", body = Error),
+        (status = 500, description = "system error", body = Error),
+    )
+)]
+#[post("/devices/{dev}/peers/{public_key}/forwards")]
+async fn create_forward(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    path: web::Path<(String, String)>,
+    forward: web::Json<CreateForwardRequest>,
+) -> impl Responder {
+    let (dev, public_key) = path.into_inner();
+    if let Err(e) = validate_path(&dev, &public_key) {
+        return HttpResponse::BadRequest().json(e);
+    }
+
+    if let Err(message) = perms.authorize(&dev, Action::ManagePeers) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
+    }
+
+    let Some(firewall) = &tm.firewall else {
+        return HttpResponse::BadRequest().json(Error::invalid("firewall is not enabled"));
+    };
+
+    if forward.public_port == 0 || forward.destination_port == 0 {
+        return HttpResponse::BadRequest()
+            .json(Error::invalid("ports must be between 1 and 65535"));
+    }
+
+    let Ok(destination_ip) = IpAddr::from_str(&forward.destination_ip) else {
+        return HttpResponse::BadRequest().json(Error::invalid(format!(
+            "invalid ip address: {}",
+            forward.destination_ip
+        )));
+    };
+
+    let manager = tm.get_ref();
+    let peer = match manager.wireguard.list_peers(&dev) {
+        Err(e) => return system_error(e),
+        Ok(peers) => peers.into_iter().find(|p| p.public_key == public_key),
+    };
+
+    let Some(peer) = peer else {
+        return HttpResponse::NotFound().json(Error::new("peer_not_found", "peer not found"));
+    };
+
+    let host_prefix = if destination_ip.is_ipv4() { 32 } else { 128 };
+    let routed_to_peer = peer
+        .allowed_ips
+        .iter()
+        .filter_map(|ip| parse_ip(ip).ok())
+        .any(|subnet| subnet_contains(subnet, (destination_ip, host_prefix)));
+    if !routed_to_peer {
+        return HttpResponse::BadRequest().json(Error::invalid(
+            "destination ip is not within the peer's allowed ips",
+        ));
+    }
+
+    let protocol = to_protocol(forward.protocol);
+    match firewall.list_forwards(&dev) {
+        Err(e) => return system_error(e),
+        Ok(forwards) => {
+            if forwards
+                .iter()
+                .any(|f| f.protocol == protocol && f.public_port == forward.public_port)
+            {
+                return HttpResponse::Conflict().json(Error::new(
+                    "forward_exists",
+                    "public port is already forwarded",
+                ));
+            }
+        }
+    }
+
+    let port_forward = PortForward {
+        peer_public_key: public_key,
+        protocol,
+        public_port: forward.public_port,
+        destination_ip,
+        destination_port: forward.destination_port,
+    };
+    if let Err(e) = manager.add_forward(&dev, &port_forward) {
+        return system_error(e);
+    }
+
+    HttpResponse::Created().json(to_response(port_forward))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/devices/{dev}/peers/{public_key}/forwards/{protocol}/{public_port}",
+    tag = "forwards",
+    params(
+        ("dev", description = "device name"),
+        ("public_key", description = "peer' public key"),
+        ("protocol", description = "tcp or udp"),
+        ("public_port", description = "forwarded port on the host")
+    ),
+    responses(
+        (status = 204, description = "successfully deleted"),
+        (status = 400, description = "validation error", body = Error),
+        (status = 403, description = "permission denied", body = Error),
+        (status = 404, description = "forward not found", body = Error),
+        (status = 500, description = "system error", body = Error),
+    )
+)]
+#[delete("/devices/{dev}/peers/{public_key}/forwards/{protocol}/{public_port}")]
+async fn delete_forward(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    path: web::Path<(String, String, ForwardProtocol, u16)>,
+) -> impl Responder {
+    let (dev, public_key, protocol, public_port) = path.into_inner();
+    if let Err(e) = validate_path(&dev, &public_key) {
+        return HttpResponse::BadRequest().json(e);
+    }
+
+    if let Err(message) = perms.authorize(&dev, Action::ManagePeers) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
+    }
+
+    let Some(firewall) = &tm.firewall else {
+        return HttpResponse::BadRequest().json(Error::invalid("firewall is not enabled"));
+    };
+
+    let protocol = to_protocol(protocol);
+    let found = match firewall.list_forwards(&dev) {
+        Err(e) => return system_error(e),
+        Ok(forwards) => forwards.into_iter().any(|f| {
+            f.peer_public_key == public_key
+                && f.protocol == protocol
+                && f.public_port == public_port
+        }),
+    };
+
+    if !found {
+        return HttpResponse::NotFound().json(Error::new("forward_not_found", "forward not found"));
+    }
+
+    match firewall.delete_forward(&dev, protocol, public_port) {
+        Err(e) => system_error(e),
+        Ok(()) => HttpResponse::NoContent().finish(),
+    }
+}
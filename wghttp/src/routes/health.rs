@@ -1,15 +1,74 @@
-use actix_web::{HttpResponse, Responder, get};
+use crate::health::readiness;
+use crate::middleware::audit::AuditLog;
+use crate::models::errors::Error;
+use crate::models::health::*;
+use crate::services::TunnelManager;
+use crate::webhooks::Webhooks;
+use actix_web::{HttpResponse, Responder, get, web};
 
 #[utoipa::path(
     get,
     path = "/",
     tag = "health",
     responses(
-        (status = 200, description = "service is healthy"),
-        (status = 503, description = "service is unhealthy")
+        (status = 200, description = "service is running"),
     )
 )]
 #[get("/")]
 async fn health() -> impl Responder {
     HttpResponse::Ok()
 }
+
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "health",
+    responses(
+        (status = 200, description = "service is running; nothing but the process is checked", body = Health),
+    )
+)]
+#[get("/healthz")]
+async fn healthz() -> impl Responder {
+    HttpResponse::Ok().json(Health {
+        status: CheckStatus::Ok,
+        checks: vec![],
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "health",
+    responses(
+        (status = 200, description = "every check passed", body = Health),
+        (status = 503, description = "a check failed", body = Health),
+    )
+)]
+#[get("/readyz")]
+async fn readyz(
+    tm: web::Data<TunnelManager>,
+    audit_log: Option<web::Data<AuditLog>>,
+    webhooks: Option<web::Data<Webhooks>>,
+) -> impl Responder {
+    let manager = tm.get_ref().clone();
+    // the checks run on a blocking thread, kept in the span of the request.
+    let span = tracing::Span::current();
+    let result = web::block(move || {
+        span.in_scope(|| {
+            readiness(
+                &manager,
+                audit_log.as_ref().map(|l| l.get_ref()),
+                webhooks.as_ref().map(|w| w.get_ref()),
+            )
+        })
+    })
+    .await;
+
+    match result {
+        Err(e) => {
+            HttpResponse::InternalServerError().json(Error::new("system_error", e.to_string()))
+        }
+        Ok(report) if report.status == CheckStatus::Ok => HttpResponse::Ok().json(report),
+        Ok(report) => HttpResponse::ServiceUnavailable().json(report),
+    }
+}
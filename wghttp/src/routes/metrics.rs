@@ -0,0 +1,78 @@
+use crate::helpers::parse_netns;
+use crate::metrics::{Metrics, PeerMetrics};
+use crate::middleware::rbac::Permissions;
+use crate::models::devices::NetNsParams;
+use crate::models::errors::{Error, system_error};
+use crate::services::TunnelManager;
+use actix_web::{HttpResponse, Responder, get, web};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "metrics",
+    params(NetNsParams),
+    responses(
+        (status = 200, description = "metrics in the prometheus text format", body = String, content_type = "text/plain"),
+        (status = 400, description = "validation error", body = Error),
+        (status = 500, description = "system error", body = Error),
+    )
+)]
+#[get("/metrics")]
+async fn metrics(
+    tm: web::Data<TunnelManager>,
+    metrics: Option<web::Data<Metrics>>,
+    perms: Permissions,
+    params: web::Query<NetNsParams>,
+) -> impl Responder {
+    let netns = match parse_netns(params.netns.as_deref(), params.netns_pid) {
+        Err(message) => return HttpResponse::BadRequest().json(Error::invalid(message)),
+        Ok(netns) => netns,
+    };
+
+    let manager = tm.get_ref();
+    let _netns = match manager.enter_netns(netns.as_ref()) {
+        Err(e) => return system_error(e),
+        Ok(guard) => guard,
+    };
+
+    let devices = match manager.wireguard.list_devices() {
+        Err(e) => return system_error(e),
+        Ok(devices) => devices,
+    };
+
+    let labels = metrics.as_ref().map(|m| m.labels).unwrap_or_default();
+    let mut peers = PeerMetrics::default();
+    for device in devices.iter().filter(|d| perms.can_access(&d.name)) {
+        peers.add_device(&labels, &device.name, device.peers);
+
+        // a device deleted since it was listed has no peers to report.
+        let Ok(device_peers) = manager.wireguard.list_peers(&device.name) else {
+            continue;
+        };
+        for peer in device_peers {
+            peers.add_peer(
+                &labels,
+                &device.name,
+                &peer.public_key,
+                peer.rx,
+                peer.tx,
+                peer.last_handshake_time,
+            );
+        }
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let mut out = String::new();
+    peers.render(&mut out, now);
+    if let Some(metrics) = &metrics {
+        metrics.render(&mut out);
+    }
+
+    HttpResponse::Ok().content_type(CONTENT_TYPE).body(out)
+}
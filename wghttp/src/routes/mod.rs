@@ -1,3 +1,66 @@
+pub mod acls;
+pub mod audit;
+pub mod batch;
 pub mod devices;
+pub mod events;
+pub mod forwards;
 pub mod health;
+pub mod metrics;
 pub mod peers;
+pub mod v1;
+pub mod webhooks;
+
+use crate::middleware::deprecation::mark_deprecated;
+use actix_web::middleware::from_fn;
+use actix_web::web;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::{SwaggerUi, Url};
+
+/// Endpoints outside of the versions of the api.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "wghttp",
+        description = "http service to manage wireguard devices",
+        version = "1.0.0",
+        license(name = "MIT")
+    ),
+    tags(
+        (name = "health", description = "liveness and readiness endpoints."),
+        (name = "metrics", description = "prometheus metrics endpoint.")
+    ),
+    paths(
+        health::health,
+        health::healthz,
+        health::readyz,
+        metrics::metrics,
+    )
+)]
+pub struct ApiDoc;
+
+/// Path of a request without its version prefix, e.g. `/devices` for `/v1/devices`.
+pub fn unversioned(path: &str) -> &str {
+    path.strip_prefix(v1::PREFIX)
+        .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+        .unwrap_or(path)
+}
+
+/// Registers every route: the unversioned endpoints, each version under its prefix with its own
+/// document, and the deprecated aliases of version 1 at the root.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(health::health)
+        .service(health::healthz)
+        .service(health::readyz)
+        .service(metrics::metrics)
+        .service(web::scope(v1::PREFIX).configure(v1::configure))
+        .service(SwaggerUi::new("/swagger-ui/{_:.*}").urls(vec![(
+            Url::new("v1", "/api-docs/v1/openapi.json"),
+            v1::openapi(),
+        )]))
+        // registered last, since a scope at the root takes every path not matched before it.
+        .service(
+            web::scope("")
+                .wrap(from_fn(mark_deprecated))
+                .configure(v1::configure),
+        );
+}
@@ -1,102 +1,313 @@
+use crate::etag::{IfMatch, peer_etag};
 use crate::helpers::*;
-use crate::models::errors::Error;
+use crate::middleware::idempotency::replay_responses;
+use crate::middleware::rbac::{Action, Permissions};
+use crate::models::devices::NetNsParams;
+use crate::models::errors::{Error, system_error};
+use crate::models::events::Change;
 use crate::models::peers::*;
 use crate::services::TunnelManager;
-use actix_web::{HttpResponse, Responder, delete, get, post, web};
+use actix_web::http::header;
+use actix_web::middleware::from_fn;
+use actix_web::{HttpResponse, Responder, delete, get, post, put, web};
+use domain::models::wg::WGPeer;
 
 #[utoipa::path(
     get,
     path = "/devices/{dev}/peers",
     tag = "peers",
     params(
-        ("dev", description = "device name")
+        ("dev", description = "device name"),
+        NetNsParams
     ),
     responses(
         (status = 200, description = "list of peers for given wireguard device", body = [ListPeerResponse]),
         (status = 400, description = "validation error", body = Error),
-        (status = 404, description = "device not found")
+        (status = 403, description = "permission denied", body = Error),
+        (status = 404, description = "device not found"),
     )
 )]
 #[get("/devices/{dev}/peers")]
-async fn list_peers(tm: web::Data<TunnelManager>, path: web::Path<String>) -> impl Responder {
+async fn list_peers(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    params: web::Query<NetNsParams>,
+    path: web::Path<String>,
+) -> impl Responder {
     let dev_name = path.into_inner();
     if dev_name.len() > DEVICE_NAME_MAX_LEN {
-        return HttpResponse::BadRequest().json(Error {
-            message: "device name must be at most 15 characters".to_owned(),
-        });
+        return HttpResponse::BadRequest()
+            .json(Error::invalid("device name must be at most 15 characters"));
+    }
+
+    if let Err(message) = perms.authorize(&dev_name, Action::Read) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
     }
 
+    let netns = match parse_netns(params.netns.as_deref(), params.netns_pid) {
+        Err(message) => return HttpResponse::BadRequest().json(Error::invalid(message)),
+        Ok(netns) => netns,
+    };
+
     let manager = tm.get_ref();
+    let _netns = match manager.enter_netns(netns.as_ref()) {
+        Err(e) => return system_error(e),
+        Ok(guard) => guard,
+    };
+
     let peers = manager.wireguard.list_peers(&dev_name);
     match peers {
-        Err(e) => HttpResponse::NotFound().json(Error { message: e.0 }),
+        Err(e) => system_error(e),
         Ok(wgpeers) => {
-            let out: Vec<ListPeerResponse> = wgpeers
-                .into_iter()
-                .map(|p| ListPeerResponse {
-                    public_key: p.public_key,
-                    endpoint: p.endpoint,
-                    allowed_ips: p.allowed_ips,
-                    last_handshake_time: p.last_handshake_time,
-                    persistent_keepalive_interval: p.persistent_keepalive_interval,
-                    rx: p.rx,
-                    tx: p.tx,
-                })
-                .collect();
+            let out: Vec<ListPeerResponse> = wgpeers.into_iter().map(peer_response).collect();
             HttpResponse::Ok().json(out)
         }
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/devices/{dev}/peers/{public_key}",
+    tag = "peers",
+    params(
+        ("dev", description = "device name"),
+        ("public_key", description = "peer' public key"),
+        NetNsParams
+    ),
+    responses(
+        (status = 200, description = "peer found", body = ListPeerResponse,
+            headers(("ETag" = String, description = "version of the peer, for If-Match"))),
+        (status = 400, description = "validation error", body = Error),
+        (status = 403, description = "permission denied", body = Error),
+        (status = 404, description = "device or peer not found", body = Error),
+        (status = 500, description = "system error", body = Error),
+        (status = 503, description = "system resources unavailable", body = Error),
+    )
+)]
+#[get("/devices/{dev}/peers/{public_key}")]
+async fn get_peer(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    params: web::Query<NetNsParams>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (dev, public_key) = path.into_inner();
+    if dev.len() > DEVICE_NAME_MAX_LEN {
+        return HttpResponse::BadRequest()
+            .json(Error::invalid("device name must be at most 15 characters"));
+    }
+
+    if let Err(message) = perms.authorize(&dev, Action::Read) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
+    }
+
+    if public_key.len() != PUBKEY_MAX_LEN {
+        return HttpResponse::BadRequest().json(Error::invalid("public key must be 44 characters"));
+    }
+
+    let netns = match parse_netns(params.netns.as_deref(), params.netns_pid) {
+        Err(message) => return HttpResponse::BadRequest().json(Error::invalid(message)),
+        Ok(netns) => netns,
+    };
+
+    let manager = tm.get_ref();
+    let _netns = match manager.enter_netns(netns.as_ref()) {
+        Err(e) => return system_error(e),
+        Ok(guard) => guard,
+    };
+
+    let peer = match manager.wireguard.list_peers(&dev) {
+        Err(e) => return system_error(e),
+        Ok(peers) => peers.into_iter().find(|p| p.public_key == public_key),
+    };
+
+    match peer {
+        None => HttpResponse::NotFound().json(Error::new("peer_not_found", "peer not found")),
+        Some(p) => {
+            let out = peer_response(p);
+            HttpResponse::Ok()
+                .insert_header((header::ETAG, out.etag.clone()))
+                .json(out)
+        }
+    }
+}
+
 #[utoipa::path(
     post,
     path = "/devices/{dev}/peers",
     tag = "peers",
     params(
-        ("dev", description = "device name")
+        ("dev", description = "device name"),
+        ("Idempotency-Key" = Option<String>, Header, description = "replays the response to an earlier request with the same key and body"),
+        NetNsParams
     ),
     request_body = CreatePeerRequest,
     responses(
         (status = 201, description = "peer created successfully", body = CreatePeerResponse),
         (status = 400, description = "validation error", body = Error),
+        (status = 403, description = "permission denied", body = Error),
         (status = 404, description = "device not found"),
+        (status = 409, description = "a request with the idempotency key is in progress", body = Error),
+        (status = 422, description = "the idempotency key was used with a different request", body = Error),
+        (status = 500, description = "system error", body = Error),
+        (status = 503, description = "system resources unavailable", body = Error),
     )
 )]
-#[post("/devices/{dev}/peers")]
+#[post("/devices/{dev}/peers", wrap = "from_fn(replay_responses)")]
 async fn create_peer(
     tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    params: web::Query<NetNsParams>,
     path: web::Path<String>,
     peer: web::Json<CreatePeerRequest>,
 ) -> impl Responder {
     let dev_name = path.into_inner();
     if dev_name.len() > DEVICE_NAME_MAX_LEN {
-        return HttpResponse::BadRequest().json(Error {
-            message: "device name must be at most 15 characters".to_owned(),
-        });
+        return HttpResponse::BadRequest()
+            .json(Error::invalid("device name must be at most 15 characters"));
+    }
+
+    if let Err(message) = perms.authorize(&dev_name, Action::ManagePeers) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
     }
 
     if let Err(e) = validate_ip_list(&peer.allowed_ips) {
-        return HttpResponse::BadRequest().json(Error { message: e });
+        return HttpResponse::BadRequest().json(Error::invalid(e));
     }
 
+    let netns = match parse_netns(params.netns.as_deref(), params.netns_pid) {
+        Err(message) => return HttpResponse::BadRequest().json(Error::invalid(message)),
+        Ok(netns) => netns,
+    };
+
     let manager = tm.get_ref();
+    let _netns = match manager.enter_netns(netns.as_ref()) {
+        Err(e) => return system_error(e),
+        Ok(guard) => guard,
+    };
+
     let ips: Vec<&str> = peer.allowed_ips.iter().map(|s| s.as_str()).collect();
     let result = manager
         .wireguard
         .add_peer(&dev_name, ips, peer.persistent_keepalive_interval);
-    match result {
-        Err(e) => HttpResponse::NotFound().json(Error { message: e.0 }),
-        Ok(wgpeer) => {
-            let peer = CreatePeerResponse {
-                public_key: wgpeer.public_key,
-                private_key: wgpeer.private_key,
-                preshared_key: wgpeer.preshared_key,
-                allowed_ips: wgpeer.allowed_ips,
-                persistent_keepalive_interval: wgpeer.persistent_keepalive_interval,
-            };
-            HttpResponse::Created().json(peer)
-        }
+    let wgpeer = match result {
+        Err(e) => return system_error(e),
+        Ok(wgpeer) => wgpeer,
+    };
+
+    let routes_result = manager
+        .peer_routes(&dev_name, &wgpeer.allowed_ips)
+        .and_then(|routes| manager.add_routes(&dev_name, &routes));
+    if let Err(e) = routes_result {
+        let _ = manager.wireguard.delete_peer(&dev_name, &wgpeer.public_key);
+        return system_error(e);
+    }
+    manager.publish(netns.as_ref(), &dev_name, Change::peer_added(&wgpeer));
+
+    let peer = CreatePeerResponse {
+        public_key: wgpeer.public_key,
+        private_key: perms.secrets.then_some(wgpeer.private_key),
+        preshared_key: perms.secrets.then_some(wgpeer.preshared_key),
+        allowed_ips: wgpeer.allowed_ips,
+        persistent_keepalive_interval: wgpeer.persistent_keepalive_interval,
+    };
+    HttpResponse::Created().json(peer)
+}
+
+#[utoipa::path(
+    put,
+    path = "/devices/{dev}/peers/{public_key}",
+    tag = "peers",
+    params(
+        ("dev", description = "device name"),
+        ("public_key", description = "peer' public key"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the peer as last read; required in strict mode"),
+        NetNsParams
+    ),
+    request_body = UpdatePeerRequest,
+    responses(
+        (status = 200, description = "peer updated successfully", body = UpdatePeerResponse),
+        (status = 400, description = "validation error", body = Error),
+        (status = 403, description = "permission denied", body = Error),
+        (status = 404, description = "device or peer not found"),
+        (status = 412, description = "the peer was changed since it was read", body = Error),
+        (status = 428, description = "If-Match is required", body = Error),
+        (status = 500, description = "system error", body = Error),
+        (status = 503, description = "system resources unavailable", body = Error),
+    )
+)]
+#[put("/devices/{dev}/peers/{public_key}")]
+async fn update_peer(
+    tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    if_match: IfMatch,
+    params: web::Query<NetNsParams>,
+    path: web::Path<(String, String)>,
+    peer: web::Json<UpdatePeerRequest>,
+) -> impl Responder {
+    let (dev, public_key) = path.into_inner();
+    if dev.len() > DEVICE_NAME_MAX_LEN {
+        return HttpResponse::BadRequest()
+            .json(Error::invalid("device name must be at most 15 characters"));
+    }
+
+    if let Err(message) = perms.authorize(&dev, Action::ManagePeers) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
+    }
+
+    if public_key.len() != PUBKEY_MAX_LEN {
+        return HttpResponse::BadRequest().json(Error::invalid("public key must be 44 characters"));
+    }
+
+    if let Err(e) = validate_ip_list(&peer.allowed_ips) {
+        return HttpResponse::BadRequest().json(Error::invalid(e));
+    }
+
+    let netns = match parse_netns(params.netns.as_deref(), params.netns_pid) {
+        Err(message) => return HttpResponse::BadRequest().json(Error::invalid(message)),
+        Ok(netns) => netns,
+    };
+
+    let manager = tm.get_ref();
+    let _netns = match manager.enter_netns(netns.as_ref()) {
+        Err(e) => return system_error(e),
+        Ok(guard) => guard,
+    };
+
+    let current = match manager.wireguard.list_peers(&dev) {
+        Err(e) => return system_error(e),
+        Ok(peers) => peers.into_iter().find(|p| p.public_key == public_key),
+    };
+
+    let Some(current) = current else {
+        return HttpResponse::NotFound().json(Error::new("peer_not_found", "peer not found"));
+    };
+
+    if let Err(e) = if_match.check(&peer_etag(&current)) {
+        return e.response();
     }
+
+    let result = manager.update_peer(
+        &dev,
+        &current,
+        &peer.allowed_ips,
+        peer.persistent_keepalive_interval,
+    );
+    let wgpeer = match result {
+        Err(e) => return e.response(),
+        Ok(wgpeer) => wgpeer,
+    };
+    manager.publish(netns.as_ref(), &dev, Change::peer_updated(&wgpeer));
+
+    let etag = peer_etag(&wgpeer);
+    let out = UpdatePeerResponse {
+        public_key: wgpeer.public_key,
+        allowed_ips: wgpeer.allowed_ips,
+        persistent_keepalive_interval: wgpeer.persistent_keepalive_interval,
+    };
+    HttpResponse::Ok()
+        .insert_header((header::ETAG, etag))
+        .json(out)
 }
 
 #[utoipa::path(
@@ -105,35 +316,88 @@ async fn create_peer(
     tag = "peers",
     params(
         ("dev", description = "device name"),
-        ("public_key", description = "peer' public key")
+        ("public_key", description = "peer' public key"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the peer as last read; required in strict mode"),
+        NetNsParams
     ),
     responses(
         (status = 204, description = "successfully deleted"),
         (status = 400, description = "validation error", body = Error),
-        (status = 404, description = "device or peer not found")
+        (status = 403, description = "permission denied", body = Error),
+        (status = 404, description = "device or peer not found"),
+        (status = 412, description = "the peer was changed since it was read", body = Error),
+        (status = 428, description = "If-Match is required", body = Error),
+        (status = 500, description = "system error", body = Error),
+        (status = 503, description = "system resources unavailable", body = Error),
     )
 )]
 #[delete("/devices/{dev}/peers/{public_key}")]
 async fn delete_peer(
     tm: web::Data<TunnelManager>,
+    perms: Permissions,
+    if_match: IfMatch,
+    params: web::Query<NetNsParams>,
     path: web::Path<(String, String)>,
 ) -> impl Responder {
     let (dev, public_key) = path.into_inner();
     if dev.len() > DEVICE_NAME_MAX_LEN {
-        return HttpResponse::BadRequest().json(Error {
-            message: "device name must be at most 15 characters".to_owned(),
-        });
+        return HttpResponse::BadRequest()
+            .json(Error::invalid("device name must be at most 15 characters"));
+    }
+
+    if let Err(message) = perms.authorize(&dev, Action::ManagePeers) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
     }
 
     if public_key.len() != PUBKEY_MAX_LEN {
-        return HttpResponse::BadRequest().json(Error {
-            message: "public key must be 44 characters".to_owned(),
-        });
+        return HttpResponse::BadRequest().json(Error::invalid("public key must be 44 characters"));
     }
 
+    let netns = match parse_netns(params.netns.as_deref(), params.netns_pid) {
+        Err(message) => return HttpResponse::BadRequest().json(Error::invalid(message)),
+        Ok(netns) => netns,
+    };
+
     let manager = tm.get_ref();
-    match manager.wireguard.delete_peer(&dev, &public_key) {
-        Err(e) => HttpResponse::NotFound().json(Error { message: e.0 }),
-        Ok(()) => HttpResponse::NoContent().finish(),
+    let _netns = match manager.enter_netns(netns.as_ref()) {
+        Err(e) => return system_error(e),
+        Ok(guard) => guard,
+    };
+
+    let current = manager
+        .wireguard
+        .list_peers(&dev)
+        .ok()
+        .and_then(|peers| peers.into_iter().find(|p| p.public_key == public_key));
+
+    if if_match.is_checked() {
+        let Some(current) = &current else {
+            return HttpResponse::NotFound().json(Error::new("peer_not_found", "peer not found"));
+        };
+        if let Err(e) = if_match.check(&peer_etag(current)) {
+            return e.response();
+        }
+    }
+
+    let allowed_ips = current.map(|p| p.allowed_ips).unwrap_or_default();
+    if let Err(e) = manager.delete_peer(&dev, &public_key, &allowed_ips) {
+        return e.response();
+    }
+
+    let change = Change::PeerRemoved { public_key };
+    manager.publish(netns.as_ref(), &dev, change);
+    HttpResponse::NoContent().finish()
+}
+
+fn peer_response(p: WGPeer) -> ListPeerResponse {
+    ListPeerResponse {
+        etag: peer_etag(&p),
+        public_key: p.public_key,
+        endpoint: p.endpoint,
+        allowed_ips: p.allowed_ips,
+        last_handshake_time: p.last_handshake_time,
+        persistent_keepalive_interval: p.persistent_keepalive_interval,
+        rx: p.rx,
+        tx: p.tx,
     }
 }
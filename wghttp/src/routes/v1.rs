@@ -0,0 +1,79 @@
+use super::*;
+use actix_web::web;
+use utoipa::OpenApi;
+
+pub const PREFIX: &str = "/v1";
+
+#[derive(OpenApi)]
+#[openapi(
+    tags(
+        (name = "devices", description = "device management endpoints."),
+        (name = "peers", description = "peer management endpoints."),
+        (name = "forwards", description = "port forwarding endpoints."),
+        (name = "acls", description = "peer access control endpoints."),
+        (name = "batch", description = "atomic batches of device and peer changes."),
+        (name = "audit", description = "audit log endpoints."),
+        (name = "events", description = "device and peer change events."),
+        (name = "webhooks", description = "webhook delivery endpoints.")
+    ),
+    paths(
+        devices::list_devices,
+        devices::create_device,
+        devices::get_device,
+        devices::delete_device,
+        devices::move_device,
+        peers::list_peers,
+        peers::get_peer,
+        peers::create_peer,
+        peers::update_peer,
+        peers::delete_peer,
+        forwards::list_forwards,
+        forwards::create_forward,
+        forwards::delete_forward,
+        acls::list_acls,
+        acls::create_acl,
+        acls::get_acl,
+        acls::update_acl,
+        acls::delete_acl,
+        batch::run_batch,
+        audit::list_audit_records,
+        events::events,
+        events::events_ws,
+        webhooks::list_deliveries,
+        webhooks::test_webhook,
+    )
+)]
+pub struct ApiDoc;
+
+/// Document of version 1, along with the endpoints every version shares.
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    super::ApiDoc::openapi().nest(PREFIX, ApiDoc::openapi())
+}
+
+/// Registers the handlers of version 1, relative to the prefix they are mounted under.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(devices::list_devices)
+        .service(devices::create_device)
+        .service(devices::get_device)
+        .service(devices::delete_device)
+        .service(devices::move_device)
+        .service(peers::list_peers)
+        .service(peers::get_peer)
+        .service(peers::create_peer)
+        .service(peers::update_peer)
+        .service(peers::delete_peer)
+        .service(forwards::list_forwards)
+        .service(forwards::create_forward)
+        .service(forwards::delete_forward)
+        .service(acls::list_acls)
+        .service(acls::create_acl)
+        .service(acls::get_acl)
+        .service(acls::update_acl)
+        .service(acls::delete_acl)
+        .service(batch::run_batch)
+        .service(audit::list_audit_records)
+        .service(events::events)
+        .service(events::events_ws)
+        .service(webhooks::list_deliveries)
+        .service(webhooks::test_webhook);
+}
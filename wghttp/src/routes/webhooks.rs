@@ -0,0 +1,83 @@
+use crate::middleware::rbac::{Action, Permissions};
+use crate::models::errors::Error;
+use crate::models::webhooks::*;
+use crate::webhooks::Webhooks;
+use actix_web::{HttpResponse, Responder, get, post, web};
+
+#[utoipa::path(
+    get,
+    path = "/webhooks/deliveries",
+    tag = "webhooks",
+    params(DeliveryQuery),
+    responses(
+        (status = 200, description = "delivery attempts, oldest first", body = [DeliveryRecord]),
+        (status = 400, description = "webhooks are not enabled or the log is not readable", body = Error),
+    )
+)]
+#[get("/webhooks/deliveries")]
+async fn list_deliveries(
+    webhooks: Option<web::Data<Webhooks>>,
+    perms: Permissions,
+    query: web::Query<DeliveryQuery>,
+) -> impl Responder {
+    let Some(webhooks) = webhooks else {
+        return HttpResponse::BadRequest().json(Error::invalid("webhooks are not enabled"));
+    };
+
+    let records = match webhooks.query(&query) {
+        Err(message) => return HttpResponse::BadRequest().json(Error::invalid(message)),
+        Ok(records) => records,
+    };
+
+    // records are visible to whoever may manage their device.
+    let out: Vec<DeliveryRecord> = records
+        .into_iter()
+        .filter(|r| {
+            let device = r.device.as_deref().unwrap_or("");
+            perms.authorize(device, Action::ManageDevices).is_ok()
+        })
+        .collect();
+    HttpResponse::Ok().json(out)
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks/{name}/test",
+    tag = "webhooks",
+    params(
+        ("name" = String, Path, description = "name of the subscriber"),
+    ),
+    responses(
+        (status = 200, description = "a ping event was posted to the subscriber, once", body = DeliveryRecord),
+        (status = 400, description = "webhooks are not enabled", body = Error),
+        (status = 403, description = "caller may not manage every device", body = Error),
+        (status = 404, description = "subscriber not found", body = Error),
+    )
+)]
+#[post("/webhooks/{name}/test")]
+async fn test_webhook(
+    webhooks: Option<web::Data<Webhooks>>,
+    perms: Permissions,
+    path: web::Path<String>,
+) -> impl Responder {
+    let Some(webhooks) = webhooks else {
+        return HttpResponse::BadRequest().json(Error::invalid("webhooks are not enabled"));
+    };
+
+    if let Err(message) = perms.authorize("*", Action::ManageDevices) {
+        return HttpResponse::Forbidden().json(Error::forbidden(message));
+    }
+
+    let name = path.into_inner();
+    let webhooks = webhooks.into_inner();
+    match web::block(move || webhooks.send_test(&name)).await {
+        Err(e) => {
+            HttpResponse::InternalServerError().json(Error::new("system_error", e.to_string()))
+        }
+        Ok(None) => HttpResponse::NotFound().json(Error::new(
+            "subscriber_not_found",
+            "webhook subscriber not found",
+        )),
+        Ok(Some(record)) => HttpResponse::Ok().json(record),
+    }
+}
@@ -0,0 +1,464 @@
+use super::{PeerRules, TunnelManager, rolled_back};
+use crate::models::errors::{Error, SystemError};
+use actix_web::http::StatusCode;
+use domain::models::netdev::{NetDevIp, NetDevRoute};
+use domain::models::wg::{WGDevice, WGPeer};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Condvar, Mutex};
+
+/// A validated operation of a batch.
+pub enum BatchStep {
+    CreateDevice {
+        device_name: String,
+        port: u16,
+        ip: NetDevIp,
+    },
+    DeleteDevice {
+        device_name: String,
+    },
+    AddPeer {
+        device_name: String,
+        allowed_ips: Vec<String>,
+        persistent_keepalive_interval: u16,
+    },
+    UpdatePeer {
+        device_name: String,
+        public_key: String,
+        allowed_ips: Vec<String>,
+        persistent_keepalive_interval: u16,
+    },
+    DeletePeer {
+        device_name: String,
+        public_key: String,
+    },
+    SetAddresses {
+        device_name: String,
+        ip: NetDevIp,
+    },
+}
+
+impl BatchStep {
+    pub fn device_name(&self) -> &str {
+        match self {
+            BatchStep::CreateDevice { device_name, .. }
+            | BatchStep::DeleteDevice { device_name }
+            | BatchStep::AddPeer { device_name, .. }
+            | BatchStep::UpdatePeer { device_name, .. }
+            | BatchStep::DeletePeer { device_name, .. }
+            | BatchStep::SetAddresses { device_name, .. } => device_name,
+        }
+    }
+
+    // Deletions cannot be undone, so a batch holds at most one and it is the last step.
+    fn is_deletion(&self) -> bool {
+        matches!(
+            self,
+            BatchStep::DeleteDevice { .. } | BatchStep::DeletePeer { .. }
+        )
+    }
+}
+
+pub enum BatchOutcome {
+    DeviceCreated(WGDevice, NetDevIp),
+    DeviceDeleted,
+    PeerAdded(WGPeer),
+    PeerUpdated(WGPeer),
+    PeerDeleted,
+    AddressesSet(NetDevIp),
+}
+
+/// Failure of the step at `index`; the steps applied before it have been rolled back.
+pub struct BatchFailure {
+    pub index: usize,
+    pub error: SystemError,
+}
+
+impl BatchFailure {
+    fn new(index: usize, error: impl Into<SystemError>) -> Self {
+        Self {
+            index,
+            error: error.into(),
+        }
+    }
+
+    fn rejected(index: usize, status: StatusCode, error: Error) -> Self {
+        Self::new(index, SystemError { status, error })
+    }
+}
+
+/// Devices with a batch in progress. A batch waits until none of its devices is taken by
+/// another one, so that the state it checked is still there when its steps run.
+#[derive(Default)]
+pub struct BatchLocks {
+    busy: Mutex<HashSet<String>>,
+    released: Condvar,
+}
+
+impl BatchLocks {
+    fn lock<'a>(&self, device_names: impl Iterator<Item = &'a str>) -> BatchLock<'_> {
+        let device_names: HashSet<String> = device_names.map(|d| d.to_owned()).collect();
+
+        let mut busy = self.busy.lock().unwrap_or_else(|e| e.into_inner());
+        while device_names.iter().any(|d| busy.contains(d)) {
+            busy = self.released.wait(busy).unwrap_or_else(|e| e.into_inner());
+        }
+        busy.extend(device_names.iter().cloned());
+
+        BatchLock {
+            locks: self,
+            device_names,
+        }
+    }
+}
+
+struct BatchLock<'a> {
+    locks: &'a BatchLocks,
+    device_names: HashSet<String>,
+}
+
+impl Drop for BatchLock<'_> {
+    fn drop(&mut self) {
+        let mut busy = self.locks.busy.lock().unwrap_or_else(|e| e.into_inner());
+        busy.retain(|d| !self.device_names.contains(d));
+        self.locks.released.notify_all();
+    }
+}
+
+// Reverts a change made by a step. Undoing is best effort, its failures are logged.
+enum Undo {
+    DeleteDevice(String),
+    DeletePeers(String, Vec<String>),
+    DeleteRoutes(String, Vec<NetDevRoute>),
+    ReplaceRoutes(String, Vec<NetDevRoute>, Vec<NetDevRoute>),
+    RestorePeer(String, WGPeer, PeerRules),
+    RestoreAddresses(String, NetDevIp),
+}
+
+impl TunnelManager {
+    /// Applies the steps as a whole. Every step is checked against the current state before
+    /// any of them runs, and the changes of the steps applied before a failing one are undone.
+    ///
+    /// Peers added to the same device are created with a single update of the device. A device
+    /// or peer deletion cannot be undone, so it can only be the last step. Batches touching the
+    /// same device run one after the other, waiting for each other on the calling thread.
+    pub fn run_batch(&self, steps: &[BatchStep]) -> Result<Vec<BatchOutcome>, BatchFailure> {
+        let _lock = self.batches.lock(steps.iter().map(|s| s.device_name()));
+        self.check_batch(steps)?;
+
+        let mut outcomes: Vec<Option<BatchOutcome>> = steps.iter().map(|_| None).collect();
+        let mut undo: Vec<Undo> = vec![];
+
+        for (index, step) in steps.iter().enumerate() {
+            if outcomes[index].is_some() {
+                continue;
+            }
+
+            if let Err(e) = self.apply_step(steps, index, step, &mut outcomes, &mut undo) {
+                for u in undo.into_iter().rev() {
+                    self.undo(u);
+                }
+                return Err(BatchFailure::new(index, e));
+            }
+        }
+
+        Ok(outcomes.into_iter().flatten().collect())
+    }
+
+    // Checks that the devices and peers the steps refer to exist, following the changes of the
+    // steps before them.
+    fn check_batch(&self, steps: &[BatchStep]) -> Result<(), BatchFailure> {
+        let devices = self
+            .wireguard
+            .list_devices()
+            .map_err(|e| BatchFailure::new(0, e))?;
+        let mut fwmarks: HashMap<String, u32> =
+            devices.into_iter().map(|d| (d.name, d.fwmark)).collect();
+        let mut created: Vec<&str> = vec![];
+        let mut deleted: Vec<&str> = vec![];
+        let mut peers: HashMap<&str, Vec<String>> = HashMap::new();
+        let mut deletion = false;
+
+        for (index, step) in steps.iter().enumerate() {
+            let device_name = step.device_name();
+
+            // steps on a deleted device are reported as such below.
+            if deletion && !deleted.contains(&device_name) {
+                return Err(BatchFailure::rejected(
+                    index,
+                    StatusCode::BAD_REQUEST,
+                    Error::invalid("a deletion must be the last operation of a batch"),
+                ));
+            }
+            deletion |= step.is_deletion();
+
+            if let BatchStep::CreateDevice { .. } = step {
+                if deleted.contains(&device_name) {
+                    return Err(BatchFailure::rejected(
+                        index,
+                        StatusCode::BAD_REQUEST,
+                        Error::invalid("device is deleted earlier in the batch"),
+                    ));
+                }
+                if fwmarks.contains_key(device_name) {
+                    return Err(BatchFailure::rejected(
+                        index,
+                        StatusCode::CONFLICT,
+                        Error::new("device_exists", "device already exists"),
+                    ));
+                }
+                fwmarks.insert(device_name.to_owned(), 0);
+                created.push(device_name);
+                continue;
+            }
+
+            let Some(&fwmark) = fwmarks.get(device_name) else {
+                return Err(BatchFailure::rejected(
+                    index,
+                    StatusCode::NOT_FOUND,
+                    Error::new("device_not_found", "device not found"),
+                ));
+            };
+
+            match step {
+                BatchStep::DeleteDevice { .. } => {
+                    fwmarks.remove(device_name);
+                    deleted.push(device_name);
+                }
+                BatchStep::SetAddresses { .. } if fwmark != 0 => {
+                    // the routes and rules of a full tunnel follow the families of its addresses.
+                    return Err(BatchFailure::rejected(
+                        index,
+                        StatusCode::BAD_REQUEST,
+                        Error::invalid("addresses of full tunnel devices cannot be changed"),
+                    ));
+                }
+                BatchStep::UpdatePeer { public_key, .. }
+                | BatchStep::DeletePeer { public_key, .. } => {
+                    let keys = match peers.entry(device_name) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) if created.contains(&device_name) => {
+                            entry.insert(vec![])
+                        }
+                        Entry::Vacant(entry) => {
+                            let listed = self
+                                .wireguard
+                                .list_peers(device_name)
+                                .map_err(|e| BatchFailure::new(index, e))?;
+                            entry.insert(listed.into_iter().map(|p| p.public_key).collect())
+                        }
+                    };
+
+                    let Some(position) = keys.iter().position(|k| k == public_key) else {
+                        return Err(BatchFailure::rejected(
+                            index,
+                            StatusCode::NOT_FOUND,
+                            Error::new("peer_not_found", "peer not found"),
+                        ));
+                    };
+                    if let BatchStep::DeletePeer { .. } = step {
+                        keys.remove(position);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply_step(
+        &self,
+        steps: &[BatchStep],
+        index: usize,
+        step: &BatchStep,
+        outcomes: &mut [Option<BatchOutcome>],
+        undo: &mut Vec<Undo>,
+    ) -> Result<(), SystemError> {
+        let outcome = match step {
+            BatchStep::CreateDevice {
+                device_name,
+                port,
+                ip,
+            } => {
+                let device = self.wireguard.create_device(device_name, *port)?;
+                undo.push(Undo::DeleteDevice(device_name.clone()));
+
+                self.netdev.set_ip(device_name, ip)?;
+                self.netdev.up(device_name)?;
+                BatchOutcome::DeviceCreated(device, copy_ip(ip))
+            }
+            BatchStep::AddPeer { device_name, .. } => {
+                let group: Vec<(usize, &Vec<String>, u16)> = steps
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, s)| match s {
+                        BatchStep::AddPeer {
+                            device_name: d,
+                            allowed_ips,
+                            persistent_keepalive_interval,
+                        } if d == device_name => {
+                            Some((i, allowed_ips, *persistent_keepalive_interval))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+
+                let requests = group
+                    .iter()
+                    .map(|(_, ips, keepalive)| {
+                        (ips.iter().map(|s| s.as_str()).collect(), *keepalive)
+                    })
+                    .collect();
+                let added = self.wireguard.add_peers(device_name, requests)?;
+                let keys = added.iter().map(|p| p.public_key.clone()).collect();
+                undo.push(Undo::DeletePeers(device_name.clone(), keys));
+
+                let allowed_ips: Vec<String> =
+                    added.iter().flat_map(|p| p.allowed_ips.clone()).collect();
+                let routes = self.peer_routes(device_name, &allowed_ips)?;
+                self.add_routes(device_name, &routes)?;
+                undo.push(Undo::DeleteRoutes(device_name.clone(), routes));
+
+                for ((i, _, _), peer) in group.iter().zip(added) {
+                    outcomes[*i] = Some(BatchOutcome::PeerAdded(peer));
+                }
+                return Ok(());
+            }
+            BatchStep::UpdatePeer {
+                device_name,
+                public_key,
+                allowed_ips,
+                persistent_keepalive_interval,
+            } => {
+                let current = self
+                    .wireguard
+                    .list_peers(device_name)?
+                    .into_iter()
+                    .find(|p| &p.public_key == public_key)
+                    .ok_or_else(|| SystemError {
+                        status: StatusCode::NOT_FOUND,
+                        error: Error::new("peer_not_found", "peer not found"),
+                    })?;
+                let old_routes = self.peer_routes(device_name, &current.allowed_ips)?;
+                let new_routes = self.peer_routes(device_name, allowed_ips)?;
+                let rules = self.peer_rules(device_name, public_key)?;
+
+                let peer = self.update_peer(
+                    device_name,
+                    &current,
+                    allowed_ips,
+                    *persistent_keepalive_interval,
+                )?;
+                undo.push(Undo::RestorePeer(device_name.clone(), current, rules));
+                undo.push(Undo::ReplaceRoutes(
+                    device_name.clone(),
+                    new_routes,
+                    old_routes,
+                ));
+                BatchOutcome::PeerUpdated(peer)
+            }
+            BatchStep::SetAddresses { device_name, ip } => {
+                let old_ip = self.netdev.get_ip(device_name)?;
+                let allowed_ips: Vec<String> = self
+                    .wireguard
+                    .list_peers(device_name)?
+                    .into_iter()
+                    .flat_map(|p| p.allowed_ips)
+                    .collect();
+                let old_routes = self.peer_routes(device_name, &allowed_ips)?;
+
+                undo.push(Undo::RestoreAddresses(device_name.clone(), old_ip));
+                self.netdev.set_ip(device_name, ip)?;
+
+                let new_routes = self.peer_routes(device_name, &allowed_ips)?;
+                undo.push(Undo::ReplaceRoutes(
+                    device_name.clone(),
+                    new_routes.clone(),
+                    old_routes.clone(),
+                ));
+                self.replace_routes(device_name, &old_routes, &new_routes)?;
+                BatchOutcome::AddressesSet(copy_ip(ip))
+            }
+            BatchStep::DeletePeer {
+                device_name,
+                public_key,
+            } => {
+                let allowed_ips = self
+                    .wireguard
+                    .list_peers(device_name)?
+                    .into_iter()
+                    .find(|p| &p.public_key == public_key)
+                    .map(|p| p.allowed_ips)
+                    .unwrap_or_default();
+
+                self.delete_peer(device_name, public_key, &allowed_ips)?;
+                BatchOutcome::PeerDeleted
+            }
+            BatchStep::DeleteDevice { device_name } => {
+                let fwmark = self.wireguard.get_device(device_name)?.fwmark;
+
+                self.wireguard.delete_device(device_name)?;
+
+                // the batch is done once the device is gone, leftovers cannot fail it anymore.
+                if fwmark != 0
+                    && let Err(e) = self.teardown_full_tunnel(device_name, fwmark)
+                {
+                    tracing::error!(
+                        device = device_name,
+                        "failed to remove full tunnel rules: {}",
+                        e
+                    );
+                }
+                if let Err(e) = self.teardown_firewall(device_name) {
+                    tracing::error!(
+                        device = device_name,
+                        "failed to remove firewall rules: {}",
+                        e
+                    );
+                }
+                BatchOutcome::DeviceDeleted
+            }
+        };
+
+        outcomes[index] = Some(outcome);
+        Ok(())
+    }
+
+    fn undo(&self, undo: Undo) {
+        match undo {
+            Undo::DeleteDevice(device_name) => {
+                let result = self.wireguard.delete_device(&device_name);
+                rolled_back(&device_name, "delete device", result);
+            }
+            Undo::DeletePeers(device_name, keys) => {
+                for key in keys {
+                    let result = self.wireguard.delete_peer(&device_name, &key);
+                    rolled_back(&device_name, "delete peer", result);
+                }
+            }
+            Undo::DeleteRoutes(device_name, routes) => {
+                let result = self.delete_routes(&device_name, &routes);
+                rolled_back(&device_name, "delete routes", result);
+            }
+            Undo::ReplaceRoutes(device_name, from, to) => {
+                let result = self.replace_routes(&device_name, &from, &to);
+                rolled_back(&device_name, "restore routes", result);
+            }
+            Undo::RestorePeer(device_name, peer, rules) => {
+                self.restore_peer(&device_name, &peer, &rules);
+            }
+            Undo::RestoreAddresses(device_name, ip) => {
+                let result = self.netdev.set_ip(&device_name, &ip);
+                rolled_back(&device_name, "restore addresses", result);
+            }
+        }
+    }
+}
+
+fn copy_ip(ip: &NetDevIp) -> NetDevIp {
+    NetDevIp {
+        ipv4: ip.ipv4,
+        ipv6: ip.ipv6,
+    }
+}
@@ -0,0 +1,308 @@
+use crate::metrics::Metrics;
+use domain::adapters::firewall::FirewallAdapter;
+use domain::adapters::netdev::NetworkDeviceAdapter;
+use domain::adapters::wg::WireguardAdapter;
+use domain::models::firewall::*;
+use domain::models::netdev::*;
+use domain::models::wg::*;
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Adapter that records the latency and failures of the calls it passes on to another adapter,
+/// and traces them.
+pub struct Instrumented<A: ?Sized> {
+    adapter: &'static str,
+    inner: Arc<A>,
+    metrics: Arc<Metrics>,
+}
+
+impl<A: ?Sized> Instrumented<A> {
+    pub fn new(adapter: &'static str, inner: Arc<A>, metrics: Arc<Metrics>) -> Self {
+        Instrumented {
+            adapter,
+            inner,
+            metrics,
+        }
+    }
+
+    // Runs the call inside a span naming the device and peer it is about; successful calls are
+    // logged at debug level, failed ones as warnings.
+    fn timed<T, E: Display>(
+        &self,
+        operation: &str,
+        device: Option<&str>,
+        peer: Option<&str>,
+        call: impl FnOnce(&A) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let span = tracing::info_span!(
+            "adapter",
+            adapter = self.adapter,
+            operation,
+            device,
+            peer,
+            otel.name = operation,
+            otel.status_code = tracing::field::Empty,
+        );
+        let _entered = span.enter();
+
+        let start = Instant::now();
+        let result = call(&self.inner);
+        let elapsed = start.elapsed();
+        self.metrics
+            .observe_call(self.adapter, operation, result.is_err(), elapsed);
+
+        let duration_ms = elapsed.as_secs_f64() * 1000.0;
+        match &result {
+            Ok(_) => tracing::debug!(duration_ms, "adapter call completed"),
+            Err(e) => {
+                span.record("otel.status_code", "error");
+                tracing::warn!(duration_ms, error = %e, "adapter call failed")
+            }
+        }
+        result
+    }
+}
+
+impl WireguardAdapter for Instrumented<dyn WireguardAdapter> {
+    fn get_device(&self, device_name: &str) -> Result<WGDevice, WGError> {
+        self.timed("get_device", Some(device_name), None, |a| {
+            a.get_device(device_name)
+        })
+    }
+
+    fn list_devices(&self) -> Result<Vec<WGDevice>, WGError> {
+        self.timed("list_devices", None, None, |a| a.list_devices())
+    }
+
+    fn create_device(&self, device_name: &str, port: u16) -> Result<WGDevice, WGError> {
+        self.timed("create_device", Some(device_name), None, |a| {
+            a.create_device(device_name, port)
+        })
+    }
+
+    fn set_fwmark(&self, device_name: &str, fwmark: u32) -> Result<(), WGError> {
+        self.timed("set_fwmark", Some(device_name), None, |a| {
+            a.set_fwmark(device_name, fwmark)
+        })
+    }
+
+    fn delete_device(&self, device_name: &str) -> Result<(), WGError> {
+        self.timed("delete_device", Some(device_name), None, |a| {
+            a.delete_device(device_name)
+        })
+    }
+
+    fn list_peers(&self, device_name: &str) -> Result<Vec<WGPeer>, WGError> {
+        self.timed("list_peers", Some(device_name), None, |a| {
+            a.list_peers(device_name)
+        })
+    }
+
+    fn add_peer(
+        &self,
+        device_name: &str,
+        allowed_ips: Vec<&str>,
+        persistent_keepalive_interval: u16,
+    ) -> Result<WGPeer, WGError> {
+        self.timed("add_peer", Some(device_name), None, |a| {
+            a.add_peer(device_name, allowed_ips, persistent_keepalive_interval)
+        })
+    }
+
+    fn add_peers(
+        &self,
+        device_name: &str,
+        peers: Vec<(Vec<&str>, u16)>,
+    ) -> Result<Vec<WGPeer>, WGError> {
+        self.timed("add_peers", Some(device_name), None, |a| {
+            a.add_peers(device_name, peers)
+        })
+    }
+
+    fn update_peer(
+        &self,
+        device_name: &str,
+        public_key: &str,
+        allowed_ips: Vec<&str>,
+        persistent_keepalive_interval: u16,
+    ) -> Result<WGPeer, WGError> {
+        self.timed("update_peer", Some(device_name), Some(public_key), |a| {
+            a.update_peer(
+                device_name,
+                public_key,
+                allowed_ips,
+                persistent_keepalive_interval,
+            )
+        })
+    }
+
+    fn delete_peer(&self, device_name: &str, public_key: &str) -> Result<(), WGError> {
+        self.timed("delete_peer", Some(device_name), Some(public_key), |a| {
+            a.delete_peer(device_name, public_key)
+        })
+    }
+
+    fn check(&self) -> Result<(), WGError> {
+        self.timed("check", None, None, |a| a.check())
+    }
+}
+
+impl NetworkDeviceAdapter for Instrumented<dyn NetworkDeviceAdapter> {
+    fn get_ip(&self, device_name: &str) -> Result<NetDevIp, NetDevError> {
+        self.timed("get_ip", Some(device_name), None, |a| a.get_ip(device_name))
+    }
+
+    fn set_ip(&self, device_name: &str, ip: &NetDevIp) -> Result<(), NetDevError> {
+        self.timed("set_ip", Some(device_name), None, |a| {
+            a.set_ip(device_name, ip)
+        })
+    }
+
+    fn up(&self, device_name: &str) -> Result<(), NetDevError> {
+        self.timed("up", Some(device_name), None, |a| a.up(device_name))
+    }
+
+    fn add_route(&self, device_name: &str, route: &NetDevRoute) -> Result<(), NetDevError> {
+        self.timed("add_route", Some(device_name), None, |a| {
+            a.add_route(device_name, route)
+        })
+    }
+
+    fn delete_route(&self, device_name: &str, route: &NetDevRoute) -> Result<(), NetDevError> {
+        self.timed("delete_route", Some(device_name), None, |a| {
+            a.delete_route(device_name, route)
+        })
+    }
+
+    fn add_rule(&self, rule: &NetDevRule) -> Result<(), NetDevError> {
+        self.timed("add_rule", None, None, |a| a.add_rule(rule))
+    }
+
+    fn delete_rule(&self, rule: &NetDevRule) -> Result<(), NetDevError> {
+        self.timed("delete_rule", None, None, |a| a.delete_rule(rule))
+    }
+
+    fn enable_src_valid_mark(&self) -> Result<(), NetDevError> {
+        self.timed("enable_src_valid_mark", None, None, |a| {
+            a.enable_src_valid_mark()
+        })
+    }
+
+    fn enter_netns(&self, netns: &NetNs) -> Result<NetNsHandle, NetDevError> {
+        self.timed("enter_netns", None, None, |a| a.enter_netns(netns))
+    }
+
+    fn leave_netns(&self, handle: NetNsHandle) -> Result<(), NetDevError> {
+        self.timed("leave_netns", None, None, |a| a.leave_netns(handle))
+    }
+
+    fn set_netns(&self, device_name: &str, netns: &NetNs) -> Result<(), NetDevError> {
+        self.timed("set_netns", Some(device_name), None, |a| {
+            a.set_netns(device_name, netns)
+        })
+    }
+
+    fn check(&self) -> Result<(), NetDevError> {
+        self.timed("check", None, None, |a| a.check())
+    }
+}
+
+impl FirewallAdapter for Instrumented<dyn FirewallAdapter> {
+    fn get_masquerade(&self, device_name: &str) -> Result<Option<Masquerade>, FirewallError> {
+        self.timed("get_masquerade", Some(device_name), None, |a| {
+            a.get_masquerade(device_name)
+        })
+    }
+
+    fn add_masquerade(
+        &self,
+        device_name: &str,
+        masquerade: &Masquerade,
+    ) -> Result<(), FirewallError> {
+        self.timed("add_masquerade", Some(device_name), None, |a| {
+            a.add_masquerade(device_name, masquerade)
+        })
+    }
+
+    fn delete_masquerade(&self, device_name: &str) -> Result<(), FirewallError> {
+        self.timed("delete_masquerade", Some(device_name), None, |a| {
+            a.delete_masquerade(device_name)
+        })
+    }
+
+    fn get_forwarding(&self, family: IpFamily) -> Result<bool, FirewallError> {
+        self.timed("get_forwarding", None, None, |a| a.get_forwarding(family))
+    }
+
+    fn set_forwarding(&self, family: IpFamily, enabled: bool) -> Result<(), FirewallError> {
+        self.timed("set_forwarding", None, None, |a| {
+            a.set_forwarding(family, enabled)
+        })
+    }
+
+    fn list_forwards(&self, device_name: &str) -> Result<Vec<PortForward>, FirewallError> {
+        self.timed("list_forwards", Some(device_name), None, |a| {
+            a.list_forwards(device_name)
+        })
+    }
+
+    fn add_forward(&self, device_name: &str, forward: &PortForward) -> Result<(), FirewallError> {
+        self.timed("add_forward", Some(device_name), None, |a| {
+            a.add_forward(device_name, forward)
+        })
+    }
+
+    fn delete_forward(
+        &self,
+        device_name: &str,
+        protocol: Protocol,
+        public_port: u16,
+    ) -> Result<(), FirewallError> {
+        self.timed("delete_forward", Some(device_name), None, |a| {
+            a.delete_forward(device_name, protocol, public_port)
+        })
+    }
+
+    fn delete_forwards(&self, device_name: &str) -> Result<(), FirewallError> {
+        self.timed("delete_forwards", Some(device_name), None, |a| {
+            a.delete_forwards(device_name)
+        })
+    }
+
+    fn list_acl_rules(&self, device_name: &str) -> Result<Vec<AclRule>, FirewallError> {
+        self.timed("list_acl_rules", Some(device_name), None, |a| {
+            a.list_acl_rules(device_name)
+        })
+    }
+
+    fn replace_acl_rules(
+        &self,
+        device_name: &str,
+        id: u32,
+        rules: &[AclRule],
+    ) -> Result<(), FirewallError> {
+        self.timed("replace_acl_rules", Some(device_name), None, |a| {
+            a.replace_acl_rules(device_name, id, rules)
+        })
+    }
+
+    fn delete_peer_acl_rules(
+        &self,
+        device_name: &str,
+        public_key: &str,
+    ) -> Result<(), FirewallError> {
+        self.timed(
+            "delete_peer_acl_rules",
+            Some(device_name),
+            Some(public_key),
+            |a| a.delete_peer_acl_rules(device_name, public_key),
+        )
+    }
+
+    fn delete_acl_rules(&self, device_name: &str) -> Result<(), FirewallError> {
+        self.timed("delete_acl_rules", Some(device_name), None, |a| {
+            a.delete_acl_rules(device_name)
+        })
+    }
+}
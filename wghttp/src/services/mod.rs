@@ -1,11 +1,75 @@
+pub mod batch;
+pub mod instrumented;
+
+use crate::events::EventBus;
+use crate::helpers::{parse_ip, routed_subnets};
+use crate::metrics::Metrics;
+use crate::models::errors::SystemError;
+use crate::models::events::{Change, Event};
+use crate::services::instrumented::Instrumented;
+use domain::adapters::firewall::FirewallAdapter;
 use domain::adapters::netdev::NetworkDeviceAdapter;
 use domain::adapters::wg::WireguardAdapter;
+use domain::models::firewall::{AclRule, FirewallError, Masquerade, PortForward};
+use domain::models::netdev::{
+    IpFamily, NetDevError, NetDevIp, NetDevRoute, NetDevRule, NetNs, NetNsHandle,
+};
+use domain::models::wg::WGPeer;
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::sync::Arc;
 
+// Routing tables of the kernel.
+const RT_TABLE_DEFAULT: u32 = 253;
+const RT_TABLE_MAIN: u32 = 254;
+const RT_TABLE_LOCAL: u32 = 255;
+
+// Full tunnel rules get explicit priorities so that re-adding them is idempotent. The suppress
+// rule has to be evaluated before the fwmark rule, as wg-quick sets them up.
+pub const SUPPRESS_RULE_PRIORITY: u32 = 32764;
+pub const FWMARK_RULE_PRIORITY: u32 = 32765;
+
+/// Port forwards and access rules of a peer, saved to put them back.
+pub struct PeerRules {
+    pub forwards: Vec<PortForward>,
+    pub acl_rules: Vec<AclRule>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RouteOptions {
+    pub table: Option<u32>,
+    pub metric: Option<u32>,
+}
+
+/// Keeps the calling thread inside a network namespace; it switches back when dropped. The
+/// process aborts if it cannot switch back.
+pub struct NetNsGuard<'a> {
+    manager: &'a TunnelManager,
+    handle: Option<NetNsHandle>,
+}
+
+impl Drop for NetNsGuard<'_> {
+    fn drop(&mut self) {
+        let Some(handle) = self.handle.take() else {
+            return;
+        };
+
+        if let Err(e) = self.manager.netdev.leave_netns(handle) {
+            // the worker thread would serve every later request inside the wrong namespace.
+            tracing::error!("failed to leave network namespace: {}", e);
+            std::process::abort();
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct TunnelManager {
     pub wireguard: Arc<dyn WireguardAdapter>,
     pub netdev: Arc<dyn NetworkDeviceAdapter>,
+    pub route_options: RouteOptions,
+    pub firewall: Option<Arc<dyn FirewallAdapter>>,
+    pub events: Option<Arc<EventBus>>,
+    batches: Arc<batch::BatchLocks>,
 }
 
 impl TunnelManager {
@@ -20,6 +84,562 @@ impl TunnelManager {
         TunnelManager {
             wireguard: wg_arc,
             netdev: nd_arc,
+            route_options: RouteOptions::default(),
+            firewall: None,
+            events: None,
+            batches: Arc::default(),
+        }
+    }
+
+    pub fn with_route_options(mut self, route_options: RouteOptions) -> Self {
+        self.route_options = route_options;
+        self
+    }
+
+    pub fn with_firewall<F>(mut self, firewall_adapter: F) -> Self
+    where
+        F: FirewallAdapter + 'static,
+    {
+        self.firewall = Some(Arc::new(firewall_adapter));
+        self
+    }
+
+    pub fn with_events(mut self, events: Arc<EventBus>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Publishes a change made through the api to the event bus, if there is one.
+    pub fn publish(&self, netns: Option<&NetNs>, device_name: &str, change: Change) {
+        if let Some(events) = &self.events {
+            events.publish(Event::new(netns, device_name, change));
         }
     }
+
+    /// Records the latency and failures of every adapter call into the metrics. Adapters set
+    /// after this call are not instrumented.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.wireguard = Arc::new(Instrumented::new(
+            "wireguard",
+            self.wireguard,
+            metrics.clone(),
+        ));
+        self.netdev = Arc::new(Instrumented::new("netdev", self.netdev, metrics.clone()));
+        self.firewall = self
+            .firewall
+            .map(|f| Arc::new(Instrumented::new("firewall", f, metrics)) as Arc<_>);
+        self
+    }
+
+    /// Moves the calling thread into the namespace, so that the adapters act inside it until
+    /// the guard is dropped. Without a namespace the thread stays where it is.
+    pub fn enter_netns(&self, netns: Option<&NetNs>) -> Result<NetNsGuard<'_>, NetDevError> {
+        let handle = netns.map(|n| self.netdev.enter_netns(n)).transpose()?;
+
+        Ok(NetNsGuard {
+            manager: self,
+            handle,
+        })
+    }
+
+    /// Returns the routes needed to reach the given allowed ips through the device.
+    pub fn peer_routes(
+        &self,
+        device_name: &str,
+        allowed_ips: &[String],
+    ) -> Result<Vec<NetDevRoute>, NetDevError> {
+        if allowed_ips.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let device_ip = self.netdev.get_ip(device_name)?;
+        let routes = routed_subnets(allowed_ips, &device_ip)
+            .into_iter()
+            .map(|destination| NetDevRoute {
+                destination,
+                table: self.route_options.table,
+                metric: self.route_options.metric,
+            })
+            .collect();
+
+        Ok(routes)
+    }
+
+    /// Adds all routes or none of them; routes added before a failure are removed again.
+    pub fn add_routes(&self, device_name: &str, routes: &[NetDevRoute]) -> Result<(), NetDevError> {
+        for (i, route) in routes.iter().enumerate() {
+            if let Err(e) = self.netdev.add_route(device_name, route) {
+                for added in &routes[..i] {
+                    let _ = self.netdev.delete_route(device_name, added);
+                }
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes all routes or none of them; routes deleted before a failure are added again.
+    pub fn delete_routes(
+        &self,
+        device_name: &str,
+        routes: &[NetDevRoute],
+    ) -> Result<(), NetDevError> {
+        for (i, route) in routes.iter().enumerate() {
+            if let Err(e) = self.netdev.delete_route(device_name, route) {
+                for deleted in &routes[..i] {
+                    let _ = self.netdev.add_route(device_name, deleted);
+                }
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves the device from the old routes to the new ones, leaving the routes both share.
+    /// On failure the device is left with the old routes.
+    pub fn replace_routes(
+        &self,
+        device_name: &str,
+        old_routes: &[NetDevRoute],
+        new_routes: &[NetDevRoute],
+    ) -> Result<(), NetDevError> {
+        let stale: Vec<_> = old_routes
+            .iter()
+            .filter(|r| !new_routes.contains(r))
+            .cloned()
+            .collect();
+        let fresh: Vec<_> = new_routes
+            .iter()
+            .filter(|r| !old_routes.contains(r))
+            .cloned()
+            .collect();
+
+        self.delete_routes(device_name, &stale)?;
+        if let Err(e) = self.add_routes(device_name, &fresh) {
+            let _ = self.add_routes(device_name, &stale);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Changes the allowed ips and keepalive of the peer along with its routes and access
+    /// rules. If any of them fails, the peer is put back as it was.
+    pub fn update_peer(
+        &self,
+        device_name: &str,
+        current: &WGPeer,
+        allowed_ips: &[String],
+        persistent_keepalive_interval: u16,
+    ) -> Result<WGPeer, SystemError> {
+        let old_routes = self.peer_routes(device_name, &current.allowed_ips)?;
+        let new_routes = self.peer_routes(device_name, allowed_ips)?;
+        let rules = self.peer_rules(device_name, &current.public_key)?;
+
+        // routes are the easiest to put back, so they change before the peer does.
+        self.replace_routes(device_name, &old_routes, &new_routes)?;
+
+        let ips: Vec<&str> = allowed_ips.iter().map(|s| s.as_str()).collect();
+        let result = self
+            .wireguard
+            .update_peer(
+                device_name,
+                &current.public_key,
+                ips,
+                persistent_keepalive_interval,
+            )
+            .map_err(SystemError::from)
+            .and_then(|peer| {
+                self.update_peer_acl_rules(device_name, &peer)?;
+                Ok(peer)
+            });
+
+        if result.is_err() {
+            self.restore_peer(device_name, current, &rules);
+            rolled_back(
+                device_name,
+                "restore routes",
+                self.replace_routes(device_name, &new_routes, &old_routes),
+            );
+        }
+        result
+    }
+
+    /// Puts the allowed ips, keepalive and access rules of the peer back to the given state.
+    /// Restoring is best effort, failures are logged.
+    pub fn restore_peer(&self, device_name: &str, peer: &WGPeer, rules: &PeerRules) {
+        let ips: Vec<&str> = peer.allowed_ips.iter().map(|s| s.as_str()).collect();
+        let result = self.wireguard.update_peer(
+            device_name,
+            &peer.public_key,
+            ips,
+            peer.persistent_keepalive_interval,
+        );
+        rolled_back(device_name, "restore peer", result.map(|_| ()));
+        rolled_back(
+            device_name,
+            "restore access rules",
+            self.restore_peer_rules(device_name, &peer.public_key, rules),
+        );
+    }
+
+    /// Removes the peer along with its routes, port forwards and access rules. These go
+    /// first and are put back if removing the peer fails, so a failed deletion can be retried.
+    pub fn delete_peer(
+        &self,
+        device_name: &str,
+        public_key: &str,
+        allowed_ips: &[String],
+    ) -> Result<(), SystemError> {
+        let routes = self.peer_routes(device_name, allowed_ips)?;
+        let rules = self.peer_rules(device_name, public_key)?;
+
+        self.delete_routes(device_name, &routes)?;
+        let result = self
+            .delete_peer_forwards(device_name, public_key)
+            .and_then(|_| self.delete_peer_acl_rules(device_name, public_key))
+            .map_err(SystemError::from)
+            .and_then(|_| Ok(self.wireguard.delete_peer(device_name, public_key)?));
+
+        if result.is_err() {
+            rolled_back(
+                device_name,
+                "restore port forwards and access rules",
+                self.restore_peer_rules(device_name, public_key, &rules),
+            );
+            rolled_back(
+                device_name,
+                "restore routes",
+                self.add_routes(device_name, &routes),
+            );
+        }
+        result
+    }
+
+    /// Routing table of a full tunnel device listening on the port, which is also its fwmark.
+    /// Ports naming a table of the kernel or the table of `--route-table` are refused.
+    pub fn full_tunnel_table(&self, port: u16) -> Result<u32, String> {
+        let table = port as u32;
+        if table == 0 {
+            return Err("full tunnel requires a listen port".to_owned());
+        }
+        if [RT_TABLE_DEFAULT, RT_TABLE_MAIN, RT_TABLE_LOCAL].contains(&table) {
+            return Err(format!(
+                "full tunnel cannot listen on port {}, it names a routing table of the kernel",
+                port
+            ));
+        }
+        if self.route_options.table == Some(table) {
+            return Err(format!(
+                "full tunnel cannot listen on port {}, it names the table of --route-table",
+                port
+            ));
+        }
+
+        Ok(table)
+    }
+
+    /// Sends everything except the device's own packets (marked with `table` as fwmark)
+    /// through the device, like wg-quick does for `0.0.0.0/0` allowed ips.
+    pub fn setup_full_tunnel(
+        &self,
+        device_name: &str,
+        table: u32,
+        ip: &NetDevIp,
+    ) -> Result<(), NetDevError> {
+        let mut families = vec![];
+        if ip.ipv4.is_some() {
+            // replies to marked packets would fail the reverse path filter otherwise.
+            self.netdev.enable_src_valid_mark()?;
+            families.push(IpFamily::V4);
+        }
+        if ip.ipv6.is_some() {
+            families.push(IpFamily::V6);
+        }
+
+        for family in families {
+            let unspecified = match family {
+                IpFamily::V4 => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                IpFamily::V6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            };
+            let default_route = NetDevRoute {
+                destination: (unspecified, 0),
+                table: Some(table),
+                metric: None,
+            };
+            self.netdev.add_route(device_name, &default_route)?;
+
+            let (fwmark_rule, suppress_rule) = full_tunnel_rules(family, table);
+            self.netdev.add_rule(&fwmark_rule)?;
+            self.netdev.add_rule(&suppress_rule)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the rules of a full tunnel device. The suppress rule is shared by all full
+    /// tunnel devices and is only removed along with the last one.
+    pub fn teardown_full_tunnel(&self, device_name: &str, table: u32) -> Result<(), NetDevError> {
+        let last = self
+            .wireguard
+            .list_devices()
+            .map(|devs| devs.iter().all(|d| d.name == device_name || d.fwmark == 0))
+            .unwrap_or(false);
+
+        for family in [IpFamily::V4, IpFamily::V6] {
+            let (fwmark_rule, suppress_rule) = full_tunnel_rules(family, table);
+            self.netdev.delete_rule(&fwmark_rule)?;
+            if last {
+                self.netdev.delete_rule(&suppress_rule)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Masquerades traffic from the device and enables forwarding for the families the device
+    /// has addresses for. Forwarding is left enabled on teardown, other devices may rely on it.
+    pub fn setup_nat(
+        &self,
+        device_name: &str,
+        masquerade: &Masquerade,
+        ip: &NetDevIp,
+    ) -> Result<(), FirewallError> {
+        let Some(firewall) = &self.firewall else {
+            return Err(FirewallError::Invalid("firewall is not enabled".to_owned()));
+        };
+
+        if ip.ipv4.is_some() {
+            firewall.set_forwarding(IpFamily::V4, true)?;
+        }
+        if ip.ipv6.is_some() {
+            firewall.set_forwarding(IpFamily::V6, true)?;
+        }
+
+        firewall.add_masquerade(device_name, masquerade)
+    }
+
+    /// Tells whether the device has a masquerade setup, port forwards or access rules.
+    pub fn has_firewall_rules(&self, device_name: &str) -> Result<bool, FirewallError> {
+        let Some(firewall) = &self.firewall else {
+            return Ok(false);
+        };
+
+        Ok(firewall.get_masquerade(device_name)?.is_some()
+            || !firewall.list_forwards(device_name)?.is_empty()
+            || !firewall.list_acl_rules(device_name)?.is_empty())
+    }
+
+    /// Removes the masquerade setup, the port forwards and the access rules of the device.
+    pub fn teardown_firewall(&self, device_name: &str) -> Result<(), FirewallError> {
+        match &self.firewall {
+            Some(firewall) => firewall
+                .delete_masquerade(device_name)
+                .and_then(|_| firewall.delete_forwards(device_name))
+                .and_then(|_| firewall.delete_acl_rules(device_name)),
+            None => Ok(()),
+        }
+    }
+
+    /// Forwards a port to a peer, enabling forwarding for the family of its destination.
+    pub fn add_forward(
+        &self,
+        device_name: &str,
+        forward: &PortForward,
+    ) -> Result<(), FirewallError> {
+        let Some(firewall) = &self.firewall else {
+            return Err(FirewallError::Invalid("firewall is not enabled".to_owned()));
+        };
+
+        let family = match forward.destination_ip {
+            IpAddr::V4(_) => IpFamily::V4,
+            IpAddr::V6(_) => IpFamily::V6,
+        };
+        firewall.set_forwarding(family, true)?;
+
+        firewall.add_forward(device_name, forward)
+    }
+
+    pub fn delete_peer_forwards(
+        &self,
+        device_name: &str,
+        public_key: &str,
+    ) -> Result<(), FirewallError> {
+        let Some(firewall) = &self.firewall else {
+            return Ok(());
+        };
+
+        for forward in firewall.list_forwards(device_name)? {
+            if forward.peer_public_key == public_key {
+                firewall.delete_forward(device_name, forward.protocol, forward.public_port)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Saves the port forwards and access rules of the peer.
+    pub fn peer_rules(
+        &self,
+        device_name: &str,
+        public_key: &str,
+    ) -> Result<PeerRules, FirewallError> {
+        let Some(firewall) = &self.firewall else {
+            return Ok(PeerRules {
+                forwards: vec![],
+                acl_rules: vec![],
+            });
+        };
+
+        let forwards = firewall
+            .list_forwards(device_name)?
+            .into_iter()
+            .filter(|f| f.peer_public_key == public_key)
+            .collect();
+        let acl_rules = firewall
+            .list_acl_rules(device_name)?
+            .into_iter()
+            .filter(|r| r.peer_public_key == public_key)
+            .collect();
+
+        Ok(PeerRules {
+            forwards,
+            acl_rules,
+        })
+    }
+
+    /// Puts back the saved port forwards and access rules of the peer, replacing the access
+    /// rules it has now.
+    pub fn restore_peer_rules(
+        &self,
+        device_name: &str,
+        public_key: &str,
+        rules: &PeerRules,
+    ) -> Result<(), FirewallError> {
+        let Some(firewall) = &self.firewall else {
+            return Ok(());
+        };
+
+        let forwards = firewall.list_forwards(device_name)?;
+        for forward in &rules.forwards {
+            if !forwards.contains(forward) {
+                firewall.add_forward(device_name, forward)?;
+            }
+        }
+
+        let current = firewall.list_acl_rules(device_name)?;
+        let mut ids: Vec<u32> = rules.acl_rules.iter().map(|r| r.id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+
+        for id in ids {
+            let mut restored: Vec<AclRule> = current
+                .iter()
+                .filter(|r| r.id == id && r.peer_public_key != public_key)
+                .cloned()
+                .collect();
+            restored.extend(rules.acl_rules.iter().filter(|r| r.id == id).cloned());
+
+            firewall.replace_acl_rules(device_name, id, &restored)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn delete_peer_acl_rules(
+        &self,
+        device_name: &str,
+        public_key: &str,
+    ) -> Result<(), FirewallError> {
+        match &self.firewall {
+            Some(firewall) => firewall.delete_peer_acl_rules(device_name, public_key),
+            None => Ok(()),
+        }
+    }
+
+    /// Rebuilds the access rules of the peer from its current allowed ips. A peer left without
+    /// allowed ips of a rule's destination family drops out of that rule.
+    pub fn update_peer_acl_rules(
+        &self,
+        device_name: &str,
+        peer: &WGPeer,
+    ) -> Result<(), FirewallError> {
+        let Some(firewall) = &self.firewall else {
+            return Ok(());
+        };
+
+        let rules = firewall.list_acl_rules(device_name)?;
+        let mut ids: Vec<u32> = rules
+            .iter()
+            .filter(|r| r.peer_public_key == peer.public_key)
+            .map(|r| r.id)
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+
+        for id in ids {
+            let mut updated: Vec<AclRule> = vec![];
+            for rule in rules.iter().filter(|r| r.id == id) {
+                if rule.peer_public_key != peer.public_key {
+                    updated.push(rule.clone());
+                } else if !updated.iter().any(|r| r.peer_public_key == peer.public_key) {
+                    updated.extend(acl_rules_for_peer(rule, peer));
+                }
+            }
+
+            firewall.replace_acl_rules(device_name, id, &updated)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Rolling back is best effort. A failure leaves the system half changed, so it is logged.
+fn rolled_back<E: Display>(device_name: &str, action: &str, result: Result<(), E>) {
+    if let Err(e) = result {
+        tracing::error!(
+            device = device_name,
+            "failed to {} on rollback: {}",
+            action,
+            e
+        );
+    }
+}
+
+/// Expands the template into one rule per allowed ip of the peer in the destination's family.
+pub fn acl_rules_for_peer(template: &AclRule, peer: &WGPeer) -> Vec<AclRule> {
+    peer.allowed_ips
+        .iter()
+        .filter_map(|ip| parse_ip(ip).ok())
+        .filter(|(ip, _)| ip.is_ipv4() == template.destination.0.is_ipv4())
+        .map(|source| AclRule {
+            peer_public_key: peer.public_key.clone(),
+            source,
+            ..template.clone()
+        })
+        .collect()
+}
+
+fn full_tunnel_rules(family: IpFamily, table: u32) -> (NetDevRule, NetDevRule) {
+    let fwmark_rule = NetDevRule {
+        family,
+        table,
+        fwmark: Some(table),
+        invert: true,
+        suppress_prefixlength: None,
+        priority: Some(FWMARK_RULE_PRIORITY),
+    };
+
+    let suppress_rule = NetDevRule {
+        family,
+        table: RT_TABLE_MAIN,
+        fwmark: None,
+        invert: false,
+        suppress_prefixlength: Some(0),
+        priority: Some(SUPPRESS_RULE_PRIORITY),
+    };
+
+    (fwmark_rule, suppress_rule)
 }
@@ -0,0 +1,60 @@
+use actix_web::http::header::HeaderMap;
+use opentelemetry::Context;
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing::{Level, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::registry::LookupSpan;
+
+pub const TRACES_PATH: &str = "/v1/traces";
+
+/// Builds the provider exporting spans in batches to an OTLP/HTTP collector, e.g.
+/// `http://127.0.0.1:4318`.
+pub fn tracer_provider(endpoint: &str, service_name: &str) -> Result<SdkTracerProvider, String> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}{}", endpoint.trim_end_matches('/'), TRACES_PATH))
+        .build()
+        .map_err(|e| format!("invalid otlp endpoint {}: {}", endpoint, e))?;
+    let resource = Resource::builder()
+        .with_service_name(service_name.to_owned())
+        .build();
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build())
+}
+
+/// Layer turning the spans of requests and adapter calls into OpenTelemetry spans. Spans of
+/// other crates are left out, the http client of the exporter among them.
+pub fn layer<S>(provider: &SdkTracerProvider) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer("wghttp"))
+        .with_filter(Targets::new().with_target("wghttp", Level::TRACE))
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+/// Trace of the caller, read from the W3C `traceparent` and `tracestate` headers.
+pub fn remote_context(headers: &HeaderMap) -> Context {
+    TraceContextPropagator::new().extract(&HeaderExtractor(headers))
+}
@@ -0,0 +1,191 @@
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::dev::Extensions;
+use actix_web::rt::net::TcpStream;
+use rustls::crypto::{CryptoProvider, ring};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{RootCertStore, ServerConfig};
+use std::any::Any;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// Subject of the certificate a client authenticated with, stored in the connection data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientCertificate {
+    pub subject: String,
+}
+
+#[derive(Debug)]
+struct LoadedCertificate {
+    modified: (Option<SystemTime>, Option<SystemTime>),
+    key: Arc<CertifiedKey>,
+    /// Modification times of the files that last failed to load, so a failure is logged once.
+    failed: Option<(Option<SystemTime>, Option<SystemTime>)>,
+}
+
+/// Serves the certificate and key from disk, reloading them when either file is modified.
+#[derive(Debug)]
+pub struct CertificateResolver {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    provider: Arc<CryptoProvider>,
+    loaded: RwLock<LoadedCertificate>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn load_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("failed to read certificates from {}: {}", path.display(), e))?;
+
+    if certs.is_empty() {
+        return Err(format!("no certificates found in {}", path.display()));
+    }
+
+    Ok(certs)
+}
+
+fn load_certified_key(
+    cert_path: &Path,
+    key_path: &Path,
+    provider: &CryptoProvider,
+) -> Result<CertifiedKey, String> {
+    let certs = load_certificates(cert_path)?;
+    let key = PrivateKeyDer::from_pem_file(key_path).map_err(|e| {
+        format!(
+            "failed to read private key from {}: {}",
+            key_path.display(),
+            e
+        )
+    })?;
+    let signing_key = provider
+        .key_provider
+        .load_private_key(key)
+        .map_err(|e| format!("unsupported private key in {}: {}", key_path.display(), e))?;
+
+    let certified = CertifiedKey::new(certs, signing_key);
+    certified
+        .keys_match()
+        .map_err(|_| "certificate does not match the private key".to_owned())?;
+
+    Ok(certified)
+}
+
+impl CertificateResolver {
+    pub fn new(cert_path: &Path, key_path: &Path) -> Result<Self, String> {
+        let provider = Arc::new(ring::default_provider());
+        let modified = (modified(cert_path), modified(key_path));
+        let key = load_certified_key(cert_path, key_path, &provider)?;
+
+        Ok(CertificateResolver {
+            cert_path: cert_path.to_path_buf(),
+            key_path: key_path.to_path_buf(),
+            provider,
+            loaded: RwLock::new(LoadedCertificate {
+                modified,
+                key: Arc::new(key),
+                failed: None,
+            }),
+        })
+    }
+
+    /// Returns the certificate in use, reloading it first if the files changed since the last
+    /// load. A certificate that fails to load, e.g. while a renewal has only written one of the
+    /// files, keeps the previous one in use and is retried on the next handshake. The failure is
+    /// logged once for each change of the files.
+    pub fn current(&self) -> Arc<CertifiedKey> {
+        let modified = (modified(&self.cert_path), modified(&self.key_path));
+
+        let loaded = self.loaded.read().unwrap_or_else(|e| e.into_inner());
+        if loaded.modified == modified {
+            return loaded.key.clone();
+        }
+        drop(loaded);
+
+        let mut loaded = self.loaded.write().unwrap_or_else(|e| e.into_inner());
+        if loaded.modified != modified {
+            match load_certified_key(&self.cert_path, &self.key_path, &self.provider) {
+                Ok(key) => {
+                    loaded.modified = modified;
+                    loaded.key = Arc::new(key);
+                    loaded.failed = None;
+                }
+                Err(e) if loaded.failed != Some(modified) => {
+                    tracing::error!("failed to reload the tls certificate: {}", e);
+                    loaded.failed = Some(modified);
+                }
+                Err(_) => {}
+            }
+        }
+
+        loaded.key.clone()
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+/// Builds the rustls configuration for the tcp listener. With a client ca, clients must present
+/// a certificate signed by it.
+pub fn server_config(
+    cert_path: &Path,
+    key_path: &Path,
+    client_ca: Option<&Path>,
+) -> Result<ServerConfig, String> {
+    let resolver = CertificateResolver::new(cert_path, key_path)?;
+    let provider = resolver.provider.clone();
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?;
+
+    let builder = match client_ca {
+        None => builder.with_no_client_auth(),
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certificates(path)? {
+                roots
+                    .add(cert)
+                    .map_err(|e| format!("invalid client ca in {}: {}", path.display(), e))?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(|e| format!("invalid client ca in {}: {}", path.display(), e))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+    };
+
+    let mut config = builder.with_cert_resolver(Arc::new(resolver));
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(config)
+}
+
+/// Stores the subject of the client certificate of tls connections as a `ClientCertificate`.
+pub fn on_connect(conn: &dyn Any, data: &mut Extensions) {
+    let Some(stream) = conn.downcast_ref::<TlsStream<TcpStream>>() else {
+        return;
+    };
+
+    let (_, session) = stream.get_ref();
+    let subject = session
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .and_then(|cert| x509_parser::parse_x509_certificate(cert).ok())
+        .map(|(_, cert)| cert.subject().to_string());
+
+    if let Some(subject) = subject {
+        data.insert(ClientCertificate { subject });
+    }
+}
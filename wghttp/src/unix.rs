@@ -0,0 +1,151 @@
+use actix_web::dev::Extensions;
+use actix_web::rt::net::UnixStream;
+use std::any::Any;
+use std::fs;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Credentials of the process on the other end of a unix socket connection, stored in the
+/// connection data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeerCredentials {
+    pub uid: u32,
+    pub gid: u32,
+    pub pid: Option<i32>,
+
+    /// supplementary groups of the process when it connected, from `SO_PEERGROUPS`.
+    pub groups: Vec<u32>,
+}
+
+/// Marks unix socket connections in the connection data, whether or not their
+/// `PeerCredentials` could be read.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UnixConnection;
+
+/// Mode and ownership of the unix socket file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SocketOptions {
+    pub mode: Option<u32>,
+    pub owner: Option<u32>,
+    pub group: Option<u32>,
+}
+
+fn lookup_id(database: &str, name: &str) -> Result<u32, String> {
+    if let Ok(id) = name.parse::<u32>() {
+        return Ok(id);
+    }
+
+    let content =
+        fs::read_to_string(database).map_err(|e| format!("failed to read {}: {}", database, e))?;
+
+    content
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() > 2 && fields[0] == name)
+        .and_then(|fields| fields[2].parse().ok())
+        .ok_or_else(|| format!("{} not found in {}", name, database))
+}
+
+/// Resolves a user name or numeric uid.
+pub fn lookup_user(name: &str) -> Result<u32, String> {
+    lookup_id("/etc/passwd", name)
+}
+
+/// Resolves a group name or numeric gid.
+pub fn lookup_group(name: &str) -> Result<u32, String> {
+    lookup_id("/etc/group", name)
+}
+
+/// Parses an octal file mode such as `660` or `0660`.
+pub fn parse_mode(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|m| *m <= 0o777)
+        .ok_or_else(|| format!("invalid socket mode: {}", mode))
+}
+
+// The umask is shared by the whole process, binds change it one at a time.
+static UMASK: Mutex<()> = Mutex::new(());
+
+/// Binds the unix socket, replacing a stale socket file. The socket file is created accessible
+/// to its owner only and gets the ownership and mode afterwards, the mode defaulting to what the
+/// umask allows.
+pub fn bind(path: &Path, options: &SocketOptions) -> io::Result<UnixListener> {
+    if let Err(e) = fs::remove_file(path)
+        && e.kind() != io::ErrorKind::NotFound
+    {
+        return Err(e);
+    }
+
+    let (listener, umask) = {
+        let _lock = UMASK.lock().unwrap_or_else(|e| e.into_inner());
+        let umask = unsafe { libc::umask(0o177) };
+        let listener = UnixListener::bind(path);
+        unsafe { libc::umask(umask) };
+        (listener?, umask)
+    };
+
+    if options.owner.is_some() || options.group.is_some() {
+        std::os::unix::fs::chown(path, options.owner, options.group)?;
+    }
+
+    let mode = options.mode.unwrap_or(0o777 & !umask);
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+
+    Ok(listener)
+}
+
+/// Supplementary groups the kernel recorded for the peer of the connection.
+fn peer_groups(stream: &UnixStream) -> Vec<u32> {
+    let size = size_of::<libc::gid_t>();
+    let mut groups: Vec<libc::gid_t> = vec![0; 16];
+
+    loop {
+        let mut len = (groups.len() * size) as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERGROUPS,
+                groups.as_mut_ptr().cast(),
+                &mut len,
+            )
+        };
+
+        // the kernel answers ERANGE with the size it needs when the buffer is too small.
+        let needed = len as usize / size;
+        if result == 0 {
+            groups.truncate(needed);
+            return groups;
+        }
+        if io::Error::last_os_error().raw_os_error() != Some(libc::ERANGE) || needed <= groups.len()
+        {
+            return vec![];
+        }
+        groups.resize(needed, 0);
+    }
+}
+
+/// Marks unix socket connections and stores their `SO_PEERCRED` credentials as
+/// `PeerCredentials`.
+pub fn on_connect(conn: &dyn Any, data: &mut Extensions) {
+    let Some(stream) = conn.downcast_ref::<UnixStream>() else {
+        return;
+    };
+
+    data.insert(UnixConnection);
+
+    if let Ok(cred) = stream.peer_cred() {
+        let pid = cred.pid();
+        data.insert(PeerCredentials {
+            uid: cred.uid(),
+            gid: cred.gid(),
+            pid,
+            groups: peer_groups(stream),
+        });
+    }
+}
@@ -0,0 +1,461 @@
+use crate::events::EventBus;
+use crate::middleware::rbac::matches_pattern;
+use crate::models::events::{Change, Event};
+use crate::models::webhooks::{DeliveryOutcome, DeliveryQuery, DeliveryRecord};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
+
+pub const SIGNATURE_HEADER: &str = "X-Wghttp-Signature";
+pub const EVENT_HEADER: &str = "X-Wghttp-Event";
+pub const DELIVERY_HEADER: &str = "X-Wghttp-Delivery";
+
+// event sent by the test endpoint, whatever the subscriber's events are.
+pub const PING: &str = "ping";
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 8;
+// the first retry waits this long, every following one twice as long as the one before.
+const BACKOFF: u64 = 5;
+const MAX_BACKOFF: u64 = 3600;
+const TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+fn default_max_attempts() -> u32 {
+    DEFAULT_MAX_ATTEMPTS
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WebhooksFile {
+    /// directory of the pending deliveries and of the delivery log.
+    queue: PathBuf,
+
+    #[serde(default = "default_max_attempts")]
+    max_attempts: u32,
+
+    #[serde(default)]
+    subscribers: Vec<Subscriber>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Subscriber {
+    pub name: String,
+    pub url: String,
+
+    /// key of the hmac-sha256 signature of the deliveries.
+    pub secret: String,
+
+    /// event types to deliver; all of them by default.
+    #[serde(default)]
+    pub events: Option<Vec<String>>,
+
+    /// device name patterns, where `*` matches any number of characters.
+    #[serde(default)]
+    pub devices: Option<Vec<String>>,
+}
+
+impl Subscriber {
+    pub fn wants(&self, event: &Event) -> bool {
+        let name = event.change.name();
+        let event_matches = self
+            .events
+            .as_ref()
+            .is_none_or(|events| events.iter().any(|e| e == name));
+        let device_matches = self
+            .devices
+            .as_ref()
+            .is_none_or(|devices| devices.iter().any(|p| matches_pattern(p, &event.device)));
+
+        event_matches && device_matches
+    }
+}
+
+/// An event waiting to be delivered to a subscriber, stored as a file until it is delivered or
+/// given up.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Delivery {
+    id: String,
+    subscriber: String,
+    event: String,
+    device: Option<String>,
+    payload: Value,
+    attempts: u32,
+    next_attempt: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Hex encoded hmac-sha256 of the body, sent as `sha256=<hex>` in the signature header.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any size");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Seconds to wait before the attempt following the given number of failed ones.
+pub fn backoff(attempts: u32) -> u64 {
+    let exponent = attempts.saturating_sub(1).min(16);
+    (BACKOFF << exponent).min(MAX_BACKOFF)
+}
+
+/// Delivers events to the configured subscribers as signed json posts. Deliveries that fail are
+/// retried with exponential backoff and survive restarts in the queue directory.
+pub struct Webhooks {
+    subscribers: Vec<Subscriber>,
+    max_attempts: u32,
+    pending_dir: PathBuf,
+    log_path: PathBuf,
+    log: Mutex<File>,
+    pending: Mutex<Vec<Delivery>>,
+    sequence: AtomicU64,
+    agent: ureq::Agent,
+}
+
+impl Webhooks {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let file = Self::read(content)?;
+        Self::open(&file.queue, file.subscribers, file.max_attempts)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+
+        Self::parse(&content)
+    }
+
+    /// Validates the file without opening the queue.
+    pub fn check_file(path: &Path) -> Result<(), String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+
+        Self::read(&content).map(|_| ())
+    }
+
+    fn read(content: &str) -> Result<WebhooksFile, String> {
+        let file: WebhooksFile =
+            toml::from_str(content).map_err(|e| format!("invalid webhooks file: {}", e))?;
+
+        for (i, subscriber) in file.subscribers.iter().enumerate() {
+            if subscriber.name.is_empty() {
+                return Err("webhook subscriber name must not be empty".to_owned());
+            }
+            if file.subscribers[..i]
+                .iter()
+                .any(|s| s.name == subscriber.name)
+            {
+                return Err(format!(
+                    "duplicate webhook subscriber name: {}",
+                    subscriber.name
+                ));
+            }
+            if !subscriber.url.starts_with("http://") && !subscriber.url.starts_with("https://") {
+                return Err(format!(
+                    "webhook url of {} must be http or https",
+                    subscriber.name
+                ));
+            }
+            if subscriber.secret.is_empty() {
+                return Err(format!("empty webhook secret for {}", subscriber.name));
+            }
+            let unknown = subscriber
+                .events
+                .iter()
+                .flatten()
+                .find(|e| !Change::NAMES.contains(&e.as_str()));
+            if let Some(event) = unknown {
+                return Err(format!(
+                    "unknown webhook event {} for {}, expected one of {}",
+                    event,
+                    subscriber.name,
+                    Change::NAMES.join(", ")
+                ));
+            }
+        }
+        if file.max_attempts == 0 {
+            return Err("webhook max_attempts must be at least 1".to_owned());
+        }
+
+        Ok(file)
+    }
+
+    /// Opens the queue directory and picks up the deliveries left pending by a previous run.
+    pub fn open(
+        queue: &Path,
+        subscribers: Vec<Subscriber>,
+        max_attempts: u32,
+    ) -> Result<Self, String> {
+        let pending_dir = queue.join("pending");
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&pending_dir)
+            .map_err(|e| format!("failed to create {}: {}", pending_dir.display(), e))?;
+
+        let log_path = queue.join("deliveries.log");
+        let log = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .mode(0o600)
+            .open(&log_path)
+            .map_err(|e| format!("failed to open {}: {}", log_path.display(), e))?;
+
+        let entries = fs::read_dir(&pending_dir)
+            .map_err(|e| format!("failed to read {}: {}", pending_dir.display(), e))?;
+        let mut pending: Vec<Delivery> = entries
+            .filter_map(|entry| fs::read(entry.ok()?.path()).ok())
+            .filter_map(|content| serde_json::from_slice(&content).ok())
+            .collect();
+        pending.sort_by_key(|d| d.next_attempt);
+
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(TIMEOUT))
+            .http_status_as_error(false)
+            .build()
+            .into();
+
+        Ok(Webhooks {
+            subscribers,
+            max_attempts,
+            pending_dir,
+            log_path,
+            log: Mutex::new(log),
+            pending: Mutex::new(pending),
+            sequence: AtomicU64::new(0),
+            agent,
+        })
+    }
+
+    pub fn subscriber(&self, name: &str) -> Option<&Subscriber> {
+        self.subscribers.iter().find(|s| s.name == name)
+    }
+
+    fn new_delivery(&self, subscriber: &str, event: &str, device: Option<String>) -> Delivery {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let id = format!(
+            "{:x}-{}",
+            nanos,
+            self.sequence.fetch_add(1, Ordering::Relaxed)
+        );
+        let payload = serde_json::json!({"id": id, "time": now(), "type": event});
+
+        Delivery {
+            id,
+            subscriber: subscriber.to_owned(),
+            event: event.to_owned(),
+            device,
+            payload,
+            attempts: 0,
+            next_attempt: 0,
+        }
+    }
+
+    fn store(&self, delivery: &Delivery) -> Result<(), String> {
+        let path = self.pending_dir.join(format!("{}.json", delivery.id));
+        let content = serde_json::to_vec(delivery).map_err(|e| e.to_string())?;
+        fs::write(&path, content).map_err(|e| format!("failed to write {}: {}", path.display(), e))
+    }
+
+    fn unstore(&self, delivery: &Delivery) {
+        let _ = fs::remove_file(self.pending_dir.join(format!("{}.json", delivery.id)));
+    }
+
+    /// Queues a delivery of the event for every subscriber that wants it.
+    pub fn enqueue(&self, event: &Event) {
+        for subscriber in self.subscribers.iter().filter(|s| s.wants(event)) {
+            let name = event.change.name();
+            let mut delivery =
+                self.new_delivery(&subscriber.name, name, Some(event.device.clone()));
+            if let (Value::Object(payload), Ok(Value::Object(fields))) =
+                (&mut delivery.payload, serde_json::to_value(event))
+            {
+                payload.extend(fields);
+            }
+
+            if let Err(e) = self.store(&delivery) {
+                tracing::error!("webhook delivery is not persisted: {}", e);
+            }
+            self.pending
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(delivery);
+        }
+    }
+
+    // Posts the delivery once; returns the status, or why there was none.
+    fn post(&self, subscriber: &Subscriber, delivery: &Delivery) -> Result<u16, String> {
+        let body = serde_json::to_vec(&delivery.payload).map_err(|e| e.to_string())?;
+        let signature = format!("sha256={}", sign(&subscriber.secret, &body));
+
+        let response = self
+            .agent
+            .post(&subscriber.url)
+            .header("Content-Type", "application/json")
+            .header(EVENT_HEADER, &delivery.event)
+            .header(DELIVERY_HEADER, &delivery.id)
+            .header(SIGNATURE_HEADER, &signature)
+            .send(&body[..])
+            .map_err(|e| e.to_string())?;
+
+        Ok(response.status().as_u16())
+    }
+
+    // Makes one attempt and records it; returns the record and whether to try again.
+    fn attempt(&self, delivery: &mut Delivery, max_attempts: u32) -> (DeliveryRecord, bool) {
+        delivery.attempts += 1;
+        let result = match self.subscriber(&delivery.subscriber) {
+            Some(subscriber) => self.post(subscriber, delivery),
+            None => Err("subscriber is no longer configured".to_owned()),
+        };
+
+        let (status, error) = match result {
+            Ok(status) if (200..300).contains(&status) => (Some(status), None),
+            Ok(status) => (
+                Some(status),
+                Some(format!("subscriber answered {}", status)),
+            ),
+            Err(e) => (None, Some(e)),
+        };
+        let retry = error.is_some()
+            && delivery.attempts < max_attempts
+            && self.subscriber(&delivery.subscriber).is_some();
+        let outcome = match (&error, retry) {
+            (None, _) => DeliveryOutcome::Delivered,
+            (Some(_), true) => DeliveryOutcome::Retrying,
+            (Some(_), false) => DeliveryOutcome::Failed,
+        };
+
+        let record = DeliveryRecord {
+            time: now(),
+            delivery: delivery.id.clone(),
+            subscriber: delivery.subscriber.clone(),
+            event: delivery.event.clone(),
+            device: delivery.device.clone(),
+            attempt: delivery.attempts,
+            status,
+            outcome,
+            error,
+        };
+        self.write_record(&record);
+
+        (record, retry)
+    }
+
+    fn write_record(&self, record: &DeliveryRecord) {
+        let Ok(line) = serde_json::to_string(record) else {
+            return;
+        };
+        let mut log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = log.write_all(format!("{}\n", line).as_bytes()) {
+            tracing::error!("failed to write webhook delivery record: {}", e);
+        }
+    }
+
+    /// Attempts the deliveries that are due at the given unix time.
+    pub fn process(&self, now: u64) {
+        let due: Vec<Delivery> = {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            let (due, later) = pending.drain(..).partition(|d| d.next_attempt <= now);
+            *pending = later;
+            due
+        };
+
+        for mut delivery in due {
+            let (_, retry) = self.attempt(&mut delivery, self.max_attempts);
+            if !retry {
+                self.unstore(&delivery);
+                continue;
+            }
+
+            delivery.next_attempt = now + backoff(delivery.attempts);
+            if let Err(e) = self.store(&delivery) {
+                tracing::error!("webhook delivery is not persisted: {}", e);
+            }
+            self.pending
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(delivery);
+        }
+    }
+
+    /// Sends a ping event to the subscriber once, without retries.
+    pub fn send_test(&self, subscriber: &str) -> Option<DeliveryRecord> {
+        self.subscriber(subscriber)?;
+
+        let mut delivery = self.new_delivery(subscriber, PING, None);
+        let (record, _) = self.attempt(&mut delivery, 1);
+        Some(record)
+    }
+
+    /// Checks that deliveries can be queued, by writing and removing a file in the queue.
+    pub fn check(&self) -> Result<(), String> {
+        let path = self.pending_dir.join(".check");
+        fs::write(&path, b"").map_err(|e| format!("{}: {}", path.display(), e))?;
+        fs::remove_file(&path).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Reads the delivery records matching the query back from the log.
+    pub fn query(&self, query: &DeliveryQuery) -> Result<Vec<DeliveryRecord>, String> {
+        let content = fs::read_to_string(&self.log_path)
+            .map_err(|e| format!("failed to read {}: {}", self.log_path.display(), e))?;
+
+        let records = content
+            .lines()
+            .filter_map(|line| serde_json::from_str::<DeliveryRecord>(line).ok())
+            .filter(|r| query.since.is_none_or(|since| r.time >= since))
+            .filter(|r| query.until.is_none_or(|until| r.time <= until))
+            .filter(|r| query.subscriber.as_ref().is_none_or(|s| &r.subscriber == s))
+            .filter(|r| query.device.is_none() || r.device == query.device)
+            .collect();
+
+        Ok(records)
+    }
+}
+
+/// Queues the events published on the bus and delivers them from a thread of its own, since
+/// subscribers may be slow to answer.
+pub fn run(webhooks: Arc<Webhooks>, bus: &EventBus) {
+    let mut rx = bus.subscribe();
+    let queue = webhooks.clone();
+    actix_web::rt::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => queue.enqueue(&event),
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!("webhooks missed {} events", missed);
+                }
+                Err(RecvError::Closed) => return,
+            }
+        }
+    });
+
+    std::thread::spawn(move || {
+        loop {
+            webhooks.process(now());
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
@@ -0,0 +1,319 @@
+use actix_web::middleware::from_fn;
+use actix_web::{App, test, web};
+use domain::models::firewall::*;
+use domain::models::wg::*;
+use wghttp::middleware::auth::require_api_key;
+use wghttp::models::acls::*;
+use wghttp::models::errors::*;
+use wghttp::models::forwards::ForwardProtocol;
+use wghttp::routes::acls::*;
+use wghttp::services::TunnelManager;
+
+pub mod mock;
+
+use mock::*;
+
+use std::net::{IpAddr, Ipv4Addr};
+
+const PUBKEY: &str = "pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu";
+const OTHER_PUBKEY: &str = "otherpubkeyotherpubkeyotherpubkeyotherpubkey";
+
+fn peer(public_key: &str, allowed_ips: Vec<&str>) -> WGPeer {
+    WGPeer {
+        allowed_ips: allowed_ips.into_iter().map(|s| s.to_owned()).collect(),
+        endpoint: "endpoint".to_owned(),
+        last_handshake_time: 0,
+        persistent_keepalive_interval: 0,
+        rx: 0,
+        tx: 0,
+        public_key: public_key.to_owned(),
+        private_key: "privkey".to_owned(),
+        preshared_key: "preshared".to_owned(),
+    }
+}
+
+fn peers(_: &str) -> Result<Vec<WGPeer>, WGError> {
+    Ok(vec![
+        peer(PUBKEY, vec!["10.0.0.2/32", "fd86:ea04:1111::2/128"]),
+        peer(OTHER_PUBKEY, vec!["10.0.0.3/32", "192.168.10.0/24"]),
+    ])
+}
+
+fn deny_rule(peer_public_key: &str, source: Ipv4Addr) -> AclRule {
+    AclRule {
+        id: 1,
+        action: AclAction::Deny,
+        peer_public_key: peer_public_key.to_owned(),
+        source: (IpAddr::V4(source), 32),
+        destination: (IpAddr::V4(Ipv4Addr::new(192, 168, 1, 0)), 24),
+        protocol: Some(Protocol::Tcp),
+        port: Some(22),
+    }
+}
+
+fn ssh_deny(_: &str) -> Result<Vec<AclRule>, FirewallError> {
+    Ok(vec![
+        deny_rule(PUBKEY, Ipv4Addr::new(10, 0, 0, 2)),
+        deny_rule(OTHER_PUBKEY, Ipv4Addr::new(10, 0, 0, 3)),
+    ])
+}
+
+fn acl_request(peers: Vec<&str>, destination: &str) -> AclRequest {
+    AclRequest {
+        action: AclPolicy::Allow,
+        peers: peers.into_iter().map(|s| s.to_owned()).collect(),
+        destination: destination.to_owned(),
+        protocol: Some(ForwardProtocol::Tcp),
+        port: Some(443),
+    }
+}
+
+fn manager(firewall_mock: Option<FirewallMockAdapter>) -> TunnelManager {
+    let wg_mock = WireguardMockAdapter::new(None, None, None, None, Some(peers), None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    match firewall_mock {
+        Some(firewall_mock) => tm.with_firewall(firewall_mock),
+        None => tm,
+    }
+}
+
+#[actix_web::test]
+async fn test_list_acls_route_with_firewall_not_enabled() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(None)))
+            .service(list_acls),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/devices/wg0/acls")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "firewall is not enabled");
+}
+
+#[actix_web::test]
+async fn test_list_acls_route_groups_rules_by_id() {
+    let firewall_mock = FirewallMockAdapter::default().with_list_acl_rules(ssh_deny);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(Some(firewall_mock))))
+            .service(list_acls),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/devices/wg0/acls")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    let body: Vec<AclResponse> = test::read_body_json(resp).await;
+    assert_eq!(body.len(), 1);
+    assert_eq!(body[0].id, 1);
+    assert_eq!(body[0].action, AclPolicy::Deny);
+    assert_eq!(body[0].peers, vec![PUBKEY, OTHER_PUBKEY]);
+    assert_eq!(body[0].destination, "192.168.1.0/24");
+    assert_eq!(body[0].protocol, Some(ForwardProtocol::Tcp));
+    assert_eq!(body[0].port, Some(22));
+}
+
+#[actix_web::test]
+async fn test_create_acl_route_with_port_without_protocol() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(
+                Some(FirewallMockAdapter::default()),
+            )))
+            .service(create_acl),
+    )
+    .await;
+
+    let mut acl = acl_request(vec![PUBKEY], "192.168.1.0/24");
+    acl.protocol = None;
+    let req = test::TestRequest::post()
+        .uri("/devices/wg0/acls")
+        .set_json(acl)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "port requires a protocol");
+}
+
+#[actix_web::test]
+async fn test_create_acl_route_with_peer_not_found() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(
+                Some(FirewallMockAdapter::default()),
+            )))
+            .service(create_acl),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices/wg0/acls")
+        .set_json(acl_request(
+            vec!["unknownpubkeyunknownpubkeyunknownpubkeyunkno"],
+            "192.168.1.0/24",
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 404);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "peer not found");
+}
+
+#[actix_web::test]
+async fn test_create_acl_route_with_peer_without_family() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(
+                Some(FirewallMockAdapter::default()),
+            )))
+            .service(create_acl),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices/wg0/acls")
+        .set_json(acl_request(vec![PUBKEY, OTHER_PUBKEY], "fd00:1::/48"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(
+        body.message,
+        "peer has no allowed ips of the destination's family"
+    );
+}
+
+#[actix_web::test]
+async fn test_create_acl_route_with_successful_result() {
+    let firewall_mock = FirewallMockAdapter::default()
+        .with_list_acl_rules(ssh_deny)
+        .with_replace_acl_rules(|_, id, rules| {
+            let sources: Vec<String> = rules
+                .iter()
+                .map(|r| format!("{} {}/{}", r.peer_public_key, r.source.0, r.source.1))
+                .collect();
+            let expected = vec![
+                format!("{} 10.0.0.2/32", PUBKEY),
+                format!("{} 10.0.0.3/32", OTHER_PUBKEY),
+                format!("{} 192.168.10.0/24", OTHER_PUBKEY),
+            ];
+            match (id, sources == expected) {
+                (2, true) => Ok(()),
+                _ => Err(FirewallError::Other("unexpected acl rules".to_owned())),
+            }
+        });
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(Some(firewall_mock))))
+            .service(create_acl),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices/wg0/acls")
+        .set_json(acl_request(vec![PUBKEY, OTHER_PUBKEY], "192.168.1.7/24"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 201);
+    let body: AclResponse = test::read_body_json(resp).await;
+    assert_eq!(body.id, 2);
+    assert_eq!(body.action, AclPolicy::Allow);
+    assert_eq!(body.peers, vec![PUBKEY, OTHER_PUBKEY]);
+    assert_eq!(body.destination, "192.168.1.0/24");
+}
+
+#[actix_web::test]
+async fn test_get_acl_route_with_not_found() {
+    let firewall_mock = FirewallMockAdapter::default().with_list_acl_rules(ssh_deny);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(Some(firewall_mock))))
+            .service(get_acl),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/devices/wg0/acls/2")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 404);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "acl not found");
+}
+
+#[actix_web::test]
+async fn test_update_acl_route_with_successful_result() {
+    let firewall_mock = FirewallMockAdapter::default()
+        .with_list_acl_rules(ssh_deny)
+        .with_replace_acl_rules(|_, id, rules| {
+            match (id, rules.len(), rules[0].peer_public_key.as_str()) {
+                (1, 1, PUBKEY) => Ok(()),
+                _ => Err(FirewallError::Other("unexpected acl rules".to_owned())),
+            }
+        });
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(Some(firewall_mock))))
+            .service(update_acl),
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri("/devices/wg0/acls/1")
+        .set_json(acl_request(vec![PUBKEY], "192.168.1.0/24"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    let body: AclResponse = test::read_body_json(resp).await;
+    assert_eq!(body.id, 1);
+    assert_eq!(body.peers, vec![PUBKEY]);
+}
+
+#[actix_web::test]
+async fn test_delete_acl_route_with_successful_result() {
+    let firewall_mock = FirewallMockAdapter::default()
+        .with_list_acl_rules(ssh_deny)
+        .with_replace_acl_rules(|_, id, rules| match (id, rules.is_empty()) {
+            (1, true) => Ok(()),
+            _ => Err(FirewallError::Other("unexpected acl rules".to_owned())),
+        });
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(Some(firewall_mock))))
+            .service(delete_acl),
+    )
+    .await;
+
+    let req = test::TestRequest::delete()
+        .uri("/devices/wg0/acls/1")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 204);
+}
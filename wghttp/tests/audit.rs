@@ -0,0 +1,194 @@
+use actix_web::middleware::from_fn;
+use actix_web::{App, test, web};
+use wghttp::middleware::audit::*;
+use wghttp::middleware::auth::require_api_key;
+use wghttp::models::audit::*;
+use wghttp::models::devices::*;
+use wghttp::models::errors::*;
+use wghttp::models::peers::*;
+use wghttp::routes::audit::*;
+use wghttp::routes::devices::*;
+use wghttp::routes::peers::*;
+use wghttp::services::TunnelManager;
+
+pub mod mock;
+
+use mock::*;
+
+use std::fs;
+use std::path::PathBuf;
+
+fn temp_log(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("wghttp-audit-{}-{}.log", std::process::id(), name))
+}
+
+fn manager() -> TunnelManager {
+    tunnel_manager(WireguardMockAdapter::new(
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(|_, i, k| Ok(peer(PUBKEY, i, k))),
+        None,
+    ))
+}
+
+fn record(time: u64, device: &str) -> AuditRecord {
+    AuditRecord {
+        time,
+        caller: "uid:0".to_owned(),
+        source: "unix:1".to_owned(),
+        operation: "delete_device".to_owned(),
+        device: Some(device.to_owned()),
+        peer: None,
+        status: 204,
+        outcome: AuditOutcome::Success,
+        error: None,
+    }
+}
+
+#[actix_web::test]
+async fn test_audit_records_created_peer() {
+    let path = temp_log("create-peer");
+    let log = web::Data::new(AuditLog::file(&path).unwrap());
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .wrap(from_fn(record_changes))
+            .app_data(log.clone())
+            .app_data(web::Data::new(manager()))
+            .service(list_peers)
+            .service(create_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/devices/wg0/peers")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let req = test::TestRequest::post()
+        .uri("/devices/wg0/peers")
+        .peer_addr("192.0.2.10:53124".parse().unwrap())
+        .set_json(CreatePeerRequest {
+            allowed_ips: vec!["10.0.0.2/32".to_owned()],
+            persistent_keepalive_interval: 25,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 201);
+    let body: CreatePeerResponse = test::read_body_json(resp).await;
+    assert_eq!(body.private_key, Some("privkey".to_owned()));
+
+    let content = fs::read_to_string(&path).unwrap();
+    let records = log.query(&AuditQuery::default());
+    fs::remove_file(&path).unwrap();
+
+    assert!(!content.contains("privkey"));
+    assert!(!content.contains("preshared"));
+
+    let records = records.unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].caller, "anonymous");
+    assert_eq!(records[0].source, "192.0.2.10:53124");
+    assert_eq!(records[0].operation, "create_peer");
+    assert_eq!(records[0].device, Some("wg0".to_owned()));
+    assert_eq!(
+        records[0].peer,
+        Some("pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu".to_owned())
+    );
+    assert_eq!(records[0].status, 201);
+    assert_eq!(records[0].outcome, AuditOutcome::Success);
+    assert_eq!(records[0].error, None);
+}
+
+#[actix_web::test]
+async fn test_audit_records_failed_device_creation() {
+    let path = temp_log("create-device");
+    let log = web::Data::new(AuditLog::file(&path).unwrap());
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .wrap(from_fn(record_changes))
+            .app_data(log.clone())
+            .app_data(web::Data::new(manager()))
+            .service(create_device),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices")
+        .set_json(CreateDeviceRequest {
+            device_name: "wg-guests".to_owned(),
+            port: 51820,
+            full_tunnel: false,
+            nat: None,
+            ip_addresses: DeviceIpAddr {
+                ipv4: Some("10.0.0.1/24".to_owned()),
+                ipv6: None,
+            },
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 500);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "not found");
+
+    let records = log.query(&AuditQuery::default());
+    fs::remove_file(&path).unwrap();
+
+    let records = records.unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].operation, "create_device");
+    assert_eq!(records[0].device, Some("wg-guests".to_owned()));
+    assert_eq!(records[0].outcome, AuditOutcome::Failure);
+    assert_eq!(records[0].error, Some("not found".to_owned()));
+}
+
+#[actix_web::test]
+async fn test_list_audit_records_route_with_filters() {
+    let path = temp_log("query");
+    let log = AuditLog::file(&path).unwrap();
+    for (time, device) in [(100, "wg0"), (200, "wg1"), (300, "wg0"), (400, "wg0")] {
+        log.write(&record(time, device)).unwrap();
+    }
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(log))
+            .service(list_audit_records),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/audit?device=wg0&since=200&until=300")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(resp.status(), 200);
+    let body: Vec<AuditRecord> = test::read_body_json(resp).await;
+    assert_eq!(body, vec![record(300, "wg0")]);
+}
+
+#[actix_web::test]
+async fn test_list_audit_records_route_with_audit_log_not_enabled() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .service(list_audit_records),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/audit").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "audit log is not enabled");
+}
@@ -0,0 +1,205 @@
+use actix_web::middleware::from_fn;
+use actix_web::{App, HttpResponse, Responder, get, test, web};
+use sha2::{Digest, Sha256};
+use wghttp::middleware::auth::*;
+use wghttp::models::errors::*;
+use wghttp::routes::health::{health, healthz};
+
+use std::fs;
+
+const KEY: &str = "s3cr3t-api-key";
+
+fn digest(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+fn keys_file(key: &str, extra: &str) -> String {
+    format!(
+        "{}\n[[keys]]\nname = \"ci\"\nsha256 = \"{}\"\n",
+        extra,
+        digest(key)
+    )
+}
+
+#[get("/whoami")]
+async fn whoami(name: Option<web::ReqData<ApiKeyName>>) -> impl Responder {
+    match name {
+        Some(name) => HttpResponse::Ok().body(name.into_inner().0),
+        None => HttpResponse::Ok().finish(),
+    }
+}
+
+#[actix_web::test]
+async fn test_auth_without_keys_configured_passes_through() {
+    let app = test::init_service(App::new().wrap(from_fn(require_api_key)).service(whoami)).await;
+
+    let req = test::TestRequest::get().uri("/whoami").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+}
+
+#[actix_web::test]
+async fn test_auth_with_missing_key() {
+    let auth = Auth::new(ApiKeys::parse(&keys_file(KEY, "")).unwrap());
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(auth))
+            .service(whoami),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/whoami").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 401);
+    assert_eq!(resp.headers().get("WWW-Authenticate").unwrap(), "Bearer");
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "missing or invalid api key");
+}
+
+#[actix_web::test]
+async fn test_auth_with_invalid_key() {
+    let auth = Auth::new(ApiKeys::parse(&keys_file(KEY, "")).unwrap());
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(auth))
+            .service(whoami),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/whoami")
+        .insert_header(("Authorization", "Bearer wrong"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 401);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "missing or invalid api key");
+}
+
+#[actix_web::test]
+async fn test_auth_with_valid_keys() {
+    let auth = Auth::new(ApiKeys::parse(&keys_file(KEY, "")).unwrap());
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(auth))
+            .service(whoami),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/whoami")
+        .insert_header(("Authorization", format!("Bearer {}", KEY)))
+        .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    assert_eq!(body, "ci");
+
+    let req = test::TestRequest::get()
+        .uri("/whoami")
+        .insert_header((API_KEY_HEADER, KEY))
+        .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    assert_eq!(body, "ci");
+}
+
+#[actix_web::test]
+async fn test_auth_with_public_health() {
+    let auth = Auth::new(ApiKeys::parse(&keys_file(KEY, "")).unwrap());
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(auth))
+            .service(health)
+            .service(healthz),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let req = test::TestRequest::get().uri("/healthz").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let auth = Auth::new(ApiKeys::parse(&keys_file(KEY, "public_health = false")).unwrap());
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(auth))
+            .service(health),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+}
+
+#[actix_web::test]
+async fn test_api_keys_public_docs() {
+    let keys = ApiKeys::parse(&keys_file(KEY, "")).unwrap();
+    assert!(!keys.is_public("/swagger-ui/"));
+    assert!(!keys.is_public("/api-docs/v1/openapi.json"));
+
+    let keys = ApiKeys::parse(&keys_file(KEY, "public_docs = true")).unwrap();
+    assert!(keys.is_public("/swagger-ui/index.html"));
+    assert!(keys.is_public("/api-docs/v1/openapi.json"));
+    assert!(!keys.is_public("/devices"));
+}
+
+#[actix_web::test]
+async fn test_api_keys_parse_errors() {
+    let err = ApiKeys::parse("[[keys]]\nname = \"ci\"\nsha256 = \"abc\"\n").unwrap_err();
+    assert_eq!(err, "invalid sha256 digest for api key ci");
+
+    let twice = format!("{}{}", keys_file(KEY, ""), keys_file("other", ""));
+    let err = ApiKeys::parse(&twice).unwrap_err();
+    assert_eq!(err, "duplicate api key name: ci");
+
+    assert!(ApiKeys::parse("keys = 1").is_err());
+}
+
+#[actix_web::test]
+async fn test_auth_reload() {
+    let path = std::env::temp_dir().join(format!("wghttp-api-keys-{}.toml", std::process::id()));
+    fs::write(&path, keys_file(KEY, "")).unwrap();
+
+    let auth = Auth::from_file(&path).unwrap();
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(auth.clone()))
+            .service(whoami),
+    )
+    .await;
+
+    fs::write(&path, "not toml [").unwrap();
+    let invalid = auth.reload();
+
+    fs::write(&path, keys_file("rotated", "")).unwrap();
+    let rotated = auth.reload();
+    fs::remove_file(&path).unwrap();
+
+    assert!(invalid.is_err());
+    assert!(rotated.is_ok());
+
+    let req = test::TestRequest::get()
+        .uri("/whoami")
+        .insert_header((API_KEY_HEADER, KEY))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+
+    let req = test::TestRequest::get()
+        .uri("/whoami")
+        .insert_header((API_KEY_HEADER, "rotated"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+}
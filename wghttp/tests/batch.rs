@@ -0,0 +1,366 @@
+use actix_web::middleware::from_fn;
+use actix_web::{App, test, web};
+use domain::models::wg::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use wghttp::middleware::auth::require_api_key;
+use wghttp::models::batch::*;
+use wghttp::models::devices::DeviceIpAddr;
+use wghttp::routes::batch::run_batch;
+use wghttp::services::TunnelManager;
+
+pub mod mock;
+
+use mock::*;
+
+fn add_peer(device_name: &str, allowed_ips: &str) -> BatchOperation {
+    BatchOperation::AddPeer {
+        device_name: device_name.to_owned(),
+        allowed_ips: vec![allowed_ips.to_owned()],
+        persistent_keepalive_interval: 0,
+    }
+}
+
+fn create_device(device_name: &str) -> BatchOperation {
+    BatchOperation::CreateDevice {
+        device_name: device_name.to_owned(),
+        port: 51821,
+        ip_addresses: DeviceIpAddr {
+            ipv4: Some("10.1.0.1/24".to_owned()),
+            ipv6: None,
+        },
+    }
+}
+
+fn batch_request(operations: Vec<BatchOperation>) -> actix_web::test::TestRequest {
+    test::TestRequest::post()
+        .uri("/batch")
+        .set_json(BatchRequest { operations })
+}
+
+#[actix_web::test]
+async fn test_batch_route_coalesces_peer_additions() {
+    static ADD_PEERS_CALLS: AtomicUsize = AtomicUsize::new(0);
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        Some(|| Ok(vec![device("wg0")])),
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .with_add_peers(|_, peers| {
+        ADD_PEERS_CALLS.fetch_add(1, Ordering::SeqCst);
+        Ok(peers
+            .into_iter()
+            .enumerate()
+            .map(|(i, (ips, keepalive))| peer(&format!("key{}", i), ips, keepalive))
+            .collect())
+    });
+    let netdev_mock = NetworkDeviceMockAdapter::new(Some(device_ip), None, None);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(TunnelManager::new(wg_mock, netdev_mock)))
+            .service(run_batch),
+    )
+    .await;
+
+    let req = batch_request(vec![
+        add_peer("wg0", "10.0.0.2/32"),
+        add_peer("wg0", "10.0.0.3/32"),
+    ])
+    .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(ADD_PEERS_CALLS.load(Ordering::SeqCst), 1);
+
+    let body: BatchResponse = test::read_body_json(resp).await;
+    assert_eq!(body.results.len(), 2);
+    let BatchResult::AddPeer { peer } = &body.results[1] else {
+        panic!("expected an added peer");
+    };
+    assert_eq!(peer.public_key, "key1");
+    assert_eq!(peer.allowed_ips, vec!["10.0.0.3/32".to_owned()]);
+}
+
+#[actix_web::test]
+async fn test_batch_route_rolls_back_applied_operations() {
+    static DELETED_DEVICES: AtomicUsize = AtomicUsize::new(0);
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        None,
+        Some(|name, _| Ok(device(name))),
+        Some(|_| {
+            DELETED_DEVICES.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }),
+        None,
+        None,
+        None,
+    )
+    .with_add_peers(|_, _| Err(WGError::Other("add failed".to_owned())));
+    let netdev_mock =
+        NetworkDeviceMockAdapter::new(Some(device_ip), Some(|_, _| Ok(())), Some(|_| Ok(())));
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(TunnelManager::new(wg_mock, netdev_mock)))
+            .service(run_batch),
+    )
+    .await;
+
+    let req =
+        batch_request(vec![create_device("wg1"), add_peer("wg1", "10.1.0.2/32")]).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 500);
+    assert_eq!(DELETED_DEVICES.load(Ordering::SeqCst), 1);
+
+    let body: BatchError = test::read_body_json(resp).await;
+    assert_eq!(body.index, 1);
+    assert_eq!(body.error.code, "system_error");
+}
+
+#[actix_web::test]
+async fn test_batch_route_checks_state_before_applying() {
+    static CREATED_DEVICES: AtomicUsize = AtomicUsize::new(0);
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        Some(|| Ok(vec![device("wg0")])),
+        Some(|name, _| {
+            CREATED_DEVICES.fetch_add(1, Ordering::SeqCst);
+            Ok(device(name))
+        }),
+        None,
+        Some(|_| Ok(vec![peer("otherkey", vec!["10.0.0.2/32"], 0)])),
+        None,
+        None,
+    );
+    let netdev_mock =
+        NetworkDeviceMockAdapter::new(Some(device_ip), Some(|_, _| Ok(())), Some(|_| Ok(())));
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(TunnelManager::new(wg_mock, netdev_mock)))
+            .service(run_batch),
+    )
+    .await;
+
+    let req = batch_request(vec![
+        create_device("wg1"),
+        BatchOperation::DeletePeer {
+            device_name: "wg0".to_owned(),
+            public_key: PUBKEY.to_owned(),
+        },
+    ])
+    .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+    assert_eq!(CREATED_DEVICES.load(Ordering::SeqCst), 0);
+
+    let body: BatchError = test::read_body_json(resp).await;
+    assert_eq!(body.index, 1);
+    assert_eq!(body.error.code, "peer_not_found");
+}
+
+#[actix_web::test]
+async fn test_batch_route_rejects_operations_on_deleted_devices() {
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        Some(|| Ok(vec![device("wg0")])),
+        None,
+        None,
+        None,
+        None,
+        None,
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(Some(device_ip), None, None);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(TunnelManager::new(wg_mock, netdev_mock)))
+            .service(run_batch),
+    )
+    .await;
+
+    let req = batch_request(vec![
+        BatchOperation::DeleteDevice {
+            device_name: "wg0".to_owned(),
+        },
+        add_peer("wg0", "10.0.0.2/32"),
+    ])
+    .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    let body: BatchError = test::read_body_json(resp).await;
+    assert_eq!(body.index, 1);
+    assert_eq!(body.error.code, "device_not_found");
+}
+
+#[actix_web::test]
+async fn test_batch_route_validates_every_operation() {
+    let wg_mock = WireguardMockAdapter::new(None, None, None, None, None, None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(TunnelManager::new(wg_mock, netdev_mock)))
+            .service(run_batch),
+    )
+    .await;
+
+    let req = batch_request(vec![
+        add_peer("wg0", "10.0.0.2/32"),
+        add_peer("wg0", "10.0.0.300/32"),
+    ])
+    .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    let body: BatchError = test::read_body_json(resp).await;
+    assert_eq!(body.index, 1);
+    assert_eq!(body.error.code, "invalid_request");
+
+    let req = batch_request(vec![]).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+}
+
+#[actix_web::test]
+async fn test_batch_route_allows_a_single_deletion() {
+    static DELETED_PEERS: AtomicUsize = AtomicUsize::new(0);
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        Some(|| Ok(vec![device("wg0")])),
+        None,
+        None,
+        Some(|_| Ok(vec![peer(PUBKEY, vec!["10.0.0.2/32"], 0)])),
+        None,
+        Some(|_, _| {
+            DELETED_PEERS.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }),
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(Some(device_ip), None, None);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(TunnelManager::new(wg_mock, netdev_mock)))
+            .service(run_batch),
+    )
+    .await;
+
+    let req = batch_request(vec![
+        BatchOperation::DeletePeer {
+            device_name: "wg0".to_owned(),
+            public_key: PUBKEY.to_owned(),
+        },
+        BatchOperation::DeleteDevice {
+            device_name: "wg0".to_owned(),
+        },
+    ])
+    .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    assert_eq!(DELETED_PEERS.load(Ordering::SeqCst), 0);
+
+    let body: BatchError = test::read_body_json(resp).await;
+    assert_eq!(body.index, 1);
+    assert_eq!(body.error.code, "invalid_request");
+}
+
+#[actix_web::test]
+async fn test_batch_route_rejects_operations_after_a_deletion() {
+    static DELETED_PEERS: AtomicUsize = AtomicUsize::new(0);
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        Some(|| Ok(vec![device("wg0")])),
+        None,
+        None,
+        Some(|_| Ok(vec![peer(PUBKEY, vec!["10.0.0.2/32"], 0)])),
+        None,
+        Some(|_, _| {
+            DELETED_PEERS.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }),
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(Some(device_ip), None, None);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(TunnelManager::new(wg_mock, netdev_mock)))
+            .service(run_batch),
+    )
+    .await;
+
+    let req = batch_request(vec![
+        BatchOperation::DeletePeer {
+            device_name: "wg0".to_owned(),
+            public_key: PUBKEY.to_owned(),
+        },
+        add_peer("wg0", "10.0.0.3/32"),
+    ])
+    .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    assert_eq!(DELETED_PEERS.load(Ordering::SeqCst), 0);
+
+    let body: BatchError = test::read_body_json(resp).await;
+    assert_eq!(body.index, 1);
+    assert_eq!(body.error.code, "invalid_request");
+}
+
+#[actix_web::test]
+async fn test_batch_route_rolls_back_when_deletion_fails() {
+    static DELETED_DEVICES: AtomicUsize = AtomicUsize::new(0);
+    static DELETED_ROUTES: AtomicUsize = AtomicUsize::new(0);
+    static ADDED_ROUTES: AtomicUsize = AtomicUsize::new(0);
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        Some(|| Ok(vec![device("wg0")])),
+        Some(|name, _| Ok(device(name))),
+        Some(|_| {
+            DELETED_DEVICES.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }),
+        Some(|_| Ok(vec![peer(PUBKEY, vec!["10.9.0.0/24"], 0)])),
+        None,
+        Some(|_, _| Err(WGError::Other("delete failed".to_owned()))),
+    );
+    let netdev_mock =
+        NetworkDeviceMockAdapter::new(Some(device_ip), Some(|_, _| Ok(())), Some(|_| Ok(())))
+            .with_delete_route(|_, _| {
+                DELETED_ROUTES.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+            .with_add_route(|_, _| {
+                ADDED_ROUTES.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            });
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(TunnelManager::new(wg_mock, netdev_mock)))
+            .service(run_batch),
+    )
+    .await;
+
+    let req = batch_request(vec![
+        create_device("wg1"),
+        BatchOperation::DeletePeer {
+            device_name: "wg0".to_owned(),
+            public_key: PUBKEY.to_owned(),
+        },
+    ])
+    .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 500);
+    assert_eq!(DELETED_DEVICES.load(Ordering::SeqCst), 1);
+    assert_eq!(DELETED_ROUTES.load(Ordering::SeqCst), 1);
+    assert_eq!(ADDED_ROUTES.load(Ordering::SeqCst), 1);
+
+    let body: BatchError = test::read_body_json(resp).await;
+    assert_eq!(body.index, 1);
+    assert_eq!(body.error.code, "system_error");
+}
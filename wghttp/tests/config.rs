@@ -0,0 +1,146 @@
+use clap::CommandFactory;
+use wghttp::cli::Args;
+use wghttp::config::Config;
+use wghttp::logging::LogFormat;
+
+use std::fs;
+use std::path::PathBuf;
+
+fn temp_config(name: &str, content: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "wghttp-config-{}-{}.toml",
+        std::process::id(),
+        name
+    ));
+    fs::write(&path, content).unwrap();
+    path
+}
+
+fn load_args(args: &[&str]) -> Result<Args, String> {
+    let matches = Args::command()
+        .try_get_matches_from(args)
+        .map_err(|e| e.to_string())?;
+    Args::from_matches(&matches)
+}
+
+#[test]
+fn test_config_parses_every_section() {
+    let config = Config::parse(
+        r#"
+firewall = true
+api_keys = "/etc/wghttp/api-keys.toml"
+
+[listen]
+tcp = "127.0.0.1:8080"
+
+[socket]
+mode = "660"
+owner = "0"
+access = ["uid:1000=read-only"]
+
+[routes]
+table = 100
+
+[metrics]
+labels = ["device", "status"]
+
+[logging]
+format = "json"
+level = "info,wghttp=debug"
+
+[idempotency]
+ttl = 0
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(config.firewall, Some(true));
+    assert_eq!(config.listen.tcp.as_deref(), Some("127.0.0.1:8080"));
+    assert_eq!(config.listen.unix, None);
+    assert_eq!(config.socket.mode, Some(0o660));
+    assert_eq!(config.socket.owner, Some(0));
+    assert_eq!(config.routes.table, Some(100));
+    assert_eq!(config.logging.format, Some(LogFormat::Json));
+    assert_eq!(config.idempotency.ttl, Some(0));
+    assert_eq!(config.otlp.endpoint, None);
+}
+
+#[test]
+fn test_config_rejects_unknown_keys() {
+    let err = Config::parse("[logging]\nformt = \"json\"\n").unwrap_err();
+
+    assert!(err.contains("line 2"));
+    assert!(err.contains("unknown field `formt`"));
+}
+
+#[test]
+fn test_config_points_to_invalid_values() {
+    let err = Config::parse("[listen]\ntcp = \"localhost\"\n").unwrap_err();
+    assert!(err.contains("line 2"));
+    assert!(err.contains("invalid tcp address localhost"));
+
+    let err = Config::parse("[metrics]\nlabels = [\"device\", \"peer\"]\n").unwrap_err();
+    assert!(err.contains("line 2"));
+
+    let err = Config::parse("[logging]\nformat = \"xml\"\n").unwrap_err();
+    assert!(err.contains("invalid log format: xml"));
+}
+
+#[test]
+fn test_args_flag_overrides_config_file() {
+    let path = temp_config("flag", "[routes]\ntable = 100\nmetric = 5\n");
+    let args = load_args(&[
+        "wghttp",
+        "--config",
+        path.to_str().unwrap(),
+        "--route-table",
+        "200",
+    ]);
+    fs::remove_file(&path).unwrap();
+
+    let args = args.unwrap();
+    assert_eq!(args.route_table, Some(200));
+    assert_eq!(args.route_metric, Some(5));
+}
+
+#[test]
+fn test_args_environment_overrides_config_file() {
+    let path = temp_config("env", "[idempotency]\nttl = 60\n\n[events]\ninterval = 5\n");
+    // no other test looks at the idempotency ttl, so setting the variable cannot affect it.
+    unsafe { std::env::set_var("WGHTTP_IDEMPOTENCY_TTL", "120") };
+    let args = load_args(&["wghttp", "--config", path.to_str().unwrap()]);
+    unsafe { std::env::remove_var("WGHTTP_IDEMPOTENCY_TTL") };
+    fs::remove_file(&path).unwrap();
+
+    let args = args.unwrap();
+    assert_eq!(args.idempotency_ttl, 120);
+    assert_eq!(args.events_interval, 5);
+}
+
+#[test]
+fn test_args_config_file_overrides_defaults() {
+    let path = temp_config("defaults", "[logging]\nlevel = \"debug\"\n");
+    let args = load_args(&["wghttp", "--config", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+
+    let args = args.unwrap();
+    assert_eq!(args.log_level, "debug");
+    assert_eq!(args.log_format, LogFormat::Text);
+    assert_eq!(args.unix, "/var/run/wghttp.sock");
+}
+
+#[test]
+fn test_args_check_reads_webhooks_and_audit_log() {
+    let webhooks = temp_config("webhooks", "queue = \"/tmp\"\nmax_attempts = 0\n");
+    let args = load_args(&["wghttp", "--webhooks", webhooks.to_str().unwrap()]).unwrap();
+    let err = args.check().unwrap_err();
+    fs::remove_file(&webhooks).unwrap();
+    assert_eq!(err, "webhook max_attempts must be at least 1");
+
+    let audit_log = std::env::temp_dir()
+        .join("wghttp-missing")
+        .join("audit.log");
+    let args = load_args(&["wghttp", "--audit-log", audit_log.to_str().unwrap()]).unwrap();
+    let err = args.check().unwrap_err();
+    assert!(err.contains("directory does not exist"));
+}
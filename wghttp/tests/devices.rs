@@ -1,12 +1,16 @@
+use actix_web::middleware::from_fn;
 use actix_web::{App, test, web};
+use domain::models::firewall::*;
 use domain::models::netdev::*;
 use domain::models::wg::*;
+use wghttp::middleware::auth::require_api_key;
 use wghttp::models::devices::*;
 use wghttp::models::errors::*;
 use wghttp::routes::devices::*;
 use wghttp::services::TunnelManager;
 
 use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 pub mod mock;
 
@@ -22,6 +26,7 @@ async fn test_list_devices_route_with_empty_result() {
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(list_devices),
     )
@@ -46,6 +51,7 @@ async fn test_list_devices_route_with_result() {
                 public_key: "public_key".to_string(),
                 private_key: "private_key".to_string(),
                 port: 51820,
+                fwmark: 0,
                 peers: 0,
             }])
         }),
@@ -61,6 +67,7 @@ async fn test_list_devices_route_with_result() {
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(list_devices),
     )
@@ -82,7 +89,7 @@ async fn test_list_devices_route_with_result() {
 async fn test_list_devices_route_with_error() {
     let wg_mock = WireguardMockAdapter::new(
         None,
-        Some(|| Err(WGError("error".to_string()))),
+        Some(|| Err(WGError::Other("error".to_string()))),
         None,
         None,
         None,
@@ -94,6 +101,7 @@ async fn test_list_devices_route_with_error() {
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(list_devices),
     )
@@ -116,6 +124,7 @@ async fn test_create_device_route_with_device_name_validation_error() {
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(create_device),
     )
@@ -126,6 +135,8 @@ async fn test_create_device_route_with_device_name_validation_error() {
         .set_json(CreateDeviceRequest {
             device_name: "device_name_16ch".to_string(),
             port: 51820,
+            full_tunnel: false,
+            nat: None,
             ip_addresses: DeviceIpAddr {
                 ipv4: None,
                 ipv6: None,
@@ -148,6 +159,7 @@ async fn test_create_device_route_with_ip_validation_error() {
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(create_device),
     )
@@ -158,6 +170,8 @@ async fn test_create_device_route_with_ip_validation_error() {
         .set_json(CreateDeviceRequest {
             device_name: "device_name".to_string(),
             port: 51820,
+            full_tunnel: false,
+            nat: None,
             ip_addresses: DeviceIpAddr {
                 ipv4: None,
                 ipv6: None,
@@ -183,6 +197,7 @@ async fn test_create_device_route_with_invalid_ip_string() {
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(create_device),
     )
@@ -193,6 +208,8 @@ async fn test_create_device_route_with_invalid_ip_string() {
         .set_json(CreateDeviceRequest {
             device_name: "device_name".to_string(),
             port: 51820,
+            full_tunnel: false,
+            nat: None,
             ip_addresses: DeviceIpAddr {
                 ipv4: Some("invalid_ip".to_string()),
                 ipv6: None,
@@ -211,6 +228,8 @@ async fn test_create_device_route_with_invalid_ip_string() {
         .set_json(CreateDeviceRequest {
             device_name: "device_name".to_string(),
             port: 51820,
+            full_tunnel: false,
+            nat: None,
             ip_addresses: DeviceIpAddr {
                 ipv4: None,
                 ipv6: Some("invalid_ip".to_string()),
@@ -230,7 +249,13 @@ async fn test_create_device_route_with_wg_fail_result() {
     let wg_mock = WireguardMockAdapter::new(
         None,
         None,
-        Some(|_, _| Err(WGError("wg error".to_owned()))),
+        Some(|_, _| {
+            Err(WGError::Lib {
+                kind: WGErrorKind::DevAddFailed,
+                errno: Some(17),
+                extack: Some("device already exists".to_owned()),
+            })
+        }),
         None,
         None,
         None,
@@ -241,6 +266,7 @@ async fn test_create_device_route_with_wg_fail_result() {
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(create_device),
     )
@@ -251,6 +277,8 @@ async fn test_create_device_route_with_wg_fail_result() {
         .set_json(CreateDeviceRequest {
             device_name: "device_name".to_string(),
             port: 51820,
+            full_tunnel: false,
+            nat: None,
             ip_addresses: DeviceIpAddr {
                 ipv4: Some("10.0.0.2/32".to_string()),
                 ipv6: None,
@@ -262,7 +290,13 @@ async fn test_create_device_route_with_wg_fail_result() {
     assert!(resp.status().is_client_error());
     assert_eq!(resp.status(), 409);
     let body: Error = test::read_body_json(resp).await;
-    assert_eq!(body.message, "wg error");
+    assert_eq!(body.code, "device_exists");
+    assert_eq!(
+        body.message,
+        "adding device failed: File exists (os error 17)"
+    );
+    assert_eq!(body.errno, Some(17));
+    assert_eq!(body.extack, Some("device already exists".to_owned()));
 }
 
 #[actix_web::test]
@@ -276,6 +310,7 @@ async fn test_create_device_route_with_netdev_set_ip_fail_result() {
                 public_key: "pubkey".to_owned(),
                 private_key: "privkey".to_owned(),
                 port: 51820,
+                fwmark: 0,
                 peers: 2,
             })
         }),
@@ -286,13 +321,14 @@ async fn test_create_device_route_with_netdev_set_ip_fail_result() {
     );
     let netdev_mock = NetworkDeviceMockAdapter::new(
         None,
-        Some(|_, _| Err(NetDevError("netdev error".to_owned()))),
+        Some(|_, _| Err(NetDevError::from(NetDevErrorKind::InvalidIpPrefix))),
         None,
     );
     let tunnel_manager = TunnelManager::new(wg_mock, netdev_mock);
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(create_device),
     )
@@ -303,6 +339,8 @@ async fn test_create_device_route_with_netdev_set_ip_fail_result() {
         .set_json(CreateDeviceRequest {
             device_name: "device_name".to_string(),
             port: 51820,
+            full_tunnel: false,
+            nat: None,
             ip_addresses: DeviceIpAddr {
                 ipv4: Some("10.0.0.2/32".to_string()),
                 ipv6: None,
@@ -312,9 +350,10 @@ async fn test_create_device_route_with_netdev_set_ip_fail_result() {
     let resp = test::call_service(&app, req).await;
 
     assert!(resp.status().is_client_error());
-    assert_eq!(resp.status(), 400);
+    assert_eq!(resp.status(), 422);
     let body: Error = test::read_body_json(resp).await;
-    assert_eq!(body.message, "netdev error");
+    assert_eq!(body.code, "invalid_address");
+    assert_eq!(body.message, "invalid ip prefix length");
 }
 
 #[actix_web::test]
@@ -328,6 +367,7 @@ async fn test_create_device_route_with_netdev_up_fail_result() {
                 public_key: "pubkey".to_owned(),
                 private_key: "privkey".to_owned(),
                 port: 51820,
+                fwmark: 0,
                 peers: 2,
             })
         }),
@@ -339,12 +379,13 @@ async fn test_create_device_route_with_netdev_up_fail_result() {
     let netdev_mock = NetworkDeviceMockAdapter::new(
         None,
         Some(|_, _| Ok(())),
-        Some(|_| Err(NetDevError("netdev error".to_owned()))),
+        Some(|_| Err(NetDevError::Other("netdev error".to_owned()))),
     );
     let tunnel_manager = TunnelManager::new(wg_mock, netdev_mock);
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(create_device),
     )
@@ -355,6 +396,8 @@ async fn test_create_device_route_with_netdev_up_fail_result() {
         .set_json(CreateDeviceRequest {
             device_name: "device_name".to_string(),
             port: 51820,
+            full_tunnel: false,
+            nat: None,
             ip_addresses: DeviceIpAddr {
                 ipv4: Some("10.0.0.2/32".to_string()),
                 ipv6: None,
@@ -363,9 +406,10 @@ async fn test_create_device_route_with_netdev_up_fail_result() {
         .to_request();
     let resp = test::call_service(&app, req).await;
 
-    assert!(resp.status().is_client_error());
-    assert_eq!(resp.status(), 400);
+    assert!(resp.status().is_server_error());
+    assert_eq!(resp.status(), 500);
     let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.code, "system_error");
     assert_eq!(body.message, "netdev error");
 }
 
@@ -380,6 +424,7 @@ async fn test_create_device_route_with_successful_result() {
                 public_key: "pubkey".to_owned(),
                 private_key: "privkey".to_owned(),
                 port: p,
+                fwmark: 0,
                 peers: 0,
             })
         }),
@@ -393,6 +438,7 @@ async fn test_create_device_route_with_successful_result() {
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(create_device),
     )
@@ -403,6 +449,8 @@ async fn test_create_device_route_with_successful_result() {
         .set_json(CreateDeviceRequest {
             device_name: "device_name".to_string(),
             port: 51820,
+            full_tunnel: false,
+            nat: None,
             ip_addresses: DeviceIpAddr {
                 ipv4: Some("10.0.0.2/32".to_string()),
                 ipv6: Some("2001:db8::2/128".to_string()),
@@ -426,6 +474,7 @@ async fn test_get_device_route_with_validation_error() {
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(get_device),
     )
@@ -445,7 +494,7 @@ async fn test_get_device_route_with_validation_error() {
 #[actix_web::test]
 async fn test_get_device_route_with_not_found_error() {
     let wg_mock = WireguardMockAdapter::new(
-        Some(|_| Err(WGError("device not found".to_owned()))),
+        Some(|_| Err(WGError::from(WGErrorKind::DevNotFound))),
         None,
         None,
         None,
@@ -458,6 +507,7 @@ async fn test_get_device_route_with_not_found_error() {
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(get_device),
     )
@@ -472,6 +522,7 @@ async fn test_get_device_route_with_not_found_error() {
     assert_eq!(resp.status(), 404);
     let body: Error = test::read_body_json(resp).await;
     assert_eq!(body.message, "device not found");
+    assert_eq!(body.code, "device_not_found");
 }
 
 #[actix_web::test]
@@ -483,6 +534,7 @@ async fn test_get_device_route_with_internal_server_error() {
                 public_key: "pubkey".to_owned(),
                 private_key: "privkey".to_owned(),
                 port: 51820,
+                fwmark: 0,
                 peers: 0,
             })
         }),
@@ -494,7 +546,7 @@ async fn test_get_device_route_with_internal_server_error() {
         None,
     );
     let netdev_mock = NetworkDeviceMockAdapter::new(
-        Some(|_| Err(NetDevError("netdev get_ip error".to_owned()))),
+        Some(|_| Err(NetDevError::Other("netdev get_ip error".to_owned()))),
         None,
         None,
     );
@@ -502,6 +554,7 @@ async fn test_get_device_route_with_internal_server_error() {
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(get_device),
     )
@@ -527,6 +580,7 @@ async fn test_get_device_route_with_successful_result() {
                 public_key: "pubkey".to_owned(),
                 private_key: "privkey".to_owned(),
                 port: 51820,
+                fwmark: 0,
                 peers: 0,
             })
         }),
@@ -551,6 +605,7 @@ async fn test_get_device_route_with_successful_result() {
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(get_device),
     )
@@ -580,6 +635,7 @@ async fn test_delete_device_route_with_validation_error() {
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(delete_device),
     )
@@ -602,7 +658,7 @@ async fn test_delete_device_route_with_not_found_error() {
         None,
         None,
         None,
-        Some(|_| Err(WGError("device not found".to_owned()))),
+        Some(|_| Err(WGError::from(WGErrorKind::DevNotFound))),
         None,
         None,
         None,
@@ -612,6 +668,7 @@ async fn test_delete_device_route_with_not_found_error() {
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(delete_device),
     )
@@ -636,6 +693,7 @@ async fn test_delete_device_route_with_successful_result() {
 
     let app = test::init_service(
         App::new()
+            .wrap(from_fn(require_api_key))
             .app_data(web::Data::new(tunnel_manager.clone()))
             .service(delete_device),
     )
@@ -649,3 +707,726 @@ async fn test_delete_device_route_with_successful_result() {
     assert!(resp.status().is_success());
     assert_eq!(resp.status(), 204);
 }
+
+fn created_device(n: &str, p: u16) -> Result<WGDevice, WGError> {
+    Ok(WGDevice {
+        name: n.to_owned(),
+        public_key: "pubkey".to_owned(),
+        private_key: "privkey".to_owned(),
+        port: p,
+        fwmark: 0,
+        peers: 0,
+    })
+}
+
+fn full_tunnel_request(port: u16) -> CreateDeviceRequest {
+    CreateDeviceRequest {
+        device_name: "device_name".to_string(),
+        port,
+        full_tunnel: true,
+        nat: None,
+        ip_addresses: DeviceIpAddr {
+            ipv4: Some("10.0.0.1/24".to_string()),
+            ipv6: None,
+        },
+    }
+}
+
+#[actix_web::test]
+async fn test_create_device_route_with_full_tunnel_without_port() {
+    let wg_mock = WireguardMockAdapter::new(None, None, None, None, None, None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let tunnel_manager = TunnelManager::new(wg_mock, netdev_mock);
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager.clone()))
+            .service(create_device),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices")
+        .set_json(full_tunnel_request(0))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "full tunnel requires a listen port");
+}
+
+#[actix_web::test]
+async fn test_create_device_route_with_full_tunnel_on_reserved_table() {
+    let wg_mock = WireguardMockAdapter::new(None, None, None, None, None, None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let mut tunnel_manager = TunnelManager::new(wg_mock, netdev_mock);
+    tunnel_manager.route_options.table = Some(51820);
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager.clone()))
+            .service(create_device),
+    )
+    .await;
+
+    for port in [254, 51820] {
+        let req = test::TestRequest::post()
+            .uri("/devices")
+            .set_json(full_tunnel_request(port))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+    }
+}
+
+#[actix_web::test]
+async fn test_create_device_route_with_full_tunnel() {
+    static SRC_VALID_MARK_CALLS: AtomicUsize = AtomicUsize::new(0);
+    let wg_mock =
+        WireguardMockAdapter::new(None, None, Some(created_device), None, None, None, None)
+            .with_set_fwmark(|_, fwmark| match fwmark {
+                51820 => Ok(()),
+                _ => Err(WGError::Other("unexpected fwmark".to_owned())),
+            });
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, Some(|_, _| Ok(())), Some(|_| Ok(())))
+        .with_add_route(|_, r| match (r.destination_str().as_str(), r.table) {
+            ("0.0.0.0/0", Some(51820)) => Ok(()),
+            _ => Err(NetDevError::Other("unexpected route".to_owned())),
+        })
+        .with_add_rule(|r| match (r.fwmark, r.invert, r.suppress_prefixlength) {
+            (Some(51820), true, None) if r.table == 51820 => Ok(()),
+            (None, false, Some(0)) if r.table == 254 => Ok(()),
+            _ => Err(NetDevError::Other("unexpected rule".to_owned())),
+        })
+        .with_enable_src_valid_mark(|| {
+            SRC_VALID_MARK_CALLS.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        });
+    let tunnel_manager = TunnelManager::new(wg_mock, netdev_mock);
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager.clone()))
+            .service(create_device),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices")
+        .set_json(full_tunnel_request(51820))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 201);
+    assert_eq!(SRC_VALID_MARK_CALLS.load(Ordering::SeqCst), 1);
+    let body: CreateDeviceResponse = test::read_body_json(resp).await;
+    assert!(body.full_tunnel);
+}
+
+#[actix_web::test]
+async fn test_create_device_route_with_full_tunnel_rule_failure() {
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        None,
+        Some(created_device),
+        Some(|_| Ok(())),
+        None,
+        None,
+        None,
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, Some(|_, _| Ok(())), Some(|_| Ok(())))
+        .with_add_rule(|_| Err(NetDevError::from(NetDevErrorKind::RuleAddFailed)));
+    let tunnel_manager = TunnelManager::new(wg_mock, netdev_mock);
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager.clone()))
+            .service(create_device),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices")
+        .set_json(full_tunnel_request(51820))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 500);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "failed to add rule");
+}
+
+#[actix_web::test]
+async fn test_delete_device_route_removes_full_tunnel_rules() {
+    let wg_mock = WireguardMockAdapter::new(
+        Some(|n| {
+            Ok(WGDevice {
+                name: n.to_owned(),
+                public_key: "pubkey".to_owned(),
+                private_key: "privkey".to_owned(),
+                port: 51820,
+                fwmark: 51820,
+                peers: 0,
+            })
+        }),
+        None,
+        None,
+        Some(|_| Ok(())),
+        None,
+        None,
+        None,
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None)
+        .with_delete_rule(|_| Err(NetDevError::from(NetDevErrorKind::RuleDelFailed)));
+    let tunnel_manager = TunnelManager::new(wg_mock, netdev_mock);
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager.clone()))
+            .service(delete_device),
+    )
+    .await;
+
+    let req = test::TestRequest::delete()
+        .uri("/devices/device_name")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 500);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "failed to delete rule");
+}
+
+fn nat_request(egress_interface: Option<&str>) -> CreateDeviceRequest {
+    CreateDeviceRequest {
+        device_name: "device_name".to_string(),
+        port: 51820,
+        full_tunnel: false,
+        nat: Some(NatConfig {
+            egress_interface: egress_interface.map(|e| e.to_string()),
+        }),
+        ip_addresses: DeviceIpAddr {
+            ipv4: Some("10.0.0.1/24".to_string()),
+            ipv6: None,
+        },
+    }
+}
+
+#[actix_web::test]
+async fn test_create_device_route_with_nat_not_enabled() {
+    let wg_mock = WireguardMockAdapter::new(None, None, None, None, None, None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let tunnel_manager = TunnelManager::new(wg_mock, netdev_mock);
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager.clone()))
+            .service(create_device),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices")
+        .set_json(nat_request(None))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "firewall is not enabled");
+}
+
+#[actix_web::test]
+async fn test_create_device_route_with_nat_egress_validation_error() {
+    let wg_mock = WireguardMockAdapter::new(None, None, None, None, None, None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let tunnel_manager =
+        TunnelManager::new(wg_mock, netdev_mock).with_firewall(FirewallMockAdapter::default());
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager.clone()))
+            .service(create_device),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices")
+        .set_json(nat_request(Some("a_very_long_interface")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "egress interface must be 1 to 15 characters");
+}
+
+#[actix_web::test]
+async fn test_create_device_route_with_nat() {
+    let wg_mock =
+        WireguardMockAdapter::new(None, None, Some(created_device), None, None, None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, Some(|_, _| Ok(())), Some(|_| Ok(())));
+    let firewall_mock = FirewallMockAdapter::default()
+        .with_set_forwarding(|family, enabled| match (family, enabled) {
+            (IpFamily::V4, true) => Ok(()),
+            _ => Err(FirewallError::Other("unexpected forwarding".to_owned())),
+        })
+        .with_add_masquerade(|_, m| match m.egress_interface.as_deref() {
+            Some("eth0") => Ok(()),
+            _ => Err(FirewallError::Other("unexpected masquerade".to_owned())),
+        });
+    let tunnel_manager = TunnelManager::new(wg_mock, netdev_mock).with_firewall(firewall_mock);
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager.clone()))
+            .service(create_device),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices")
+        .set_json(nat_request(Some("eth0")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 201);
+    let body: CreateDeviceResponse = test::read_body_json(resp).await;
+    assert_eq!(
+        body.nat.and_then(|n| n.egress_interface),
+        Some("eth0".to_string())
+    );
+}
+
+#[actix_web::test]
+async fn test_create_device_route_with_nat_failure() {
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        None,
+        Some(created_device),
+        Some(|_| Ok(())),
+        None,
+        None,
+        None,
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, Some(|_, _| Ok(())), Some(|_| Ok(())));
+    let firewall_mock = FirewallMockAdapter::default().with_add_masquerade(|_, _| {
+        Err(FirewallError::Other("failed to update ruleset".to_owned()))
+    });
+    let tunnel_manager = TunnelManager::new(wg_mock, netdev_mock).with_firewall(firewall_mock);
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager.clone()))
+            .service(create_device),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices")
+        .set_json(nat_request(None))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 500);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "failed to update ruleset");
+}
+
+#[actix_web::test]
+async fn test_get_device_route_reports_nat_state() {
+    let wg_mock = WireguardMockAdapter::new(
+        Some(|n| created_device(n, 51820)),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(
+        Some(|_| {
+            Ok(NetDevIp {
+                ipv4: Some((Ipv4Addr::new(10, 0, 0, 1), 24)),
+                ipv6: None,
+            })
+        }),
+        None,
+        None,
+    );
+    let firewall_mock = FirewallMockAdapter::default()
+        .with_get_masquerade(|_| {
+            Ok(Some(Masquerade {
+                egress_interface: None,
+            }))
+        })
+        .with_get_forwarding(|family| Ok(family == IpFamily::V4));
+    let tunnel_manager = TunnelManager::new(wg_mock, netdev_mock).with_firewall(firewall_mock);
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager.clone()))
+            .service(get_device),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/devices/device_name")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    let body: DetailDeviceResponse = test::read_body_json(resp).await;
+    let nat = body.nat.expect("nat state");
+    assert_eq!(nat.egress_interface, None);
+    assert!(nat.ipv4_forwarding);
+    assert!(!nat.ipv6_forwarding);
+}
+
+#[actix_web::test]
+async fn test_delete_device_route_removes_masquerade() {
+    let wg_mock = WireguardMockAdapter::new(
+        Some(|n| created_device(n, 51820)),
+        None,
+        None,
+        Some(|_| Ok(())),
+        None,
+        None,
+        None,
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let firewall_mock = FirewallMockAdapter::default().with_delete_masquerade(|_| {
+        Err(FirewallError::Other("failed to update ruleset".to_owned()))
+    });
+    let tunnel_manager = TunnelManager::new(wg_mock, netdev_mock).with_firewall(firewall_mock);
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager.clone()))
+            .service(delete_device),
+    )
+    .await;
+
+    let req = test::TestRequest::delete()
+        .uri("/devices/device_name")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 500);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "failed to update ruleset");
+}
+
+fn netns_device(_: &str) -> Result<WGDevice, WGError> {
+    Ok(WGDevice {
+        name: "wg0".to_string(),
+        public_key: "public_key".to_string(),
+        private_key: "private_key".to_string(),
+        port: 51820,
+        fwmark: 0,
+        peers: 1,
+    })
+}
+
+#[actix_web::test]
+async fn test_list_devices_route_with_conflicting_netns() {
+    let wg_mock = WireguardMockAdapter::new(None, None, None, None, None, None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(list_devices),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/devices?netns=tenant1&netns_pid=42")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "netns and netns_pid are mutually exclusive");
+}
+
+#[actix_web::test]
+async fn test_get_device_route_with_netns_not_found() {
+    let wg_mock = WireguardMockAdapter::new(Some(netns_device), None, None, None, None, None, None);
+    let netdev_mock =
+        NetworkDeviceMockAdapter::new(None, None, None).with_enter_netns(|ns| match ns {
+            NetNs::Named(name) if name == "tenant1" => Ok(NetNsHandle(3)),
+            _ => Err(NetDevError::from(NetDevErrorKind::NetnsOpenFailed)),
+        });
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(get_device),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/devices/wg0?netns=tenant2")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 404);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "network namespace not found");
+    assert_eq!(body.code, "namespace_not_found");
+}
+
+#[actix_web::test]
+async fn test_get_device_route_leaves_netns() {
+    use std::sync::atomic::{AtomicI32, Ordering};
+    static LEFT: AtomicI32 = AtomicI32::new(0);
+
+    let wg_mock = WireguardMockAdapter::new(Some(netns_device), None, None, None, None, None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(
+        Some(|_| {
+            Ok(NetDevIp::new(
+                Some((Ipv4Addr::new(10, 0, 0, 1).into(), 24)),
+                None,
+            ))
+        }),
+        None,
+        None,
+    )
+    .with_enter_netns(|ns| match ns {
+        NetNs::Pid(42) => Ok(NetNsHandle(3)),
+        _ => Err(NetDevError::from(NetDevErrorKind::NetnsOpenFailed)),
+    })
+    .with_leave_netns(|handle| {
+        LEFT.store(handle.0, Ordering::SeqCst);
+        Ok(())
+    });
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(get_device),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/devices/wg0?netns_pid=42")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    assert_eq!(LEFT.load(Ordering::SeqCst), 3);
+}
+
+#[actix_web::test]
+async fn test_move_device_route_with_full_tunnel() {
+    let wg_mock = WireguardMockAdapter::new(
+        Some(|n| {
+            let mut d = netns_device(n)?;
+            d.fwmark = 51820;
+            Ok(d)
+        }),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(move_device),
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri("/devices/wg0/netns")
+        .set_json(NetNsParams {
+            netns: Some("tenant1".to_owned()),
+            netns_pid: None,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "full tunnel devices cannot be moved");
+}
+
+#[actix_web::test]
+async fn test_move_device_route_with_successful_result() {
+    let wg_mock = WireguardMockAdapter::new(
+        Some(netns_device),
+        None,
+        None,
+        None,
+        Some(|_| {
+            Ok(vec![WGPeer {
+                allowed_ips: vec!["192.168.10.0/24".to_owned()],
+                endpoint: "endpoint".to_owned(),
+                last_handshake_time: 0,
+                persistent_keepalive_interval: 0,
+                rx: 0,
+                tx: 0,
+                public_key: "public_key".to_owned(),
+                private_key: "private_key".to_owned(),
+                preshared_key: "preshared_key".to_owned(),
+            }])
+        }),
+        None,
+        None,
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(
+        Some(|_| {
+            Ok(NetDevIp::new(
+                Some((Ipv4Addr::new(10, 0, 0, 1).into(), 24)),
+                None,
+            ))
+        }),
+        Some(|_, ip| match ip.ipv4_str().as_deref() {
+            Some("10.0.0.1/24") => Ok(()),
+            _ => Err(NetDevError::Other("unexpected ip".to_owned())),
+        }),
+        Some(|_| Ok(())),
+    )
+    .with_set_netns(|d, ns| match (d, ns) {
+        ("wg0", NetNs::Named(name)) if name == "tenant1" => Ok(()),
+        _ => Err(NetDevError::Other("unexpected namespace".to_owned())),
+    })
+    .with_add_route(|_, r| match r.destination_str().as_str() {
+        "192.168.10.0/24" => Ok(()),
+        other => Err(NetDevError::Other(format!("unexpected route {}", other))),
+    });
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(move_device),
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri("/devices/wg0/netns")
+        .set_json(NetNsParams {
+            netns: Some("tenant1".to_owned()),
+            netns_pid: None,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 204);
+}
+
+#[actix_web::test]
+async fn test_move_device_route_with_firewall_rules() {
+    let wg_mock = WireguardMockAdapter::new(Some(netns_device), None, None, None, None, None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(
+        Some(|_| {
+            Ok(NetDevIp::new(
+                Some((Ipv4Addr::new(10, 0, 0, 1).into(), 24)),
+                None,
+            ))
+        }),
+        None,
+        None,
+    )
+    .with_set_netns(|_, _| Err(NetDevError::Other("unexpected move".to_owned())));
+    let firewall_mock = FirewallMockAdapter::default().with_get_masquerade(|_| {
+        Ok(Some(Masquerade {
+            egress_interface: None,
+        }))
+    });
+    let tm = TunnelManager::new(wg_mock, netdev_mock).with_firewall(firewall_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(move_device),
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri("/devices/wg0/netns")
+        .set_json(MoveDeviceRequest {
+            netns: Some("tenant1".to_owned()),
+            netns_pid: None,
+            drop_firewall: false,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 409);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.code, "firewall_rules_exist");
+}
+
+#[actix_web::test]
+async fn test_move_device_route_drops_firewall_rules() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    static DROPPED: AtomicBool = AtomicBool::new(false);
+
+    let wg_mock = WireguardMockAdapter::new(Some(netns_device), None, None, None, None, None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(
+        Some(|_| {
+            Ok(NetDevIp::new(
+                Some((Ipv4Addr::new(10, 0, 0, 1).into(), 24)),
+                None,
+            ))
+        }),
+        Some(|_, _| Ok(())),
+        Some(|_| Ok(())),
+    )
+    .with_set_netns(|_, _| Ok(()));
+    let firewall_mock = FirewallMockAdapter::default()
+        .with_get_masquerade(|_| {
+            Ok(Some(Masquerade {
+                egress_interface: None,
+            }))
+        })
+        .with_delete_masquerade(|_| {
+            DROPPED.store(true, Ordering::SeqCst);
+            Ok(())
+        });
+    let tm = TunnelManager::new(wg_mock, netdev_mock).with_firewall(firewall_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(move_device),
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri("/devices/wg0/netns")
+        .set_json(MoveDeviceRequest {
+            netns: Some("tenant1".to_owned()),
+            netns_pid: None,
+            drop_firewall: true,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 204);
+    assert!(DROPPED.load(Ordering::SeqCst));
+}
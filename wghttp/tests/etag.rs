@@ -0,0 +1,234 @@
+use actix_web::middleware::from_fn;
+use actix_web::{App, test, web};
+use wghttp::etag::{Preconditions, device_etag, peer_etag};
+use wghttp::middleware::auth::require_api_key;
+use wghttp::models::errors::*;
+use wghttp::models::peers::*;
+use wghttp::routes::devices::{delete_device, get_device};
+use wghttp::routes::peers::{delete_peer, get_peer, list_peers, update_peer};
+use wghttp::services::TunnelManager;
+
+pub mod mock;
+
+use mock::*;
+
+fn manager() -> TunnelManager {
+    let wg_mock = WireguardMockAdapter::new(
+        Some(|name| Ok(device(name))),
+        None,
+        None,
+        Some(|_| Ok(())),
+        Some(|_| Ok(vec![peer(PUBKEY, vec!["10.0.0.2/32"], 0)])),
+        None,
+        Some(|_, _| Ok(())),
+    )
+    .with_update_peer(|_, _, i, p| Ok(peer(PUBKEY, i, p)));
+    tunnel_manager(wg_mock)
+}
+
+fn update_request(if_match: Option<&str>) -> test::TestRequest {
+    let mut req = test::TestRequest::put()
+        .uri(&format!("/devices/wg0/peers/{}", PUBKEY))
+        .set_json(UpdatePeerRequest {
+            allowed_ips: vec!["10.0.0.3/32".to_owned()],
+            persistent_keepalive_interval: 25,
+        });
+    if let Some(if_match) = if_match {
+        req = req.insert_header(("If-Match", if_match));
+    }
+    req
+}
+
+#[actix_web::test]
+async fn test_get_device_route_returns_etag() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager()))
+            .service(get_device),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/devices/wg0").to_request();
+    let first = test::call_service(&app, req).await;
+    let req = test::TestRequest::get().uri("/devices/wg0").to_request();
+    let second = test::call_service(&app, req).await;
+
+    assert_eq!(first.status(), 200);
+    let etag = first.headers().get("etag").unwrap();
+    assert!(etag.to_str().unwrap().starts_with('"'));
+    assert_eq!(etag, second.headers().get("etag").unwrap());
+}
+
+#[actix_web::test]
+async fn test_peer_etag_ignores_endpoint_and_counters() {
+    let mut roamed = peer(PUBKEY, vec!["10.0.0.2/32"], 0);
+    roamed.endpoint = "other".to_owned();
+    roamed.rx = 100;
+    roamed.last_handshake_time = 1000;
+
+    assert_eq!(
+        peer_etag(&roamed),
+        peer_etag(&peer(PUBKEY, vec!["10.0.0.2/32"], 0))
+    );
+    assert_ne!(
+        peer_etag(&roamed),
+        peer_etag(&peer(PUBKEY, vec!["10.0.0.2/32", "10.0.1.0/24"], 0))
+    );
+    assert_ne!(
+        peer_etag(&roamed),
+        peer_etag(&peer(PUBKEY, vec!["10.0.0.2/32"], 25))
+    );
+}
+
+#[actix_web::test]
+async fn test_etags_leave_out_secrets() {
+    let mut rekeyed = device("wg0");
+    rekeyed.private_key = "otherprivkey".to_owned();
+    let ip = device_ip("wg0").unwrap();
+    assert_eq!(device_etag(&rekeyed, &ip), device_etag(&device("wg0"), &ip));
+
+    let mut other_psk = peer(PUBKEY, vec!["10.0.0.2/32"], 0);
+    other_psk.preshared_key = "otherpreshared".to_owned();
+    assert_eq!(
+        peer_etag(&other_psk),
+        peer_etag(&peer(PUBKEY, vec!["10.0.0.2/32"], 0))
+    );
+
+    let mut without_psk = peer(PUBKEY, vec!["10.0.0.2/32"], 0);
+    without_psk.preshared_key = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_owned();
+    assert_ne!(
+        peer_etag(&without_psk),
+        peer_etag(&peer(PUBKEY, vec!["10.0.0.2/32"], 0))
+    );
+}
+
+#[actix_web::test]
+async fn test_get_peer_route_returns_etag() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager()))
+            .service(list_peers)
+            .service(get_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/devices/wg0/peers/{}", PUBKEY))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let etag = resp.headers().get("etag").unwrap().clone();
+    let body: ListPeerResponse = test::read_body_json(resp).await;
+    assert_eq!(body.etag, etag.to_str().unwrap());
+
+    let req = test::TestRequest::get()
+        .uri("/devices/wg0/peers")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: Vec<ListPeerResponse> = test::read_body_json(resp).await;
+    assert_eq!(body[0].etag, etag.to_str().unwrap());
+
+    let req = test::TestRequest::get()
+        .uri("/devices/wg0/peers/otherotherotherotherotherotherotherotherothe")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_update_peer_route_with_if_match() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager()))
+            .service(update_peer),
+    )
+    .await;
+    let current = peer_etag(&peer(PUBKEY, vec!["10.0.0.2/32"], 0));
+
+    let req = update_request(Some("\"stale\"")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 412);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.code, "precondition_failed");
+
+    let req = update_request(Some(&format!("\"stale\", {}", current))).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("etag").unwrap().to_str().unwrap(),
+        peer_etag(&peer(PUBKEY, vec!["10.0.0.3/32"], 25))
+    );
+
+    let req = update_request(Some("*")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let req = update_request(None).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+}
+
+#[actix_web::test]
+async fn test_mutations_require_if_match_in_strict_mode() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager()))
+            .app_data(web::Data::new(Preconditions { required: true }))
+            .service(update_peer)
+            .service(delete_peer),
+    )
+    .await;
+
+    let req = update_request(None).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 428);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.code, "precondition_required");
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/devices/wg0/peers/{}", PUBKEY))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 428);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/devices/wg0/peers/{}", PUBKEY))
+        .insert_header(("If-Match", peer_etag(&peer(PUBKEY, vec!["10.0.0.2/32"], 0))))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 204);
+}
+
+#[actix_web::test]
+async fn test_delete_device_route_with_stale_if_match() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager()))
+            .service(get_device)
+            .service(delete_device),
+    )
+    .await;
+
+    let req = test::TestRequest::delete()
+        .uri("/devices/wg0")
+        .insert_header(("If-Match", "\"stale\""))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 412);
+
+    let req = test::TestRequest::get().uri("/devices/wg0").to_request();
+    let resp = test::call_service(&app, req).await;
+    let etag = resp.headers().get("etag").unwrap().clone();
+
+    let req = test::TestRequest::delete()
+        .uri("/devices/wg0")
+        .insert_header(("If-Match", etag))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 204);
+}
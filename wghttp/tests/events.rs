@@ -0,0 +1,228 @@
+use actix_web::body::MessageBody;
+use actix_web::middleware::from_fn;
+use actix_web::{App, test, web};
+use domain::models::netdev::NetNs;
+use domain::models::wg::*;
+use std::sync::Arc;
+use wghttp::events::EventBus;
+use wghttp::middleware::auth::require_api_key;
+use wghttp::models::errors::Error;
+use wghttp::models::events::*;
+use wghttp::routes::events::*;
+use wghttp::routes::peers::delete_peer;
+use wghttp::services::TunnelManager;
+
+pub mod mock;
+
+use mock::*;
+
+const PUBKEY: &str = "pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu";
+
+fn peer(endpoint: &str, last_handshake_time: i64) -> WGPeer {
+    WGPeer {
+        allowed_ips: vec!["10.0.0.2/32".to_owned()],
+        endpoint: endpoint.to_owned(),
+        last_handshake_time,
+        persistent_keepalive_interval: 0,
+        rx: 0,
+        tx: 0,
+        public_key: PUBKEY.to_owned(),
+        private_key: "privkey".to_owned(),
+        preshared_key: "preshared".to_owned(),
+    }
+}
+
+fn tunnel_manager(bus: Option<Arc<EventBus>>) -> TunnelManager {
+    let wg_mock =
+        WireguardMockAdapter::new(None, None, None, None, None, None, Some(|_, _| Ok(())));
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    match bus {
+        Some(bus) => tm.with_events(bus),
+        None => tm,
+    }
+}
+
+#[actix_web::test]
+async fn test_event_bus_sample_publishes_changes_since_the_last_sample() {
+    let bus = EventBus::new();
+    let mut rx = bus.subscribe();
+
+    bus.sample(vec![("wg0".to_owned(), Some(vec![peer("", 0)]))]);
+    assert!(rx.try_recv().is_err());
+
+    bus.sample(vec![
+        ("wg0".to_owned(), Some(vec![peer("192.0.2.1:51820", 1000)])),
+        ("wg1".to_owned(), Some(vec![])),
+    ]);
+    let changes: Vec<Change> = std::iter::from_fn(|| rx.try_recv().ok())
+        .map(|e| e.change)
+        .collect();
+    assert_eq!(
+        changes,
+        vec![
+            Change::EndpointChanged {
+                public_key: PUBKEY.to_owned(),
+                endpoint: "192.0.2.1:51820".to_owned(),
+            },
+            Change::Handshake {
+                public_key: PUBKEY.to_owned(),
+                last_handshake_time: 1000,
+            },
+            Change::PeerConnected {
+                public_key: PUBKEY.to_owned(),
+                endpoint: "192.0.2.1:51820".to_owned(),
+            },
+            Change::DeviceCreated,
+        ]
+    );
+
+    bus.sample(vec![("wg0".to_owned(), Some(vec![]))]);
+    let published: Vec<Event> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+    assert_eq!(published.len(), 2);
+    assert!(published.contains(&Event::new(None, "wg1", Change::DeviceDeleted)));
+    let removed = Change::PeerRemoved {
+        public_key: PUBKEY.to_owned(),
+    };
+    assert!(published.contains(&Event::new(None, "wg0", removed)));
+}
+
+#[actix_web::test]
+async fn test_event_bus_sample_keeps_devices_whose_peers_cannot_be_listed() {
+    let bus = EventBus::new();
+    bus.sample(vec![(
+        "wg0".to_owned(),
+        Some(vec![peer("192.0.2.1:51820", 1000)]),
+    )]);
+    let mut rx = bus.subscribe();
+
+    bus.sample(vec![("wg0".to_owned(), None)]);
+    assert!(rx.try_recv().is_err());
+
+    bus.sample(vec![(
+        "wg0".to_owned(),
+        Some(vec![peer("192.0.2.1:51820", 1000)]),
+    )]);
+    assert!(rx.try_recv().is_err());
+}
+
+#[actix_web::test]
+async fn test_event_bus_sample_skips_changes_published_by_the_api() {
+    let bus = EventBus::new();
+    bus.sample(vec![("wg0".to_owned(), Some(vec![]))]);
+    let mut rx = bus.subscribe();
+
+    bus.publish(Event::new(None, "wg0", Change::peer_added(&peer("", 0))));
+    let netns = NetNs::Named("tenant1".to_owned());
+    bus.publish(Event::new(Some(&netns), "wg1", Change::DeviceCreated));
+    bus.sample(vec![("wg0".to_owned(), Some(vec![peer("", 0)]))]);
+
+    let published: Vec<Event> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+    assert_eq!(published.len(), 2);
+    assert_eq!(published[0].change.name(), "peer_added");
+    assert_eq!(published[1].netns, Some("tenant1".to_owned()));
+}
+
+#[actix_web::test]
+async fn test_delete_peer_route_publishes_peer_removed() {
+    let bus = Arc::new(EventBus::new());
+    let mut rx = bus.subscribe();
+    let tm = tunnel_manager(Some(bus));
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(delete_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/devices/wg0/peers/{}", PUBKEY))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 204);
+    let event = rx.try_recv().unwrap();
+    assert_eq!(event.device, "wg0");
+    assert_eq!(
+        event.change,
+        Change::PeerRemoved {
+            public_key: PUBKEY.to_owned()
+        }
+    );
+}
+
+#[actix_web::test]
+async fn test_events_route_streams_events_of_the_requested_devices() {
+    let bus = Arc::new(EventBus::new());
+    let tm = tunnel_manager(Some(bus.clone()));
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(events),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/events?device=wg0")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/event-stream"
+    );
+
+    bus.publish(Event::new(None, "wg1", Change::DeviceCreated));
+    bus.publish(Event::new(None, "wg0", Change::DeviceDeleted));
+
+    let mut body = Box::pin(resp.into_body());
+    let chunk = std::future::poll_fn(|cx| body.as_mut().poll_next(cx))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        chunk,
+        "event: device_deleted\ndata: {\"device\":\"wg0\",\"type\":\"device_deleted\"}\n\n"
+    );
+}
+
+#[actix_web::test]
+async fn test_events_route_without_event_bus() {
+    let tm = tunnel_manager(None);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(events),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/events").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "events are not enabled");
+}
+
+#[actix_web::test]
+async fn test_events_ws_route_without_upgrade() {
+    let tm = tunnel_manager(Some(Arc::new(EventBus::new())));
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(events_ws),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/events/ws").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.code, "invalid_request");
+}
@@ -0,0 +1,310 @@
+use actix_web::middleware::from_fn;
+use actix_web::{App, test, web};
+use domain::models::firewall::*;
+use domain::models::wg::*;
+use wghttp::middleware::auth::require_api_key;
+use wghttp::models::errors::*;
+use wghttp::models::forwards::*;
+use wghttp::routes::forwards::*;
+use wghttp::services::TunnelManager;
+
+pub mod mock;
+
+use mock::*;
+
+use std::net::{IpAddr, Ipv4Addr};
+
+const PUBKEY: &str = "pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu";
+
+fn peers(_: &str) -> Result<Vec<WGPeer>, WGError> {
+    Ok(vec![WGPeer {
+        allowed_ips: vec!["10.0.0.2/32".to_owned(), "192.168.10.0/24".to_owned()],
+        endpoint: "endpoint".to_owned(),
+        last_handshake_time: 0,
+        persistent_keepalive_interval: 0,
+        rx: 0,
+        tx: 0,
+        public_key: PUBKEY.to_owned(),
+        private_key: "privkey".to_owned(),
+        preshared_key: "preshared".to_owned(),
+    }])
+}
+
+fn https_forward(_: &str) -> Result<Vec<PortForward>, FirewallError> {
+    Ok(vec![PortForward {
+        peer_public_key: PUBKEY.to_owned(),
+        protocol: Protocol::Tcp,
+        public_port: 8443,
+        destination_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+        destination_port: 443,
+    }])
+}
+
+fn forward_request(destination_ip: &str) -> CreateForwardRequest {
+    CreateForwardRequest {
+        protocol: ForwardProtocol::Tcp,
+        public_port: 8443,
+        destination_ip: destination_ip.to_owned(),
+        destination_port: 443,
+    }
+}
+
+fn manager(firewall_mock: Option<FirewallMockAdapter>) -> TunnelManager {
+    let wg_mock = WireguardMockAdapter::new(None, None, None, None, Some(peers), None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    match firewall_mock {
+        Some(firewall_mock) => tm.with_firewall(firewall_mock),
+        None => tm,
+    }
+}
+
+#[actix_web::test]
+async fn test_list_forwards_route_with_nat_not_enabled() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(None)))
+            .service(list_forwards),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/devices/wg0/peers/{}/forwards", PUBKEY))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "firewall is not enabled");
+}
+
+#[actix_web::test]
+async fn test_list_forwards_route_with_result() {
+    let firewall_mock = FirewallMockAdapter::default().with_list_forwards(|d| {
+        let mut forwards = https_forward(d)?;
+        forwards.push(PortForward {
+            peer_public_key: "otherpubkeyotherpubkeyotherpubkeyotherpubkey".to_owned(),
+            protocol: Protocol::Udp,
+            public_port: 5353,
+            destination_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3)),
+            destination_port: 53,
+        });
+        Ok(forwards)
+    });
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(Some(firewall_mock))))
+            .service(list_forwards),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/devices/wg0/peers/{}/forwards", PUBKEY))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    let body: Vec<ForwardResponse> = test::read_body_json(resp).await;
+    assert_eq!(body.len(), 1);
+    assert_eq!(body[0].protocol, ForwardProtocol::Tcp);
+    assert_eq!(body[0].public_port, 8443);
+    assert_eq!(body[0].destination_ip, "10.0.0.2");
+    assert_eq!(body[0].destination_port, 443);
+}
+
+#[actix_web::test]
+async fn test_create_forward_route_with_destination_outside_allowed_ips() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(
+                Some(FirewallMockAdapter::default()),
+            )))
+            .service(create_forward),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/devices/wg0/peers/{}/forwards", PUBKEY))
+        .set_json(forward_request("10.0.0.3"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(
+        body.message,
+        "destination ip is not within the peer's allowed ips"
+    );
+}
+
+#[actix_web::test]
+async fn test_create_forward_route_with_peer_not_found() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(
+                Some(FirewallMockAdapter::default()),
+            )))
+            .service(create_forward),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices/wg0/peers/otherpubkeyotherpubkeyotherpubkeyotherpubkey/forwards")
+        .set_json(forward_request("10.0.0.2"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 404);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "peer not found");
+}
+
+#[actix_web::test]
+async fn test_create_forward_route_with_conflict() {
+    let firewall_mock = FirewallMockAdapter::default().with_list_forwards(https_forward);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(Some(firewall_mock))))
+            .service(create_forward),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/devices/wg0/peers/{}/forwards", PUBKEY))
+        .set_json(forward_request("192.168.10.5"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 409);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "public port is already forwarded");
+}
+
+#[actix_web::test]
+async fn test_create_forward_route_with_port_taken_by_firewall() {
+    let firewall_mock = FirewallMockAdapter::default()
+        .with_add_forward(|_, _| Err(FirewallError::from(FirewallErrorKind::ForwardExists)));
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(Some(firewall_mock))))
+            .service(create_forward),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/devices/wg0/peers/{}/forwards", PUBKEY))
+        .set_json(forward_request("192.168.10.5"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 409);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.code, "forward_exists");
+}
+
+#[actix_web::test]
+async fn test_create_forward_route_with_successful_result() {
+    let firewall_mock = FirewallMockAdapter::default()
+        .with_set_forwarding(|family, enabled| match (family, enabled) {
+            (domain::models::netdev::IpFamily::V4, true) => Ok(()),
+            _ => Err(FirewallError::Other("unexpected forwarding".to_owned())),
+        })
+        .with_add_forward(|_, f| match (f.peer_public_key.as_str(), f.public_port) {
+            (PUBKEY, 8443) => Ok(()),
+            _ => Err(FirewallError::Other("unexpected forward".to_owned())),
+        });
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(Some(firewall_mock))))
+            .service(create_forward),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/devices/wg0/peers/{}/forwards", PUBKEY))
+        .set_json(forward_request("192.168.10.5"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 201);
+    let body: ForwardResponse = test::read_body_json(resp).await;
+    assert_eq!(body.destination_ip, "192.168.10.5");
+    assert_eq!(body.destination_port, 443);
+}
+
+#[actix_web::test]
+async fn test_delete_forward_route_with_not_found() {
+    let firewall_mock = FirewallMockAdapter::default().with_list_forwards(https_forward);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(Some(firewall_mock))))
+            .service(delete_forward),
+    )
+    .await;
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/devices/wg0/peers/{}/forwards/udp/8443", PUBKEY))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 404);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "forward not found");
+}
+
+#[actix_web::test]
+async fn test_delete_forward_route_with_successful_result() {
+    let firewall_mock = FirewallMockAdapter::default()
+        .with_list_forwards(https_forward)
+        .with_delete_forward(|_, protocol, port| match (protocol, port) {
+            (Protocol::Tcp, 8443) => Ok(()),
+            _ => Err(FirewallError::Other("unexpected forward".to_owned())),
+        });
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(Some(firewall_mock))))
+            .service(delete_forward),
+    )
+    .await;
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/devices/wg0/peers/{}/forwards/tcp/8443", PUBKEY))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 204);
+}
+
+#[actix_web::test]
+async fn test_delete_forward_route_with_forward_removed_by_firewall() {
+    let firewall_mock = FirewallMockAdapter::default()
+        .with_list_forwards(https_forward)
+        .with_delete_forward(|_, _, _| {
+            Err(FirewallError::from(FirewallErrorKind::ForwardNotFound))
+        });
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(Some(firewall_mock))))
+            .service(delete_forward),
+    )
+    .await;
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/devices/wg0/peers/{}/forwards/tcp/8443", PUBKEY))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 404);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.code, "forward_not_found");
+}
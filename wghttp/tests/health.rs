@@ -1,5 +1,20 @@
-use actix_web::{App, test};
-use wghttp::routes::health::health;
+use actix_web::{App, test, web};
+use domain::models::wg::*;
+use wghttp::health::*;
+use wghttp::models::health::*;
+use wghttp::routes::health::*;
+use wghttp::services::TunnelManager;
+
+pub mod mock;
+
+use mock::*;
+
+fn manager(wg_check: fn() -> Result<(), WGError>) -> TunnelManager {
+    let wg_mock =
+        WireguardMockAdapter::new(None, None, None, None, None, None, None).with_check(wg_check);
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    TunnelManager::new(wg_mock, netdev_mock)
+}
 
 #[actix_web::test]
 async fn test_health_route_returns_ok() {
@@ -11,3 +26,71 @@ async fn test_health_route_returns_ok() {
     assert!(resp.status().is_success());
     assert_eq!(resp.status(), 200);
 }
+
+#[actix_web::test]
+async fn test_healthz_route_returns_ok() {
+    let app = test::init_service(App::new().service(healthz)).await;
+
+    let req = test::TestRequest::get().uri("/healthz").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    let body: Health = test::read_body_json(resp).await;
+    assert_eq!(body.status, CheckStatus::Ok);
+}
+
+#[actix_web::test]
+async fn test_readyz_route_reports_each_check() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(manager(|| Ok(()))))
+            .service(readyz),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/readyz").to_request();
+    let resp = test::call_service(&app, req).await;
+    let status = resp.status();
+    let body: Health = test::read_body_json(resp).await;
+
+    let names: Vec<&str> = body.checks.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["wireguard", "rtnetlink", "capabilities"]);
+    assert_eq!(body.checks[0].status, CheckStatus::Ok);
+    assert_eq!(body.checks[1].status, CheckStatus::Ok);
+    assert!(body.checks.iter().all(|c| c.latency_ms >= 0.0));
+    // capabilities depend on the user running the tests.
+    assert_eq!(status == 200, body.status == CheckStatus::Ok);
+}
+
+#[actix_web::test]
+async fn test_readyz_route_without_wireguard() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(manager(|| {
+                Err(WGErrorKind::FamilyNotFound.into())
+            })))
+            .service(readyz),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/readyz").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 503);
+    let body: Health = test::read_body_json(resp).await;
+    assert_eq!(body.status, CheckStatus::Fail);
+    assert_eq!(body.checks[0].status, CheckStatus::Fail);
+    assert_eq!(
+        body.checks[0].error,
+        Some("WGError: wireguard netlink family not found".to_owned())
+    );
+}
+
+#[actix_web::test]
+async fn test_effective_capabilities() {
+    let status = "Name:\twghttp\nCapPrm:\t0000000000001000\nCapEff:\t0000000000001000\n";
+
+    let caps = effective_capabilities(status).unwrap();
+    assert_eq!(caps, 1 << CAP_NET_ADMIN);
+    assert_eq!(effective_capabilities("Name:\twghttp\n"), None);
+}
@@ -0,0 +1,182 @@
+use actix_web::middleware::from_fn;
+use actix_web::{App, test, web};
+use domain::models::wg::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use wghttp::middleware::auth::require_api_key;
+use wghttp::middleware::idempotency::{IdempotencyStore, REPLAYED_HEADER};
+use wghttp::models::errors::*;
+use wghttp::models::peers::*;
+use wghttp::routes::peers::create_peer;
+use wghttp::services::TunnelManager;
+
+pub mod mock;
+
+use mock::*;
+
+// the n-th peer created, each with its own keys.
+fn nth_peer(n: usize, allowed_ips: Vec<&str>) -> WGPeer {
+    WGPeer {
+        private_key: format!("privkey{}", n),
+        ..peer(&format!("pubkey{}", n), allowed_ips, 0)
+    }
+}
+
+fn manager(add_peer: fn(&str, Vec<&str>, u16) -> Result<WGPeer, WGError>) -> TunnelManager {
+    tunnel_manager(WireguardMockAdapter::new(
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(add_peer),
+        None,
+    ))
+}
+
+fn create_peer_request(key: &str, allowed_ip: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/devices/wg0/peers")
+        .insert_header(("Idempotency-Key", key))
+        .set_json(CreatePeerRequest {
+            allowed_ips: vec![allowed_ip.to_owned()],
+            persistent_keepalive_interval: 0,
+        })
+}
+
+#[actix_web::test]
+async fn test_replay_returns_the_stored_response() {
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    let store = IdempotencyStore::new(Duration::from_secs(60)).unwrap();
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(|_, i, _| {
+                Ok(nth_peer(CALLS.fetch_add(1, Ordering::SeqCst), i))
+            })))
+            .app_data(web::Data::new(store))
+            .service(create_peer),
+    )
+    .await;
+
+    let req = create_peer_request("retry-1", "10.0.0.2/32").to_request();
+    let first = test::call_service(&app, req).await;
+    assert_eq!(first.status(), 201);
+    assert!(first.headers().get(REPLAYED_HEADER).is_none());
+    let first = test::read_body(first).await;
+
+    let req = create_peer_request("retry-1", "10.0.0.2/32").to_request();
+    let second = test::call_service(&app, req).await;
+    assert_eq!(second.status(), 201);
+    assert_eq!(second.headers().get(REPLAYED_HEADER).unwrap(), "true");
+    assert_eq!(
+        second.headers().get("content-type").unwrap(),
+        "application/json"
+    );
+    let second = test::read_body(second).await;
+
+    assert_eq!(first, second);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    let body: CreatePeerResponse = serde_json::from_slice(&second).unwrap();
+    assert_eq!(body.private_key, Some("privkey0".to_owned()));
+}
+
+#[actix_web::test]
+async fn test_replay_with_a_different_body() {
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    let store = IdempotencyStore::new(Duration::from_secs(60)).unwrap();
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(|_, i, _| {
+                Ok(nth_peer(CALLS.fetch_add(1, Ordering::SeqCst), i))
+            })))
+            .app_data(web::Data::new(store))
+            .service(create_peer),
+    )
+    .await;
+
+    let req = create_peer_request("retry-2", "10.0.0.2/32").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let req = create_peer_request("retry-2", "10.0.0.3/32").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 422);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.code, "idempotency_key_reused");
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+    let req = create_peer_request("retry-3", "10.0.0.3/32").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+}
+
+#[actix_web::test]
+async fn test_replay_after_the_response_expired() {
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    let store = IdempotencyStore::new(Duration::ZERO).unwrap();
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(|_, i, _| {
+                Ok(nth_peer(CALLS.fetch_add(1, Ordering::SeqCst), i))
+            })))
+            .app_data(web::Data::new(store))
+            .service(create_peer),
+    )
+    .await;
+
+    for _ in 0..2 {
+        let req = create_peer_request("retry-4", "10.0.0.2/32").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+        assert!(resp.headers().get(REPLAYED_HEADER).is_none());
+    }
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+}
+
+#[actix_web::test]
+async fn test_idempotency_key_is_validated() {
+    let store = IdempotencyStore::new(Duration::from_secs(60)).unwrap();
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(|_, i, _| Ok(nth_peer(0, i)))))
+            .app_data(web::Data::new(store))
+            .service(create_peer),
+    )
+    .await;
+
+    let req = create_peer_request("has spaces", "10.0.0.2/32").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(
+        body.message,
+        "idempotency key must be 1 to 255 printable ascii characters"
+    );
+}
+
+#[actix_web::test]
+async fn test_idempotency_key_is_ignored_when_not_enabled() {
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(manager(|_, i, _| {
+                Ok(nth_peer(CALLS.fetch_add(1, Ordering::SeqCst), i))
+            })))
+            .service(create_peer),
+    )
+    .await;
+
+    for _ in 0..2 {
+        let req = create_peer_request("retry-5", "10.0.0.2/32").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+    }
+    assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,181 @@
+use actix_web::middleware::from_fn;
+use actix_web::{App, test, web};
+use domain::models::wg::*;
+use std::sync::Arc;
+use wghttp::metrics::{Metrics, MetricsLabels};
+use wghttp::middleware::auth::require_api_key;
+use wghttp::middleware::metrics::observe_requests;
+use wghttp::routes::metrics::*;
+use wghttp::routes::peers::list_peers;
+use wghttp::services::TunnelManager;
+
+pub mod mock;
+
+use mock::*;
+
+fn device(name: &str, peers: u64) -> WGDevice {
+    WGDevice {
+        name: name.to_owned(),
+        public_key: "devpubkey".to_owned(),
+        private_key: "devprivkey".to_owned(),
+        port: 51820,
+        fwmark: 0,
+        peers,
+    }
+}
+
+fn peer(public_key: &str, rx: u64, tx: u64, last_handshake_time: i64) -> WGPeer {
+    WGPeer {
+        allowed_ips: vec!["10.0.0.2/32".to_owned()],
+        endpoint: "endpoint".to_owned(),
+        last_handshake_time,
+        persistent_keepalive_interval: 0,
+        rx,
+        tx,
+        public_key: public_key.to_owned(),
+        private_key: "privkey".to_owned(),
+        preshared_key: "preshared".to_owned(),
+    }
+}
+
+fn two_peers(_: &str) -> Result<Vec<WGPeer>, WGError> {
+    Ok(vec![
+        peer("pubkey1", 100, 200, 1000),
+        peer("pubkey2", 10, 20, 0),
+    ])
+}
+
+fn tunnel_manager() -> TunnelManager {
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        Some(|| Ok(vec![device("wg0", 2)])),
+        None,
+        None,
+        Some(two_peers),
+        None,
+        None,
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    TunnelManager::new(wg_mock, netdev_mock)
+}
+
+#[actix_web::test]
+async fn test_metrics_route_exports_peer_series() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager()))
+            .service(metrics),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/metrics").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    assert!(
+        resp.headers()
+            .get("content-type")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain; version=0.0.4")
+    );
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(body.contains("wghttp_device_peers{device=\"wg0\"} 2\n"));
+    assert!(
+        body.contains(
+            "wghttp_peer_receive_bytes_total{device=\"wg0\",public_key=\"pubkey1\"} 100\n"
+        )
+    );
+    assert!(
+        body.contains(
+            "wghttp_peer_transmit_bytes_total{device=\"wg0\",public_key=\"pubkey2\"} 20\n"
+        )
+    );
+    assert!(body.contains(
+        "wghttp_peer_last_handshake_timestamp_seconds{device=\"wg0\",public_key=\"pubkey1\"} 1000\n"
+    ));
+    assert!(body.contains(
+        "wghttp_peer_seconds_since_last_handshake{device=\"wg0\",public_key=\"pubkey1\"}"
+    ));
+    assert!(!body.contains(
+        "wghttp_peer_seconds_since_last_handshake{device=\"wg0\",public_key=\"pubkey2\"}"
+    ));
+}
+
+#[actix_web::test]
+async fn test_metrics_route_merges_left_out_labels() {
+    let labels = MetricsLabels::parse(&["device".to_owned()]).unwrap();
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager()))
+            .app_data(web::Data::new(Metrics::new(labels)))
+            .service(metrics),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/metrics").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(!body.contains("public_key="));
+    assert!(body.contains("wghttp_peer_receive_bytes_total{device=\"wg0\"} 110\n"));
+    assert!(body.contains("wghttp_peer_transmit_bytes_total{device=\"wg0\"} 220\n"));
+    assert!(body.contains("wghttp_peer_last_handshake_timestamp_seconds{device=\"wg0\"} 1000\n"));
+}
+
+#[actix_web::test]
+async fn test_metrics_route_exports_request_and_call_latencies() {
+    let registry = Arc::new(Metrics::new(MetricsLabels::default()));
+    let tm = tunnel_manager().with_metrics(registry.clone());
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .wrap(from_fn(observe_requests))
+            .app_data(web::Data::new(tm))
+            .app_data(web::Data::from(registry))
+            .service(list_peers)
+            .service(metrics),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/devices/wg0/peers")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+    let req = test::TestRequest::get()
+        .uri("/devices/device_name_16ch/peers")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 400);
+
+    let req = test::TestRequest::get().uri("/metrics").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(body.contains(
+        "wghttp_http_request_duration_seconds_count{method=\"GET\",route=\"/devices/{dev}/peers\",status=\"200\"} 1\n"
+    ));
+    assert!(body.contains(
+        "wghttp_http_request_errors_total{method=\"GET\",route=\"/devices/{dev}/peers\",status=\"400\"} 1\n"
+    ));
+    assert!(body.contains(
+        "wghttp_ffi_call_duration_seconds_bucket{adapter=\"wireguard\",operation=\"list_peers\",le=\"+Inf\"}"
+    ));
+    assert!(body.contains(
+        "wghttp_ffi_call_duration_seconds_count{adapter=\"wireguard\",operation=\"list_devices\"} 1\n"
+    ));
+}
+
+#[actix_web::test]
+async fn test_metrics_labels_parse_rejects_unknown_label() {
+    let result = MetricsLabels::parse(&["device".to_owned(), "endpoint".to_owned()]);
+
+    assert_eq!(
+        result.unwrap_err(),
+        "unknown metrics label endpoint, expected one of device, public_key, route, status"
+    );
+}
@@ -1,17 +1,62 @@
+use domain::adapters::firewall::FirewallAdapter;
 use domain::adapters::netdev::NetworkDeviceAdapter;
 use domain::adapters::wg::WireguardAdapter;
+use domain::models::firewall::*;
 use domain::models::netdev::*;
 use domain::models::wg::*;
+use std::net::{IpAddr, Ipv4Addr};
+use wghttp::services::TunnelManager;
+
+type GetDeviceFn = fn(&str) -> Result<WGDevice, WGError>;
+type ListDevicesFn = fn() -> Result<Vec<WGDevice>, WGError>;
+type CreateDeviceFn = fn(&str, u16) -> Result<WGDevice, WGError>;
+type SetFwmarkFn = fn(&str, u32) -> Result<(), WGError>;
+type DeleteDeviceFn = fn(&str) -> Result<(), WGError>;
+type ListPeersFn = fn(&str) -> Result<Vec<WGPeer>, WGError>;
+type AddPeerFn = fn(&str, Vec<&str>, u16) -> Result<WGPeer, WGError>;
+type AddPeersFn = fn(&str, Vec<(Vec<&str>, u16)>) -> Result<Vec<WGPeer>, WGError>;
+type UpdatePeerFn = fn(&str, &str, Vec<&str>, u16) -> Result<WGPeer, WGError>;
+type DeletePeerFn = fn(&str, &str) -> Result<(), WGError>;
+type WGCheckFn = fn() -> Result<(), WGError>;
+
+type GetIpFn = fn(&str) -> Result<NetDevIp, NetDevError>;
+type SetIpFn = fn(&str, &NetDevIp) -> Result<(), NetDevError>;
+type UpFn = fn(&str) -> Result<(), NetDevError>;
+type RouteFn = fn(&str, &NetDevRoute) -> Result<(), NetDevError>;
+type RuleFn = fn(&NetDevRule) -> Result<(), NetDevError>;
+type SrcValidMarkFn = fn() -> Result<(), NetDevError>;
+type EnterNetNsFn = fn(&NetNs) -> Result<NetNsHandle, NetDevError>;
+type LeaveNetNsFn = fn(NetNsHandle) -> Result<(), NetDevError>;
+type SetNetNsFn = fn(&str, &NetNs) -> Result<(), NetDevError>;
+type NetDevCheckFn = fn() -> Result<(), NetDevError>;
+
+type GetMasqueradeFn = fn(&str) -> Result<Option<Masquerade>, FirewallError>;
+type AddMasqueradeFn = fn(&str, &Masquerade) -> Result<(), FirewallError>;
+type DeleteMasqueradeFn = fn(&str) -> Result<(), FirewallError>;
+type GetForwardingFn = fn(IpFamily) -> Result<bool, FirewallError>;
+type SetForwardingFn = fn(IpFamily, bool) -> Result<(), FirewallError>;
+type ListForwardsFn = fn(&str) -> Result<Vec<PortForward>, FirewallError>;
+type AddForwardFn = fn(&str, &PortForward) -> Result<(), FirewallError>;
+type DeleteForwardFn = fn(&str, Protocol, u16) -> Result<(), FirewallError>;
+type DeleteForwardsFn = fn(&str) -> Result<(), FirewallError>;
+type ListAclRulesFn = fn(&str) -> Result<Vec<AclRule>, FirewallError>;
+type ReplaceAclRulesFn = fn(&str, u32, &[AclRule]) -> Result<(), FirewallError>;
+type DeletePeerAclRulesFn = fn(&str, &str) -> Result<(), FirewallError>;
+type DeleteAclRulesFn = fn(&str) -> Result<(), FirewallError>;
 
 #[cfg(test)]
 pub struct WireguardMockAdapter {
-    get_fn: fn(&str) -> Result<WGDevice, WGError>,
-    list_fn: fn() -> Result<Vec<WGDevice>, WGError>,
-    create_fn: fn(&str, u16) -> Result<WGDevice, WGError>,
-    delete_fn: fn(&str) -> Result<(), WGError>,
-    list_peers_fn: fn(&str) -> Result<Vec<WGPeer>, WGError>,
-    add_peer_fn: fn(&str, Vec<&str>, u16) -> Result<WGPeer, WGError>,
-    delete_peer_fn: fn(&str, &str) -> Result<(), WGError>,
+    get_fn: GetDeviceFn,
+    list_fn: ListDevicesFn,
+    create_fn: CreateDeviceFn,
+    set_fwmark_fn: SetFwmarkFn,
+    delete_fn: DeleteDeviceFn,
+    list_peers_fn: ListPeersFn,
+    add_peer_fn: AddPeerFn,
+    add_peers_fn: AddPeersFn,
+    update_peer_fn: UpdatePeerFn,
+    delete_peer_fn: DeletePeerFn,
+    check_fn: WGCheckFn,
 }
 
 #[cfg(test)]
@@ -28,6 +73,10 @@ impl WireguardAdapter for WireguardMockAdapter {
         (self.create_fn)(device_name, port)
     }
 
+    fn set_fwmark(&self, device_name: &str, fwmark: u32) -> Result<(), WGError> {
+        (self.set_fwmark_fn)(device_name, fwmark)
+    }
+
     fn delete_device(&self, device_name: &str) -> Result<(), WGError> {
         (self.delete_fn)(device_name)
     }
@@ -45,38 +94,100 @@ impl WireguardAdapter for WireguardMockAdapter {
         (self.add_peer_fn)(device_name, allowed_ips, persistent_keepalive_interval)
     }
 
+    fn add_peers(
+        &self,
+        device_name: &str,
+        peers: Vec<(Vec<&str>, u16)>,
+    ) -> Result<Vec<WGPeer>, WGError> {
+        (self.add_peers_fn)(device_name, peers)
+    }
+
+    fn update_peer(
+        &self,
+        device_name: &str,
+        public_key: &str,
+        allowed_ips: Vec<&str>,
+        persistent_keepalive_interval: u16,
+    ) -> Result<WGPeer, WGError> {
+        (self.update_peer_fn)(
+            device_name,
+            public_key,
+            allowed_ips,
+            persistent_keepalive_interval,
+        )
+    }
+
     fn delete_peer(&self, device_name: &str, public_key: &str) -> Result<(), WGError> {
         (self.delete_peer_fn)(device_name, public_key)
     }
+
+    fn check(&self) -> Result<(), WGError> {
+        (self.check_fn)()
+    }
 }
 
 impl WireguardMockAdapter {
     pub fn new(
-        get_fn: Option<fn(&str) -> Result<WGDevice, WGError>>,
-        list_fn: Option<fn() -> Result<Vec<WGDevice>, WGError>>,
-        create_fn: Option<fn(&str, u16) -> Result<WGDevice, WGError>>,
-        delete_fn: Option<fn(&str) -> Result<(), WGError>>,
-        list_peers_fn: Option<fn(&str) -> Result<Vec<WGPeer>, WGError>>,
-        add_peer_fn: Option<fn(&str, Vec<&str>, u16) -> Result<WGPeer, WGError>>,
-        delete_peer_fn: Option<fn(&str, &str) -> Result<(), WGError>>,
+        get_fn: Option<GetDeviceFn>,
+        list_fn: Option<ListDevicesFn>,
+        create_fn: Option<CreateDeviceFn>,
+        delete_fn: Option<DeleteDeviceFn>,
+        list_peers_fn: Option<ListPeersFn>,
+        add_peer_fn: Option<AddPeerFn>,
+        delete_peer_fn: Option<DeletePeerFn>,
     ) -> Self {
         WireguardMockAdapter {
-            get_fn: get_fn.unwrap_or(|_| Err(WGError("not found".to_owned()))),
+            get_fn: get_fn.unwrap_or(|_| Err(WGError::Other("not found".to_owned()))),
             list_fn: list_fn.unwrap_or(|| Ok(vec![])),
-            create_fn: create_fn.unwrap_or(|_, _| Err(WGError("not found".to_owned()))),
-            delete_fn: delete_fn.unwrap_or(|_| Err(WGError("not found".to_owned()))),
+            create_fn: create_fn.unwrap_or(|_, _| Err(WGError::Other("not found".to_owned()))),
+            set_fwmark_fn: |_, _| Ok(()),
+            delete_fn: delete_fn.unwrap_or(|_| Err(WGError::Other("not found".to_owned()))),
             list_peers_fn: list_peers_fn.unwrap_or(|_| Ok(vec![])),
-            add_peer_fn: add_peer_fn.unwrap_or(|_, _, _| Err(WGError("not found".to_owned()))),
-            delete_peer_fn: delete_peer_fn.unwrap_or(|_, _| Err(WGError("not found".to_owned()))),
+            add_peer_fn: add_peer_fn
+                .unwrap_or(|_, _, _| Err(WGError::Other("not found".to_owned()))),
+            add_peers_fn: |_, _| Err(WGError::Other("not found".to_owned())),
+            update_peer_fn: |_, _, _, _| Err(WGError::Other("not found".to_owned())),
+            delete_peer_fn: delete_peer_fn
+                .unwrap_or(|_, _| Err(WGError::Other("not found".to_owned()))),
+            check_fn: || Ok(()),
         }
     }
+
+    pub fn with_set_fwmark(mut self, set_fwmark_fn: SetFwmarkFn) -> Self {
+        self.set_fwmark_fn = set_fwmark_fn;
+        self
+    }
+
+    pub fn with_add_peers(mut self, add_peers_fn: AddPeersFn) -> Self {
+        self.add_peers_fn = add_peers_fn;
+        self
+    }
+
+    pub fn with_update_peer(mut self, update_peer_fn: UpdatePeerFn) -> Self {
+        self.update_peer_fn = update_peer_fn;
+        self
+    }
+
+    pub fn with_check(mut self, check_fn: WGCheckFn) -> Self {
+        self.check_fn = check_fn;
+        self
+    }
 }
 
 #[cfg(test)]
 pub struct NetworkDeviceMockAdapter {
-    get_ip_fn: fn(&str) -> Result<NetDevIp, NetDevError>,
-    set_ip_fn: fn(&str, &NetDevIp) -> Result<(), NetDevError>,
-    up_fn: fn(&str) -> Result<(), NetDevError>,
+    get_ip_fn: GetIpFn,
+    set_ip_fn: SetIpFn,
+    up_fn: UpFn,
+    add_route_fn: RouteFn,
+    delete_route_fn: RouteFn,
+    add_rule_fn: RuleFn,
+    delete_rule_fn: RuleFn,
+    src_valid_mark_fn: SrcValidMarkFn,
+    enter_netns_fn: EnterNetNsFn,
+    leave_netns_fn: LeaveNetNsFn,
+    set_netns_fn: SetNetNsFn,
+    check_fn: NetDevCheckFn,
 }
 
 #[cfg(test)]
@@ -92,18 +203,336 @@ impl NetworkDeviceAdapter for NetworkDeviceMockAdapter {
     fn up(&self, device_name: &str) -> Result<(), NetDevError> {
         (self.up_fn)(device_name)
     }
+
+    fn add_route(&self, device_name: &str, route: &NetDevRoute) -> Result<(), NetDevError> {
+        (self.add_route_fn)(device_name, route)
+    }
+
+    fn delete_route(&self, device_name: &str, route: &NetDevRoute) -> Result<(), NetDevError> {
+        (self.delete_route_fn)(device_name, route)
+    }
+
+    fn add_rule(&self, rule: &NetDevRule) -> Result<(), NetDevError> {
+        (self.add_rule_fn)(rule)
+    }
+
+    fn delete_rule(&self, rule: &NetDevRule) -> Result<(), NetDevError> {
+        (self.delete_rule_fn)(rule)
+    }
+
+    fn enable_src_valid_mark(&self) -> Result<(), NetDevError> {
+        (self.src_valid_mark_fn)()
+    }
+
+    fn enter_netns(&self, netns: &NetNs) -> Result<NetNsHandle, NetDevError> {
+        (self.enter_netns_fn)(netns)
+    }
+
+    fn leave_netns(&self, handle: NetNsHandle) -> Result<(), NetDevError> {
+        (self.leave_netns_fn)(handle)
+    }
+
+    fn set_netns(&self, device_name: &str, netns: &NetNs) -> Result<(), NetDevError> {
+        (self.set_netns_fn)(device_name, netns)
+    }
+
+    fn check(&self) -> Result<(), NetDevError> {
+        (self.check_fn)()
+    }
 }
 
 impl NetworkDeviceMockAdapter {
     pub fn new(
-        get_ip_fn: Option<fn(&str) -> Result<NetDevIp, NetDevError>>,
-        set_ip_fn: Option<fn(&str, &NetDevIp) -> Result<(), NetDevError>>,
-        up_fn: Option<fn(&str) -> Result<(), NetDevError>>,
+        get_ip_fn: Option<GetIpFn>,
+        set_ip_fn: Option<SetIpFn>,
+        up_fn: Option<UpFn>,
     ) -> Self {
         NetworkDeviceMockAdapter {
-            get_ip_fn: get_ip_fn.unwrap_or(|_| Err(NetDevError("not found".to_owned()))),
-            set_ip_fn: set_ip_fn.unwrap_or(|_, _| Err(NetDevError("not found".to_owned()))),
-            up_fn: up_fn.unwrap_or(|_| Err(NetDevError("not found".to_owned()))),
+            get_ip_fn: get_ip_fn.unwrap_or(|_| Err(NetDevError::Other("not found".to_owned()))),
+            set_ip_fn: set_ip_fn.unwrap_or(|_, _| Err(NetDevError::Other("not found".to_owned()))),
+            up_fn: up_fn.unwrap_or(|_| Err(NetDevError::Other("not found".to_owned()))),
+            add_route_fn: |_, _| Ok(()),
+            delete_route_fn: |_, _| Ok(()),
+            add_rule_fn: |_| Ok(()),
+            delete_rule_fn: |_| Ok(()),
+            src_valid_mark_fn: || Ok(()),
+            enter_netns_fn: |_| Ok(NetNsHandle(-1)),
+            leave_netns_fn: |_| Ok(()),
+            set_netns_fn: |_, _| Ok(()),
+            check_fn: || Ok(()),
         }
     }
+
+    pub fn with_add_route(mut self, add_route_fn: RouteFn) -> Self {
+        self.add_route_fn = add_route_fn;
+        self
+    }
+
+    pub fn with_delete_route(mut self, delete_route_fn: RouteFn) -> Self {
+        self.delete_route_fn = delete_route_fn;
+        self
+    }
+
+    pub fn with_add_rule(mut self, add_rule_fn: RuleFn) -> Self {
+        self.add_rule_fn = add_rule_fn;
+        self
+    }
+
+    pub fn with_delete_rule(mut self, delete_rule_fn: RuleFn) -> Self {
+        self.delete_rule_fn = delete_rule_fn;
+        self
+    }
+
+    pub fn with_enable_src_valid_mark(mut self, src_valid_mark_fn: SrcValidMarkFn) -> Self {
+        self.src_valid_mark_fn = src_valid_mark_fn;
+        self
+    }
+
+    pub fn with_enter_netns(mut self, enter_netns_fn: EnterNetNsFn) -> Self {
+        self.enter_netns_fn = enter_netns_fn;
+        self
+    }
+
+    pub fn with_leave_netns(mut self, leave_netns_fn: LeaveNetNsFn) -> Self {
+        self.leave_netns_fn = leave_netns_fn;
+        self
+    }
+
+    pub fn with_set_netns(mut self, set_netns_fn: SetNetNsFn) -> Self {
+        self.set_netns_fn = set_netns_fn;
+        self
+    }
+
+    pub fn with_check(mut self, check_fn: NetDevCheckFn) -> Self {
+        self.check_fn = check_fn;
+        self
+    }
+}
+
+#[cfg(test)]
+pub struct FirewallMockAdapter {
+    get_masquerade_fn: GetMasqueradeFn,
+    add_masquerade_fn: AddMasqueradeFn,
+    delete_masquerade_fn: DeleteMasqueradeFn,
+    get_forwarding_fn: GetForwardingFn,
+    set_forwarding_fn: SetForwardingFn,
+    list_forwards_fn: ListForwardsFn,
+    add_forward_fn: AddForwardFn,
+    delete_forward_fn: DeleteForwardFn,
+    delete_forwards_fn: DeleteForwardsFn,
+    list_acl_rules_fn: ListAclRulesFn,
+    replace_acl_rules_fn: ReplaceAclRulesFn,
+    delete_peer_acl_rules_fn: DeletePeerAclRulesFn,
+    delete_acl_rules_fn: DeleteAclRulesFn,
+}
+
+#[cfg(test)]
+impl FirewallAdapter for FirewallMockAdapter {
+    fn get_masquerade(&self, device_name: &str) -> Result<Option<Masquerade>, FirewallError> {
+        (self.get_masquerade_fn)(device_name)
+    }
+
+    fn add_masquerade(
+        &self,
+        device_name: &str,
+        masquerade: &Masquerade,
+    ) -> Result<(), FirewallError> {
+        (self.add_masquerade_fn)(device_name, masquerade)
+    }
+
+    fn delete_masquerade(&self, device_name: &str) -> Result<(), FirewallError> {
+        (self.delete_masquerade_fn)(device_name)
+    }
+
+    fn get_forwarding(&self, family: IpFamily) -> Result<bool, FirewallError> {
+        (self.get_forwarding_fn)(family)
+    }
+
+    fn set_forwarding(&self, family: IpFamily, enabled: bool) -> Result<(), FirewallError> {
+        (self.set_forwarding_fn)(family, enabled)
+    }
+
+    fn list_forwards(&self, device_name: &str) -> Result<Vec<PortForward>, FirewallError> {
+        (self.list_forwards_fn)(device_name)
+    }
+
+    fn add_forward(&self, device_name: &str, forward: &PortForward) -> Result<(), FirewallError> {
+        (self.add_forward_fn)(device_name, forward)
+    }
+
+    fn delete_forward(
+        &self,
+        device_name: &str,
+        protocol: Protocol,
+        public_port: u16,
+    ) -> Result<(), FirewallError> {
+        (self.delete_forward_fn)(device_name, protocol, public_port)
+    }
+
+    fn delete_forwards(&self, device_name: &str) -> Result<(), FirewallError> {
+        (self.delete_forwards_fn)(device_name)
+    }
+
+    fn list_acl_rules(&self, device_name: &str) -> Result<Vec<AclRule>, FirewallError> {
+        (self.list_acl_rules_fn)(device_name)
+    }
+
+    fn replace_acl_rules(
+        &self,
+        device_name: &str,
+        id: u32,
+        rules: &[AclRule],
+    ) -> Result<(), FirewallError> {
+        (self.replace_acl_rules_fn)(device_name, id, rules)
+    }
+
+    fn delete_peer_acl_rules(
+        &self,
+        device_name: &str,
+        public_key: &str,
+    ) -> Result<(), FirewallError> {
+        (self.delete_peer_acl_rules_fn)(device_name, public_key)
+    }
+
+    fn delete_acl_rules(&self, device_name: &str) -> Result<(), FirewallError> {
+        (self.delete_acl_rules_fn)(device_name)
+    }
+}
+
+impl Default for FirewallMockAdapter {
+    fn default() -> Self {
+        FirewallMockAdapter {
+            get_masquerade_fn: |_| Ok(None),
+            add_masquerade_fn: |_, _| Ok(()),
+            delete_masquerade_fn: |_| Ok(()),
+            get_forwarding_fn: |_| Ok(false),
+            set_forwarding_fn: |_, _| Ok(()),
+            list_forwards_fn: |_| Ok(vec![]),
+            add_forward_fn: |_, _| Ok(()),
+            delete_forward_fn: |_, _, _| Ok(()),
+            delete_forwards_fn: |_| Ok(()),
+            list_acl_rules_fn: |_| Ok(vec![]),
+            replace_acl_rules_fn: |_, _, _| Ok(()),
+            delete_peer_acl_rules_fn: |_, _| Ok(()),
+            delete_acl_rules_fn: |_| Ok(()),
+        }
+    }
+}
+
+impl FirewallMockAdapter {
+    pub fn with_get_masquerade(mut self, get_masquerade_fn: GetMasqueradeFn) -> Self {
+        self.get_masquerade_fn = get_masquerade_fn;
+        self
+    }
+
+    pub fn with_add_masquerade(mut self, add_masquerade_fn: AddMasqueradeFn) -> Self {
+        self.add_masquerade_fn = add_masquerade_fn;
+        self
+    }
+
+    pub fn with_delete_masquerade(mut self, delete_masquerade_fn: DeleteMasqueradeFn) -> Self {
+        self.delete_masquerade_fn = delete_masquerade_fn;
+        self
+    }
+
+    pub fn with_get_forwarding(mut self, get_forwarding_fn: GetForwardingFn) -> Self {
+        self.get_forwarding_fn = get_forwarding_fn;
+        self
+    }
+
+    pub fn with_set_forwarding(mut self, set_forwarding_fn: SetForwardingFn) -> Self {
+        self.set_forwarding_fn = set_forwarding_fn;
+        self
+    }
+
+    pub fn with_list_forwards(mut self, list_forwards_fn: ListForwardsFn) -> Self {
+        self.list_forwards_fn = list_forwards_fn;
+        self
+    }
+
+    pub fn with_add_forward(mut self, add_forward_fn: AddForwardFn) -> Self {
+        self.add_forward_fn = add_forward_fn;
+        self
+    }
+
+    pub fn with_delete_forward(mut self, delete_forward_fn: DeleteForwardFn) -> Self {
+        self.delete_forward_fn = delete_forward_fn;
+        self
+    }
+
+    pub fn with_delete_forwards(mut self, delete_forwards_fn: DeleteForwardsFn) -> Self {
+        self.delete_forwards_fn = delete_forwards_fn;
+        self
+    }
+
+    pub fn with_list_acl_rules(mut self, list_acl_rules_fn: ListAclRulesFn) -> Self {
+        self.list_acl_rules_fn = list_acl_rules_fn;
+        self
+    }
+
+    pub fn with_replace_acl_rules(mut self, replace_acl_rules_fn: ReplaceAclRulesFn) -> Self {
+        self.replace_acl_rules_fn = replace_acl_rules_fn;
+        self
+    }
+
+    pub fn with_delete_peer_acl_rules(
+        mut self,
+        delete_peer_acl_rules_fn: DeletePeerAclRulesFn,
+    ) -> Self {
+        self.delete_peer_acl_rules_fn = delete_peer_acl_rules_fn;
+        self
+    }
+
+    pub fn with_delete_acl_rules(mut self, delete_acl_rules_fn: DeleteAclRulesFn) -> Self {
+        self.delete_acl_rules_fn = delete_acl_rules_fn;
+        self
+    }
+}
+
+/// Public key of the peers the tests work with.
+#[cfg(test)]
+pub const PUBKEY: &str = "pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu";
+
+/// Device listening on 51820, with neither a fwmark nor peers.
+#[cfg(test)]
+pub fn device(name: &str) -> WGDevice {
+    WGDevice {
+        name: name.to_owned(),
+        public_key: "devpubkey".to_owned(),
+        private_key: "devprivkey".to_owned(),
+        port: 51820,
+        fwmark: 0,
+        peers: 0,
+    }
+}
+
+#[cfg(test)]
+pub fn peer(public_key: &str, allowed_ips: Vec<&str>, keepalive: u16) -> WGPeer {
+    WGPeer {
+        allowed_ips: allowed_ips.into_iter().map(|s| s.to_owned()).collect(),
+        endpoint: "endpoint".to_owned(),
+        last_handshake_time: 0,
+        persistent_keepalive_interval: keepalive,
+        rx: 0,
+        tx: 0,
+        public_key: public_key.to_owned(),
+        private_key: "privkey".to_owned(),
+        preshared_key: "preshared".to_owned(),
+    }
+}
+
+/// Addresses of every device, 10.0.0.1/24.
+#[cfg(test)]
+pub fn device_ip(_: &str) -> Result<NetDevIp, NetDevError> {
+    Ok(NetDevIp::new(
+        Some((IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 24)),
+        None,
+    ))
+}
+
+/// Manager over the wireguard mock, with devices that only have addresses.
+#[cfg(test)]
+pub fn tunnel_manager(wg_mock: WireguardMockAdapter) -> TunnelManager {
+    let netdev_mock = NetworkDeviceMockAdapter::new(Some(device_ip), None, None);
+    TunnelManager::new(wg_mock, netdev_mock)
 }
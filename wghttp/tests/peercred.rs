@@ -0,0 +1,253 @@
+use actix_web::dev::Extensions;
+use actix_web::middleware::from_fn;
+use actix_web::{App, HttpRequest, HttpServer, Responder, get, web};
+use wghttp::middleware::auth::require_api_key;
+use wghttp::middleware::peercred::*;
+use wghttp::middleware::rbac::*;
+use wghttp::unix::*;
+
+use std::any::Any;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+fn credentials(uid: u32, gid: u32, groups: Vec<u32>) -> PeerCredentials {
+    PeerCredentials {
+        uid,
+        gid,
+        pid: None,
+        groups,
+    }
+}
+
+fn rules(specs: &[&str]) -> Result<SocketAccess, String> {
+    let specs: Vec<String> = specs.iter().map(|s| s.to_string()).collect();
+    SocketAccess::parse(&specs)
+}
+
+fn temp_socket(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("wghttp-{}-{}.sock", std::process::id(), name))
+}
+
+fn request(path: &Path, uri: &str) -> String {
+    let mut stream = UnixStream::connect(path).unwrap();
+    let req = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        uri
+    );
+    stream.write_all(req.as_bytes()).unwrap();
+
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    out
+}
+
+#[get("/role")]
+async fn role(perms: Permissions) -> impl Responder {
+    format!("{:?}", perms.role)
+}
+
+#[get("/groups")]
+async fn peer_groups(req: HttpRequest) -> impl Responder {
+    let cred = req.conn_data::<PeerCredentials>().unwrap();
+    format!("{:?}", cred.groups)
+}
+
+// Marks the connection as a unix one whose credentials could not be read.
+fn without_credentials(_: &dyn Any, data: &mut Extensions) {
+    data.insert(UnixConnection);
+}
+
+async fn serve_with(
+    path: &Path,
+    access: SocketAccess,
+    uri: &'static str,
+    connect: fn(&dyn Any, &mut Extensions),
+) -> String {
+    let listener = bind(path, &SocketOptions::default()).unwrap();
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .wrap(from_fn(require_socket_access))
+            .app_data(web::Data::new(access.clone()))
+            .service(role)
+            .service(peer_groups)
+    })
+    .workers(1)
+    .on_connect(connect)
+    .listen_uds(listener)
+    .unwrap()
+    .run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+
+    let socket = path.to_path_buf();
+    let out = actix_web::rt::task::spawn_blocking(move || request(&socket, uri))
+        .await
+        .unwrap();
+
+    handle.stop(true).await;
+    fs::remove_file(path).unwrap();
+    out
+}
+
+async fn serve_uri(path: &Path, access: SocketAccess, uri: &'static str) -> String {
+    serve_with(path, access, uri, on_connect).await
+}
+
+async fn serve(path: &Path, access: SocketAccess) -> String {
+    serve_uri(path, access, "/role").await
+}
+
+#[actix_web::test]
+async fn test_socket_access_rules() {
+    let access = rules(&[]).unwrap();
+    assert_eq!(
+        access.access(&credentials(1000, 1000, vec![])),
+        Access::Full
+    );
+
+    let access = rules(&["uid:1000=read-only", "gid:10=full", "gid:1000=denied"]).unwrap();
+    assert_eq!(
+        access.access(&credentials(1000, 10, vec![])),
+        Access::ReadOnly
+    );
+    assert_eq!(access.access(&credentials(1001, 10, vec![])), Access::Full);
+    assert_eq!(
+        access.access(&credentials(1001, 1001, vec![10])),
+        Access::Full
+    );
+    assert_eq!(
+        access.access(&credentials(1001, 1000, vec![])),
+        Access::Denied
+    );
+    assert_eq!(
+        access.access(&credentials(1002, 1002, vec![])),
+        Access::Denied
+    );
+
+    let access = rules(&["user:root=full", "group:root=read-only"]).unwrap();
+    assert_eq!(access.access(&credentials(0, 0, vec![])), Access::Full);
+    assert_eq!(
+        access.access(&credentials(1000, 0, vec![])),
+        Access::ReadOnly
+    );
+}
+
+#[actix_web::test]
+async fn test_socket_access_parse_errors() {
+    assert_eq!(
+        rules(&["uid:1000"]).unwrap_err(),
+        "invalid socket access rule: uid:1000"
+    );
+    assert_eq!(
+        rules(&["pid:1=full"]).unwrap_err(),
+        "invalid socket access rule: pid:1=full"
+    );
+    assert_eq!(
+        rules(&["uid:1000=admin"]).unwrap_err(),
+        "invalid socket access rule: uid:1000=admin"
+    );
+    assert_eq!(
+        rules(&["user:no-such-user-wghttp=full"]).unwrap_err(),
+        "no-such-user-wghttp not found in /etc/passwd"
+    );
+}
+
+#[actix_web::test]
+async fn test_parse_mode() {
+    assert_eq!(parse_mode("660"), Ok(0o660));
+    assert_eq!(parse_mode("0600"), Ok(0o600));
+    assert_eq!(
+        parse_mode("888"),
+        Err("invalid socket mode: 888".to_owned())
+    );
+    assert_eq!(
+        parse_mode("1777"),
+        Err("invalid socket mode: 1777".to_owned())
+    );
+}
+
+#[actix_web::test]
+async fn test_bind_applies_socket_options() {
+    let path = temp_socket("options");
+    fs::write(&path, "stale").unwrap();
+
+    let options = SocketOptions {
+        mode: Some(0o600),
+        owner: Some(0),
+        group: Some(0),
+    };
+    let listener = bind(&path, &options);
+    let metadata = fs::metadata(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(listener.is_ok());
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+}
+
+#[actix_web::test]
+async fn test_socket_access_with_peer_credentials() {
+    let uid = fs::metadata("/proc/self").unwrap();
+    let uid = std::os::unix::fs::MetadataExt::uid(&uid);
+
+    let out = serve(&temp_socket("full"), SocketAccess::default()).await;
+    assert!(out.starts_with("HTTP/1.1 200 OK"));
+    assert!(out.ends_with("Admin"));
+
+    let access = rules(&[&format!("uid:{}=read-only", uid)]).unwrap();
+    let out = serve(&temp_socket("read-only"), access).await;
+    assert!(out.starts_with("HTTP/1.1 200 OK"));
+    assert!(out.ends_with("Viewer"));
+
+    let access = rules(&[&format!("uid:{}=denied", uid + 1)]).unwrap();
+    let out = serve(&temp_socket("denied"), access).await;
+    assert!(out.starts_with("HTTP/1.1 403 Forbidden"));
+    assert!(out.ends_with(&format!(
+        "{{\"code\":\"permission_denied\",\"message\":\"uid {} is not allowed to use the socket\"}}",
+        uid
+    )));
+}
+
+#[actix_web::test]
+async fn test_peer_credentials_include_supplementary_groups() {
+    let status = fs::read_to_string("/proc/self/status").unwrap();
+    let expected: Vec<u32> = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Groups:"))
+        .unwrap()
+        .split_whitespace()
+        .map(|g| g.parse().unwrap())
+        .collect();
+
+    let out = serve_uri(&temp_socket("groups"), SocketAccess::default(), "/groups").await;
+    assert!(out.ends_with(&format!("{:?}", expected)));
+}
+
+#[actix_web::test]
+async fn test_socket_access_without_peer_credentials() {
+    let out = serve_with(
+        &temp_socket("no-cred-open"),
+        SocketAccess::default(),
+        "/role",
+        without_credentials,
+    )
+    .await;
+    assert!(out.starts_with("HTTP/1.1 200 OK"));
+    assert!(out.ends_with("Admin"));
+
+    let access = rules(&["uid:0=full"]).unwrap();
+    let out = serve_with(
+        &temp_socket("no-cred"),
+        access,
+        "/role",
+        without_credentials,
+    )
+    .await;
+    assert!(out.starts_with("HTTP/1.1 403 Forbidden"));
+    assert!(out.ends_with(
+        "{\"code\":\"permission_denied\",\"message\":\"peer credentials of the connection are unavailable\"}"
+    ));
+}
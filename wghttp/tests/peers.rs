@@ -1,5 +1,9 @@
+use actix_web::middleware::from_fn;
 use actix_web::{App, test, web};
+use domain::models::firewall::*;
+use domain::models::netdev::*;
 use domain::models::wg::*;
+use wghttp::middleware::auth::require_api_key;
 use wghttp::models::errors::*;
 use wghttp::models::peers::*;
 use wghttp::routes::peers::*;
@@ -9,19 +13,28 @@ pub mod mock;
 
 use mock::*;
 
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 #[actix_web::test]
 async fn test_list_peers_route_with_validation_error() {
     let wg_mock = WireguardMockAdapter::new(None, None, None, None, None, None, None);
     let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
     let tm = TunnelManager::new(wg_mock, netdev_mock);
-    let app = test::init_service(App::new().app_data(web::Data::new(tm)).service(list_peers)).await;
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(list_peers),
+    )
+    .await;
 
     let req = test::TestRequest::get()
         .uri("/devices/device_name_16ch/peers")
         .to_request();
     let resp = test::call_service(&app, req).await;
 
-    assert_eq!(resp.status().is_client_error(), true);
+    assert!(resp.status().is_client_error());
     assert_eq!(resp.status(), 400);
     let body: Error = test::read_body_json(resp).await;
     assert_eq!(body.message, "device name must be at most 15 characters");
@@ -34,20 +47,26 @@ async fn test_list_peers_route_with_not_found() {
         None,
         None,
         None,
-        Some(|_| Err(WGError("device not found".to_owned()))),
+        Some(|_| Err(WGError::from(WGErrorKind::DevNotFound))),
         None,
         None,
     );
     let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
     let tm = TunnelManager::new(wg_mock, netdev_mock);
-    let app = test::init_service(App::new().app_data(web::Data::new(tm)).service(list_peers)).await;
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(list_peers),
+    )
+    .await;
 
     let req = test::TestRequest::get()
         .uri("/devices/device_name/peers")
         .to_request();
     let resp = test::call_service(&app, req).await;
 
-    assert_eq!(resp.status().is_client_error(), true);
+    assert!(resp.status().is_client_error());
     assert_eq!(resp.status(), 404);
     let body: Error = test::read_body_json(resp).await;
     assert_eq!(body.message, "device not found");
@@ -59,14 +78,20 @@ async fn test_list_peers_route_with_empty_success_result() {
         WireguardMockAdapter::new(None, None, None, None, Some(|_| Ok(vec![])), None, None);
     let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
     let tm = TunnelManager::new(wg_mock, netdev_mock);
-    let app = test::init_service(App::new().app_data(web::Data::new(tm)).service(list_peers)).await;
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(list_peers),
+    )
+    .await;
 
     let req = test::TestRequest::get()
         .uri("/devices/device_name/peers")
         .to_request();
     let resp = test::call_service(&app, req).await;
 
-    assert_eq!(resp.status().is_success(), true);
+    assert!(resp.status().is_success());
     assert_eq!(resp.status(), 200);
     let body: Vec<ListPeerResponse> = test::read_body_json(resp).await;
     assert_eq!(body.len(), 0);
@@ -97,14 +122,20 @@ async fn test_list_peers_route_with_success_result() {
     );
     let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
     let tm = TunnelManager::new(wg_mock, netdev_mock);
-    let app = test::init_service(App::new().app_data(web::Data::new(tm)).service(list_peers)).await;
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(list_peers),
+    )
+    .await;
 
     let req = test::TestRequest::get()
         .uri("/devices/device_name/peers")
         .to_request();
     let resp = test::call_service(&app, req).await;
 
-    assert_eq!(resp.status().is_success(), true);
+    assert!(resp.status().is_success());
     assert_eq!(resp.status(), 200);
     let body: Vec<ListPeerResponse> = test::read_body_json(resp).await;
     assert_eq!(body.len(), 1);
@@ -120,8 +151,13 @@ async fn test_create_peer_route_with_validation_error() {
     let wg_mock = WireguardMockAdapter::new(None, None, None, None, None, None, None);
     let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
     let tm = TunnelManager::new(wg_mock, netdev_mock);
-    let app =
-        test::init_service(App::new().app_data(web::Data::new(tm)).service(create_peer)).await;
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(create_peer),
+    )
+    .await;
 
     let req = test::TestRequest::post()
         .uri("/devices/device_name_16ch/peers")
@@ -132,7 +168,7 @@ async fn test_create_peer_route_with_validation_error() {
         .to_request();
     let resp = test::call_service(&app, req).await;
 
-    assert_eq!(resp.status().is_client_error(), true);
+    assert!(resp.status().is_client_error());
     assert_eq!(resp.status(), 400);
     let body: Error = test::read_body_json(resp).await;
     assert_eq!(body.message, "device name must be at most 15 characters");
@@ -143,8 +179,13 @@ async fn test_create_peer_route_with_invalid_ip_string() {
     let wg_mock = WireguardMockAdapter::new(None, None, None, None, None, None, None);
     let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
     let tm = TunnelManager::new(wg_mock, netdev_mock);
-    let app =
-        test::init_service(App::new().app_data(web::Data::new(tm)).service(create_peer)).await;
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(create_peer),
+    )
+    .await;
 
     let req = test::TestRequest::post()
         .uri("/devices/device_name/peers")
@@ -155,7 +196,7 @@ async fn test_create_peer_route_with_invalid_ip_string() {
         .to_request();
     let resp = test::call_service(&app, req).await;
 
-    assert_eq!(resp.status().is_client_error(), true);
+    assert!(resp.status().is_client_error());
     assert_eq!(resp.status(), 400);
     let body: Error = test::read_body_json(resp).await;
     assert_eq!(body.message, "invalid ip address: invalid_ip_str");
@@ -169,13 +210,18 @@ async fn test_create_peer_route_with_not_found() {
         None,
         None,
         None,
-        Some(|_, _, _| Err(WGError("device not found".to_owned()))),
+        Some(|_, _, _| Err(WGError::from(WGErrorKind::DevNotFound))),
         None,
     );
     let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
     let tm = TunnelManager::new(wg_mock, netdev_mock);
-    let app =
-        test::init_service(App::new().app_data(web::Data::new(tm)).service(create_peer)).await;
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(create_peer),
+    )
+    .await;
 
     let req = test::TestRequest::post()
         .uri("/devices/device_name/peers")
@@ -186,7 +232,7 @@ async fn test_create_peer_route_with_not_found() {
         .to_request();
     let resp = test::call_service(&app, req).await;
 
-    assert_eq!(resp.status().is_client_error(), true);
+    assert!(resp.status().is_client_error());
     assert_eq!(resp.status(), 404);
     let body: Error = test::read_body_json(resp).await;
     assert_eq!(body.message, "device not found");
@@ -215,10 +261,15 @@ async fn test_create_peer_route_with_successful_result() {
         }),
         None,
     );
-    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(Some(device_ip), None, None);
     let tm = TunnelManager::new(wg_mock, netdev_mock);
-    let app =
-        test::init_service(App::new().app_data(web::Data::new(tm)).service(create_peer)).await;
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(create_peer),
+    )
+    .await;
 
     let req = test::TestRequest::post()
         .uri("/devices/device_name/peers")
@@ -229,12 +280,12 @@ async fn test_create_peer_route_with_successful_result() {
         .to_request();
     let resp = test::call_service(&app, req).await;
 
-    assert_eq!(resp.status().is_success(), true);
+    assert!(resp.status().is_success());
     assert_eq!(resp.status(), 201);
     let body: CreatePeerResponse = test::read_body_json(resp).await;
     assert_eq!(body.public_key, "pubkey");
-    assert_eq!(body.private_key, "privkey");
-    assert_eq!(body.preshared_key, "preshared");
+    assert_eq!(body.private_key, Some("privkey".to_owned()));
+    assert_eq!(body.preshared_key, Some("preshared".to_owned()));
     assert_eq!(body.allowed_ips, vec!["10.0.0.2/32".to_owned()]);
     assert_eq!(body.persistent_keepalive_interval, 30);
 }
@@ -244,15 +295,20 @@ async fn test_delete_peer_route_with_validation_error_dev_name() {
     let wg_mock = WireguardMockAdapter::new(None, None, None, None, None, None, None);
     let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
     let tm = TunnelManager::new(wg_mock, netdev_mock);
-    let app =
-        test::init_service(App::new().app_data(web::Data::new(tm)).service(delete_peer)).await;
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(delete_peer),
+    )
+    .await;
 
     let req = test::TestRequest::delete()
         .uri("/devices/device_name_16ch/peers/public_key")
         .to_request();
     let resp = test::call_service(&app, req).await;
 
-    assert_eq!(resp.status().is_client_error(), true);
+    assert!(resp.status().is_client_error());
     assert_eq!(resp.status(), 400);
     let body: Error = test::read_body_json(resp).await;
     assert_eq!(body.message, "device name must be at most 15 characters");
@@ -263,15 +319,20 @@ async fn test_delete_peer_route_with_validation_error_pub_key() {
     let wg_mock = WireguardMockAdapter::new(None, None, None, None, None, None, None);
     let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
     let tm = TunnelManager::new(wg_mock, netdev_mock);
-    let app =
-        test::init_service(App::new().app_data(web::Data::new(tm)).service(delete_peer)).await;
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(delete_peer),
+    )
+    .await;
 
     let req = test::TestRequest::delete()
         .uri("/devices/device_name/peers/pubkeypubkeypubkeypubkeypubkeypubkeypubkeypub")
         .to_request();
     let resp = test::call_service(&app, req).await;
 
-    assert_eq!(resp.status().is_client_error(), true);
+    assert!(resp.status().is_client_error());
     assert_eq!(resp.status(), 400);
     let body: Error = test::read_body_json(resp).await;
     assert_eq!(body.message, "public key must be 44 characters");
@@ -286,22 +347,28 @@ async fn test_delete_peer_route_with_not_found() {
         None,
         None,
         None,
-        Some(|_, _| Err(WGError("peer not found".to_owned()))),
+        Some(|_, _| Err(WGError::from(WGErrorKind::PeerNotFound))),
     );
     let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
     let tm = TunnelManager::new(wg_mock, netdev_mock);
-    let app =
-        test::init_service(App::new().app_data(web::Data::new(tm)).service(delete_peer)).await;
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(delete_peer),
+    )
+    .await;
 
     let req = test::TestRequest::delete()
         .uri("/devices/device_name/peers/pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu")
         .to_request();
     let resp = test::call_service(&app, req).await;
 
-    assert_eq!(resp.status().is_client_error(), true);
+    assert!(resp.status().is_client_error());
     assert_eq!(resp.status(), 404);
     let body: Error = test::read_body_json(resp).await;
     assert_eq!(body.message, "peer not found");
+    assert_eq!(body.code, "peer_not_found");
 }
 
 #[actix_web::test]
@@ -310,14 +377,616 @@ async fn test_delete_peer_route_with_successful_result() {
         WireguardMockAdapter::new(None, None, None, None, None, None, Some(|_, _| Ok(())));
     let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
     let tm = TunnelManager::new(wg_mock, netdev_mock);
-    let app =
-        test::init_service(App::new().app_data(web::Data::new(tm)).service(delete_peer)).await;
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(delete_peer),
+    )
+    .await;
 
     let req = test::TestRequest::delete()
         .uri("/devices/device_name/peers/pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu")
         .to_request();
     let resp = test::call_service(&app, req).await;
 
-    assert_eq!(resp.status().is_success(), true);
+    assert!(resp.status().is_success());
     assert_eq!(resp.status(), 204);
 }
+
+#[actix_web::test]
+async fn test_create_peer_route_with_routed_subnet() {
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(|_, i, p| Ok(peer(PUBKEY, i, p))),
+        None,
+    );
+    let netdev_mock =
+        NetworkDeviceMockAdapter::new(Some(device_ip), None, None).with_add_route(|_, r| {
+            match r.destination_str().as_str() {
+                "192.168.10.0/24" => Ok(()),
+                other => Err(NetDevError::Other(format!("unexpected route {}", other))),
+            }
+        });
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(create_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices/device_name/peers")
+        .set_json(CreatePeerRequest {
+            allowed_ips: vec!["10.0.0.2/32".to_owned(), "192.168.10.0/24".to_owned()],
+            persistent_keepalive_interval: 30,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 201);
+}
+
+#[actix_web::test]
+async fn test_create_peer_route_with_route_failure() {
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(|_, i, p| Ok(peer(PUBKEY, i, p))),
+        Some(|_, _| Ok(())),
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(Some(device_ip), None, None)
+        .with_add_route(|_, _| Err(NetDevError::from(NetDevErrorKind::RouteAddFailed)));
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(create_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices/device_name/peers")
+        .set_json(CreatePeerRequest {
+            allowed_ips: vec!["192.168.10.0/24".to_owned()],
+            persistent_keepalive_interval: 30,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert!(resp.status().is_server_error());
+    assert_eq!(resp.status(), 500);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "failed to add route");
+}
+
+#[actix_web::test]
+async fn test_create_peer_route_with_route_of_another_device() {
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(|_, i, p| Ok(peer(PUBKEY, i, p))),
+        Some(|_, _| Ok(())),
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(Some(device_ip), None, None)
+        .with_add_route(|_, _| Err(NetDevError::from(NetDevErrorKind::RouteExists)));
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(create_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices/device_name/peers")
+        .set_json(CreatePeerRequest {
+            allowed_ips: vec!["192.168.10.0/24".to_owned()],
+            persistent_keepalive_interval: 30,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 409);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.code, "route_exists");
+}
+
+#[actix_web::test]
+async fn test_update_peer_route_with_validation_error() {
+    let wg_mock = WireguardMockAdapter::new(None, None, None, None, None, None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(update_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri("/devices/device_name/peers/pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu")
+        .set_json(UpdatePeerRequest {
+            allowed_ips: vec!["10.0.0.256/32".to_owned()],
+            persistent_keepalive_interval: 30,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "invalid ip address: 10.0.0.256");
+}
+
+#[actix_web::test]
+async fn test_update_peer_route_with_not_found() {
+    let wg_mock = WireguardMockAdapter::new(None, None, None, None, None, None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(update_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri("/devices/device_name/peers/pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu")
+        .set_json(UpdatePeerRequest {
+            allowed_ips: vec!["10.0.0.2/32".to_owned()],
+            persistent_keepalive_interval: 30,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 404);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "peer not found");
+}
+
+#[actix_web::test]
+async fn test_update_peer_route_with_successful_result() {
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        None,
+        None,
+        None,
+        Some(|_| {
+            Ok(vec![peer(
+                PUBKEY,
+                vec!["10.0.0.2/32", "192.168.10.0/24"],
+                0,
+            )])
+        }),
+        None,
+        None,
+    )
+    .with_update_peer(|_, _, i, p| Ok(peer(PUBKEY, i, p)));
+    let netdev_mock = NetworkDeviceMockAdapter::new(Some(device_ip), None, None)
+        .with_add_route(|_, r| match r.destination_str().as_str() {
+            "192.168.20.0/24" => Ok(()),
+            other => Err(NetDevError::Other(format!("unexpected route {}", other))),
+        })
+        .with_delete_route(|_, r| match r.destination_str().as_str() {
+            "192.168.10.0/24" => Ok(()),
+            other => Err(NetDevError::Other(format!("unexpected route {}", other))),
+        });
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(update_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri("/devices/device_name/peers/pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu")
+        .set_json(UpdatePeerRequest {
+            allowed_ips: vec!["10.0.0.2/32".to_owned(), "192.168.20.0/24".to_owned()],
+            persistent_keepalive_interval: 25,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    let body: UpdatePeerResponse = test::read_body_json(resp).await;
+    assert_eq!(
+        body.allowed_ips,
+        vec!["10.0.0.2/32".to_owned(), "192.168.20.0/24".to_owned()]
+    );
+    assert_eq!(body.persistent_keepalive_interval, 25);
+}
+
+#[actix_web::test]
+async fn test_delete_peer_route_removes_routes() {
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        None,
+        None,
+        None,
+        Some(|_| {
+            Ok(vec![peer(
+                PUBKEY,
+                vec!["10.0.0.2/32", "192.168.10.0/24"],
+                0,
+            )])
+        }),
+        None,
+        Some(|_, _| Ok(())),
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(Some(device_ip), None, None)
+        .with_delete_route(|_, _| Err(NetDevError::from(NetDevErrorKind::RouteDelFailed)));
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(delete_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::delete()
+        .uri("/devices/device_name/peers/pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 500);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "failed to delete route");
+}
+
+#[actix_web::test]
+async fn test_delete_peer_route_removes_forwards() {
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        None,
+        None,
+        None,
+        Some(|_| Ok(vec![peer(PUBKEY, vec!["10.0.0.2/32"], 0)])),
+        None,
+        Some(|_, _| Ok(())),
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(Some(device_ip), None, None);
+    let firewall_mock = FirewallMockAdapter::default()
+        .with_list_forwards(|_| {
+            Ok(vec![PortForward {
+                peer_public_key: "pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu".to_owned(),
+                protocol: Protocol::Tcp,
+                public_port: 8443,
+                destination_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+                destination_port: 443,
+            }])
+        })
+        .with_delete_forward(|_, _, _| {
+            Err(FirewallError::Other("failed to delete forward".to_owned()))
+        });
+    let tm = TunnelManager::new(wg_mock, netdev_mock).with_firewall(firewall_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(delete_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::delete()
+        .uri("/devices/device_name/peers/pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 500);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "failed to delete forward");
+}
+
+#[actix_web::test]
+async fn test_delete_peer_route_removes_acl_rules() {
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        None,
+        None,
+        None,
+        Some(|_| Ok(vec![peer(PUBKEY, vec!["10.0.0.2/32"], 0)])),
+        None,
+        Some(|_, _| Ok(())),
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(Some(device_ip), None, None);
+    let firewall_mock = FirewallMockAdapter::default().with_delete_peer_acl_rules(|_, pk| {
+        Err(FirewallError::Other(format!(
+            "failed to delete acl rules of {}",
+            pk
+        )))
+    });
+    let tm = TunnelManager::new(wg_mock, netdev_mock).with_firewall(firewall_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(delete_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::delete()
+        .uri("/devices/device_name/peers/pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 500);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(
+        body.message,
+        "failed to delete acl rules of pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu"
+    );
+}
+
+#[actix_web::test]
+async fn test_update_peer_route_rebuilds_acl_rules() {
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        None,
+        None,
+        None,
+        Some(|_| Ok(vec![peer(PUBKEY, vec!["10.0.0.2/32"], 0)])),
+        None,
+        None,
+    )
+    .with_update_peer(|_, _, i, p| Ok(peer(PUBKEY, i, p)));
+    let netdev_mock = NetworkDeviceMockAdapter::new(Some(device_ip), None, None)
+        .with_add_route(|_, _| Ok(()))
+        .with_delete_route(|_, _| Ok(()));
+    let firewall_mock = FirewallMockAdapter::default()
+        .with_list_acl_rules(|_| {
+            Ok(vec![AclRule {
+                id: 7,
+                action: AclAction::Deny,
+                peer_public_key: "pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu".to_owned(),
+                source: (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 32),
+                destination: (IpAddr::V4(Ipv4Addr::new(192, 168, 1, 0)), 24),
+                protocol: None,
+                port: None,
+            }])
+        })
+        .with_replace_acl_rules(|_, id, rules| {
+            let sources: Vec<_> = rules.iter().map(|r| r.source).collect();
+            match (id, sources.as_slice()) {
+                (7, [(IpAddr::V4(a), 32), (IpAddr::V4(b), 24)])
+                    if *a == Ipv4Addr::new(10, 0, 0, 2) && *b == Ipv4Addr::new(192, 168, 20, 0) =>
+                {
+                    Ok(())
+                }
+                _ => Err(FirewallError::Other("unexpected acl rules".to_owned())),
+            }
+        });
+    let tm = TunnelManager::new(wg_mock, netdev_mock).with_firewall(firewall_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(update_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri("/devices/device_name/peers/pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu")
+        .set_json(UpdatePeerRequest {
+            allowed_ips: vec![
+                "10.0.0.2/32".to_owned(),
+                "192.168.20.0/24".to_owned(),
+                "fd86:ea04:1111::2/128".to_owned(),
+            ],
+            persistent_keepalive_interval: 0,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+}
+
+#[actix_web::test]
+async fn test_update_peer_route_keeps_peer_when_routes_fail() {
+    static UPDATES: AtomicUsize = AtomicUsize::new(0);
+
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        None,
+        None,
+        None,
+        Some(|_| Ok(vec![peer(PUBKEY, vec!["10.0.0.2/32"], 0)])),
+        None,
+        None,
+    )
+    .with_update_peer(|_, _, i, p| {
+        UPDATES.fetch_add(1, Ordering::SeqCst);
+        Ok(peer(PUBKEY, i, p))
+    });
+    let netdev_mock = NetworkDeviceMockAdapter::new(Some(device_ip), None, None)
+        .with_add_route(|_, _| Err(NetDevError::from(NetDevErrorKind::RouteAddFailed)));
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(update_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri("/devices/device_name/peers/pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu")
+        .set_json(UpdatePeerRequest {
+            allowed_ips: vec!["10.0.0.2/32".to_owned(), "192.168.20.0/24".to_owned()],
+            persistent_keepalive_interval: 0,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 500);
+    assert_eq!(UPDATES.load(Ordering::SeqCst), 0);
+}
+
+#[actix_web::test]
+async fn test_update_peer_route_restores_peer_when_acl_rules_fail() {
+    static RESTORED: AtomicUsize = AtomicUsize::new(0);
+
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        None,
+        None,
+        None,
+        Some(|_| Ok(vec![peer(PUBKEY, vec!["10.0.0.2/32"], 0)])),
+        None,
+        None,
+    )
+    .with_update_peer(|_, _, i, p| {
+        if i == vec!["10.0.0.2/32"] {
+            RESTORED.fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(peer(PUBKEY, i, p))
+    });
+    let netdev_mock = NetworkDeviceMockAdapter::new(Some(device_ip), None, None)
+        .with_add_route(|_, _| Ok(()))
+        .with_delete_route(|_, _| Ok(()));
+    let firewall_mock = FirewallMockAdapter::default()
+        .with_list_acl_rules(|_| {
+            Ok(vec![AclRule {
+                id: 7,
+                action: AclAction::Deny,
+                peer_public_key: "pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu".to_owned(),
+                source: (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 32),
+                destination: (IpAddr::V4(Ipv4Addr::new(192, 168, 1, 0)), 24),
+                protocol: None,
+                port: None,
+            }])
+        })
+        .with_replace_acl_rules(|_, _, rules| match rules.len() {
+            1 => Ok(()),
+            _ => Err(FirewallError::Other(
+                "failed to replace acl rules".to_owned(),
+            )),
+        });
+    let tm = TunnelManager::new(wg_mock, netdev_mock).with_firewall(firewall_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(update_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::put()
+        .uri("/devices/device_name/peers/pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu")
+        .set_json(UpdatePeerRequest {
+            allowed_ips: vec!["10.0.0.2/32".to_owned(), "192.168.20.0/24".to_owned()],
+            persistent_keepalive_interval: 0,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 500);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "failed to replace acl rules");
+    assert_eq!(RESTORED.load(Ordering::SeqCst), 1);
+}
+
+#[actix_web::test]
+async fn test_delete_peer_route_keeps_peer_when_cleanup_fails() {
+    static DELETED_PEERS: AtomicUsize = AtomicUsize::new(0);
+    static DELETED_FORWARDS: AtomicUsize = AtomicUsize::new(0);
+    static RESTORED_FORWARDS: AtomicUsize = AtomicUsize::new(0);
+
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        None,
+        None,
+        None,
+        Some(|_| Ok(vec![peer(PUBKEY, vec!["10.0.0.2/32"], 0)])),
+        None,
+        Some(|_, _| {
+            DELETED_PEERS.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }),
+    );
+    let netdev_mock = NetworkDeviceMockAdapter::new(Some(device_ip), None, None);
+    let firewall_mock = FirewallMockAdapter::default()
+        .with_list_forwards(|_| {
+            if DELETED_FORWARDS.load(Ordering::SeqCst) > 0 {
+                return Ok(vec![]);
+            }
+            Ok(vec![PortForward {
+                peer_public_key: "pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu".to_owned(),
+                protocol: Protocol::Tcp,
+                public_port: 8443,
+                destination_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+                destination_port: 443,
+            }])
+        })
+        .with_delete_forward(|_, _, _| {
+            DELETED_FORWARDS.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+        .with_add_forward(|_, _| {
+            RESTORED_FORWARDS.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+        .with_delete_peer_acl_rules(|_, _| {
+            Err(FirewallError::Other(
+                "failed to delete acl rules".to_owned(),
+            ))
+        });
+    let tm = TunnelManager::new(wg_mock, netdev_mock).with_firewall(firewall_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(delete_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::delete()
+        .uri("/devices/device_name/peers/pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 500);
+    assert_eq!(DELETED_PEERS.load(Ordering::SeqCst), 0);
+    assert_eq!(RESTORED_FORWARDS.load(Ordering::SeqCst), 1);
+}
+
+#[actix_web::test]
+async fn test_list_peers_route_with_invalid_netns() {
+    let wg_mock = WireguardMockAdapter::new(None, None, None, None, None, None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let tm = TunnelManager::new(wg_mock, netdev_mock);
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tm))
+            .service(list_peers),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/devices/wg0/peers?netns=..")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "invalid network namespace name: ..");
+}
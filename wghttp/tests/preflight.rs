@@ -0,0 +1,95 @@
+use domain::models::wg::*;
+use std::fs;
+use std::path::Path;
+use wghttp::models::health::*;
+use wghttp::preflight::*;
+
+pub mod mock;
+
+use mock::*;
+
+#[actix_web::test]
+async fn test_check_socket_path() {
+    let dir = std::env::temp_dir();
+    let file = dir.join(format!("wghttp-preflight-{}.sock", std::process::id()));
+    assert!(check_socket_path(&file).is_ok());
+
+    fs::write(&file, b"").unwrap();
+    let result = check_socket_path(&file);
+    fs::remove_file(&file).unwrap();
+    assert_eq!(
+        result,
+        Err(format!("{} exists and is not a socket", file.display()))
+    );
+
+    let result = check_socket_path(Path::new("/nonexistent/wghttp.sock"));
+    assert!(
+        result
+            .unwrap_err()
+            .starts_with("cannot create files in /nonexistent")
+    );
+}
+
+#[actix_web::test]
+async fn test_parse_tcp_address() {
+    assert_eq!(
+        parse_tcp_address("127.0.0.1:8080").unwrap().to_string(),
+        "127.0.0.1:8080"
+    );
+    assert_eq!(
+        parse_tcp_address("[::1]:8080").unwrap().to_string(),
+        "[::1]:8080"
+    );
+    assert_eq!(
+        parse_tcp_address("127.0.0.1"),
+        Err("invalid tcp address 127.0.0.1, expected ip:port".to_owned())
+    );
+}
+
+#[actix_web::test]
+async fn test_preflight_reports_failures_with_hints() {
+    let wg_mock = WireguardMockAdapter::new(None, None, None, None, None, None, None)
+        .with_check(|| Err(WGErrorKind::FamilyNotFound.into()));
+
+    let checks = preflight(&wg_mock, &Listen::Tcp("0.0.0.0"));
+
+    let names: Vec<&str> = checks.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["capabilities", "wireguard", "tcp_address"]);
+    assert_eq!(checks[1].status, CheckStatus::Fail);
+    assert_eq!(
+        checks[1].error,
+        Some(
+            "WGError: wireguard netlink family not found; load the kernel module with `modprobe wireguard`"
+                .to_owned()
+        )
+    );
+    assert_eq!(checks[2].status, CheckStatus::Fail);
+}
+
+#[actix_web::test]
+async fn test_render_doctor_report() {
+    let checks = vec![
+        Check {
+            name: "wireguard".to_owned(),
+            status: CheckStatus::Ok,
+            latency_ms: 0.5,
+            error: None,
+        },
+        Check {
+            name: "tcp_address".to_owned(),
+            status: CheckStatus::Fail,
+            latency_ms: 0.01,
+            error: Some("invalid tcp address".to_owned()),
+        },
+    ];
+    let versions = [("kernel", Some("6.8.0".to_owned())), ("wireguard", None)];
+
+    assert_eq!(
+        render(&versions, &checks),
+        "kernel          6.8.0\n\
+         wireguard       unknown\n\
+         \n\
+         ok    wireguard       0.50ms\n\
+         fail  tcp_address     0.01ms  invalid tcp address\n"
+    );
+}
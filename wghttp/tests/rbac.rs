@@ -0,0 +1,260 @@
+use actix_web::middleware::from_fn;
+use actix_web::{App, test, web};
+use domain::models::wg::*;
+use sha2::{Digest, Sha256};
+use wghttp::middleware::auth::*;
+use wghttp::middleware::rbac::*;
+use wghttp::models::devices::*;
+use wghttp::models::errors::*;
+use wghttp::models::peers::*;
+use wghttp::routes::devices::*;
+use wghttp::routes::peers::*;
+use wghttp::services::TunnelManager;
+
+pub mod mock;
+
+use mock::*;
+
+const KEYS: &str = r#"
+[[keys]]
+name = "helpdesk"
+sha256 = "{helpdesk}"
+role = "peer-operator"
+devices = ["wg-guest*"]
+
+[[keys]]
+name = "monitoring"
+sha256 = "{monitoring}"
+role = "viewer"
+
+[[keys]]
+name = "ops"
+sha256 = "{ops}"
+"#;
+
+fn auth() -> Auth {
+    let mut keys = KEYS.to_owned();
+    for name in ["helpdesk", "monitoring", "ops"] {
+        let digest = hex::encode(Sha256::digest(name.as_bytes()));
+        keys = keys.replace(&format!("{{{}}}", name), &digest);
+    }
+    Auth::new(ApiKeys::parse(&keys).unwrap())
+}
+
+fn devices() -> Result<Vec<WGDevice>, WGError> {
+    Ok(vec![device("wg-core"), device("wg-guests")])
+}
+
+fn manager() -> TunnelManager {
+    let wg_mock = WireguardMockAdapter::new(
+        None,
+        Some(devices),
+        None,
+        Some(|_| Ok(())),
+        None,
+        Some(|_, i, k| Ok(peer(PUBKEY, i, k))),
+        None,
+    );
+    tunnel_manager(wg_mock)
+}
+
+fn peer_request() -> CreatePeerRequest {
+    CreatePeerRequest {
+        allowed_ips: vec!["10.0.0.2/32".to_owned()],
+        persistent_keepalive_interval: 25,
+    }
+}
+
+#[actix_web::test]
+async fn test_permissions_authorize() {
+    let perms = Permissions {
+        role: Role::PeerOperator,
+        devices: vec!["wg-guest*".to_owned(), "wg-lab".to_owned()],
+        secrets: false,
+    };
+
+    assert!(perms.authorize("wg-guests", Action::ManagePeers).is_ok());
+    assert!(perms.authorize("wg-guest", Action::Read).is_ok());
+    assert!(perms.authorize("wg-lab", Action::Read).is_ok());
+    assert!(perms.authorize("wg-lab2", Action::Read).is_err());
+    assert_eq!(
+        perms.authorize("wg-guests", Action::ManageDevices),
+        Err("not allowed to manage device wg-guests".to_owned())
+    );
+    assert_eq!(
+        perms.authorize("wg-core", Action::ManagePeers),
+        Err("not allowed to manage peers of device wg-core".to_owned())
+    );
+}
+
+#[actix_web::test]
+async fn test_api_keys_default_permissions() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(auth()))
+            .app_data(web::Data::new(manager()))
+            .service(create_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices/wg-core/peers")
+        .insert_header((API_KEY_HEADER, "ops"))
+        .set_json(peer_request())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 201);
+    let body: CreatePeerResponse = test::read_body_json(resp).await;
+    assert_eq!(body.private_key, Some("privkey".to_owned()));
+    assert_eq!(body.preshared_key, Some("preshared".to_owned()));
+}
+
+#[actix_web::test]
+async fn test_list_devices_route_filtered_by_scope() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(auth()))
+            .app_data(web::Data::new(manager()))
+            .service(list_devices),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/devices")
+        .insert_header((API_KEY_HEADER, "helpdesk"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    let body: Vec<ListDeviceResponse> = test::read_body_json(resp).await;
+    assert_eq!(body.len(), 1);
+    assert_eq!(body[0].device_name, "wg-guests");
+}
+
+#[actix_web::test]
+async fn test_create_peer_route_as_peer_operator() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(auth()))
+            .app_data(web::Data::new(manager()))
+            .service(create_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices/wg-guests/peers")
+        .insert_header((API_KEY_HEADER, "helpdesk"))
+        .set_json(peer_request())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 201);
+    let body: CreatePeerResponse = test::read_body_json(resp).await;
+    assert_eq!(
+        body.public_key,
+        "pubkeypubkeypubkeypubkeypubkeypubkeypubkeypu"
+    );
+    assert_eq!(body.private_key, None);
+    assert_eq!(body.preshared_key, None);
+
+    let req = test::TestRequest::post()
+        .uri("/devices/wg-core/peers")
+        .insert_header((API_KEY_HEADER, "helpdesk"))
+        .set_json(peer_request())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 403);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(
+        body.message,
+        "not allowed to manage peers of device wg-core"
+    );
+}
+
+#[actix_web::test]
+async fn test_create_peer_route_as_viewer() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(auth()))
+            .app_data(web::Data::new(manager()))
+            .service(create_peer)
+            .service(list_peers),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/devices/wg-core/peers")
+        .insert_header((API_KEY_HEADER, "monitoring"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let req = test::TestRequest::post()
+        .uri("/devices/wg-core/peers")
+        .insert_header((API_KEY_HEADER, "monitoring"))
+        .set_json(peer_request())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 403);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(
+        body.message,
+        "not allowed to manage peers of device wg-core"
+    );
+}
+
+#[actix_web::test]
+async fn test_delete_device_route_as_peer_operator() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(auth()))
+            .app_data(web::Data::new(manager()))
+            .service(delete_device),
+    )
+    .await;
+
+    let req = test::TestRequest::delete()
+        .uri("/devices/wg-guests")
+        .insert_header((API_KEY_HEADER, "helpdesk"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 403);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "not allowed to manage device wg-guests");
+
+    let req = test::TestRequest::delete()
+        .uri("/devices/wg-core")
+        .insert_header((API_KEY_HEADER, "ops"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 204);
+}
+
+#[actix_web::test]
+async fn test_create_peer_route_without_auth_middleware() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(auth()))
+            .app_data(web::Data::new(manager()))
+            .service(create_peer),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/devices/wg-core/peers")
+        .set_json(peer_request())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 403);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.code, "permission_denied");
+}
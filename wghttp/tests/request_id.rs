@@ -0,0 +1,166 @@
+use actix_web::middleware::from_fn;
+use actix_web::{App, test, web};
+use domain::models::wg::*;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use tracing_subscriber::fmt::MakeWriter;
+use wghttp::metrics::{Metrics, MetricsLabels};
+use wghttp::middleware::auth::require_api_key;
+use wghttp::middleware::request_id::trace_requests;
+use wghttp::routes::health::health;
+use wghttp::routes::peers::list_peers;
+use wghttp::services::TunnelManager;
+
+pub mod mock;
+
+use mock::*;
+
+#[derive(Clone, Default)]
+struct Logs(Arc<Mutex<Vec<u8>>>);
+
+impl Logs {
+    fn lines(&self) -> Vec<serde_json::Value> {
+        let buf = self.0.lock().unwrap();
+        String::from_utf8_lossy(&buf)
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+}
+
+impl Write for Logs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Logs {
+    type Writer = Logs;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+fn list_two_peers(_: &str) -> Result<Vec<WGPeer>, WGError> {
+    let peer = |public_key: &str| WGPeer {
+        allowed_ips: vec!["10.0.0.2/32".to_owned()],
+        endpoint: "endpoint".to_owned(),
+        last_handshake_time: 0,
+        persistent_keepalive_interval: 0,
+        rx: 0,
+        tx: 0,
+        public_key: public_key.to_owned(),
+        private_key: "secretprivkey".to_owned(),
+        preshared_key: "secretpresharedkey".to_owned(),
+    };
+    Ok(vec![peer("pubkey1"), peer("pubkey2")])
+}
+
+fn tunnel_manager() -> TunnelManager {
+    let wg_mock =
+        WireguardMockAdapter::new(None, None, None, None, Some(list_two_peers), None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let metrics = Arc::new(Metrics::new(MetricsLabels::default()));
+    TunnelManager::new(wg_mock, netdev_mock).with_metrics(metrics)
+}
+
+#[actix_web::test]
+async fn test_request_id_is_echoed() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .wrap(from_fn(trace_requests))
+            .service(health),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/")
+        .insert_header(("X-Request-ID", "req-1234"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers().get("x-request-id").unwrap(), "req-1234");
+}
+
+#[actix_web::test]
+async fn test_request_id_is_generated_when_missing_or_invalid() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .wrap(from_fn(trace_requests))
+            .service(health),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/").to_request();
+    let first = test::call_service(&app, req).await;
+    let first = first.headers().get("x-request-id").unwrap().clone();
+
+    let req = test::TestRequest::get()
+        .uri("/")
+        .insert_header(("X-Request-ID", "has spaces"))
+        .to_request();
+    let second = test::call_service(&app, req).await;
+    let second = second.headers().get("x-request-id").unwrap().clone();
+
+    assert!(!first.is_empty());
+    assert_ne!(second, "has spaces");
+    assert_ne!(first, second);
+}
+
+#[actix_web::test]
+async fn test_adapter_calls_are_traced_without_secrets() {
+    let logs = Logs::default();
+    let subscriber = tracing_subscriber::fmt()
+        .json()
+        .flatten_event(true)
+        .with_max_level(tracing::Level::DEBUG)
+        .with_writer(logs.clone())
+        .finish();
+    let _default = tracing::subscriber::set_default(subscriber);
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .wrap(from_fn(trace_requests))
+            .app_data(web::Data::new(tunnel_manager()))
+            .service(list_peers),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/devices/wg0/peers")
+        .insert_header(("X-Request-ID", "req-5678"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let lines = logs.lines();
+    let call = lines
+        .iter()
+        .find(|l| l["message"] == "adapter call completed")
+        .expect("adapter call is logged");
+    assert_eq!(call["span"]["operation"], "list_peers");
+    assert_eq!(call["span"]["device"], "wg0");
+    assert!(call["duration_ms"].is_number());
+    assert_eq!(call["spans"][0]["request_id"], "req-5678");
+
+    let completed = lines
+        .iter()
+        .find(|l| l["message"] == "request completed")
+        .expect("request is logged");
+    assert_eq!(completed["status"], 200);
+    assert_eq!(completed["span"]["request_id"], "req-5678");
+
+    let raw = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    assert!(!raw.contains("secretprivkey"));
+    assert!(!raw.contains("secretpresharedkey"));
+}
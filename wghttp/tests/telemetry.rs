@@ -0,0 +1,132 @@
+use actix_web::middleware::from_fn;
+use actix_web::{App, test, web};
+use domain::models::wg::*;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, channel};
+use std::thread;
+use std::time::Duration;
+use tracing_subscriber::layer::SubscriberExt;
+use wghttp::metrics::{Metrics, MetricsLabels};
+use wghttp::middleware::auth::require_api_key;
+use wghttp::middleware::request_id::trace_requests;
+use wghttp::routes::peers::list_peers;
+use wghttp::services::TunnelManager;
+use wghttp::telemetry;
+
+pub mod mock;
+
+use mock::*;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_ID: &str = "00f067aa0ba902b7";
+
+// Stand-in for a collector, answering every request with 200 and passing on its path and body.
+fn collector() -> (String, Receiver<(String, Vec<u8>)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let (tx, rx) = channel();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let path = request_line
+                .split(' ')
+                .nth(1)
+                .unwrap_or_default()
+                .to_owned();
+
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':')
+                    && name.eq_ignore_ascii_case("content-length")
+                {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            let _ = tx.send((path, body));
+        }
+    });
+
+    (endpoint, rx)
+}
+
+fn list_one_peer(_: &str) -> Result<Vec<WGPeer>, WGError> {
+    Ok(vec![WGPeer {
+        allowed_ips: vec!["10.0.0.2/32".to_owned()],
+        endpoint: "endpoint".to_owned(),
+        last_handshake_time: 0,
+        persistent_keepalive_interval: 0,
+        rx: 0,
+        tx: 0,
+        public_key: "pubkey1".to_owned(),
+        private_key: "privkey".to_owned(),
+        preshared_key: "preshared".to_owned(),
+    }])
+}
+
+fn tunnel_manager() -> TunnelManager {
+    let wg_mock =
+        WireguardMockAdapter::new(None, None, None, None, Some(list_one_peer), None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    let metrics = Arc::new(Metrics::new(MetricsLabels::default()));
+    TunnelManager::new(wg_mock, netdev_mock).with_metrics(metrics)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+#[actix_web::test]
+async fn test_tracer_provider_rejects_invalid_endpoint() {
+    assert!(telemetry::tracer_provider("not a url", "wghttp").is_err());
+}
+
+#[actix_web::test]
+async fn test_spans_are_exported_within_the_callers_trace() {
+    let (endpoint, received) = collector();
+    let provider = telemetry::tracer_provider(&endpoint, "wghttp-test").unwrap();
+    let subscriber = tracing_subscriber::registry().with(telemetry::layer(&provider));
+    let _default = tracing::subscriber::set_default(subscriber);
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .wrap(from_fn(trace_requests))
+            .app_data(web::Data::new(tunnel_manager()))
+            .service(list_peers),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/devices/wg0/peers")
+        .insert_header(("traceparent", format!("00-{}-{}-01", TRACE_ID, PARENT_ID)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    provider.force_flush().unwrap();
+    let (path, body) = received.recv_timeout(Duration::from_secs(5)).unwrap();
+
+    assert_eq!(path, "/v1/traces");
+    assert!(contains(&body, b"wghttp-test"));
+    assert!(contains(&body, b"GET /devices/{dev}/peers"));
+    assert!(contains(&body, b"list_peers"));
+    assert!(contains(&body, &hex::decode(TRACE_ID).unwrap()));
+    assert!(contains(&body, &hex::decode(PARENT_ID).unwrap()));
+}
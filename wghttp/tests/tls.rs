@@ -0,0 +1,211 @@
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, Responder, get};
+use rcgen::{
+    BasicConstraints, CertificateParams, DistinguishedName, DnType, IsCa, Issuer, KeyPair,
+};
+use rustls::crypto::ring;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use wghttp::tls::*;
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+struct Ca {
+    cert: CertificateDer<'static>,
+    pem: String,
+    issuer: Issuer<'static, KeyPair>,
+}
+
+struct Leaf {
+    cert: CertificateDer<'static>,
+    pem: String,
+    key: KeyPair,
+}
+
+fn ca() -> Ca {
+    let mut params = CertificateParams::new(vec![]).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.distinguished_name = DistinguishedName::new();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "wghttp test ca");
+
+    let key = KeyPair::generate().unwrap();
+    let cert = params.self_signed(&key).unwrap();
+    Ca {
+        cert: cert.der().clone(),
+        pem: cert.pem(),
+        issuer: Issuer::new(params, key),
+    }
+}
+
+fn leaf(ca: &Ca, common_name: &str) -> Leaf {
+    let mut params = CertificateParams::new(vec!["localhost".to_owned()]).unwrap();
+    params.distinguished_name = DistinguishedName::new();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, common_name);
+
+    let key = KeyPair::generate().unwrap();
+    let cert = params.signed_by(&key, &ca.issuer).unwrap();
+    Leaf {
+        cert: cert.der().clone(),
+        pem: cert.pem(),
+        key,
+    }
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("wghttp-tls-{}-{}", std::process::id(), name))
+}
+
+fn write_leaf(leaf: &Leaf, name: &str) -> (PathBuf, PathBuf) {
+    let cert_path = temp_path(&format!("{}.crt", name));
+    let key_path = temp_path(&format!("{}.key", name));
+    fs::write(&cert_path, &leaf.pem).unwrap();
+    fs::write(&key_path, leaf.key.serialize_pem()).unwrap();
+    (cert_path, key_path)
+}
+
+fn request(addr: SocketAddr, ca: CertificateDer<'static>, client: Option<&Leaf>) -> String {
+    let mut roots = RootCertStore::empty();
+    roots.add(ca).unwrap();
+
+    let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots);
+    let config = match client {
+        Some(leaf) => {
+            let key = PrivateKeyDer::try_from(leaf.key.serialize_der()).unwrap();
+            builder
+                .with_client_auth_cert(vec![leaf.cert.clone()], key)
+                .unwrap()
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let name = ServerName::try_from("localhost").unwrap();
+    let conn = ClientConnection::new(Arc::new(config), name).unwrap();
+    let mut stream = StreamOwned::new(conn, TcpStream::connect(addr).unwrap());
+
+    let mut out = String::new();
+    let _ = stream
+        .write_all(b"GET /whoami HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .and_then(|_| stream.read_to_string(&mut out));
+    out
+}
+
+#[get("/whoami")]
+async fn whoami(req: HttpRequest) -> impl Responder {
+    match req.conn_data::<ClientCertificate>() {
+        Some(cert) => HttpResponse::Ok().body(cert.subject.clone()),
+        None => HttpResponse::Ok().finish(),
+    }
+}
+
+#[actix_web::test]
+async fn test_server_config_with_missing_files() {
+    let err =
+        server_config(&temp_path("missing.crt"), &temp_path("missing.key"), None).unwrap_err();
+    assert!(err.starts_with("failed to read certificates from"));
+}
+
+#[actix_web::test]
+async fn test_server_config_with_mismatched_key() {
+    let ca = ca();
+    let (cert_path, _) = write_leaf(&leaf(&ca, "server"), "mismatch-a");
+    let (_, key_path) = write_leaf(&leaf(&ca, "server"), "mismatch-b");
+
+    let result = server_config(&cert_path, &key_path, None);
+    for name in [
+        "mismatch-a.crt",
+        "mismatch-a.key",
+        "mismatch-b.crt",
+        "mismatch-b.key",
+    ] {
+        fs::remove_file(temp_path(name)).unwrap();
+    }
+
+    assert_eq!(
+        result.unwrap_err(),
+        "certificate does not match the private key"
+    );
+}
+
+#[actix_web::test]
+async fn test_certificate_resolver_reloads_renewed_certificate() {
+    let ca = ca();
+    let (cert_path, key_path) = write_leaf(&leaf(&ca, "server"), "reload");
+    let resolver = CertificateResolver::new(&cert_path, &key_path).unwrap();
+
+    let renewed = leaf(&ca, "server");
+    let later = SystemTime::now() + Duration::from_secs(60);
+    fs::write(&cert_path, &renewed.pem).unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&cert_path)
+        .and_then(|f| f.set_modified(later))
+        .unwrap();
+    // only the certificate is renewed so far, the old key does not match it.
+    let partial = resolver.current();
+
+    fs::write(&key_path, renewed.key.serialize_pem()).unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&key_path)
+        .and_then(|f| f.set_modified(later))
+        .unwrap();
+    let complete = resolver.current();
+
+    fs::remove_file(&cert_path).unwrap();
+    fs::remove_file(&key_path).unwrap();
+
+    assert_ne!(partial.cert[0], renewed.cert);
+    assert_eq!(complete.cert[0], renewed.cert);
+}
+
+#[actix_web::test]
+async fn test_mutual_tls_with_client_certificate() {
+    let ca = ca();
+    let (cert_path, key_path) = write_leaf(&leaf(&ca, "server"), "mtls");
+    let ca_path = temp_path("mtls-ca.crt");
+    fs::write(&ca_path, &ca.pem).unwrap();
+
+    let config = server_config(&cert_path, &key_path, Some(&ca_path)).unwrap();
+    for path in [&cert_path, &key_path, &ca_path] {
+        fs::remove_file(path).unwrap();
+    }
+
+    let server = HttpServer::new(|| App::new().service(whoami))
+        .workers(1)
+        .on_connect(on_connect)
+        .bind_rustls_0_23(("127.0.0.1", 0), config)
+        .unwrap();
+    let addr = server.addrs()[0];
+    let server = server.run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+
+    let client = leaf(&ca, "helpdesk");
+    let ca_cert = ca.cert.clone();
+    let authenticated =
+        actix_web::rt::task::spawn_blocking(move || request(addr, ca_cert, Some(&client)))
+            .await
+            .unwrap();
+
+    let ca_cert = ca.cert.clone();
+    let anonymous = actix_web::rt::task::spawn_blocking(move || request(addr, ca_cert, None))
+        .await
+        .unwrap();
+
+    handle.stop(true).await;
+
+    assert!(authenticated.starts_with("HTTP/1.1 200 OK"));
+    assert!(authenticated.ends_with("CN=helpdesk"));
+    assert!(!anonymous.contains("200 OK"));
+}
@@ -0,0 +1,123 @@
+use actix_web::middleware::from_fn;
+use actix_web::{App, test, web};
+use domain::models::wg::*;
+use serde_json::Value;
+use wghttp::middleware::auth::require_api_key;
+use wghttp::middleware::deprecation::{DEPRECATION, SUNSET};
+use wghttp::routes;
+use wghttp::services::TunnelManager;
+
+pub mod mock;
+
+use mock::*;
+
+fn list_devices() -> Result<Vec<WGDevice>, WGError> {
+    Ok(vec![WGDevice {
+        name: "wg0".to_owned(),
+        public_key: "devpubkey".to_owned(),
+        private_key: "devprivkey".to_owned(),
+        port: 51820,
+        fwmark: 0,
+        peers: 0,
+    }])
+}
+
+fn tunnel_manager() -> TunnelManager {
+    let wg_mock = WireguardMockAdapter::new(None, Some(list_devices), None, None, None, None, None);
+    let netdev_mock = NetworkDeviceMockAdapter::new(None, None, None);
+    TunnelManager::new(wg_mock, netdev_mock)
+}
+
+#[actix_web::test]
+async fn test_v1_routes_are_not_deprecated() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager()))
+            .configure(routes::configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/v1/devices").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    assert!(resp.headers().get("deprecation").is_none());
+    assert!(resp.headers().get("sunset").is_none());
+}
+
+#[actix_web::test]
+async fn test_root_aliases_are_deprecated() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager()))
+            .configure(routes::configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/devices").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers().get("deprecation").unwrap(), DEPRECATION);
+    assert_eq!(resp.headers().get("sunset").unwrap(), SUNSET);
+    assert_eq!(
+        resp.headers().get("link").unwrap(),
+        "</v1/devices>; rel=\"successor-version\""
+    );
+}
+
+#[actix_web::test]
+async fn test_unversioned_endpoints_and_unknown_paths_are_not_deprecated() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager()))
+            .configure(routes::configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/healthz").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    assert!(resp.headers().get("deprecation").is_none());
+
+    let req = test::TestRequest::get().uri("/nothing/here").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+    assert!(resp.headers().get("deprecation").is_none());
+}
+
+#[actix_web::test]
+async fn test_v1_openapi_document() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(web::Data::new(tunnel_manager()))
+            .configure(routes::configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api-docs/v1/openapi.json")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    let doc: Value = test::read_body_json(resp).await;
+    let paths = doc["paths"].as_object().unwrap();
+    assert!(paths.contains_key("/v1/devices"));
+    assert!(paths.contains_key("/v1/devices/{dev}/peers"));
+    assert!(paths.contains_key("/healthz"));
+    assert!(paths.contains_key("/metrics"));
+    assert!(!paths.contains_key("/devices"));
+}
+
+#[actix_web::test]
+async fn test_unversioned_path() {
+    assert_eq!(routes::unversioned("/v1/devices"), "/devices");
+    assert_eq!(routes::unversioned("/v1"), "");
+    assert_eq!(routes::unversioned("/devices"), "/devices");
+    assert_eq!(routes::unversioned("/v10/devices"), "/v10/devices");
+}
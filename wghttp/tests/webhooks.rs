@@ -0,0 +1,277 @@
+use actix_web::middleware::from_fn;
+use actix_web::{App, test, web};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use wghttp::middleware::auth::require_api_key;
+use wghttp::models::errors::Error;
+use wghttp::models::events::*;
+use wghttp::models::webhooks::*;
+use wghttp::routes::webhooks::*;
+use wghttp::webhooks::*;
+
+struct Request {
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+// Local stand-in of a subscriber, answering requests with the given statuses in turn.
+fn stand_in(statuses: Vec<u16>) -> (String, mpsc::Receiver<Request>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        for status in statuses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut headers = vec![];
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                let Some((name, value)) = line.trim_end().split_once(": ") else {
+                    break;
+                };
+                headers.push((name.to_owned(), value.to_owned()));
+            }
+            let length = headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case("content-length"))
+                .map(|(_, v)| v.parse().unwrap())
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let mut stream = stream;
+            write!(
+                stream,
+                "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            )
+            .unwrap();
+            tx.send(Request { headers, body }).unwrap();
+        }
+    });
+
+    (url, rx)
+}
+
+// Url nothing listens on.
+fn closed_url() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    format!("http://{}/hook", listener.local_addr().unwrap())
+}
+
+fn temp_queue(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("wghttp-webhooks-{}-{}", std::process::id(), name))
+}
+
+fn webhooks(queue: &Path, url: &str, extra: &str) -> Webhooks {
+    let content = format!(
+        r#"
+queue = "{}"
+{}
+
+[[subscribers]]
+name = "chatops"
+url = "{}"
+secret = "s3cret"
+devices = ["wg-*"]
+"#,
+        queue.display(),
+        extra,
+        url
+    );
+    Webhooks::parse(&content).unwrap()
+}
+
+fn peer_removed(device: &str) -> Event {
+    let change = Change::PeerRemoved {
+        public_key: "pubkey".to_owned(),
+    };
+    Event::new(None, device, change)
+}
+
+#[actix_web::test]
+async fn test_webhooks_deliver_signed_events_of_matching_devices() {
+    let queue = temp_queue("signed");
+    let (url, requests) = stand_in(vec![204]);
+    let hooks = webhooks(&queue, &url, "");
+
+    hooks.enqueue(&peer_removed("wg0"));
+    hooks.enqueue(&peer_removed("wg-guests"));
+    assert_eq!(hooks.pending(), 1);
+    hooks.process(1000);
+
+    let request = requests.recv().unwrap();
+    let signature = format!("sha256={}", sign("s3cret", &request.body));
+    assert_eq!(request.header(SIGNATURE_HEADER), Some(signature.as_str()));
+    assert_eq!(request.header(EVENT_HEADER), Some("peer_removed"));
+    assert_eq!(request.header("content-type"), Some("application/json"));
+
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(body["device"], "wg-guests");
+    assert_eq!(body["type"], "peer_removed");
+    assert_eq!(body["public_key"], "pubkey");
+    assert_eq!(body["id"], request.header(DELIVERY_HEADER).unwrap());
+
+    let records = hooks.query(&DeliveryQuery::default()).unwrap();
+    fs::remove_dir_all(&queue).unwrap();
+
+    assert_eq!(hooks.pending(), 0);
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].status, Some(204));
+    assert_eq!(records[0].outcome, DeliveryOutcome::Delivered);
+}
+
+#[actix_web::test]
+async fn test_webhooks_retry_with_backoff_across_restarts() {
+    let queue = temp_queue("retry");
+    let (url, requests) = stand_in(vec![500, 200]);
+    let hooks = webhooks(&queue, &url, "");
+
+    hooks.enqueue(&peer_removed("wg-guests"));
+    hooks.process(1000);
+    let first = requests.recv().unwrap();
+    drop(hooks);
+
+    // the pending delivery is picked up again after a restart.
+    let hooks = webhooks(&queue, &url, "");
+    assert_eq!(hooks.pending(), 1);
+    hooks.process(1000 + backoff(1) - 1);
+    assert_eq!(hooks.pending(), 1);
+    hooks.process(1000 + backoff(1));
+    let second = requests.recv().unwrap();
+
+    let records = hooks.query(&DeliveryQuery::default()).unwrap();
+    fs::remove_dir_all(&queue).unwrap();
+
+    assert_eq!(hooks.pending(), 0);
+    assert_eq!(
+        first.header(DELIVERY_HEADER),
+        second.header(DELIVERY_HEADER)
+    );
+    let outcomes: Vec<_> = records.iter().map(|r| (r.attempt, r.outcome)).collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            (1, DeliveryOutcome::Retrying),
+            (2, DeliveryOutcome::Delivered)
+        ]
+    );
+    assert_eq!(records[0].error, Some("subscriber answered 500".to_owned()));
+}
+
+#[actix_web::test]
+async fn test_webhooks_give_up_after_max_attempts() {
+    let queue = temp_queue("give-up");
+    let hooks = webhooks(&queue, &closed_url(), "max_attempts = 2");
+
+    hooks.enqueue(&peer_removed("wg-guests"));
+    hooks.process(1000);
+    hooks.process(2000);
+
+    let records = hooks.query(&DeliveryQuery::default()).unwrap();
+    let pending = fs::read_dir(queue.join("pending")).unwrap().count();
+    fs::remove_dir_all(&queue).unwrap();
+
+    assert_eq!(hooks.pending(), 0);
+    assert_eq!(pending, 0);
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[1].outcome, DeliveryOutcome::Failed);
+    assert_eq!(records[1].status, None);
+    assert!(records[1].error.is_some());
+}
+
+#[actix_web::test]
+async fn test_webhooks_parse_rejects_unknown_events() {
+    let queue = temp_queue("unknown-event");
+    let content = format!(
+        r#"
+queue = "{}"
+
+[[subscribers]]
+name = "chatops"
+url = "http://127.0.0.1:9/hook"
+secret = "s3cret"
+events = ["peer_deleted"]
+"#,
+        queue.display()
+    );
+
+    let err = Webhooks::parse(&content).err().unwrap();
+    assert!(err.starts_with("unknown webhook event peer_deleted for chatops"));
+}
+
+#[actix_web::test]
+async fn test_test_webhook_route_sends_ping() {
+    let queue = temp_queue("ping");
+    let (url, requests) = stand_in(vec![200]);
+    let hooks = web::Data::new(webhooks(&queue, &url, ""));
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .app_data(hooks.clone())
+            .service(test_webhook)
+            .service(list_deliveries),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/webhooks/chatops/test")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let record: DeliveryRecord = test::read_body_json(resp).await;
+    assert_eq!(record.event, PING);
+    assert_eq!(record.outcome, DeliveryOutcome::Delivered);
+    assert_eq!(requests.recv().unwrap().header(EVENT_HEADER), Some(PING));
+
+    let req = test::TestRequest::post()
+        .uri("/webhooks/pager/test")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    let req = test::TestRequest::get()
+        .uri("/webhooks/deliveries?subscriber=chatops")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    fs::remove_dir_all(&queue).unwrap();
+
+    assert_eq!(resp.status(), 200);
+    let body: Vec<DeliveryRecord> = test::read_body_json(resp).await;
+    assert_eq!(body, vec![record]);
+}
+
+#[actix_web::test]
+async fn test_list_deliveries_route_with_webhooks_not_enabled() {
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(require_api_key))
+            .service(list_deliveries),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/webhooks/deliveries")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 400);
+    let body: Error = test::read_body_json(resp).await;
+    assert_eq!(body.message, "webhooks are not enabled");
+}
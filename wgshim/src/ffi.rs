@@ -1,5 +1,5 @@
 use domain::models::wg::{WGDevice, WGPeer};
-use std::os::raw::{c_char, c_int, c_longlong, c_ulonglong, c_ushort};
+use std::os::raw::{c_char, c_int, c_longlong, c_uint, c_ulonglong, c_ushort};
 
 pub const IF_NAMESIZE: usize = 16;
 pub const LIBWGSHIM_B64_KEY_SIZE: usize = 45;
@@ -14,6 +14,7 @@ pub enum LibWGShimError {
     DevAddFailed,
     DevSetFailed,
     PeerNotFound,
+    FamilyNotFound,
 }
 
 #[repr(C)]
@@ -22,6 +23,7 @@ pub struct LibWGShimDevice {
     pub name: [c_char; IF_NAMESIZE],
 
     pub port: c_ushort,
+    pub fwmark: c_uint,
     pub peers: c_ulonglong,
 
     pub public_key: [c_char; LIBWGSHIM_B64_KEY_SIZE],
@@ -69,6 +71,8 @@ unsafe extern "C" {
         dev: *mut *mut LibWGShimDevice,
     ) -> c_int;
 
+    pub unsafe fn libwgshim_set_fwmark(device_name: *const c_char, fwmark: c_uint) -> c_int;
+
     pub unsafe fn libwgshim_delete_device(device_name: *const c_char) -> c_int;
 
     pub unsafe fn libwgshim_add_peer(
@@ -78,6 +82,19 @@ unsafe extern "C" {
         peer: *mut *mut LibWGShimPeer,
     ) -> c_int;
 
+    pub unsafe fn libwgshim_add_peers(
+        device_name: *const c_char,
+        peer_head: *mut LibWGShimPeer,
+    ) -> c_int;
+
+    pub unsafe fn libwgshim_update_peer(
+        device_name: *const c_char,
+        public_key: *const c_char,
+        allowed_ip_head: *mut LibWGShimAllowedIp,
+        persistent_keepalive_interval: c_ushort,
+        peer: *mut *mut LibWGShimPeer,
+    ) -> c_int;
+
     pub unsafe fn libwgshim_list_peers(
         device_name: *const c_char,
         peer_head: *mut *mut LibWGShimPeer,
@@ -88,6 +105,12 @@ unsafe extern "C" {
         public_key: *const c_char,
     ) -> c_int;
 
+    pub unsafe fn libwgshim_check() -> c_int;
+
+    pub unsafe fn libwgshim_last_errno() -> c_int;
+
+    pub unsafe fn libwgshim_last_extack() -> *const c_char;
+
     pub unsafe fn libwgshim_free_device(dev: *mut LibWGShimDevice);
 
     pub unsafe fn libwgshim_free_peer(peer: *mut LibWGShimPeer);
@@ -105,6 +128,7 @@ impl LibWGShimDevice {
             public_key: c_char_array_to_string(&self.public_key),
             private_key: c_char_array_to_string(&self.private_key),
             port: self.port,
+            fwmark: self.fwmark,
             peers: self.peers,
         }
     }
@@ -127,6 +151,24 @@ impl LibWGShimAllowedIp {
 }
 
 impl LibWGShimPeer {
+    pub fn new(
+        allowed_ip: *mut LibWGShimAllowedIp,
+        persistent_keepalive_interval: c_ushort,
+    ) -> LibWGShimPeer {
+        LibWGShimPeer {
+            allowed_ip,
+            endpoint: [0; ENDPOINT_STRLEN],
+            last_handshake_time: 0,
+            persistent_keepalive_interval,
+            rx: 0,
+            tx: 0,
+            public_key: [0; LIBWGSHIM_B64_KEY_SIZE],
+            private_key: [0; LIBWGSHIM_B64_KEY_SIZE],
+            preshared_key: [0; LIBWGSHIM_B64_KEY_SIZE],
+            next: std::ptr::null_mut(),
+        }
+    }
+
     pub fn to_wg_peer(&self) -> WGPeer {
         let mut ips = Vec::<String>::new();
         let mut head = self.allowed_ip;
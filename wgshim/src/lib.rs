@@ -19,32 +19,52 @@ impl TryFrom<i32> for ffi::LibWGShimError {
             3 => Ok(Self::DevAddFailed),
             4 => Ok(Self::DevSetFailed),
             5 => Ok(Self::PeerNotFound),
+            6 => Ok(Self::FamilyNotFound),
             _ => Err(()),
         }
     }
 }
 
-impl From<ffi::LibWGShimError> for WGError {
+impl From<ffi::LibWGShimError> for WGErrorKind {
     fn from(value: ffi::LibWGShimError) -> Self {
         match value {
-            ffi::LibWGShimError::NoMem => WGError("no memory".to_owned()),
-            ffi::LibWGShimError::DevNotFound => WGError("device not found".to_owned()),
-            ffi::LibWGShimError::DevAddFailed => WGError("adding device failed".to_owned()),
-            ffi::LibWGShimError::DevSetFailed => WGError("setting device failed".to_owned()),
-            ffi::LibWGShimError::PeerNotFound => WGError("peer not found".to_owned()),
+            ffi::LibWGShimError::NoMem => WGErrorKind::NoMem,
+            ffi::LibWGShimError::DevNotFound => WGErrorKind::DevNotFound,
+            ffi::LibWGShimError::DevAddFailed => WGErrorKind::DevAddFailed,
+            ffi::LibWGShimError::DevSetFailed => WGErrorKind::DevSetFailed,
+            ffi::LibWGShimError::PeerNotFound => WGErrorKind::PeerNotFound,
+            ffi::LibWGShimError::FamilyNotFound => WGErrorKind::FamilyNotFound,
         }
     }
 }
 
+// Reads the errno and the extended ack message libwgshim kept for the last failure of the thread.
+unsafe fn last_error() -> (Option<i32>, Option<String>) {
+    let errno = unsafe { ffi::libwgshim_last_errno() };
+    let extack = unsafe { CStr::from_ptr(ffi::libwgshim_last_extack()) }
+        .to_string_lossy()
+        .into_owned();
+
+    (
+        (errno != 0).then_some(errno),
+        (!extack.is_empty()).then_some(extack),
+    )
+}
+
 // Checks the return code from a libwgshim ffi function.
-// If the code is non-zero, maps to error to WGError and returns early.
+// If the code is non-zero, maps to error to WGError along with its cause and returns early.
 macro_rules! libwgshim_try {
     ($code:expr) => {
         let result: i32 = unsafe { $code };
         if result != 0 {
-            let err: WGError = ffi::LibWGShimError::try_from(result)
-                .map(|e| e.into())
-                .unwrap_or_else(|_| WGError("wireguard error".to_owned()));
+            let (errno, extack) = unsafe { last_error() };
+            let err = ffi::LibWGShimError::try_from(result)
+                .map(|e| WGError::Lib {
+                    kind: e.into(),
+                    errno,
+                    extack,
+                })
+                .unwrap_or_else(|_| WGError::Other("wireguard error".to_owned()));
             return Err(err);
         }
     };
@@ -54,13 +74,13 @@ pub struct WGShimAdapter;
 
 impl WireguardAdapter for WGShimAdapter {
     fn get_device(&self, device_name: &str) -> Result<WGDevice, WGError> {
-        let dev_name = CString::new(device_name).map_err(|e| WGError(e.to_string()))?;
+        let dev_name = CString::new(device_name).map_err(|e| WGError::Invalid(e.to_string()))?;
 
         let mut dev_ptr: *mut ffi::LibWGShimDevice = ptr::null_mut();
         libwgshim_try!(ffi::libwgshim_get_device(dev_name.as_ptr(), &mut dev_ptr));
 
         if dev_ptr.is_null() {
-            return Err(WGError("wireguard error".to_owned()));
+            return Err(WGError::Other("wireguard error".to_owned()));
         }
 
         let shim_dev = unsafe { &(*dev_ptr) };
@@ -82,15 +102,14 @@ impl WireguardAdapter for WGShimAdapter {
             loop {
                 let dev_name = CStr::from_ptr(names.offset(offset))
                     .to_str()
-                    .map_err(|e| WGError(e.to_string()))?;
+                    .map_err(|e| WGError::Other(e.to_string()))?;
 
-                if dev_name == "" {
+                if dev_name.is_empty() {
                     break;
                 }
 
-                let dev = self.get_device(dev_name).map_err(|e| {
+                let dev = self.get_device(dev_name).inspect_err(|_| {
                     libc::free(names as *mut libc::c_void);
-                    e
                 })?;
 
                 devices.push(dev);
@@ -104,7 +123,7 @@ impl WireguardAdapter for WGShimAdapter {
     }
 
     fn create_device(&self, device_name: &str, port: u16) -> Result<WGDevice, WGError> {
-        let dev_name = CString::new(device_name).map_err(|e| WGError(e.to_string()))?;
+        let dev_name = CString::new(device_name).map_err(|e| WGError::Invalid(e.to_string()))?;
 
         let mut dev_ptr: *mut ffi::LibWGShimDevice = ptr::null_mut();
         libwgshim_try!(ffi::libwgshim_create_device(
@@ -114,7 +133,7 @@ impl WireguardAdapter for WGShimAdapter {
         ));
 
         if dev_ptr.is_null() {
-            return Err(WGError("wireguard error".to_owned()));
+            return Err(WGError::Other("wireguard error".to_owned()));
         }
 
         let shim_dev = unsafe { &(*dev_ptr) };
@@ -127,8 +146,16 @@ impl WireguardAdapter for WGShimAdapter {
         Ok(dev)
     }
 
+    fn set_fwmark(&self, device_name: &str, fwmark: u32) -> Result<(), WGError> {
+        let dev_name = CString::new(device_name).map_err(|e| WGError::Invalid(e.to_string()))?;
+
+        libwgshim_try!(ffi::libwgshim_set_fwmark(dev_name.as_ptr(), fwmark));
+
+        Ok(())
+    }
+
     fn delete_device(&self, device_name: &str) -> Result<(), WGError> {
-        let dev_name = CString::new(device_name).map_err(|e| WGError(e.to_string()))?;
+        let dev_name = CString::new(device_name).map_err(|e| WGError::Invalid(e.to_string()))?;
 
         libwgshim_try!(ffi::libwgshim_delete_device(dev_name.as_ptr()));
 
@@ -136,7 +163,7 @@ impl WireguardAdapter for WGShimAdapter {
     }
 
     fn list_peers(&self, device_name: &str) -> Result<Vec<WGPeer>, WGError> {
-        let dev_name = CString::new(device_name).map_err(|e| WGError(e.to_string()))?;
+        let dev_name = CString::new(device_name).map_err(|e| WGError::Invalid(e.to_string()))?;
 
         let mut head_ptr: *mut ffi::LibWGShimPeer = ptr::null_mut();
         libwgshim_try!(ffi::libwgshim_list_peers(dev_name.as_ptr(), &mut head_ptr));
@@ -151,7 +178,7 @@ impl WireguardAdapter for WGShimAdapter {
 
                 // heap allocation for wg_device causes the list function
                 // returning an empty peer, skip it.
-                if wg_peer.public_key != "" {
+                if !wg_peer.public_key.is_empty() {
                     peers.push(wg_peer);
                 }
 
@@ -171,7 +198,7 @@ impl WireguardAdapter for WGShimAdapter {
         allowed_ips: Vec<&str>,
         persistent_keepalive_interval: u16,
     ) -> Result<WGPeer, WGError> {
-        let dev_name = CString::new(device_name).map_err(|e| WGError(e.to_string()))?;
+        let dev_name = CString::new(device_name).map_err(|e| WGError::Invalid(e.to_string()))?;
 
         let mut raw_ip_nodes: Vec<*mut ffi::LibWGShimAllowedIp> = allowed_ips
             .iter()
@@ -187,7 +214,7 @@ impl WireguardAdapter for WGShimAdapter {
             }
         }
 
-        let allowed_ip_head = raw_ip_nodes.get(0).copied().unwrap_or(ptr::null_mut());
+        let allowed_ip_head = raw_ip_nodes.first().copied().unwrap_or(ptr::null_mut());
 
         let mut peer_ptr: *mut ffi::LibWGShimPeer = ptr::null_mut();
         libwgshim_try! {
@@ -204,12 +231,115 @@ impl WireguardAdapter for WGShimAdapter {
         Ok(peer)
     }
 
+    fn add_peers(
+        &self,
+        device_name: &str,
+        peers: Vec<(Vec<&str>, u16)>,
+    ) -> Result<Vec<WGPeer>, WGError> {
+        let dev_name = CString::new(device_name).map_err(|e| WGError::Invalid(e.to_string()))?;
+
+        let mut ip_lists: Vec<Vec<ffi::LibWGShimAllowedIp>> = peers
+            .iter()
+            .map(|(allowed_ips, _)| {
+                allowed_ips
+                    .iter()
+                    .map(|s| ffi::LibWGShimAllowedIp::new(s))
+                    .collect()
+            })
+            .collect();
+
+        // the lists are only read and filled in by libwgshim, so they stay owned here.
+        for ip_nodes in ip_lists.iter_mut() {
+            for i in 0..ip_nodes.len().saturating_sub(1) {
+                let next: *mut ffi::LibWGShimAllowedIp = &mut ip_nodes[i + 1];
+                ip_nodes[i].next = next;
+            }
+        }
+
+        let mut peer_nodes: Vec<ffi::LibWGShimPeer> = ip_lists
+            .iter_mut()
+            .zip(peers.iter())
+            .map(|(ip_nodes, (_, keepalive))| {
+                let allowed_ip_head = ip_nodes
+                    .first_mut()
+                    .map(|n| n as *mut ffi::LibWGShimAllowedIp)
+                    .unwrap_or(ptr::null_mut());
+                ffi::LibWGShimPeer::new(allowed_ip_head, *keepalive as std::os::raw::c_ushort)
+            })
+            .collect();
+
+        for i in 0..peer_nodes.len().saturating_sub(1) {
+            let next: *mut ffi::LibWGShimPeer = &mut peer_nodes[i + 1];
+            peer_nodes[i].next = next;
+        }
+
+        let peer_head = peer_nodes
+            .first_mut()
+            .map(|n| n as *mut ffi::LibWGShimPeer)
+            .unwrap_or(ptr::null_mut());
+
+        libwgshim_try!(ffi::libwgshim_add_peers(dev_name.as_ptr(), peer_head));
+
+        Ok(peer_nodes.iter().map(|p| p.to_wg_peer()).collect())
+    }
+
+    fn update_peer(
+        &self,
+        device_name: &str,
+        public_key: &str,
+        allowed_ips: Vec<&str>,
+        persistent_keepalive_interval: u16,
+    ) -> Result<WGPeer, WGError> {
+        let dev_name = CString::new(device_name).map_err(|e| WGError::Invalid(e.to_string()))?;
+        let pk = CString::new(public_key).map_err(|e| WGError::Invalid(e.to_string()))?;
+
+        let mut ip_nodes: Vec<ffi::LibWGShimAllowedIp> = allowed_ips
+            .iter()
+            .map(|s| ffi::LibWGShimAllowedIp::new(s))
+            .collect();
+
+        // the list is only read by libwgshim, so it stays owned here.
+        for i in 0..ip_nodes.len().saturating_sub(1) {
+            let next: *mut ffi::LibWGShimAllowedIp = &mut ip_nodes[i + 1];
+            ip_nodes[i].next = next;
+        }
+
+        let allowed_ip_head = ip_nodes
+            .first_mut()
+            .map(|n| n as *mut ffi::LibWGShimAllowedIp)
+            .unwrap_or(ptr::null_mut());
+
+        let mut peer_ptr: *mut ffi::LibWGShimPeer = ptr::null_mut();
+        libwgshim_try! {
+            ffi::libwgshim_update_peer(dev_name.as_ptr(), pk.as_ptr(), allowed_ip_head, persistent_keepalive_interval as std::os::raw::c_ushort, &mut peer_ptr)
+        };
+
+        if peer_ptr.is_null() {
+            return Err(WGError::Other("wireguard error".to_owned()));
+        }
+
+        let shim_peer = unsafe { &(*peer_ptr) };
+        let peer = shim_peer.to_wg_peer();
+
+        unsafe {
+            ffi::libwgshim_free_peer(peer_ptr);
+        }
+
+        Ok(peer)
+    }
+
     fn delete_peer(&self, device_name: &str, public_key: &str) -> Result<(), WGError> {
-        let dev_name = CString::new(device_name).map_err(|e| WGError(e.to_string()))?;
-        let pk = CString::new(public_key).map_err(|e| WGError(e.to_string()))?;
+        let dev_name = CString::new(device_name).map_err(|e| WGError::Invalid(e.to_string()))?;
+        let pk = CString::new(public_key).map_err(|e| WGError::Invalid(e.to_string()))?;
 
         libwgshim_try!(ffi::libwgshim_delete_peer(dev_name.as_ptr(), pk.as_ptr()));
 
         Ok(())
     }
+
+    fn check(&self) -> Result<(), WGError> {
+        libwgshim_try!(ffi::libwgshim_check());
+
+        Ok(())
+    }
 }
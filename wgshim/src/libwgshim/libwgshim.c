@@ -1,5 +1,6 @@
 #include "libwgshim.h"
 
+#include <errno.h>
 #include <net/if.h>
 #include <stdio.h>
 #include <stdlib.h>
@@ -7,9 +8,32 @@
 
 #include "wireguard.h"
 
+// errno behind the last failure on this thread, reported through libwgshim_last_errno.
+static __thread int last_errno;
+
+static void clear_error(void) {
+    last_errno = 0;
+    wg_clear_extack();
+}
+
+// Records the errno behind a failure and passes the error code through.
+static int fail(int code, int err) {
+    last_errno = err;
+    return code;
+}
+
+int libwgshim_last_errno(void) {
+    return last_errno;
+}
+
+const char *libwgshim_last_extack(void) {
+    return wg_last_extack();
+}
+
 void libwgshim_from_wg_device(wg_device *wgdev, libwgshim_device *dev) {
     strncpy(dev->name, wgdev->name, IF_NAMESIZE);
     dev->port = wgdev->listen_port;
+    dev->fwmark = wgdev->fwmark;
 
     uint64_t peers = 0;
     for (struct wg_peer *p = wgdev->first_peer; p != NULL; p = p->next_peer) {
@@ -22,15 +46,17 @@ void libwgshim_from_wg_device(wg_device *wgdev, libwgshim_device *dev) {
 }
 
 int libwgshim_get_device(const char *device_name, libwgshim_device **dev) {
+    clear_error();
     wg_device *wgdev = NULL;
-    if (wg_get_device(&wgdev, device_name) != 0) {
-        return LIBWGSHIM_ERR_DEV_NOT_FOUND;
+    int ret = wg_get_device(&wgdev, device_name);
+    if (ret != 0) {
+        return fail(LIBWGSHIM_ERR_DEV_NOT_FOUND, -ret);
     }
 
     *dev = calloc(1, sizeof(libwgshim_device));
     if (!*dev) {
         wg_free_device(wgdev);
-        return LIBWGSHIM_ERR_NOMEM;
+        return fail(LIBWGSHIM_ERR_NOMEM, ENOMEM);
     }
 
     libwgshim_from_wg_device(wgdev, *dev);
@@ -43,13 +69,16 @@ char *libwgshim_list_device_names() {
 }
 
 int libwgshim_create_device(const char *device_name, uint16_t port, libwgshim_device **dev) {
-    if (wg_add_device(device_name) != 0) {
-        return LIBWGSHIM_ERR_DEV_ADD_FAILED;
+    clear_error();
+    int ret = wg_add_device(device_name);
+    if (ret != 0) {
+        return fail(LIBWGSHIM_ERR_DEV_ADD_FAILED, -ret);
     }
 
     wg_device *wgdev = NULL;
-    if (wg_get_device(&wgdev, device_name) != 0) {
-        return LIBWGSHIM_ERR_DEV_NOT_FOUND;
+    ret = wg_get_device(&wgdev, device_name);
+    if (ret != 0) {
+        return fail(LIBWGSHIM_ERR_DEV_NOT_FOUND, -ret);
     }
 
     wg_generate_private_key(wgdev->private_key);
@@ -57,15 +86,16 @@ int libwgshim_create_device(const char *device_name, uint16_t port, libwgshim_de
     wgdev->listen_port = port;
     wgdev->flags = WGDEVICE_HAS_PRIVATE_KEY | WGDEVICE_HAS_PUBLIC_KEY | WGDEVICE_HAS_LISTEN_PORT;
 
-    if (wg_set_device(wgdev) != 0) {
+    ret = wg_set_device(wgdev);
+    if (ret != 0) {
         wg_free_device(wgdev);
-        return LIBWGSHIM_ERR_DEV_SET_FAILED;
+        return fail(LIBWGSHIM_ERR_DEV_SET_FAILED, -ret);
     }
 
     *dev = calloc(1, sizeof(libwgshim_device));
     if (!*dev) {
         wg_free_device(wgdev);
-        return LIBWGSHIM_ERR_NOMEM;
+        return fail(LIBWGSHIM_ERR_NOMEM, ENOMEM);
     }
 
     libwgshim_from_wg_device(wgdev, *dev);
@@ -74,8 +104,37 @@ int libwgshim_create_device(const char *device_name, uint16_t port, libwgshim_de
     return 0;
 }
 
+int libwgshim_set_fwmark(const char *device_name, uint32_t fwmark) {
+    clear_error();
+    wg_device *wgdev = NULL;
+    int ret = wg_get_device(&wgdev, device_name);
+    if (ret != 0) {
+        return fail(LIBWGSHIM_ERR_DEV_NOT_FOUND, -ret);
+    }
+    wg_free_device(wgdev);
+
+    wg_device update = {0};
+    strncpy(update.name, device_name, IFNAMSIZ - 1);
+    update.flags = WGDEVICE_HAS_FWMARK;
+    update.fwmark = fwmark;
+
+    ret = wg_set_device(&update);
+    if (ret != 0) {
+        return fail(LIBWGSHIM_ERR_DEV_SET_FAILED, -ret);
+    }
+
+    return 0;
+}
+
 int libwgshim_delete_device(const char *device_name) {
-    return wg_del_device(device_name);
+    clear_error();
+    int ret = wg_del_device(device_name);
+    if (ret != 0) {
+        int code = ret == -ENODEV ? LIBWGSHIM_ERR_DEV_NOT_FOUND : LIBWGSHIM_ERR_DEV_SET_FAILED;
+        return fail(code, -ret);
+    }
+
+    return 0;
 }
 
 wg_allowedip *to_wg_allowedip(libwgshim_allowed_ip *allowed_ip_head) {
@@ -146,15 +205,17 @@ void libwgshim_from_wg_peer(wg_peer *wgpeer, wg_key private_key,
 
 int libwgshim_add_peer(const char *device_name, libwgshim_allowed_ip *allowed_ip_head,
                        uint16_t persistent_keepalive_interval, libwgshim_peer **peer) {
+    clear_error();
     wg_device *wgdev = NULL;
-    if (wg_get_device(&wgdev, device_name) != 0) {
-        return LIBWGSHIM_ERR_DEV_NOT_FOUND;
+    int ret = wg_get_device(&wgdev, device_name);
+    if (ret != 0) {
+        return fail(LIBWGSHIM_ERR_DEV_NOT_FOUND, -ret);
     }
 
     wg_peer *p = calloc(1, sizeof(wg_peer));
     if (!p) {
         wg_free_device(wgdev);
-        return LIBWGSHIM_ERR_NOMEM;
+        return fail(LIBWGSHIM_ERR_NOMEM, ENOMEM);
     }
 
     wg_key private_key;
@@ -180,7 +241,7 @@ int libwgshim_add_peer(const char *device_name, libwgshim_allowed_ip *allowed_ip
     wg_allowedip *wg_ip = to_wg_allowedip(allowed_ip_head);
     if (!wg_ip) {
         wg_free_device(wgdev);
-        return LIBWGSHIM_ERR_NOMEM;
+        return fail(LIBWGSHIM_ERR_NOMEM, ENOMEM);
     }
     p->first_allowedip = wg_ip;
     wg_allowedip *current = wg_ip;
@@ -189,15 +250,16 @@ int libwgshim_add_peer(const char *device_name, libwgshim_allowed_ip *allowed_ip
         current = current->next_allowedip;
     }
 
-    if (wg_set_device(wgdev) != 0) {
+    ret = wg_set_device(wgdev);
+    if (ret != 0) {
         wg_free_device(wgdev);
-        return LIBWGSHIM_ERR_DEV_SET_FAILED;
+        return fail(LIBWGSHIM_ERR_DEV_SET_FAILED, -ret);
     }
 
     *peer = calloc(1, sizeof(libwgshim_peer));
     if (!*peer) {
         wg_free_device(wgdev);
-        return LIBWGSHIM_ERR_NOMEM;
+        return fail(LIBWGSHIM_ERR_NOMEM, ENOMEM);
     }
 
     libwgshim_from_wg_peer(p, private_key, allowed_ip_head, *peer);
@@ -205,6 +267,83 @@ int libwgshim_add_peer(const char *device_name, libwgshim_allowed_ip *allowed_ip
     return 0;
 }
 
+static void free_wg_peers(wg_peer *peer) {
+    while (peer) {
+        wg_peer *next_peer = peer->next_peer;
+
+        wg_allowedip *ip = peer->first_allowedip;
+        while (ip) {
+            wg_allowedip *next_ip = ip->next_allowedip;
+            free(ip);
+            ip = next_ip;
+        }
+
+        free(peer);
+        peer = next_peer;
+    }
+}
+
+int libwgshim_add_peers(const char *device_name, libwgshim_peer *peer_head) {
+    clear_error();
+    wg_device *wgdev = NULL;
+    int ret = wg_get_device(&wgdev, device_name);
+    if (ret != 0) {
+        return fail(LIBWGSHIM_ERR_DEV_NOT_FOUND, -ret);
+    }
+
+    // only the new peers are sent, so the existing ones are left untouched.
+    wg_device update = {0};
+    strncpy(update.name, wgdev->name, IFNAMSIZ - 1);
+    wg_free_device(wgdev);
+
+    for (libwgshim_peer *peer = peer_head; peer != NULL; peer = peer->next) {
+        wg_peer *p = calloc(1, sizeof(wg_peer));
+        if (!p) {
+            free_wg_peers(update.first_peer);
+            return fail(LIBWGSHIM_ERR_NOMEM, ENOMEM);
+        }
+
+        if (!update.first_peer) {
+            update.first_peer = p;
+        } else {
+            update.last_peer->next_peer = p;
+        }
+        update.last_peer = p;
+
+        wg_key private_key;
+        wg_generate_private_key(private_key);
+
+        p->flags = WGPEER_HAS_PUBLIC_KEY | WGPEER_HAS_PRESHARED_KEY;
+        if (peer->persistent_keepalive_interval > 0) {
+            p->flags |= WGPEER_HAS_PERSISTENT_KEEPALIVE_INTERVAL;
+        }
+        wg_generate_public_key(p->public_key, private_key);
+        wg_generate_preshared_key(p->preshared_key);
+        p->persistent_keepalive_interval = peer->persistent_keepalive_interval;
+
+        p->first_allowedip = to_wg_allowedip(peer->allowed_ip);
+        if (peer->allowed_ip && !p->first_allowedip) {
+            free_wg_peers(update.first_peer);
+            return fail(LIBWGSHIM_ERR_NOMEM, ENOMEM);
+        }
+        for (wg_allowedip *ip = p->first_allowedip; ip != NULL; ip = ip->next_allowedip) {
+            p->last_allowedip = ip;
+        }
+
+        wg_key_to_base64(peer->public_key, p->public_key);
+        wg_key_to_base64(peer->private_key, private_key);
+        wg_key_to_base64(peer->preshared_key, p->preshared_key);
+    }
+
+    ret = wg_set_device(&update);
+    free_wg_peers(update.first_peer);
+    if (ret != 0) {
+        return fail(LIBWGSHIM_ERR_DEV_SET_FAILED, -ret);
+    }
+
+    return 0;
+}
+
 void wg_endpoint_str(wg_endpoint *endpoint, char *buf, size_t size) {
     char ip_str[INET6_ADDRSTRLEN];
 
@@ -272,15 +411,17 @@ void libwgshim_from_wg_peer_list(wg_peer *wgpeer, libwgshim_peer *peer) {
 }
 
 int libwgshim_list_peers(const char *device_name, libwgshim_peer **peer_head) {
+    clear_error();
     wg_device *wgdev = NULL;
-    if (wg_get_device(&wgdev, device_name) != 0) {
-        return LIBWGSHIM_ERR_DEV_NOT_FOUND;
+    int ret = wg_get_device(&wgdev, device_name);
+    if (ret != 0) {
+        return fail(LIBWGSHIM_ERR_DEV_NOT_FOUND, -ret);
     }
 
     *peer_head = calloc(1, sizeof(libwgshim_peer));
     if (!*peer_head) {
         wg_free_device(wgdev);
-        return LIBWGSHIM_ERR_NOMEM;
+        return fail(LIBWGSHIM_ERR_NOMEM, ENOMEM);
     }
 
     wg_peer *p = wgdev->first_peer;
@@ -303,10 +444,99 @@ int libwgshim_list_peers(const char *device_name, libwgshim_peer **peer_head) {
     return 0;
 }
 
+int libwgshim_update_peer(const char *device_name, const char *public_key,
+                          libwgshim_allowed_ip *allowed_ip_head,
+                          uint16_t persistent_keepalive_interval, libwgshim_peer **peer) {
+    clear_error();
+    wg_device *wgdev = NULL;
+    int ret = wg_get_device(&wgdev, device_name);
+    if (ret != 0) {
+        return fail(LIBWGSHIM_ERR_DEV_NOT_FOUND, -ret);
+    }
+
+    wg_peer *found = NULL;
+    for (wg_peer *wp = wgdev->first_peer; wp != NULL; wp = wp->next_peer) {
+        wg_key_b64_string peer_pk;
+        wg_key_to_base64(peer_pk, wp->public_key);
+
+        if (strcmp(public_key, peer_pk) == 0) {
+            found = wp;
+            break;
+        }
+    }
+
+    if (!found) {
+        wg_free_device(wgdev);
+        return LIBWGSHIM_ERR_PEER_NOT_FOUND;
+    }
+
+    // only the updated peer is sent, so the others are left untouched.
+    wg_device update = {0};
+    strncpy(update.name, wgdev->name, IFNAMSIZ - 1);
+
+    wg_peer changed = {0};
+    memcpy(changed.public_key, found->public_key, sizeof(wg_key));
+    changed.flags =
+        WGPEER_HAS_PUBLIC_KEY | WGPEER_REPLACE_ALLOWEDIPS | WGPEER_HAS_PERSISTENT_KEEPALIVE_INTERVAL;
+    changed.persistent_keepalive_interval = persistent_keepalive_interval;
+    wg_free_device(wgdev);
+
+    changed.first_allowedip = to_wg_allowedip(allowed_ip_head);
+    for (wg_allowedip *ip = changed.first_allowedip; ip != NULL; ip = ip->next_allowedip) {
+        changed.last_allowedip = ip;
+    }
+
+    update.first_peer = update.last_peer = &changed;
+
+    int res = wg_set_device(&update);
+
+    wg_allowedip *ip = changed.first_allowedip;
+    while (ip) {
+        wg_allowedip *next_ip = ip->next_allowedip;
+        free(ip);
+        ip = next_ip;
+    }
+
+    if (res != 0) {
+        return fail(LIBWGSHIM_ERR_DEV_SET_FAILED, -res);
+    }
+
+    wgdev = NULL;
+    ret = wg_get_device(&wgdev, device_name);
+    if (ret != 0) {
+        return fail(LIBWGSHIM_ERR_DEV_NOT_FOUND, -ret);
+    }
+
+    found = NULL;
+    for (wg_peer *wp = wgdev->first_peer; wp != NULL; wp = wp->next_peer) {
+        if (memcmp(wp->public_key, changed.public_key, sizeof(wg_key)) == 0) {
+            found = wp;
+            break;
+        }
+    }
+
+    if (!found) {
+        wg_free_device(wgdev);
+        return LIBWGSHIM_ERR_PEER_NOT_FOUND;
+    }
+
+    *peer = calloc(1, sizeof(libwgshim_peer));
+    if (!*peer) {
+        wg_free_device(wgdev);
+        return fail(LIBWGSHIM_ERR_NOMEM, ENOMEM);
+    }
+
+    libwgshim_from_wg_peer_list(found, *peer);
+    wg_free_device(wgdev);
+    return 0;
+}
+
 int libwgshim_delete_peer(const char *device_name, const char *public_key) {
+    clear_error();
     wg_device *wgdev = NULL;
-    if (wg_get_device(&wgdev, device_name) != 0) {
-        return LIBWGSHIM_ERR_DEV_NOT_FOUND;
+    int ret = wg_get_device(&wgdev, device_name);
+    if (ret != 0) {
+        return fail(LIBWGSHIM_ERR_DEV_NOT_FOUND, -ret);
     }
 
     uint8_t found = 0;
@@ -329,15 +559,26 @@ int libwgshim_delete_peer(const char *device_name, const char *public_key) {
         return LIBWGSHIM_ERR_PEER_NOT_FOUND;
     }
 
-    if (wg_set_device(wgdev) != 0) {
+    ret = wg_set_device(wgdev);
+    if (ret != 0) {
         wg_free_device(wgdev);
-        return LIBWGSHIM_ERR_DEV_SET_FAILED;
+        return fail(LIBWGSHIM_ERR_DEV_SET_FAILED, -ret);
     }
 
     wg_free_device(wgdev);
     return 0;
 }
 
+int libwgshim_check(void) {
+    clear_error();
+    int ret = wg_resolve_family();
+    if (ret < 0) {
+        return fail(LIBWGSHIM_ERR_FAMILY_NOT_FOUND, -ret);
+    }
+
+    return 0;
+}
+
 void libwgshim_free_device(libwgshim_device *dev) {
     if (!dev) {
         return;
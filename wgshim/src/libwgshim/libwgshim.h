@@ -32,6 +32,7 @@ typedef enum {
     LIBWGSHIM_ERR_DEV_ADD_FAILED,
     LIBWGSHIM_ERR_DEV_SET_FAILED,
     LIBWGSHIM_ERR_PEER_NOT_FOUND,
+    LIBWGSHIM_ERR_FAMILY_NOT_FOUND,
 } libwgshim_error;
 
 /**
@@ -40,8 +41,9 @@ typedef enum {
 typedef struct libwgshim_device {
     char name[IF_NAMESIZE];  // Interface name (e.g., "wg0")
 
-    uint16_t port;   // Listening port
-    uint64_t peers;  // Number of associated peers
+    uint16_t port;    // Listening port
+    uint32_t fwmark;  // Firewall mark of outgoing packets, 0 if unset
+    uint64_t peers;   // Number of associated peers
 
     char public_key[LIBWGSHIM_B64_KEY_SIZE];   // Base64-encoded public key
     char private_key[LIBWGSHIM_B64_KEY_SIZE];  // Base64-encoded private key
@@ -106,6 +108,15 @@ char *libwgshim_list_device_names();
  */
 int libwgshim_create_device(const char *device_name, uint16_t port, libwgshim_device **dev);
 
+/**
+ * @brief Sets the firewall mark of packets sent by a WireGuard device.
+ *
+ * @param device_name Name of the device
+ * @param fwmark Firewall mark to apply, or 0 to clear it
+ * @return 0 on success, non-zero on failure
+ */
+int libwgshim_set_fwmark(const char *device_name, uint32_t fwmark);
+
 /**
  * @brief Deletes a WireGuard device by name.
  *
@@ -126,6 +137,18 @@ int libwgshim_delete_device(const char *device_name);
 int libwgshim_add_peer(const char *device_name, libwgshim_allowed_ip *allowed_ip_head,
                        uint16_t persistent_keepalive_interval, libwgshim_peer **peer);
 
+/**
+ * @brief Adds several peers to the given WireGuard device in a single update.
+ *
+ * The allowed IPs and keepalive interval of each entry are read, and the keys generated for it
+ * are written back into the entry. The list stays owned by the caller.
+ *
+ * @param device_name Name of the target device
+ * @param peer_head Head of the linked list of peers to add
+ * @return 0 on success, non-zero on failure
+ */
+int libwgshim_add_peers(const char *device_name, libwgshim_peer *peer_head);
+
 /**
  * @brief Lists all peers associated with a given WireGuard device.
  *
@@ -135,6 +158,23 @@ int libwgshim_add_peer(const char *device_name, libwgshim_allowed_ip *allowed_ip
  */
 int libwgshim_list_peers(const char *device_name, libwgshim_peer **peer_head);
 
+/**
+ * @brief Updates the allowed IPs and keepalive interval of an existing peer.
+ *
+ * The allowed IPs of the peer are replaced with the given list. Keys and the endpoint of the peer
+ * are left untouched.
+ *
+ * @param device_name Name of the device
+ * @param public_key Base64-encoded public key of the peer to update
+ * @param allowed_ip_head Head of the allowed IP linked list
+ * @param persistent_keepalive_interval Interval in seconds, or 0 to disable
+ * @param peer Output pointer to the updated peer
+ * @return 0 on success, non-zero on failure
+ */
+int libwgshim_update_peer(const char *device_name, const char *public_key,
+                          libwgshim_allowed_ip *allowed_ip_head,
+                          uint16_t persistent_keepalive_interval, libwgshim_peer **peer);
+
 /**
  * @brief Deletes a peer from a WireGuard device using its public key.
  *
@@ -144,6 +184,31 @@ int libwgshim_list_peers(const char *device_name, libwgshim_peer **peer_head);
  */
 int libwgshim_delete_peer(const char *device_name, const char *public_key);
 
+/**
+ * @brief Checks that the "wireguard" generic netlink family can be resolved, i.e. that the kernel
+ * module is loaded and the caller may talk to it.
+ *
+ * @return 0 on success, non-zero on failure
+ */
+int libwgshim_check(void);
+
+/**
+ * @brief Returns the errno behind the last failed libwgshim call of the calling thread.
+ *
+ * @return errno of the failed system call or netlink request, 0 if the failure had no such cause
+ */
+int libwgshim_last_errno(void);
+
+/**
+ * @brief Returns the extended ack message the kernel sent for the last failed libwgshim call of
+ * the calling thread.
+ *
+ * The string is owned by the library and valid until the next libwgshim call on the thread.
+ *
+ * @return Message of the kernel, empty if it sent none
+ */
+const char *libwgshim_last_extack(void);
+
 /**
  * @brief Frees memory allocated for a libwgshim_device struct.
  *
@@ -448,6 +448,44 @@ static void mnl_attr_nest_cancel(struct nlmsghdr *nlh, struct nlattr *start)
 	nlh->nlmsg_len -= mnl_nlmsg_get_payload_tail(nlh) - (void *)start;
 }
 
+#define WG_EXTACK_MAXLEN 256
+
+static __thread char wg_extack[WG_EXTACK_MAXLEN];
+
+const char *wg_last_extack(void)
+{
+	return wg_extack;
+}
+
+void wg_clear_extack(void)
+{
+	wg_extack[0] = '\0';
+}
+
+/* Keeps the NLMSGERR_ATTR_MSG attribute of an error message for wg_last_extack(). */
+static void mnl_store_extack(const struct nlmsghdr *nlh)
+{
+	const struct nlmsgerr *err = mnl_nlmsg_get_payload(nlh);
+	struct nlattr *attr;
+	size_t offset = sizeof(*err);
+
+	if (!(nlh->nlmsg_flags & NLM_F_ACK_TLVS))
+		return;
+	/* the request is echoed back between the error and its attributes unless capped */
+	if (!(nlh->nlmsg_flags & NLM_F_CAPPED))
+		offset += err->msg.nlmsg_len - sizeof(struct nlmsghdr);
+	if (mnl_nlmsg_size(offset) > nlh->nlmsg_len)
+		return;
+
+	mnl_attr_for_each(attr, nlh, offset) {
+		if (mnl_attr_get_type(attr) == NLMSGERR_ATTR_MSG) {
+			snprintf(wg_extack, sizeof(wg_extack), "%.*s", (int)mnl_attr_get_payload_len(attr),
+				 (const char *)mnl_attr_get_payload(attr));
+			return;
+		}
+	}
+}
+
 static int mnl_cb_noop(__attribute__((unused)) const struct nlmsghdr *nlh, __attribute__((unused)) void *data)
 {
 	return MNL_CB_OK;
@@ -467,6 +505,9 @@ static int mnl_cb_error(const struct nlmsghdr *nlh, __attribute__((unused)) void
 	else
 		errno = err->error;
 
+	if (err->error)
+		mnl_store_extack(nlh);
+
 	return err->error == 0 ? MNL_CB_STOP : MNL_CB_ERROR;
 }
 
@@ -569,6 +610,9 @@ static struct mnl_socket *__mnl_socket_open(int bus, int flags)
 		return NULL;
 	}
 
+	/* best effort, errors are reported without the kernel's message where unsupported */
+	setsockopt(nl->fd, SOL_NETLINK, NETLINK_EXT_ACK, &(int){ 1 }, sizeof(int));
+
 	return nl;
 }
 
@@ -720,6 +764,9 @@ static int mnlg_cb_error(const struct nlmsghdr *nlh, void *data)
 	else
 		errno = err->error;
 
+	if (err->error)
+		mnl_store_extack(nlh);
+
 	return err->error == 0 ? MNL_CB_STOP : MNL_CB_ERROR;
 }
 
@@ -1061,6 +1108,18 @@ cleanup:
 	return ret;
 }
 
+int wg_resolve_family(void)
+{
+	struct mnlg_socket *nlg;
+
+	nlg = mnlg_socket_open(WG_GENL_NAME, WG_GENL_VERSION);
+	if (!nlg)
+		return -errno;
+
+	mnlg_socket_close(nlg);
+	return 0;
+}
+
 int wg_set_device(wg_device *dev)
 {
 	int ret = 0;
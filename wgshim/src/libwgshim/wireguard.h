@@ -101,5 +101,8 @@ bool wg_key_is_zero(const wg_key key);
 void wg_generate_public_key(wg_key public_key, const wg_key private_key);
 void wg_generate_private_key(wg_key private_key);
 void wg_generate_preshared_key(wg_key preshared_key);
+const char *wg_last_extack(void); /* message of the last netlink error on this thread, or "" */
+void wg_clear_extack(void);
+int wg_resolve_family(void); /* 0 once the "wireguard" generic netlink family is resolved */
 
 #endif
@@ -63,7 +63,7 @@ fn test_get_device_non_existing_dev_returns_err() {
     let result = adapter.get_device("nadev");
     assert!(result.is_err());
     if let Err(err) = result {
-        assert_eq!(err.0, "device not found");
+        assert_eq!(err.message(), "device not found");
     }
 }
 
@@ -110,7 +110,7 @@ fn test_create_device_fails_if_device_exists() {
     let result = adapter.create_device("wgtest3", 51820);
     assert!(result.is_err());
     if let Err(err) = result {
-        assert_eq!(err.0, "adding device failed");
+        assert_eq!(err.message(), "adding device failed");
     }
     delete_wg_device("wgtest3");
 }
@@ -131,6 +131,19 @@ fn test_create_device_with_successful_result() {
     delete_wg_device("wgtest4");
 }
 
+#[test]
+#[serial]
+fn test_set_fwmark_with_successful_result() {
+    create_wg_device("wgtest11");
+    let adapter = WGShimAdapter;
+    let result = adapter.set_fwmark("wgtest11", 51820);
+    assert!(result.is_ok());
+    if let Ok(device) = adapter.get_device("wgtest11") {
+        assert_eq!(device.fwmark, 51820);
+    }
+    delete_wg_device("wgtest11");
+}
+
 #[test]
 #[serial]
 fn test_delete_device_non_existing_dev_returns_err() {
@@ -138,7 +151,7 @@ fn test_delete_device_non_existing_dev_returns_err() {
     let result = adapter.delete_device("nadev");
     assert!(result.is_err());
     if let Err(err) = result {
-        assert_eq!(err.0, "wireguard error");
+        assert_eq!(err.message(), "device not found");
     }
 }
 
@@ -149,11 +162,11 @@ fn test_delete_device_with_successful_result() {
     let adapter = WGShimAdapter;
     let result = adapter.delete_device("wgtest5");
     assert!(result.is_ok());
-    if let Ok(_) = result {
+    if result.is_ok() {
         let result = adapter.get_device("wgtest5");
         assert!(result.is_err());
         if let Err(err) = result {
-            assert_eq!(err.0, "device not found");
+            assert_eq!(err.message(), "device not found");
         }
     }
 }
@@ -165,7 +178,7 @@ fn test_list_peers_returns_device_not_found() {
     let result = adapter.list_peers("nadev");
     assert!(result.is_err());
     if let Err(err) = result {
-        assert_eq!(err.0, "device not found");
+        assert_eq!(err.message(), "device not found");
     }
 }
 
@@ -198,7 +211,7 @@ fn test_add_peer_returns_device_not_found() {
     let result = adapter.add_peer("nodev", vec![], 30);
     assert!(result.is_err());
     if let Err(err) = result {
-        assert_eq!(err.0, "device not found");
+        assert_eq!(err.message(), "device not found");
     }
 }
 
@@ -219,6 +232,77 @@ fn test_add_peer_returns_successful_result() {
     delete_wg_device("wgtest7");
 }
 
+#[test]
+#[serial]
+fn test_add_peers_returns_successful_result() {
+    create_wg_device("wgtest12");
+    let adapter = WGShimAdapter;
+    let result = adapter.add_peers(
+        "wgtest12",
+        vec![
+            (vec!["10.0.0.2/32"], 0),
+            (vec!["10.0.0.3/32", "10.0.1.0/24"], 25),
+        ],
+    );
+    assert!(result.is_ok());
+    if let Ok(peers) = result {
+        assert_eq!(peers.len(), 2);
+        assert_ne!(peers[0].public_key, peers[1].public_key);
+        assert_ne!(peers[1].private_key, "");
+        assert_eq!(
+            peers[1].allowed_ips,
+            vec!["10.0.0.3/32".to_owned(), "10.0.1.0/24".to_owned()]
+        );
+        assert_eq!(peers[1].persistent_keepalive_interval, 25);
+    }
+    assert_eq!(adapter.list_peers("wgtest12").unwrap().len(), 2);
+    delete_wg_device("wgtest12");
+}
+
+#[test]
+#[serial]
+fn test_update_peer_returns_peer_not_found() {
+    create_wg_device("wgtest9");
+    let adapter = WGShimAdapter;
+    let result = adapter.update_peer(
+        "wgtest9",
+        "wfbGOdrEgIGn15y6FMgfJjpaZv02ZQb5xQ5yvnkPhyg=",
+        vec!["10.0.0.3/32"],
+        0,
+    );
+    assert!(result.is_err());
+    if let Err(err) = result {
+        assert_eq!(err.message(), "peer not found");
+    }
+    delete_wg_device("wgtest9");
+}
+
+#[test]
+#[serial]
+fn test_update_peer_returns_successful_result() {
+    create_wg_device("wgtest10");
+    let adapter = WGShimAdapter;
+    let result = adapter.update_peer(
+        "wgtest10",
+        "CCc0ghN+bKWt176pH6eTWVivrgrSfA1YjPFSa5b9Xho=",
+        vec!["10.0.0.3/32", "192.168.10.0/24"],
+        25,
+    );
+    assert!(result.is_ok());
+    if let Ok(peer) = result {
+        assert_eq!(
+            peer.public_key,
+            "CCc0ghN+bKWt176pH6eTWVivrgrSfA1YjPFSa5b9Xho="
+        );
+        assert_eq!(
+            peer.allowed_ips,
+            vec!["10.0.0.3/32".to_owned(), "192.168.10.0/24".to_owned()]
+        );
+        assert_eq!(peer.persistent_keepalive_interval, 25);
+    }
+    delete_wg_device("wgtest10");
+}
+
 #[test]
 #[serial]
 fn test_delete_peer_returns_device_not_found() {
@@ -226,7 +310,7 @@ fn test_delete_peer_returns_device_not_found() {
     let result = adapter.delete_peer("nodev", "nodev");
     assert!(result.is_err());
     if let Err(err) = result {
-        assert_eq!(err.0, "device not found");
+        assert_eq!(err.message(), "device not found");
     }
 }
 
@@ -237,7 +321,7 @@ fn test_delete_peer_returns_successful_result() {
     let adapter = WGShimAdapter;
     let result = adapter.delete_peer("wgtest8", "CCc0ghN+bKWt176pH6eTWVivrgrSfA1YjPFSa5b9Xho=");
     assert!(result.is_ok());
-    if let Ok(_) = result {
+    if result.is_ok() {
         let result = adapter.list_peers("wgtest8");
         assert!(result.is_ok());
         if let Ok(peers) = result {
@@ -246,3 +330,11 @@ fn test_delete_peer_returns_successful_result() {
     }
     delete_wg_device("wgtest8");
 }
+
+#[test]
+#[serial]
+fn test_check_resolves_wireguard_family() {
+    let adapter = WGShimAdapter;
+    let result = adapter.check();
+    assert!(result.is_ok());
+}